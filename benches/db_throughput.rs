@@ -0,0 +1,136 @@
+//! Throughput of the service layer under concurrent reads, writes and
+//! password verification, plus how late a 1ms timer on the async executor
+//! fires while that load is running. Run with `cargo bench`; tune with
+//! `BENCH_SECS`, `BENCH_READERS`, `BENCH_WRITERS` and `BENCH_LOGINS`.
+
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use blog::models::auth::{hash_password, verify_password};
+use blog::models::post::{CreatePost, PostSort};
+use blog::models::user::CreateUser;
+use blog::services::db::{connect, DbConfig};
+use blog::services::{post_service, user_service};
+use uuid::Uuid;
+
+fn setting(name: &str, default: u64) -> u64 {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+fn main() {
+    let secs = setting("BENCH_SECS", 5);
+    let readers = setting("BENCH_READERS", 16);
+    let writers = setting("BENCH_WRITERS", 4);
+    let logins = setting("BENCH_LOGINS", 4);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(4)
+        .max_blocking_threads(DbConfig::default().blocking_threads)
+        .enable_all()
+        .build()
+        .expect("Failed to build Tokio runtime");
+
+    let db_path = env::temp_dir().join(format!("bench_{}.db", Uuid::new_v4()));
+
+    runtime.block_on(async {
+        let db = connect(db_path.to_str().unwrap(), &DbConfig::default()).await.unwrap();
+        let password_hash = hash_password("benchpass").await.unwrap();
+        let author = user_service::create_user(
+            &db,
+            CreateUser { username: "bench".to_string(), password: String::new(), email: String::new() },
+            password_hash.clone(),
+        )
+        .await
+        .unwrap();
+
+        for i in 0..50 {
+            let post = CreatePost {
+                title: format!("Seed post {}", i),
+                content: "Lorem ipsum dolor sit amet. ".repeat(40),
+            };
+            post_service::create_post(&db, post, author.id).await.unwrap();
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let reads = Arc::new(AtomicU64::new(0));
+        let writes = Arc::new(AtomicU64::new(0));
+        let verifies = Arc::new(AtomicU64::new(0));
+        let mut tasks = Vec::new();
+
+        for _ in 0..readers {
+            let (db, stop, reads) = (db.clone(), stop.clone(), reads.clone());
+            tasks.push(tokio::spawn(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    post_service::get_posts(&db, PostSort::Newest).await.unwrap();
+                    reads.fetch_add(1, Ordering::Relaxed);
+                }
+            }));
+        }
+
+        for _ in 0..writers {
+            let (db, stop, writes) = (db.clone(), stop.clone(), writes.clone());
+            tasks.push(tokio::spawn(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    let post = CreatePost {
+                        title: "Bench post".to_string(),
+                        content: "Written during the benchmark.".to_string(),
+                    };
+                    post_service::create_post(&db, post, author.id).await.unwrap();
+                    writes.fetch_add(1, Ordering::Relaxed);
+                }
+            }));
+        }
+
+        for _ in 0..logins {
+            let (hash, stop, verifies) = (password_hash.clone(), stop.clone(), verifies.clone());
+            tasks.push(tokio::spawn(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    verify_password("benchpass", &hash).await.unwrap();
+                    verifies.fetch_add(1, Ordering::Relaxed);
+                }
+            }));
+        }
+
+        // A well-behaved executor wakes this task roughly every millisecond;
+        // blocking work on the async workers shows up as large lag.
+        let heartbeat = {
+            let stop = stop.clone();
+            tokio::spawn(async move {
+                let mut lags = Vec::new();
+                while !stop.load(Ordering::Relaxed) {
+                    let start = Instant::now();
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    lags.push(start.elapsed().saturating_sub(Duration::from_millis(1)));
+                }
+                lags
+            })
+        };
+
+        tokio::time::sleep(Duration::from_secs(secs)).await;
+        stop.store(true, Ordering::Relaxed);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let mut lags = heartbeat.await.unwrap();
+        lags.sort();
+
+        let per_sec = |n: &AtomicU64| n.load(Ordering::Relaxed) as f64 / secs as f64;
+        let percentile = |p: f64| lags[((lags.len() - 1) as f64 * p) as usize];
+
+        println!("duration        {}s ({} readers, {} writers, {} logins)", secs, readers, writers, logins);
+        println!("reads/sec       {:.0}", per_sec(&reads));
+        println!("writes/sec      {:.0}", per_sec(&writes));
+        println!("verifies/sec    {:.1}", per_sec(&verifies));
+        println!("timer lag p50   {:?}", percentile(0.50));
+        println!("timer lag p99   {:?}", percentile(0.99));
+        println!("timer lag max   {:?}", lags.last().unwrap());
+    });
+    drop(runtime);
+
+    for suffix in ["", "-wal", "-shm"] {
+        fs::remove_file(format!("{}{}", db_path.display(), suffix)).ok();
+    }
+}
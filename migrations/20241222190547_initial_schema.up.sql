@@ -1,37 +0,0 @@
--- Add up migration script here
-
-PRAGMA foreign_keys=OFF;
-
--- Create users table
-CREATE TABLE users (
-    id TEXT PRIMARY KEY NOT NULL,
-    username TEXT UNIQUE NOT NULL,
-    password_hash TEXT NOT NULL,
-    created_at TEXT NOT NULL,
-    updated_at TEXT NOT NULL
-);
-
--- Create posts table with cascade delete
-CREATE TABLE posts (
-    id TEXT PRIMARY KEY NOT NULL,
-    title TEXT NOT NULL,
-    content TEXT NOT NULL,
-    author_id TEXT NOT NULL,
-    created_at TEXT NOT NULL,
-    updated_at TEXT NOT NULL,
-    FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE CASCADE
-);
-
--- Create comments table with cascade delete
-CREATE TABLE comments (
-    id TEXT PRIMARY KEY NOT NULL,
-    content TEXT NOT NULL,
-    post_id TEXT NOT NULL,
-    author_id TEXT NOT NULL,
-    created_at TEXT NOT NULL,
-    updated_at TEXT NOT NULL,
-    FOREIGN KEY (post_id) REFERENCES posts(id) ON DELETE CASCADE,
-    FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE CASCADE
-);
-
-PRAGMA foreign_keys=ON;
@@ -0,0 +1,606 @@
+//! Operator tool for the blog database. Shares the service layer with the
+//! web server, so the same validation, hashing and migrations apply.
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use dotenvy::dotenv;
+use serde::Serialize;
+use serde_json::json;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use uuid::Uuid;
+use validator::Validate;
+
+use blog::models::auth::hash_password;
+use blog::models::user::{CreateUser, Role, UpdateEmail};
+use blog::models::User;
+use blog::services::db::{connect, Database, DbConfig};
+use blog::services::backup_service::{self, BackupConfig};
+use blog::importers::{ghost, wordpress};
+use blog::seo::SeoConfig;
+use blog::static_site::{self, StaticSiteConfig};
+use blog::services::{
+    import_service, login_service, maintenance_service, sync_service, transfer_service, two_factor_service, user_service,
+};
+
+#[derive(Parser)]
+#[command(name = "blogctl", about = "Administer the blog database")]
+struct Cli {
+    /// Database to operate on; same format as the server's DATABASE_URL.
+    #[arg(long, env = "DATABASE_URL", default_value = "blog.db", global = true)]
+    database_url: String,
+
+    /// Print machine-readable JSON instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage user accounts.
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Apply pending schema migrations and report the schema version.
+    Migrate,
+    /// Rebuild database indexes and refresh query planner statistics.
+    Reindex,
+    /// Reclaim unused space in the database.
+    Vacuum,
+    /// Write all users, posts and comments as JSON or as an archive.
+    Export {
+        /// Output file; standard output when omitted.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// `archive` writes a .tar.gz with a JSON manifest and one Markdown
+        /// file per post.
+        #[arg(long, value_enum, default_value = "json")]
+        format: ExportFormat,
+        /// Include password hashes so accounts keep working after import.
+        #[arg(long)]
+        include_password_hashes: bool,
+    },
+    /// Load a file produced by `export` in either format. Existing records
+    /// are skipped and conflicting ones reported.
+    Import {
+        /// Input file; standard input when `-`.
+        file: PathBuf,
+    },
+    /// Import published posts, tags and comments from another platform's
+    /// export. Old post URLs are recorded as redirects to the new ones.
+    ImportFrom {
+        #[arg(value_enum)]
+        platform: Platform,
+        /// WordPress WXR (.xml) or Ghost JSON export.
+        file: PathBuf,
+        /// Report what would be imported without writing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Create, update and delete posts to match a directory or bare git
+    /// repository of Markdown files with front matter.
+    Sync {
+        /// Directory of `.md` files, or a bare git repository.
+        path: PathBuf,
+        /// Author of new posts whose front matter names none.
+        #[arg(long)]
+        author: Option<String>,
+        /// Write posts edited on the web back to their files.
+        #[arg(long)]
+        write_back: bool,
+        /// Delete posts whose file was removed from this directory or
+        /// became a draft.
+        #[arg(long)]
+        prune: bool,
+        /// Commit to read from a bare repository.
+        #[arg(long = "ref", default_value = "HEAD")]
+        git_ref: String,
+        /// Report what would change without writing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Render the public site into a directory of static files. Only posts
+    /// that changed since the last build are rendered again.
+    BuildStatic {
+        /// Output directory; defaults to `static_site.output_dir` in Rocket.toml.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Public URL the site will be served from, used in the feed and
+        /// sitemap; defaults to `static_site.base_url`.
+        #[arg(long)]
+        base_url: Option<String>,
+        /// Render every page, ignoring the previous build.
+        #[arg(long)]
+        full: bool,
+    },
+    /// Take, inspect and restore SQLite backups.
+    #[command(subcommand)]
+    Backup(BackupCommand),
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Json,
+    Archive,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Platform {
+    Wordpress,
+    Ghost,
+}
+
+/// Leading bytes of a gzip stream, used to tell archives from JSON exports.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Subcommand)]
+enum BackupCommand {
+    /// Back up the live database, then apply the retention rules.
+    Create {
+        /// Backup directory; defaults to `backup.dir` in Rocket.toml.
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// Leave the backup uncompressed.
+        #[arg(long)]
+        no_compress: bool,
+    },
+    /// List backups, newest first.
+    List {
+        /// Backup directory; defaults to `backup.dir` in Rocket.toml.
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Check a backup's integrity and report its schema version.
+    Verify { file: PathBuf },
+    /// Replace the database with a verified backup.
+    Restore { file: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum UserCommand {
+    /// Create an account.
+    Create {
+        username: String,
+        #[arg(long, default_value = "user")]
+        role: String,
+        /// Address for password reset emails.
+        #[arg(long, default_value = "")]
+        email: String,
+        #[command(flatten)]
+        password: PasswordArgs,
+    },
+    /// List all accounts.
+    List,
+    /// Block an account from logging in.
+    Disable { username: String },
+    /// Re-enable a disabled account.
+    Enable { username: String },
+    /// Set a new password without knowing the current one.
+    ResetPassword {
+        username: String,
+        #[command(flatten)]
+        password: PasswordArgs,
+    },
+    /// Change an account's role (user, editor or admin).
+    Grant { username: String, role: String },
+    /// Set an account's email address, counted as verified; an empty one
+    /// removes it.
+    SetEmail { username: String, email: String },
+    /// Turn off two-factor authentication for someone who lost their device
+    /// and recovery codes.
+    #[command(name = "reset-2fa")]
+    ResetTwoFactor { username: String },
+    /// Lift a lock from too many failed logins or two-factor codes.
+    Unlock { username: String },
+}
+
+#[derive(Args)]
+struct PasswordArgs {
+    /// Password to set. Prefer --password-stdin so it stays out of shell history.
+    #[arg(long, conflicts_with = "password_stdin")]
+    password: Option<String>,
+    /// Read the password from the first line of standard input.
+    #[arg(long)]
+    password_stdin: bool,
+}
+
+impl PasswordArgs {
+    /// Returns the password and whether it was generated here.
+    fn resolve(self) -> Result<(String, bool)> {
+        if let Some(password) = self.password {
+            return Ok((password, false));
+        }
+        if self.password_stdin {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            return Ok((line.trim_end_matches(['\r', '\n']).to_string(), false));
+        }
+        Ok((Uuid::new_v4().simple().to_string(), true))
+    }
+}
+
+#[derive(Serialize)]
+struct UserSummary<'a> {
+    id: Uuid,
+    username: &'a str,
+    role: Role,
+    disabled: bool,
+    created_at: &'a str,
+}
+
+impl<'a> From<&'a User> for UserSummary<'a> {
+    fn from(user: &'a User) -> Self {
+        UserSummary {
+            id: user.id,
+            username: &user.username,
+            role: user.role,
+            disabled: user.disabled,
+            created_at: &user.created_at,
+        }
+    }
+}
+
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    /// Prints `value` as JSON in `--json` mode, otherwise prints `text`.
+    fn emit(&self, value: serde_json::Value, text: impl FnOnce() -> String) {
+        if self.json {
+            println!("{}", value);
+        } else {
+            println!("{}", text());
+        }
+    }
+}
+
+async fn find_user(db: &Database, username: &str) -> Result<User> {
+    user_service::get_user_by_username(db, username)
+        .await?
+        .ok_or_else(|| anyhow!("No user named {}", username))
+}
+
+async fn set_disabled(db: &Database, username: &str, disabled: bool, out: &Output) -> Result<()> {
+    let user = find_user(db, username).await?;
+    user_service::set_disabled(db, user.id, disabled).await?;
+    out.emit(json!({ "username": user.username, "disabled": disabled }), || {
+        format!("{} {}", user.username, if disabled { "disabled" } else { "enabled" })
+    });
+    Ok(())
+}
+
+async fn run_user(db: &Database, command: UserCommand, out: &Output) -> Result<()> {
+    match command {
+        UserCommand::Create { username, role, email, password } => {
+            let role: Role = role.parse()?;
+            let (password, generated) = password.resolve()?;
+            let form = CreateUser { username, password, email };
+            form.validate()?;
+
+            let password_hash = hash_password(&form.password).await?;
+            let password = form.password.clone();
+            let user = user_service::create_user(db, form, password_hash).await?;
+            if role != Role::User {
+                user_service::set_role(db, user.id, role).await?;
+            }
+
+            let shown_password = generated.then_some(password);
+            out.emit(
+                json!({ "id": user.id, "username": user.username, "role": role, "generated_password": shown_password }),
+                || match &shown_password {
+                    Some(password) => format!("Created {} ({}) with password {}", user.username, role, password),
+                    None => format!("Created {} ({})", user.username, role),
+                },
+            );
+        }
+        UserCommand::List => {
+            let users = user_service::list_users(db).await?;
+            let summaries: Vec<UserSummary> = users.iter().map(UserSummary::from).collect();
+            out.emit(json!(summaries), || {
+                let mut text = format!("{:<36}  {:<20}  {:<6}  {:<8}  {}", "ID", "USERNAME", "ROLE", "STATUS", "CREATED");
+                for user in &summaries {
+                    let status = if user.disabled { "disabled" } else { "active" };
+                    text.push_str(&format!(
+                        "\n{:<36}  {:<20}  {:<6}  {:<8}  {}",
+                        user.id, user.username, user.role, status, user.created_at
+                    ));
+                }
+                text
+            });
+        }
+        UserCommand::Disable { username } => set_disabled(db, &username, true, out).await?,
+        UserCommand::Enable { username } => set_disabled(db, &username, false, out).await?,
+        UserCommand::ResetPassword { username, password } => {
+            let user = find_user(db, &username).await?;
+            let (password, generated) = password.resolve()?;
+            CreateUser { username: user.username.clone(), password: password.clone(), email: String::new() }.validate()?;
+            user_service::reset_password(db, user.id, &password).await?;
+
+            let shown_password = generated.then_some(password);
+            out.emit(
+                json!({ "username": user.username, "generated_password": shown_password }),
+                || match &shown_password {
+                    Some(password) => format!("Password for {} reset to {}", user.username, password),
+                    None => format!("Password for {} reset", user.username),
+                },
+            );
+        }
+        UserCommand::SetEmail { username, email } => {
+            let user = find_user(db, &username).await?;
+            let form = UpdateEmail { email };
+            form.validate()?;
+            let email = user_service::set_email(db, user.id, &form.email, true).await?;
+            out.emit(json!({ "username": user.username, "email": email }), || match &email {
+                Some(email) => format!("Email for {} set to {}", user.username, email),
+                None => format!("Email for {} removed", user.username),
+            });
+        }
+        UserCommand::ResetTwoFactor { username } => {
+            let user = find_user(db, &username).await?;
+            let reset = two_factor_service::reset(db, user.id).await?;
+            out.emit(json!({ "username": user.username, "reset": reset }), || {
+                if reset {
+                    format!("Two-factor authentication for {} turned off", user.username)
+                } else {
+                    format!("{} does not use two-factor authentication", user.username)
+                }
+            });
+        }
+        UserCommand::Unlock { username } => {
+            let user = find_user(db, &username).await?;
+            let unlocked = login_service::unlock(db, user.id).await?;
+            out.emit(json!({ "username": user.username, "unlocked": unlocked }), || {
+                if unlocked {
+                    format!("{} unlocked", user.username)
+                } else {
+                    format!("{} was not locked; failed logins forgotten", user.username)
+                }
+            });
+        }
+        UserCommand::Grant { username, role } => {
+            let role: Role = role.parse()?;
+            let user = find_user(db, &username).await?;
+            user_service::set_role(db, user.id, role).await?;
+            out.emit(json!({ "username": user.username, "role": role }), || {
+                format!("{} is now {}", user.username, role)
+            });
+        }
+    }
+    Ok(())
+}
+
+async fn run_backup(db: &Database, command: BackupCommand, mut config: BackupConfig, out: &Output) -> Result<()> {
+    match command {
+        BackupCommand::Create { dir, no_compress } => {
+            if let Some(dir) = dir {
+                config.dir = dir;
+            }
+            config.compress &= !no_compress;
+            let report = backup_service::create_backup(db, &config).await?;
+            out.emit(json!(report), || {
+                let mut text = format!(
+                    "Backed up to {} ({} bytes, schema version {})",
+                    report.backup.path.display(), report.backup.size_bytes, report.schema_version
+                );
+                for path in &report.removed {
+                    text.push_str(&format!("\nRemoved {}", path.display()));
+                }
+                text
+            });
+        }
+        BackupCommand::List { dir } => {
+            let backups = backup_service::list_backups(dir.as_ref().unwrap_or(&config.dir)).await?;
+            out.emit(json!(backups), || {
+                let mut text = format!("{:<25}  {:>12}  {}", "CREATED", "BYTES", "FILE");
+                for backup in &backups {
+                    text.push_str(&format!(
+                        "\n{:<25}  {:>12}  {}",
+                        backup.created_at, backup.size_bytes, backup.path.display()
+                    ));
+                }
+                text
+            });
+        }
+        BackupCommand::Verify { file } => {
+            let version = backup_service::verify_backup(&file).await?;
+            out.emit(json!({ "file": file, "ok": true, "schema_version": version }), || {
+                format!("{} is intact (schema version {})", file.display(), version)
+            });
+        }
+        BackupCommand::Restore { file } => {
+            let version = backup_service::restore_backup(db, &file).await?;
+            out.emit(json!({ "file": file, "restored": true, "schema_version": version }), || {
+                format!("Restored {} (schema version {})", file.display(), version)
+            });
+        }
+    }
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let out = Output { json: cli.json };
+    let figment = rocket::Config::figment();
+    let db_config: DbConfig = figment.extract_inner("database").unwrap_or_default();
+    let db = connect(&cli.database_url, &db_config)
+        .await
+        .with_context(|| format!("Failed to open {}", cli.database_url))?;
+
+    match cli.command {
+        Command::User(command) => run_user(&db, command, &out).await?,
+        Command::Backup(command) => {
+            let config = figment.extract_inner("backup").unwrap_or_default();
+            run_backup(&db, command, config, &out).await?
+        }
+        Command::Sync { path, author, write_back, prune, git_ref, dry_run } => {
+            let options = sync_service::SyncOptions { author, write_back, prune, dry_run, git_ref };
+            let report = sync_service::sync(&db, &path, &options).await?;
+            out.emit(json!(report), || {
+                let mut text = format!(
+                    "{}Posts: {} created, {} updated, {} deleted, {} unchanged",
+                    if dry_run { "Dry run, nothing written.\n" } else { "" },
+                    report.created.len(), report.updated.len(), report.deleted.len(), report.unchanged
+                );
+                for path in &report.orphaned {
+                    text.push_str(&format!("\nNo longer published (use --prune to delete the post): {}", path));
+                }
+                for path in &report.written_back {
+                    text.push_str(&format!("\nWritten back: {}", path));
+                }
+                for path in &report.web_edits {
+                    text.push_str(&format!("\nEdited on the web (use --write-back): {}", path));
+                }
+                for issue in &report.conflicts {
+                    text.push_str(&format!("\nConflict: {}: {}", issue.path, issue.reason));
+                }
+                for issue in &report.skipped {
+                    text.push_str(&format!("\nSkipped: {}: {}", issue.path, issue.reason));
+                }
+                text
+            });
+        }
+        Command::BuildStatic { output, base_url, full } => {
+            let mut config: StaticSiteConfig = figment.extract_inner("static_site").unwrap_or_default();
+            config.output_dir = output.unwrap_or(config.output_dir);
+            config.base_url = base_url.or(config.base_url);
+            let seo: SeoConfig = figment.extract_inner("seo").unwrap_or_default();
+            let report = static_site::build(&db, &config, &seo, full).await?;
+            out.emit(json!(report), || {
+                format!(
+                    "Built {}: {} posts rendered, {} unchanged; {} pages written, {} unchanged; {} assets copied; {} files removed",
+                    config.output_dir.display(),
+                    report.posts_rendered, report.posts_unchanged,
+                    report.pages_written, report.pages_unchanged,
+                    report.assets_copied, report.removed.len()
+                )
+            });
+        }
+        Command::Migrate => {
+            let status = maintenance_service::schema_status(&db).await?;
+            out.emit(json!(status), || {
+                format!("{} schema at version {} (expected {})", status.backend, status.version, status.expected)
+            });
+        }
+        Command::Reindex => {
+            maintenance_service::reindex(&db).await?;
+            out.emit(json!({ "reindexed": true }), || "Indexes rebuilt".to_string());
+        }
+        Command::Vacuum => {
+            maintenance_service::vacuum(&db).await?;
+            out.emit(json!({ "vacuumed": true }), || "Database vacuumed".to_string());
+        }
+        Command::Export { output, format, include_password_hashes } => {
+            let export = transfer_service::export_site(&db, include_password_hashes).await?;
+            let counts = (export.users.len(), export.posts.len(), export.comments.len());
+            let body = match format {
+                ExportFormat::Json => serde_json::to_vec_pretty(&export)?,
+                ExportFormat::Archive => {
+                    let mut body = Vec::new();
+                    transfer_service::write_archive(export, &mut body)?;
+                    body
+                }
+            };
+            match output {
+                Some(path) => {
+                    fs::write(&path, body)?;
+                    let summary = json!({
+                        "file": path,
+                        "users": counts.0,
+                        "posts": counts.1,
+                        "comments": counts.2,
+                    });
+                    out.emit(summary, || {
+                        format!(
+                            "Exported {} users, {} posts, {} comments to {}",
+                            counts.0, counts.1, counts.2, path.display()
+                        )
+                    });
+                }
+                None => io::stdout().write_all(&body)?,
+            }
+        }
+        Command::Import { file } => {
+            let body = if file.as_os_str() == "-" {
+                let mut body = Vec::new();
+                io::stdin().read_to_end(&mut body)?;
+                body
+            } else {
+                fs::read(&file)?
+            };
+            let export = if body.starts_with(&GZIP_MAGIC) {
+                transfer_service::read_archive(body.as_slice())?
+            } else {
+                serde_json::from_slice(&body).context("Not a blogctl export file")?
+            };
+            let report = transfer_service::import_site(&db, export).await?;
+            out.emit(json!(report), || {
+                let mut text = format!(
+                    "Users: {} created, {} matched\nPosts: {} created, {} skipped\nComments: {} created, {} skipped\n\
+                     Profiles: {} created, {} skipped\nSeries: {} created, {} skipped\n\
+                     Reactions: {} created, {} skipped\nBookmarks: {} created, {} skipped",
+                    report.users_created, report.users_matched,
+                    report.posts_created, report.posts_skipped,
+                    report.comments_created, report.comments_skipped,
+                    report.profiles_created, report.profiles_skipped,
+                    report.series_created, report.series_skipped,
+                    report.reactions_created, report.reactions_skipped,
+                    report.bookmarks_created, report.bookmarks_skipped
+                );
+                for conflict in &report.conflicts {
+                    text.push_str(&format!("\nConflict: {} {}: {}", conflict.kind, conflict.id, conflict.reason));
+                }
+                text
+            });
+        }
+        Command::ImportFrom { platform, file, dry_run } => {
+            let body = fs::read_to_string(&file)?;
+            let site = match platform {
+                Platform::Wordpress => wordpress::parse(&body).context("Not a WordPress WXR export")?,
+                Platform::Ghost => ghost::parse(&body).context("Not a Ghost JSON export")?,
+            };
+            let summary = import_service::import_site(&db, site, dry_run).await?;
+            out.emit(json!(summary), || {
+                let mut text = format!(
+                    "{}Users: {} created, {} matched\nPosts: {} created, {} already imported, {} skipped\nComments: {} created",
+                    if dry_run { "Dry run, nothing written.\n" } else { "" },
+                    summary.users_created.len(), summary.users_matched.len(),
+                    summary.posts_created.len(), summary.posts_existing.len(), summary.skipped.len(),
+                    summary.comments_created
+                );
+                for change in &summary.slugs_changed {
+                    text.push_str(&format!("\nSlug taken: {}: {} -> {}", change.title, change.from, change.to));
+                }
+                for title in &summary.posts_lossy {
+                    text.push_str(&format!("\nReview formatting: {}", title));
+                }
+                for entry in &summary.skipped {
+                    text.push_str(&format!("\nSkipped: {}: {}", entry.title, entry.reason));
+                }
+                for redirect in &summary.redirects {
+                    text.push_str(&format!("\nRedirect: {} -> {}", redirect.from, redirect.to));
+                }
+                text
+            });
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    let cli = Cli::parse();
+    let json = cli.json;
+
+    if let Err(e) = run(cli).await {
+        if json {
+            eprintln!("{}", json!({ "error": format!("{:#}", e) }));
+        } else {
+            eprintln!("error: {:#}", e);
+        }
+        std::process::exit(1);
+    }
+}
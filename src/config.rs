@@ -0,0 +1,20 @@
+use rocket::figment::providers::{Env, Format, Toml};
+use rocket::figment::{Figment, Profile};
+use rocket::Config;
+
+/// Rocket's built-in settings, except that no header is trusted for the
+/// client address. With Rocket's default of `X-Real-IP`, a client talking
+/// to the site directly could pick the address that rate limits, login
+/// limits and the metrics allowlist see. Behind a proxy that sets the
+/// header, opt in with `ip_header` in `Rocket.toml`.
+pub fn defaults() -> Config {
+    Config { ip_header: None, ..Config::default() }
+}
+
+/// The sources `rocket::Config::figment` reads, on top of [`defaults`].
+pub fn figment() -> Figment {
+    Figment::from(defaults())
+        .merge(Toml::file(Env::var_or("ROCKET_CONFIG", "Rocket.toml")).nested())
+        .merge(Env::prefixed("ROCKET_").ignore(&["PROFILE"]).global())
+        .select(Profile::from_env_or("ROCKET_PROFILE", Config::DEFAULT_PROFILE))
+}
@@ -0,0 +1,72 @@
+//! Per-session tokens against cross-site request forgery. Each browser gets
+//! a random token in a private cookie; pages put it in their forms, and
+//! [`crate::fairings::Csrf`] refuses state-changing requests from a signed-in
+//! browser that do not send it back.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rocket::http::{Cookie, CookieJar, SameSite};
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+use serde::Serialize;
+use std::convert::Infallible;
+use subtle::ConstantTimeEq;
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+/// The form field forms send the token in. It must come first in the form,
+/// or straight after `_method`, to be found.
+pub const CSRF_FIELD: &str = "csrf_token";
+/// The header scripts can send the token in instead.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// The browser's token, rendered into pages as `csrf_token`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    /// The token in the browser's cookie, issuing one first if it has none.
+    pub fn from_cookies(cookies: &CookieJar<'_>) -> CsrfToken {
+        match cookies.get_private(CSRF_COOKIE) {
+            Some(cookie) => CsrfToken(cookie.value().to_string()),
+            None => CsrfToken::renew(cookies),
+        }
+    }
+
+    /// Issues the browser a new token in place of any it had. Called
+    /// whenever someone logs in or out.
+    pub fn renew(cookies: &CookieJar<'_>) -> CsrfToken {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token = BASE64URL.encode(bytes);
+        // Lax, so a page opened from a link on another site does not
+        // replace the token other open pages are using
+        cookies.add_private(Cookie::build((CSRF_COOKIE, token.clone())).same_site(SameSite::Lax));
+        CsrfToken(token)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Whether `submitted` is the browser's token. A browser without one has
+/// nothing to match.
+pub fn verify(cookies: &CookieJar<'_>, submitted: &str) -> bool {
+    cookies
+        .get_private(CSRF_COOKIE)
+        .is_some_and(|cookie| bool::from(cookie.value().as_bytes().ct_eq(submitted.as_bytes())))
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfToken {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // Cached, so a page given a new token uses the same one throughout
+        let token = request.local_cache(|| CsrfToken::from_cookies(request.cookies()));
+        Outcome::Success(token.clone())
+    }
+}
@@ -0,0 +1,184 @@
+//! Outgoing email. Messages are rendered from the Tera templates in
+//! `emails/` and handed to the configured transport: SMTP in production, or
+//! an outbox directory of `.eml` files for development and tests.
+
+pub mod smtp;
+
+use anyhow::{anyhow, Context as _, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::message::header::ContentType;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::Message;
+use rocket_dyn_templates::tera::{Context, Tera};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::instrument;
+use uuid::Uuid;
+
+pub use smtp::{SmtpConfig, SmtpTransport};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Outbox,
+    Smtp,
+}
+
+/// Whether accounts must confirm their email address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verification {
+    /// Addresses are taken as given and changes apply at once.
+    Off,
+    /// New and changed addresses are confirmed by a mailed link, but
+    /// unverified accounts can do everything.
+    #[default]
+    Optional,
+    /// As `Optional`, and registration needs an address. Accounts cannot
+    /// comment until it is verified.
+    Required,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EmailConfig {
+    pub transport: TransportKind,
+    pub verification: Verification,
+    /// Sender, e.g. `Blog <no-reply@blog.example.com>`.
+    pub from: String,
+    /// Where the outbox transport writes messages.
+    pub outbox_dir: PathBuf,
+    pub templates_dir: PathBuf,
+    pub smtp: SmtpConfig,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        EmailConfig {
+            transport: TransportKind::Outbox,
+            verification: Verification::Optional,
+            from: "Blog <no-reply@localhost>".to_string(),
+            outbox_dir: PathBuf::from("outbox"),
+            templates_dir: PathBuf::from("emails"),
+            smtp: SmtpConfig::default(),
+        }
+    }
+}
+
+/// A rendered message, ready for a transport.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>,
+}
+
+impl Email {
+    /// Builds the message, with a plain text and, when there is one, an HTML
+    /// alternative. Display names and the subject are encoded as needed.
+    pub fn to_message(&self, from: &str) -> Result<Message> {
+        for (name, value) in [("From", from), ("To", &self.to), ("Subject", &self.subject)] {
+            if value.contains(['\r', '\n']) {
+                return Err(anyhow!("{} header contains a line break", name));
+            }
+        }
+
+        let from: Mailbox = from.parse().with_context(|| format!("Invalid sender {}", from))?;
+        let message_id = format!("<{}@{}>", Uuid::new_v4(), from.email.domain());
+        let builder = Message::builder()
+            .from(from)
+            .to(self.to.parse().with_context(|| format!("Invalid recipient {}", self.to))?)
+            .subject(&self.subject)
+            .message_id(Some(message_id));
+        Ok(match &self.html {
+            Some(html) => builder.multipart(MultiPart::alternative_plain_html(self.text.clone(), html.clone()))?,
+            None => builder.header(ContentType::TEXT_PLAIN).body(self.text.clone())?,
+        })
+    }
+}
+
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send(&self, from: &str, email: &Email) -> Result<()>;
+}
+
+/// Writes each message to `<dir>/<timestamp>-<id>.eml` instead of sending
+/// it. Any mail client can open the files.
+pub struct OutboxTransport {
+    dir: PathBuf,
+}
+
+impl OutboxTransport {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        OutboxTransport { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Transport for OutboxTransport {
+    async fn send(&self, from: &str, email: &Email) -> Result<()> {
+        let message = email.to_message(from)?.formatted();
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let name = format!("{}-{}.eml", Utc::now().format("%Y%m%dT%H%M%S%.3fZ"), Uuid::new_v4().simple());
+        let path = self.dir.join(name);
+        tokio::fs::write(&path, message)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        tracing::info!(path = %path.display(), "email written to outbox");
+        Ok(())
+    }
+}
+
+/// Renders templated messages and sends them through a transport. Cheap to
+/// clone, so background tasks can hold their own.
+#[derive(Clone)]
+pub struct Mailer {
+    transport: Arc<dyn Transport>,
+    from: String,
+    templates: Arc<Tera>,
+}
+
+impl Mailer {
+    pub fn from_config(config: &EmailConfig) -> Result<Self> {
+        let transport: Arc<dyn Transport> = match config.transport {
+            TransportKind::Outbox => Arc::new(OutboxTransport::new(&config.outbox_dir)),
+            TransportKind::Smtp => Arc::new(SmtpTransport::new(config.smtp.clone())),
+        };
+        Mailer::new(transport, &config.from, &config.templates_dir)
+    }
+
+    /// Loads every `*.tera` file under `templates_dir`. A message named
+    /// `name` needs `name.txt.tera`; `name.html.tera` is optional and
+    /// autoescaped.
+    pub fn new(transport: Arc<dyn Transport>, from: &str, templates_dir: &Path) -> Result<Self> {
+        let glob = format!("{}/**/*.tera", templates_dir.display());
+        let mut templates = Tera::new(&glob).with_context(|| format!("Failed to load {}", glob))?;
+        templates.autoescape_on(vec![".html.tera"]);
+        Ok(Mailer { transport, from: from.to_string(), templates: Arc::new(templates) })
+    }
+
+    pub fn render(&self, to: &str, subject: &str, template: &str, context: &Value) -> Result<Email> {
+        let context = Context::from_value(context.clone())?;
+        let text = self
+            .templates
+            .render(&format!("{}.txt.tera", template), &context)
+            .with_context(|| format!("Failed to render email {}", template))?;
+        let html_name = format!("{}.html.tera", template);
+        let html = match self.templates.get_template_names().any(|name| name == html_name) {
+            true => Some(self.templates.render(&html_name, &context)?),
+            false => None,
+        };
+        Ok(Email { to: to.to_string(), subject: subject.to_string(), text, html })
+    }
+
+    #[instrument(skip_all, fields(template = %template), err)]
+    pub async fn send_template(&self, to: &str, subject: &str, template: &str, context: &Value) -> Result<()> {
+        let email = self.render(to, subject, template, context)?;
+        self.transport.send(&self.from, &email).await
+    }
+}
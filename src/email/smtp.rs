@@ -0,0 +1,95 @@
+//! Delivery over SMTP with `lettre`: STARTTLS, implicit TLS and
+//! `AUTH PLAIN`. Each message uses its own connection.
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{Tls as SmtpTls, TlsParameters};
+use lettre::transport::smtp::extension::ClientId;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use serde::Deserialize;
+use std::time::Duration;
+
+use super::{Email, Transport};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tls {
+    /// Plain text throughout; only for a relay on the same host.
+    None,
+    /// Upgrade with `STARTTLS`, usually on port 587.
+    #[default]
+    Starttls,
+    /// TLS from the first byte, usually on port 465.
+    Tls,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: Tls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Name sent with `EHLO`.
+    pub hello_name: String,
+    pub timeout_secs: u64,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        SmtpConfig {
+            host: "localhost".to_string(),
+            port: 587,
+            tls: Tls::Starttls,
+            username: None,
+            password: None,
+            hello_name: "localhost".to_string(),
+            timeout_secs: 10,
+        }
+    }
+}
+
+pub struct SmtpTransport {
+    config: SmtpConfig,
+}
+
+impl SmtpTransport {
+    pub fn new(config: SmtpConfig) -> Self {
+        SmtpTransport { config }
+    }
+
+    fn client(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let config = &self.config;
+        let tls = match config.tls {
+            Tls::None => SmtpTls::None,
+            Tls::Starttls => SmtpTls::Required(TlsParameters::new(config.host.clone())?),
+            Tls::Tls => SmtpTls::Wrapper(TlsParameters::new(config.host.clone())?),
+        };
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            .port(config.port)
+            .tls(tls)
+            .hello_name(ClientId::Domain(config.hello_name.clone()))
+            .timeout(Some(Duration::from_secs(config.timeout_secs)));
+        if let Some(username) = &config.username {
+            let password = config.password.clone().unwrap_or_default();
+            builder = builder
+                .credentials(Credentials::new(username.clone(), password))
+                .authentication(vec![Mechanism::Plain]);
+        }
+        Ok(builder.build())
+    }
+}
+
+#[async_trait]
+impl Transport for SmtpTransport {
+    async fn send(&self, from: &str, email: &Email) -> Result<()> {
+        let message = email.to_message(from)?;
+        self.client()?
+            .send(message)
+            .await
+            .with_context(|| format!("SMTP delivery via {}:{} failed", self.config.host, self.config.port))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::tokio::time::{interval, MissedTickBehavior};
+use rocket::{Orbit, Rocket};
+use std::time::Duration;
+
+use crate::services::backup_service::{self, BackupConfig};
+use crate::services::db::Database;
+
+/// Takes a backup every `interval_secs` from the `backup` table of
+/// `Rocket.toml` until the server shuts down. Does nothing when no interval
+/// is configured.
+pub struct BackupSchedule;
+
+#[rocket::async_trait]
+impl Fairing for BackupSchedule {
+    fn info(&self) -> Info {
+        Info {
+            name: "Backup Schedule",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let config: BackupConfig = rocket.figment().extract_inner("backup").unwrap_or_default();
+        let Some(secs) = config.interval_secs.filter(|secs| *secs > 0) else {
+            return;
+        };
+        let Some(db) = rocket.state::<Database>().cloned() else {
+            return;
+        };
+        if db.backend() != "sqlite" {
+            tracing::warn!(backend = db.backend(), "scheduled backups are only supported for SQLite");
+            return;
+        }
+
+        let shutdown = rocket.shutdown();
+        rocket::tokio::spawn(async move {
+            let mut ticks = interval(Duration::from_secs(secs));
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes immediately; wait a full period instead.
+            ticks.tick().await;
+
+            loop {
+                rocket::tokio::select! {
+                    _ = shutdown.clone() => break,
+                    _ = ticks.tick() => {}
+                }
+                match backup_service::create_backup(&db, &config).await {
+                    Ok(report) => tracing::info!(
+                        path = %report.backup.path.display(),
+                        size_bytes = report.backup.size_bytes,
+                        removed = report.removed.len(),
+                        "scheduled backup complete"
+                    ),
+                    Err(e) => tracing::error!(error = %format!("{:#}", e), "scheduled backup failed"),
+                }
+            }
+        });
+    }
+}
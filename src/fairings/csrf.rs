@@ -0,0 +1,90 @@
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Method, RawStr, Status};
+use rocket::{get, routes, Build, Data, Request, Rocket};
+use rocket_dyn_templates::{context, Template};
+
+use crate::csrf::{self, CsrfToken, CSRF_FIELD, CSRF_HEADER};
+use crate::models::auth::AuthenticatedUser;
+use crate::routes::auth::PENDING_LOGIN_COOKIE;
+
+/// Where refused requests are sent, as with rate limiting.
+const REFUSED_PATH: &str = "/__csrf_failed";
+
+/// How much of a form body is read ahead to find the token.
+const PEEK_BYTES: usize = 512;
+
+/// Refuses POST, PUT, PATCH and DELETE requests from a signed-in browser,
+/// or one half way through logging in, unless they carry its CSRF token in
+/// the `csrf_token` form field or the `X-CSRF-Token` header. Requests with
+/// a bearer token, or with a body another site could not send without the
+/// browser asking first (such as CSP violation reports), are let through.
+pub struct Csrf;
+
+#[get("/__csrf_failed")]
+fn refused(user: Option<AuthenticatedUser>, csrf: CsrfToken) -> (Status, Template) {
+    (Status::Forbidden, Template::render("csrf_error", context! {
+        user: user.map(|u| u.0),
+        csrf_token: csrf,
+        title: "Form Expired",
+    }))
+}
+
+/// The token among the first fields of a urlencoded form.
+fn form_token(body: &[u8]) -> Option<String> {
+    let body = std::str::from_utf8(body).ok()?;
+    body.split('&')
+        .filter_map(|field| field.split_once('='))
+        .find(|(name, _)| *name == CSRF_FIELD)
+        .and_then(|(_, value)| RawStr::new(value).url_decode().ok())
+        .map(|value| value.into_owned())
+}
+
+/// Whether a page on another site could make the browser send this request
+/// without a CORS preflight: bodies of any other type cannot be.
+fn cross_site_sendable(request: &Request<'_>) -> bool {
+    request.content_type().is_none_or(|kind| {
+        kind.is_form() || kind.is_form_data() || (kind.top() == "text" && kind.sub() == "plain")
+    })
+}
+
+#[rocket::async_trait]
+impl Fairing for Csrf {
+    fn info(&self) -> Info {
+        Info {
+            name: "CSRF Protection",
+            kind: Kind::Ignite | Kind::Request,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.mount("/", routes![refused]))
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, data: &mut Data<'_>) {
+        if !matches!(request.method(), Method::Post | Method::Put | Method::Patch | Method::Delete) {
+            return;
+        }
+        let bearer = request
+            .headers()
+            .get_one("Authorization")
+            .is_some_and(|value| value.starts_with("Bearer "));
+        let cookies = request.cookies();
+        let session = cookies.get_private("user_id").is_some() || cookies.get_private(PENDING_LOGIN_COOKIE).is_some();
+        if bearer || !session || !cross_site_sendable(request) {
+            return;
+        }
+
+        let submitted = match request.headers().get_one(CSRF_HEADER) {
+            Some(token) => Some(token.to_string()),
+            None if request.content_type().is_some_and(|kind| kind.is_form()) => form_token(data.peek(PEEK_BYTES).await),
+            None => None,
+        };
+        if submitted.is_some_and(|token| csrf::verify(request.cookies(), &token)) {
+            return;
+        }
+        tracing::warn!(method = %request.method(), uri = %request.uri(), "missing or wrong CSRF token");
+        request.set_method(Method::Get);
+        request.set_uri(Origin::parse(REFUSED_PATH).expect("valid path"));
+    }
+}
@@ -0,0 +1,41 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+use std::time::Instant;
+
+use crate::metrics::{HTTP_REQUESTS, HTTP_REQUEST_DURATION};
+
+struct RequestStart(Instant);
+
+/// Records per-route request counts and latencies. Routes are labelled by
+/// their URI template rather than the concrete path to bound cardinality.
+pub struct HttpMetrics;
+
+#[rocket::async_trait]
+impl Fairing for HttpMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "HTTP Metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let started = request.local_cache(|| RequestStart(Instant::now())).0;
+        let route = request
+            .route()
+            .map(|r| r.uri.to_string())
+            .unwrap_or_else(|| "unmatched".to_string());
+        let method = request.method().as_str();
+        let status = response.status().code.to_string();
+        let labels = [method, route.as_str(), status.as_str()];
+
+        HTTP_REQUESTS.with_label_values(&labels).inc();
+        HTTP_REQUEST_DURATION
+            .with_label_values(&labels)
+            .observe(started.elapsed().as_secs_f64());
+    }
+}
@@ -0,0 +1,13 @@
+pub mod backup;
+pub mod csrf;
+pub mod metrics;
+pub mod rate_limit;
+pub mod security_headers;
+pub mod request_log;
+
+pub use backup::BackupSchedule;
+pub use csrf::Csrf;
+pub use metrics::HttpMetrics;
+pub use rate_limit::RateLimit;
+pub use security_headers::SecurityHeaders;
+pub use request_log::RequestLog;
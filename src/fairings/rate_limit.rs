@@ -0,0 +1,89 @@
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Header, Method, Status};
+use rocket::{get, routes, Build, Data, Request, Response, Rocket};
+use uuid::Uuid;
+
+use crate::metrics;
+use crate::rate_limit::{Decision, RateLimitConfig, RateLimiter};
+
+/// Where refused requests are sent. Fairings cannot answer a request
+/// themselves, so the request is rewritten to reach this route instead of
+/// the one it asked for.
+const REFUSED_PATH: &str = "/__rate_limited";
+
+/// The decision made for the current request, if any group applied.
+struct Checked(Option<Decision>);
+
+/// Counts each request against the buckets of the `rate_limit` table of
+/// `Rocket.toml`. Requests over a limit get a 429 without reaching their
+/// route; every limited response carries `RateLimit-*` headers.
+pub struct RateLimit;
+
+#[get("/__rate_limited")]
+fn refused() -> (Status, &'static str) {
+    (Status::TooManyRequests, "Too many requests. Please wait a moment and try again.\n")
+}
+
+#[rocket::async_trait]
+impl Fairing for RateLimit {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate Limiting",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config: RateLimitConfig = rocket.figment().extract_inner("rate_limit").unwrap_or_default();
+        if !config.enabled {
+            return Ok(rocket);
+        }
+        match RateLimiter::new(&config) {
+            Ok(limiter) => Ok(rocket.manage(limiter).mount("/", routes![refused])),
+            Err(e) => {
+                tracing::error!(error = %format!("{:#}", e), "invalid rate limit configuration");
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let Some(limiter) = request.rocket().state::<RateLimiter>() else {
+            return;
+        };
+        let path = request.uri().path().to_string();
+        let user_id = request
+            .cookies()
+            .get_private("user_id")
+            .and_then(|cookie| Uuid::parse_str(cookie.value()).ok());
+
+        // A broken store should not take the site down with it
+        let decision = match limiter.check(request.method(), &path, request.client_ip(), user_id).await {
+            Ok(decision) => decision,
+            Err(e) => {
+                tracing::warn!(error = %format!("{:#}", e), "rate limit check failed");
+                None
+            }
+        };
+        if let Some(decision) = decision.as_ref().filter(|decision| !decision.allowed) {
+            tracing::info!(group = %decision.group, "request over rate limit");
+            metrics::record_rate_limited(&decision.group);
+            request.set_method(Method::Get);
+            request.set_uri(Origin::parse(REFUSED_PATH).expect("valid path"));
+        }
+        request.local_cache(|| Checked(decision));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Checked(Some(decision)) = request.local_cache(|| Checked(None)) else {
+            return;
+        };
+        response.set_header(Header::new("RateLimit-Limit", decision.limit.to_string()));
+        response.set_header(Header::new("RateLimit-Remaining", decision.remaining.to_string()));
+        response.set_header(Header::new("RateLimit-Reset", decision.reset_secs.to_string()));
+        if !decision.allowed {
+            response.set_header(Header::new("Retry-After", decision.retry_after_secs.to_string()));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::route::{self, Handler};
+use rocket::{Data, Request, Response, Route};
+use std::time::Instant;
+use tracing::{Instrument, Span};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Correlation ID for the current request. Taken from an incoming
+/// `X-Request-Id` header when it looks sane, generated otherwise.
+#[derive(Debug, Clone)]
+struct RequestId(String);
+
+struct RequestTiming {
+    started: Instant,
+    span: Span,
+}
+
+pub struct RequestLog;
+
+/// A route handler run inside its request's span, request guards included,
+/// so that the service and SQL events it causes carry the `request_id`.
+#[derive(Clone)]
+struct InRequestSpan(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for InRequestSpan {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        self.0.handle(request, data).instrument(timing(request).span.clone()).await
+    }
+}
+
+/// `routes`, with each handler run inside the span `RequestLog` opens for
+/// its request.
+pub fn in_request_span(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(InRequestSpan(route.handler));
+            route
+        })
+        .collect()
+}
+
+fn incoming_request_id(request: &Request<'_>) -> Option<String> {
+    let value = request.headers().get_one(REQUEST_ID_HEADER)?;
+    let valid = !value.is_empty()
+        && value.len() <= 64
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| value.to_string())
+}
+
+/// Set by `on_request`; requests that never reached it get no span.
+fn timing<'r>(request: &'r Request<'_>) -> &'r RequestTiming {
+    request.local_cache(|| RequestTiming {
+        started: Instant::now(),
+        span: Span::none(),
+    })
+}
+
+fn request_id<'r>(request: &'r Request<'_>) -> &'r RequestId {
+    request.local_cache(|| {
+        RequestId(incoming_request_id(request).unwrap_or_else(|| Uuid::new_v4().to_string()))
+    })
+}
+
+#[rocket::async_trait]
+impl Fairing for RequestLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Logging",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let id = request_id(request).0.clone();
+        let span = tracing::info_span!(
+            "request",
+            request_id = %id,
+            method = %request.method(),
+            uri = %request.uri(),
+            route = tracing::field::Empty,
+            status = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+        );
+        request.local_cache(|| RequestTiming {
+            started: Instant::now(),
+            span,
+        });
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let id = request_id(request);
+        response.set_header(Header::new(REQUEST_ID_HEADER, id.0.clone()));
+
+        let timing = timing(request);
+        let latency = timing.started.elapsed();
+        let status = response.status().code;
+        let route = request
+            .route()
+            .map(|r| r.uri.to_string())
+            .unwrap_or_else(|| "unmatched".to_string());
+
+        let span = &timing.span;
+        span.record("route", route.as_str());
+        span.record("status", status);
+        span.record("latency_ms", latency.as_secs_f64() * 1000.0);
+        span.in_scope(|| {
+            if status >= 500 {
+                tracing::error!("request failed");
+            } else {
+                tracing::info!("request completed");
+            }
+        });
+    }
+}
@@ -0,0 +1,114 @@
+use rocket::data::{Data, ToByteUnit};
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::shield::Shield;
+use rocket::{post, routes, Build, Request, Response, Rocket};
+use serde_json::Value;
+
+use crate::security_headers::{CspNonce, SecurityHeadersConfig, CSP_REPORT_PATH};
+
+/// Most reports a single request may log, since browsers batch them.
+const MAX_REPORTS: usize = 20;
+
+/// Adds the headers of the `security_headers` table of `Rocket.toml` to
+/// every response, pages and static files alike, and logs the policy
+/// violations browsers report back.
+pub struct SecurityHeaders;
+
+/// Takes both the `application/csp-report` bodies sent for `report-uri` and
+/// the `application/reports+json` lists sent for `report-to`.
+#[post("/csp-reports", data = "<body>")]
+async fn csp_report(body: Data<'_>) -> Status {
+    let Ok(body) = body.open(64.kibibytes()).into_bytes().await else {
+        return Status::BadRequest;
+    };
+    if !body.is_complete() {
+        return Status::PayloadTooLarge;
+    }
+    let reports = match serde_json::from_slice::<Value>(&body) {
+        Ok(Value::Array(reports)) => reports.into_iter().filter_map(|report| report.get("body").cloned()).collect(),
+        Ok(report @ Value::Object(_)) => report.get("csp-report").cloned().into_iter().collect(),
+        _ => Vec::new(),
+    };
+    if reports.is_empty() {
+        return Status::BadRequest;
+    }
+    for report in reports.iter().take(MAX_REPORTS) {
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| report.get(*name).and_then(Value::as_str))
+                .unwrap_or("")
+                .to_string()
+        };
+        tracing::warn!(
+            document = %field(&["document-uri", "documentURL"]),
+            directive = %field(&["effective-directive", "violated-directive", "effectiveDirective"]),
+            blocked = %field(&["blocked-uri", "blockedURL"]),
+            disposition = %field(&["disposition"]),
+            "content security policy violation"
+        );
+    }
+    Status::NoContent
+}
+
+/// Sets `name` unless the route already did.
+fn set_default(response: &mut Response<'_>, name: &'static str, value: impl Into<String>) {
+    if !response.headers().contains(name) {
+        response.set_header(Header::new(name, value.into()));
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for SecurityHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Security Headers",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config: SecurityHeadersConfig = rocket.figment().extract_inner("security_headers").unwrap_or_default();
+        if !config.enabled {
+            return Ok(rocket);
+        }
+        // Stands in for Rocket's default Shield, whose headers would
+        // otherwise be taken for the route's own
+        let rocket = rocket.attach(Shield::new());
+        let rocket = match config.report_violations {
+            true => rocket.mount("/", routes![csp_report]),
+            false => rocket,
+        };
+        Ok(rocket.manage(config))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(config) = request.rocket().state::<SecurityHeadersConfig>() else {
+            return;
+        };
+        // The same nonce any template on this request was given
+        let nonce = CspNonce::for_request(request);
+        let csp_header = match config.csp_report_only {
+            true => "Content-Security-Policy-Report-Only",
+            false => "Content-Security-Policy",
+        };
+        set_default(response, csp_header, config.content_security_policy(&nonce));
+        if config.report_violations {
+            set_default(response, "Reporting-Endpoints", format!("csp=\"{}\"", CSP_REPORT_PATH));
+        }
+        if let Some(hsts) = config.strict_transport_security() {
+            set_default(response, "Strict-Transport-Security", hsts);
+        }
+        if let Some(frame_options) = config.frame_options() {
+            set_default(response, "X-Frame-Options", frame_options);
+        }
+        set_default(response, "X-Content-Type-Options", "nosniff");
+        if !config.referrer_policy.is_empty() {
+            set_default(response, "Referrer-Policy", config.referrer_policy.clone());
+        }
+        if !config.permissions_policy.is_empty() {
+            set_default(response, "Permissions-Policy", config.permissions_policy.clone());
+        }
+    }
+}
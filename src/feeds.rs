@@ -0,0 +1,57 @@
+//! Template context for the Atom feeds rendered by `templates/feed.xml.tera`,
+//! shared by the server and the static site export.
+
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::models::post::Post;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SiteConfig {
+    /// Public address of the server, used for absolute URLs in feeds.
+    pub base_url: String,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        SiteConfig { base_url: "http://localhost:8000".to_string() }
+    }
+}
+
+pub struct FeedEntry<'a> {
+    pub post: &'a Post,
+    pub tags: Vec<String>,
+}
+
+/// Builds the context for a feed at `feed_path` under `base_url`.
+/// `post_path` gives the site-relative address of each post.
+pub fn atom_context(
+    base_url: &str,
+    feed_path: &str,
+    title: &str,
+    entries: &[FeedEntry],
+    post_path: impl Fn(&Post) -> String,
+) -> Value {
+    let base_url = base_url.trim_end_matches('/');
+    let updated = match entries.iter().map(|entry| entry.post.updated_at.as_str()).max() {
+        Some(updated) => updated.to_string(),
+        None => Utc::now().to_rfc3339(),
+    };
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|entry| json!({
+            "post": entry.post,
+            "url": format!("{}{}", base_url, post_path(entry.post)),
+            "tags": entry.tags,
+        }))
+        .collect();
+    json!({
+        "feed_title": title,
+        "site_url": format!("{}/", base_url),
+        "feed_url": format!("{}{}", base_url, feed_path),
+        "updated": updated,
+        "entries": entries,
+    })
+}
@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use super::html;
+use super::{rfc3339_to_utc, ImportedAuthor, ImportedPost, ImportedSite, SkippedEntry};
+
+#[derive(Deserialize)]
+struct Export {
+    db: Vec<Database>,
+}
+
+#[derive(Deserialize)]
+struct Database {
+    data: Data,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Data {
+    posts: Vec<Post>,
+    users: Vec<User>,
+    tags: Vec<Tag>,
+    posts_tags: Vec<PostTag>,
+    posts_authors: Vec<PostAuthor>,
+}
+
+#[derive(Deserialize)]
+struct Post {
+    id: String,
+    title: String,
+    slug: String,
+    html: Option<String>,
+    status: String,
+    #[serde(rename = "type", default = "default_post_type")]
+    post_type: String,
+    published_at: Option<String>,
+    created_at: String,
+    updated_at: Option<String>,
+    /// Ghost 1.x names the author directly; later versions use `posts_authors`.
+    author_id: Option<String>,
+}
+
+fn default_post_type() -> String {
+    "post".to_string()
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: String,
+    slug: String,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    id: String,
+    name: String,
+    #[serde(default)]
+    visibility: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PostTag {
+    post_id: String,
+    tag_id: String,
+    #[serde(default)]
+    sort_order: i64,
+}
+
+#[derive(Deserialize)]
+struct PostAuthor {
+    post_id: String,
+    author_id: String,
+    #[serde(default)]
+    sort_order: i64,
+}
+
+/// Parses a Ghost JSON export (Ghost 1.x and later). Ghost does not include
+/// comments in its export, and posts without an HTML rendering (some newer
+/// exports only carry the editor's document format) are skipped.
+pub fn parse(json: &str) -> Result<ImportedSite> {
+    let export: Export = serde_json::from_str(json)?;
+    let data = export
+        .db
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Not a Ghost export: empty db list"))?
+        .data;
+
+    let mut site = ImportedSite { platform: "ghost", ..Default::default() };
+    for user in &data.users {
+        site.authors.push(ImportedAuthor { key: user.id.clone(), username: user.slug.clone() });
+    }
+
+    // Internal tags (`#name`) are Ghost's own bookkeeping and not shown to readers.
+    let tag_names: HashMap<&str, &str> = data
+        .tags
+        .iter()
+        .filter(|tag| tag.visibility.as_deref() != Some("internal") && !tag.name.starts_with('#'))
+        .map(|tag| (tag.id.as_str(), tag.name.as_str()))
+        .collect();
+
+    let mut post_tags: HashMap<&str, Vec<(i64, &str)>> = HashMap::new();
+    for link in &data.posts_tags {
+        if let Some(name) = tag_names.get(link.tag_id.as_str()) {
+            post_tags.entry(&link.post_id).or_default().push((link.sort_order, name));
+        }
+    }
+
+    let mut primary_authors: HashMap<&str, (i64, &str)> = HashMap::new();
+    for link in &data.posts_authors {
+        let entry = primary_authors.entry(&link.post_id).or_insert((link.sort_order, &link.author_id));
+        if link.sort_order < entry.0 {
+            *entry = (link.sort_order, &link.author_id);
+        }
+    }
+
+    for post in &data.posts {
+        let skip = |reason: String| SkippedEntry { title: post.title.clone(), reason };
+        if post.post_type != "post" {
+            site.skipped.push(skip(format!("{} entries are not imported", post.post_type)));
+            continue;
+        }
+        if post.status != "published" {
+            site.skipped.push(skip(format!("Status is {}", post.status)));
+            continue;
+        }
+        let Some(body) = &post.html else {
+            site.skipped.push(skip("No HTML body in the export".to_string()));
+            continue;
+        };
+        let author_key = primary_authors
+            .get(post.id.as_str())
+            .map(|(_, id)| id.to_string())
+            .or_else(|| post.author_id.clone());
+        let Some(author_key) = author_key else {
+            site.skipped.push(skip("No author".to_string()));
+            continue;
+        };
+
+        let published = post.published_at.as_deref().unwrap_or(&post.created_at);
+        let Some(created_at) = rfc3339_to_utc(published) else {
+            site.skipped.push(skip(format!("Unreadable date {}", published)));
+            continue;
+        };
+        let updated_at = post
+            .updated_at
+            .as_deref()
+            .and_then(rfc3339_to_utc)
+            .unwrap_or_else(|| created_at.clone());
+
+        let mut tags = post_tags.remove(post.id.as_str()).unwrap_or_default();
+        tags.sort_by_key(|(order, _)| *order);
+
+        let converted = html::to_markdown(body);
+        site.posts.push(ImportedPost {
+            key: post.id.clone(),
+            title: post.title.clone(),
+            slug: Some(post.slug.clone()),
+            markdown: converted.markdown,
+            lossless: converted.lossless,
+            author_key,
+            created_at,
+            updated_at,
+            tags: tags.into_iter().map(|(_, name)| name.to_string()).collect(),
+            // Ghost's default permalink structure.
+            old_url: Some(format!("/{}/", post.slug)),
+            comments: Vec::new(),
+        });
+    }
+
+    Ok(site)
+}
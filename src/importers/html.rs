@@ -0,0 +1,243 @@
+use ego_tree::NodeRef;
+use scraper::{ElementRef, Html, Node};
+
+/// Result of converting an HTML body to Markdown.
+#[derive(Debug)]
+pub struct Converted {
+    pub markdown: String,
+    /// False when some elements had no Markdown equivalent and were kept as
+    /// raw HTML or dropped.
+    pub lossless: bool,
+}
+
+/// Converts common HTML (paragraphs, headings, emphasis, links, images,
+/// lists, quotes, code) to Markdown. Anything else is passed through as
+/// inline HTML, which Markdown allows, and the result is flagged as lossy.
+pub fn to_markdown(html: &str) -> Converted {
+    let fragment = Html::parse_fragment(html);
+    let mut converter = Converter { lossless: true };
+    let blocks = converter.blocks(*fragment.root_element());
+    Converted { markdown: blocks.join("\n\n"), lossless: converter.lossless }
+}
+
+struct Converter {
+    lossless: bool,
+}
+
+const CONTAINERS: &[&str] = &[
+    "p", "div", "section", "article", "header", "footer", "main", "aside", "figure",
+    "figcaption", "body", "html", "center",
+];
+const UNSUPPORTED_BLOCKS: &[&str] = &[
+    "table", "iframe", "video", "audio", "form", "dl", "details", "object", "embed", "canvas",
+    "svg",
+];
+const DROPPED: &[&str] = &["script", "style", "noscript", "template"];
+
+fn is_block(name: &str) -> bool {
+    CONTAINERS.contains(&name)
+        || UNSUPPORTED_BLOCKS.contains(&name)
+        || DROPPED.contains(&name)
+        || matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "pre" | "blockquote" | "ul" | "ol" | "hr")
+}
+
+impl Converter {
+    /// Renders the children of `node` as a list of Markdown blocks. Runs of
+    /// inline content between block elements become paragraphs.
+    fn blocks(&mut self, node: NodeRef<Node>) -> Vec<String> {
+        let mut blocks = Vec::new();
+        let mut paragraph = String::new();
+
+        for child in node.children() {
+            match child.value() {
+                Node::Element(element) if is_block(element.name()) => {
+                    flush_paragraph(&mut paragraph, &mut blocks);
+                    blocks.extend(self.block(child, element.name()));
+                }
+                _ => paragraph.push_str(&self.inline(child)),
+            }
+        }
+        flush_paragraph(&mut paragraph, &mut blocks);
+        blocks
+    }
+
+    fn block(&mut self, node: NodeRef<Node>, name: &str) -> Vec<String> {
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap_or(1);
+                let text = self.inline_children(node);
+                let text = text.trim();
+                if text.is_empty() {
+                    return Vec::new();
+                }
+                vec![format!("{} {}", "#".repeat(level), text)]
+            }
+            "hr" => vec!["---".to_string()],
+            "pre" => {
+                let code = text_content(node);
+                vec![format!("```\n{}\n```", code.trim_end_matches('\n'))]
+            }
+            "blockquote" => {
+                let inner = self.blocks(node).join("\n\n");
+                let quoted = inner
+                    .lines()
+                    .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                vec![quoted]
+            }
+            "ul" | "ol" => vec![self.list(node, name == "ol")],
+            _ if DROPPED.contains(&name) => {
+                self.lossless = false;
+                Vec::new()
+            }
+            _ if UNSUPPORTED_BLOCKS.contains(&name) => {
+                self.lossless = false;
+                vec![outer_html(node)]
+            }
+            _ => self.blocks(node),
+        }
+    }
+
+    fn list(&mut self, node: NodeRef<Node>, ordered: bool) -> String {
+        let mut items = Vec::new();
+        for child in node.children() {
+            let Node::Element(element) = child.value() else { continue };
+            if element.name() != "li" {
+                continue;
+            }
+
+            let marker = if ordered { format!("{}. ", items.len() + 1) } else { "- ".to_string() };
+            let indent = " ".repeat(marker.len());
+            let body = self.blocks(child).join("\n\n");
+            let mut item = String::new();
+            for (index, line) in body.lines().enumerate() {
+                if index == 0 {
+                    item.push_str(&marker);
+                } else {
+                    item.push('\n');
+                    if !line.is_empty() {
+                        item.push_str(&indent);
+                    }
+                }
+                item.push_str(line);
+            }
+            if item.is_empty() {
+                item.push_str(marker.trim_end());
+            }
+            items.push(item);
+        }
+        items.join("\n")
+    }
+
+    fn inline_children(&mut self, node: NodeRef<Node>) -> String {
+        node.children().map(|child| self.inline(child)).collect()
+    }
+
+    fn inline(&mut self, node: NodeRef<Node>) -> String {
+        let element = match node.value() {
+            Node::Text(text) => return escape(&collapse_whitespace(text)),
+            Node::Element(element) => element,
+            _ => return String::new(),
+        };
+
+        match element.name() {
+            "br" => "\\\n".to_string(),
+            "strong" | "b" => self.wrap(node, "**"),
+            "em" | "i" => self.wrap(node, "*"),
+            "del" | "s" | "strike" => self.wrap(node, "~~"),
+            "code" | "kbd" | "tt" => {
+                let code = collapse_whitespace(&text_content(node));
+                let fence = if code.contains('`') { "``" } else { "`" };
+                format!("{}{}{}", fence, code, fence)
+            }
+            "a" => {
+                let text = self.inline_children(node);
+                match element.attr("href") {
+                    Some(href) if !href.is_empty() => format!("[{}]({})", text.trim(), href),
+                    _ => text,
+                }
+            }
+            "img" => match element.attr("src") {
+                Some(src) => format!("![{}]({})", escape(element.attr("alt").unwrap_or("")), src),
+                None => String::new(),
+            },
+            "span" | "font" | "u" | "small" | "abbr" | "cite" | "mark" | "sup" | "sub" | "li"
+            | "label" | "time" => self.inline_children(node),
+            name if DROPPED.contains(&name) => {
+                self.lossless = false;
+                String::new()
+            }
+            // A block element inside inline content, e.g. <p> inside <a>.
+            name if is_block(name) && !UNSUPPORTED_BLOCKS.contains(&name) => self.inline_children(node),
+            _ => {
+                self.lossless = false;
+                outer_html(node)
+            }
+        }
+    }
+
+    /// Wraps inline content in `marker`, moving surrounding whitespace
+    /// outside so the emphasis stays valid Markdown.
+    fn wrap(&mut self, node: NodeRef<Node>, marker: &str) -> String {
+        let text = self.inline_children(node);
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            return text;
+        }
+        let leading = if text.starts_with(char::is_whitespace) { " " } else { "" };
+        let trailing = if text.ends_with(char::is_whitespace) { " " } else { "" };
+        format!("{}{}{}{}{}", leading, marker, trimmed, marker, trailing)
+    }
+}
+
+fn flush_paragraph(paragraph: &mut String, blocks: &mut Vec<String>) {
+    let text = paragraph
+        .split("\\\n")
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("\\\n");
+    let text = text.trim_end_matches("\\\n").trim();
+    if !text.is_empty() {
+        blocks.push(text.to_string());
+    }
+    paragraph.clear();
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_space {
+                result.push(' ');
+            }
+            last_space = true;
+        } else {
+            result.push(c);
+            last_space = false;
+        }
+    }
+    result
+}
+
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn text_content(node: NodeRef<Node>) -> String {
+    node.descendants()
+        .filter_map(|node| node.value().as_text().map(|text| text.to_string()))
+        .collect()
+}
+
+fn outer_html(node: NodeRef<Node>) -> String {
+    ElementRef::wrap(node).map(|element| element.html()).unwrap_or_default()
+}
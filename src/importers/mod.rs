@@ -0,0 +1,76 @@
+//! Parsers for other blogging platforms' exports. Each produces an
+//! [`ImportedSite`], which `import_service` writes through the service layer.
+
+pub mod ghost;
+pub mod html;
+pub mod wordpress;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+/// Platform-neutral content read from an export file.
+#[derive(Debug, Default)]
+pub struct ImportedSite {
+    /// The platform, e.g. `wordpress`. With a post's key it records where
+    /// an imported post came from.
+    pub platform: &'static str,
+    pub authors: Vec<ImportedAuthor>,
+    pub posts: Vec<ImportedPost>,
+    /// Entries in the export that will not be imported, with the reason.
+    pub skipped: Vec<SkippedEntry>,
+}
+
+#[derive(Debug)]
+pub struct ImportedAuthor {
+    /// Identifier the export uses to refer to this author.
+    pub key: String,
+    pub username: String,
+}
+
+#[derive(Debug)]
+pub struct ImportedPost {
+    /// Identifier the export uses for this post.
+    pub key: String,
+    pub title: String,
+    pub slug: Option<String>,
+    pub markdown: String,
+    /// False when the HTML body could not be fully converted to Markdown.
+    pub lossless: bool,
+    /// Key of an [`ImportedAuthor`].
+    pub author_key: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub tags: Vec<String>,
+    /// Where the post lived on the old site, as a full URL or a path.
+    pub old_url: Option<String>,
+    pub comments: Vec<ImportedComment>,
+}
+
+#[derive(Debug)]
+pub struct ImportedComment {
+    pub key: String,
+    pub parent_key: Option<String>,
+    /// Key of an [`ImportedAuthor`] when a site user wrote the comment.
+    pub author_key: Option<String>,
+    /// Display name for visitors without an account.
+    pub author_name: String,
+    pub markdown: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SkippedEntry {
+    pub title: String,
+    pub reason: String,
+}
+
+/// Converts a UTC timestamp in `format` to the RFC 3339 form used for
+/// `created_at` and `updated_at`.
+fn naive_utc_to_rfc3339(value: &str, format: &str) -> Option<String> {
+    let parsed = NaiveDateTime::parse_from_str(value.trim(), format).ok()?;
+    Some(parsed.and_utc().to_rfc3339())
+}
+
+fn rfc3339_to_utc(value: &str) -> Option<String> {
+    let parsed = DateTime::parse_from_rfc3339(value.trim()).ok()?;
+    Some(parsed.with_timezone(&Utc).to_rfc3339())
+}
@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Result};
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+
+use super::html;
+use super::{naive_utc_to_rfc3339, ImportedAuthor, ImportedComment, ImportedPost, ImportedSite, SkippedEntry};
+
+const WP_NAMESPACE: &str = "wordpress.org/export/";
+const CONTENT_NAMESPACE: &str = "purl.org/rss/1.0/modules/content/";
+const DC_NAMESPACE: &str = "purl.org/dc/elements/1.1/";
+const WP_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Parses a WordPress eXtended RSS (WXR) export. Published posts are
+/// imported with their approved comments; drafts, pages and attachments are
+/// listed as skipped.
+pub fn parse(xml: &str) -> Result<ImportedSite> {
+    let document = Document::parse_with_options(
+        xml,
+        roxmltree::ParsingOptions { allow_dtd: true, ..Default::default() },
+    )?;
+    let channel = document
+        .root_element()
+        .children()
+        .find(|node| node.has_tag_name("channel"))
+        .ok_or_else(|| anyhow!("Not a WordPress export: no <channel> element"))?;
+
+    let mut site = ImportedSite { platform: "wordpress", ..Default::default() };
+    // Comments refer to site users by numeric ID, posts by login
+    let mut logins = HashMap::new();
+    for author in channel.children().filter(|node| is(node, WP_NAMESPACE, "author")) {
+        if let Some(login) = child_text(author, WP_NAMESPACE, "author_login") {
+            if let Some(id) = child_text(author, WP_NAMESPACE, "author_id") {
+                logins.insert(id, login.clone());
+            }
+            site.authors.push(ImportedAuthor { key: login.clone(), username: login });
+        }
+    }
+
+    for item in channel.children().filter(|node| node.has_tag_name("item")) {
+        let title = child_text(item, "", "title").unwrap_or_default();
+        let post_type = child_text(item, WP_NAMESPACE, "post_type").unwrap_or_default();
+        let status = child_text(item, WP_NAMESPACE, "status").unwrap_or_default();
+        if post_type != "post" {
+            site.skipped.push(SkippedEntry { title, reason: format!("{} entries are not imported", post_type) });
+            continue;
+        }
+        if status != "publish" {
+            site.skipped.push(SkippedEntry { title, reason: format!("Status is {}", status) });
+            continue;
+        }
+
+        let Some(key) = child_text(item, WP_NAMESPACE, "post_id") else {
+            site.skipped.push(SkippedEntry { title, reason: "No post ID".to_string() });
+            continue;
+        };
+        let Some(author_key) = child_text(item, DC_NAMESPACE, "creator") else {
+            site.skipped.push(SkippedEntry { title, reason: "No author".to_string() });
+            continue;
+        };
+        if !site.authors.iter().any(|author| author.key == author_key) {
+            site.authors.push(ImportedAuthor { key: author_key.clone(), username: author_key.clone() });
+        }
+
+        let body = child_text(item, CONTENT_NAMESPACE, "encoded").unwrap_or_default();
+        let converted = html::to_markdown(&autop(&body));
+        let created_at = post_date(item, "post_date");
+        let updated_at = post_date(item, "post_modified").or_else(|| created_at.clone());
+        let (Some(created_at), Some(updated_at)) = (created_at, updated_at) else {
+            site.skipped.push(SkippedEntry { title, reason: "No publication date".to_string() });
+            continue;
+        };
+
+        let tags = item
+            .children()
+            .filter(|node| node.has_tag_name("category"))
+            .filter(|node| matches!(node.attribute("domain"), Some("post_tag" | "category")))
+            .filter(|node| node.attribute("nicename") != Some("uncategorized"))
+            .filter_map(|node| node.text().map(|text| text.trim().to_string()))
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        site.posts.push(ImportedPost {
+            key,
+            title,
+            slug: child_text(item, WP_NAMESPACE, "post_name").filter(|slug| !slug.is_empty()),
+            markdown: converted.markdown,
+            lossless: converted.lossless,
+            author_key,
+            created_at,
+            updated_at,
+            tags,
+            old_url: child_text(item, "", "link"),
+            comments: comments(item, &logins),
+        });
+    }
+
+    Ok(site)
+}
+
+fn comments(item: Node, logins: &HashMap<String, String>) -> Vec<ImportedComment> {
+    let mut comments = Vec::new();
+    for comment in item.children().filter(|node| is(node, WP_NAMESPACE, "comment")) {
+        let approved = child_text(comment, WP_NAMESPACE, "comment_approved");
+        let kind = child_text(comment, WP_NAMESPACE, "comment_type").unwrap_or_default();
+        if approved.as_deref() != Some("1") || matches!(kind.as_str(), "pingback" | "trackback") {
+            continue;
+        }
+        let (Some(key), Some(created_at)) = (
+            child_text(comment, WP_NAMESPACE, "comment_id"),
+            comment_date(comment),
+        ) else {
+            continue;
+        };
+
+        let content = child_text(comment, WP_NAMESPACE, "comment_content").unwrap_or_default();
+        comments.push(ImportedComment {
+            key,
+            parent_key: child_text(comment, WP_NAMESPACE, "comment_parent").filter(|parent| parent != "0"),
+            // Visitors have user ID 0
+            author_key: child_text(comment, WP_NAMESPACE, "comment_user_id")
+                .and_then(|id| logins.get(&id).cloned()),
+            author_name: child_text(comment, WP_NAMESPACE, "comment_author").unwrap_or_default(),
+            markdown: html::to_markdown(&autop(&content)).markdown,
+            created_at,
+        });
+    }
+    comments
+}
+
+/// Prefers the GMT column; WordPress leaves it zeroed for some entries.
+fn post_date(item: Node, column: &str) -> Option<String> {
+    child_text(item, WP_NAMESPACE, &format!("{}_gmt", column))
+        .and_then(|value| naive_utc_to_rfc3339(&value, WP_DATE_FORMAT))
+        .or_else(|| {
+            child_text(item, WP_NAMESPACE, column)
+                .and_then(|value| naive_utc_to_rfc3339(&value, WP_DATE_FORMAT))
+        })
+}
+
+fn comment_date(comment: Node) -> Option<String> {
+    child_text(comment, WP_NAMESPACE, "comment_date_gmt")
+        .and_then(|value| naive_utc_to_rfc3339(&value, WP_DATE_FORMAT))
+        .or_else(|| {
+            child_text(comment, WP_NAMESPACE, "comment_date")
+                .and_then(|value| naive_utc_to_rfc3339(&value, WP_DATE_FORMAT))
+        })
+}
+
+/// WordPress stores post bodies without paragraph tags and adds them when
+/// rendering. Do the same for bodies that have none, so blank lines become
+/// paragraphs and single newlines become line breaks.
+fn autop(body: &str) -> String {
+    if body.contains("<p") || body.contains("<div") {
+        return body.to_string();
+    }
+    body.replace("\r\n", "\n")
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>", paragraph.replace('\n', "<br>\n")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Matches an element by local name and a fragment of its namespace URI, so
+/// all WXR versions (1.0 to 1.2) are accepted. An empty namespace matches
+/// elements without one.
+fn is(node: &Node, namespace: &str, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().name() == name
+        && match node.tag_name().namespace() {
+            Some(uri) => !namespace.is_empty() && uri.contains(namespace),
+            None => namespace.is_empty(),
+        }
+}
+
+fn child_text(node: Node, namespace: &str, name: &str) -> Option<String> {
+    node.children()
+        .find(|child| is(child, namespace, name))
+        .map(|child| child.text().unwrap_or_default().trim().to_string())
+}
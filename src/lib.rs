@@ -0,0 +1,20 @@
+pub mod config;
+pub mod csrf;
+pub mod email;
+pub mod fairings;
+pub mod feeds;
+pub mod importers;
+pub mod markdown;
+pub mod metrics;
+pub mod models;
+pub mod oidc;
+pub mod qr;
+pub mod rate_limit;
+pub mod repositories;
+pub mod routes;
+pub mod security_headers;
+pub mod seo;
+pub mod services;
+pub mod static_site;
+pub mod telemetry;
+pub mod totp;
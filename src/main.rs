@@ -1,27 +1,87 @@
-#[macro_use]
-extern crate rocket;
-
 use dotenvy::dotenv;
+use rocket::figment::Figment;
 use rocket::fs::FileServer;
+use rocket::{Build, Rocket};
 use rocket_dyn_templates::Template;
 use std::env;
 
-mod models;
-mod routes;
-mod services;
+use blog::email::{EmailConfig, Mailer};
+use blog::services::db::{connect, Database, DbConfig};
+use blog::oidc::{self, OidcConfig};
+use blog::seo::SeoConfig;
+use blog::services::login_service::LoginConfig;
+use blog::services::reaction_service::ReactionsConfig;
+use blog::services::two_factor_service::TwoFactorConfig;
+use blog::{config, fairings, feeds, metrics, routes, telemetry};
+
+fn rocket(figment: Figment, db: Database) -> Rocket<Build> {
+    let metrics_config: metrics::MetricsConfig =
+        figment.extract_inner("metrics").unwrap_or_default();
+    let health_config: routes::health::HealthConfig =
+        figment.extract_inner("health").unwrap_or_default();
+    let site_config: feeds::SiteConfig = figment.extract_inner("site").unwrap_or_default();
+    let seo_config: SeoConfig = figment.extract_inner("seo").unwrap_or_default();
+    let email_config: EmailConfig = figment.extract_inner("email").unwrap_or_default();
+    let mailer = Mailer::from_config(&email_config).expect("Failed to set up email");
+    let login_config: LoginConfig = figment.extract_inner("login").unwrap_or_default();
+    let two_factor_config: TwoFactorConfig = figment.extract_inner("two_factor").unwrap_or_default();
+    let oidc_config: OidcConfig = figment.extract_inner("oidc").unwrap_or_default();
+    let reactions_config: ReactionsConfig = figment.extract_inner("reactions").unwrap_or_default();
 
-use services::db::create_pool;
+    rocket::custom(figment)
+        .mount("/", routes::routes())
+        .mount("/static", FileServer::from(rocket::fs::relative!("static")))
+        .manage(db)
+        .manage(metrics_config)
+        .manage(health_config)
+        .manage(site_config)
+        .manage(seo_config)
+        .manage(mailer)
+        .manage(email_config)
+        .manage(login_config)
+        .manage(two_factor_config)
+        .manage(oidc::Client::new(oidc_config))
+        .manage(reactions_config)
+        .attach(Template::fairing())
+        .attach(fairings::RequestLog)
+        .attach(fairings::HttpMetrics)
+        .attach(fairings::RateLimit)
+        .attach(fairings::Csrf)
+        .attach(fairings::SecurityHeaders)
+        .attach(fairings::BackupSchedule)
+}
 
-#[launch]
-fn rocket() -> _ {
+fn main() {
     dotenv().ok();
 
+    let figment = config::figment();
+    telemetry::init(&figment).expect("Failed to install the tracing subscriber");
+
+    let db_config: DbConfig = figment.extract_inner("database").unwrap_or_default();
+    let workers: usize = figment
+        .extract_inner("workers")
+        .unwrap_or_else(|_| rocket::Config::default().workers);
+
+    // Rocket's default runtime leaves the blocking pool at Tokio's default of
+    // 512 threads; database and bcrypt work runs there, so bound it explicitly.
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(workers)
+        .max_blocking_threads(db_config.blocking_threads)
+        .thread_name("rocket-worker-thread")
+        .enable_all()
+        .build()
+        .expect("Failed to build Tokio runtime");
+
     let db_url = env::var("DATABASE_URL").unwrap_or_else(|_| "blog.db".to_string());
-    let pool = create_pool(&db_url);
+    let result = runtime.block_on(async {
+        let db = connect(&db_url, &db_config).await?;
+        tracing::info!(backend = db.backend(), "database connected");
+        rocket(figment, db).launch().await?;
+        anyhow::Ok(())
+    });
 
-    rocket::build()
-        .mount("/", routes::routes())
-        .mount("/static", FileServer::from(rocket::fs::relative!("static")))
-        .manage(pool)
-        .attach(Template::fairing())
+    if let Err(e) = result {
+        tracing::error!(error = %e, "server failed");
+        std::process::exit(1);
+    }
 }
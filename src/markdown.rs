@@ -0,0 +1,190 @@
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::models::post::slugify;
+
+const FENCE: &str = "---";
+
+/// Ends the part of a post shown in listings.
+pub const MORE_MARKER: &str = "<!--more-->";
+
+/// Words in an excerpt taken from a post without a `<!--more-->` marker.
+const EXCERPT_WORDS: usize = 55;
+
+/// Reading speed behind the estimated reading time.
+pub const WORDS_PER_MINUTE: usize = 200;
+
+/// Renders `body` as a Markdown document with `meta` as YAML front matter.
+pub fn with_front_matter<T: Serialize>(meta: &T, body: &str) -> Result<String> {
+    let yaml = serde_yaml::to_string(meta)?;
+    Ok(format!("{}\n{}{}\n{}", FENCE, yaml, FENCE, body))
+}
+
+/// Splits a Markdown document into its YAML front matter and body. The body
+/// is returned exactly as written after the closing fence.
+pub fn parse_front_matter<T: DeserializeOwned>(document: &str) -> Result<(T, &str)> {
+    let rest = document
+        .strip_prefix(FENCE)
+        .and_then(|rest| rest.strip_prefix('\n').or_else(|| rest.strip_prefix("\r\n")))
+        .ok_or_else(|| anyhow!("Missing front matter"))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == FENCE {
+            let meta = serde_yaml::from_str(&rest[..offset])?;
+            return Ok((meta, &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    Err(anyhow!("Unterminated front matter"))
+}
+
+/// A heading and the fragment identifier it is linked by.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Heading {
+    /// 1 to 6, for `#` to `######`.
+    pub level: u8,
+    pub text: String,
+    pub anchor: String,
+}
+
+/// The Markdown under one heading, or before the first.
+#[derive(Debug, Clone, Serialize)]
+pub struct Section {
+    pub heading: Option<Heading>,
+    pub body: String,
+}
+
+/// Splits `markdown` at its `#` headings, skipping any in fenced code
+/// blocks. Anchors are slugs of the heading text, with `-1`, `-2`, ...
+/// added to repeats, so links to them hold as long as the headings do. The
+/// `<!--more-->` marker is left out of the bodies.
+pub fn sections(markdown: &str) -> Vec<Section> {
+    let mut sections = vec![Section { heading: None, body: String::new() }];
+    let mut anchors = HashSet::new();
+    let mut fence: Option<&str> = None;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        let indented = line.len() - trimmed.len() > 3;
+        if let Some(open) = fence {
+            if trimmed.starts_with(open) {
+                fence = None;
+            }
+        } else if !indented && (trimmed.starts_with("```") || trimmed.starts_with("~~~")) {
+            fence = Some(&trimmed[..3]);
+        } else if let Some((level, text)) = atx_heading(line) {
+            let text = plain_text(text);
+            let anchor = unique_anchor(&text, &mut anchors);
+            sections.push(Section { heading: Some(Heading { level, text, anchor }), body: String::new() });
+            continue;
+        }
+        if let Some(section) = sections.last_mut() {
+            section.body.push_str(&line.replace(MORE_MARKER, ""));
+            section.body.push('\n');
+        }
+    }
+    for section in &mut sections {
+        section.body = section.body.trim_matches('\n').to_string();
+    }
+    if sections.len() > 1 && sections[0].body.trim().is_empty() {
+        sections.remove(0);
+    }
+    sections
+}
+
+/// The table of contents of `markdown`, in document order.
+pub fn headings(markdown: &str) -> Vec<Heading> {
+    sections(markdown).into_iter().filter_map(|section| section.heading).collect()
+}
+
+/// Plain text standing in for `markdown` in listings: everything before a
+/// `<!--more-->` marker, or else its opening words, without headings.
+pub fn excerpt(markdown: &str) -> String {
+    let (intro, marked) = match markdown.split_once(MORE_MARKER) {
+        Some((intro, _)) => (intro, true),
+        None => (markdown, false),
+    };
+    let bodies: Vec<String> = sections(intro).into_iter().map(|section| section.body).collect();
+    let text = plain_text(&bodies.join("\n\n"));
+    if marked {
+        return text;
+    }
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.len() <= EXCERPT_WORDS {
+        return text;
+    }
+    format!("{}\u{2026}", words[..EXCERPT_WORDS].join(" ").trim_end_matches(|c: char| c.is_ascii_punctuation()))
+}
+
+pub fn word_count(markdown: &str) -> usize {
+    plain_text(markdown).split_whitespace().count()
+}
+
+/// Minutes to read `words` words, rounded up and never less than one.
+pub fn reading_minutes(words: usize) -> usize {
+    words.div_ceil(WORDS_PER_MINUTE).max(1)
+}
+
+/// The words of `markdown` with HTML tags, link targets and Markdown
+/// markers dropped and whitespace collapsed to single spaces.
+pub fn plain_text(markdown: &str) -> String {
+    let mut text = String::with_capacity(markdown.len());
+    let mut in_tag = false;
+    let mut in_link_target = false;
+    let mut chars = markdown.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' if chars.peek().is_some_and(|next| next.is_ascii_alphabetic() || *next == '/' || *next == '!') => {
+                in_tag = true
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if in_tag => {}
+            ')' if in_link_target => in_link_target = false,
+            _ if in_link_target => {}
+            ']' if chars.peek() == Some(&'(') => {
+                chars.next();
+                in_link_target = true;
+            }
+            '!' if chars.peek() == Some(&'[') => {}
+            '#' | '*' | '`' | '>' | '[' | ']' => {}
+            _ => text.push(c),
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The level and text of an ATX heading line, without any closing `#`s.
+fn atx_heading(line: &str) -> Option<(u8, &str)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let level = trimmed.bytes().take_while(|byte| *byte == b'#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    let text = rest.trim();
+    let open = text.trim_end_matches('#');
+    let text = if open.is_empty() || open.ends_with([' ', '\t']) { open.trim_end() } else { text };
+    Some((level as u8, text))
+}
+
+fn unique_anchor(text: &str, taken: &mut HashSet<String>) -> String {
+    let base = match slugify(text) {
+        slug if slug.is_empty() => "section".to_string(),
+        slug => slug,
+    };
+    let mut anchor = base.clone();
+    let mut repeat = 1;
+    while !taken.insert(anchor.clone()) {
+        anchor = format!("{}-{}", base, repeat);
+        repeat += 1;
+    }
+    anchor
+}
@@ -0,0 +1,216 @@
+use prometheus::core::Collector;
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use crate::repositories::{PoolStatus, SiteCounts};
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Bearer token required to scrape `/metrics`. Unset means no token check.
+    pub token: Option<String>,
+    /// Client addresses allowed to scrape `/metrics`. Empty means any address.
+    pub allowed_ips: Vec<IpAddr>,
+}
+
+pub static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+fn register<C: Collector + Clone + 'static>(collector: C) -> C {
+    REGISTRY
+        .register(Box::new(collector.clone()))
+        .expect("metric registered twice");
+    collector
+}
+
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("blog_http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new("blog_http_request_duration_seconds", "HTTP request latency"),
+            &["method", "route", "status"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static SQL_QUERY_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new("blog_sql_query_duration_seconds", "SQLite statement latency")
+                .buckets(exponential_buckets(0.00005, 4.0, 10).unwrap()),
+            &["statement"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static LOGIN_ATTEMPTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("blog_login_attempts_total", "Login attempts by outcome"),
+            &["outcome"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static LOGIN_LOCKOUTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("blog_login_lockouts_total", "Logins locked after too many failures, by account or address"),
+            &["scope"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static RATE_LIMITED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("blog_rate_limited_total", "Requests refused for going over a rate limit, by route group"),
+            &["group"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static BACKUPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("blog_backups_total", "Database backups by outcome"),
+            &["outcome"],
+        )
+        .unwrap(),
+    )
+});
+
+static LAST_BACKUP: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(
+        IntGauge::new(
+            "blog_last_backup_timestamp_seconds",
+            "Unix time of the last successful backup",
+        )
+        .unwrap(),
+    )
+});
+
+pub static PASSWORD_HASHES_RUNNING: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new("blog_password_hashes_running", "bcrypt jobs running on the blocking pool").unwrap())
+});
+
+static POOL_CONNECTIONS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new("blog_db_pool_connections", "Open pooled connections").unwrap())
+});
+
+static POOL_IDLE_CONNECTIONS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new("blog_db_pool_idle_connections", "Idle pooled connections").unwrap())
+});
+
+static POOL_MAX_SIZE: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new("blog_db_pool_max_size", "Maximum pool size").unwrap())
+});
+
+static USERS: LazyLock<IntGauge> =
+    LazyLock::new(|| register(IntGauge::new("blog_users", "Registered users").unwrap()));
+
+static POSTS: LazyLock<IntGauge> =
+    LazyLock::new(|| register(IntGauge::new("blog_posts", "Published posts").unwrap()));
+
+static COMMENTS: LazyLock<IntGauge> =
+    LazyLock::new(|| register(IntGauge::new("blog_comments", "Comments on posts").unwrap()));
+
+pub fn record_login(success: bool) {
+    let outcome = if success { "success" } else { "failure" };
+    LOGIN_ATTEMPTS.with_label_values(&[outcome]).inc();
+}
+
+/// `scope` is `account` or `address`.
+pub fn record_lockout(scope: &str) {
+    LOGIN_LOCKOUTS.with_label_values(&[scope]).inc();
+}
+
+pub fn record_rate_limited(group: &str) {
+    RATE_LIMITED.with_label_values(&[group]).inc();
+}
+
+pub fn record_backup(success: bool) {
+    let outcome = if success { "success" } else { "failure" };
+    BACKUPS.with_label_values(&[outcome]).inc();
+    if success {
+        LAST_BACKUP.set(chrono::Utc::now().timestamp());
+    }
+}
+
+/// Counts a bcrypt job as running until it is dropped.
+pub struct RunningHash(());
+
+impl RunningHash {
+    pub fn start() -> Self {
+        PASSWORD_HASHES_RUNNING.inc();
+        RunningHash(())
+    }
+}
+
+impl Drop for RunningHash {
+    fn drop(&mut self) {
+        PASSWORD_HASHES_RUNNING.dec();
+    }
+}
+
+pub fn record_sql(sql: &str, elapsed: Duration) {
+    let statement = sql
+        .split_whitespace()
+        .next()
+        .map(|word| word.to_ascii_uppercase())
+        .filter(|word| matches!(word.as_str(), "SELECT" | "INSERT" | "UPDATE" | "DELETE"))
+        .unwrap_or_else(|| "OTHER".to_string());
+    SQL_QUERY_DURATION
+        .with_label_values(&[&statement])
+        .observe(elapsed.as_secs_f64());
+}
+
+/// Refreshes the gauges that are sampled at scrape time and renders the
+/// registry in the Prometheus text format.
+pub fn render(pool: PoolStatus, counts: Option<SiteCounts>) -> String {
+    POOL_CONNECTIONS.set(pool.connections as i64);
+    POOL_IDLE_CONNECTIONS.set(pool.idle_connections as i64);
+    POOL_MAX_SIZE.set(pool.max_size as i64);
+
+    if let Some(counts) = counts {
+        USERS.set(counts.users);
+        POSTS.set(counts.posts);
+        COMMENTS.set(counts.comments);
+    }
+
+    // Touch the lazily registered collectors so they appear before first use.
+    LazyLock::force(&HTTP_REQUESTS);
+    LazyLock::force(&HTTP_REQUEST_DURATION);
+    LazyLock::force(&SQL_QUERY_DURATION);
+    LazyLock::force(&LOGIN_ATTEMPTS);
+    LazyLock::force(&LOGIN_LOCKOUTS);
+    LazyLock::force(&RATE_LIMITED);
+    LazyLock::force(&BACKUPS);
+    LazyLock::force(&LAST_BACKUP);
+    LazyLock::force(&PASSWORD_HASHES_RUNNING);
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .expect("text encoding of metrics failed");
+    String::from_utf8(buffer).unwrap_or_default()
+}
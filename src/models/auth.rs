@@ -3,12 +3,22 @@ use bcrypt::{hash, verify, DEFAULT_COST};
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome};
 use rocket::Request;
+use std::sync::LazyLock;
+use std::thread;
+use tokio::sync::{OnceCell, Semaphore};
 use uuid::Uuid;
 
+use crate::metrics::RunningHash;
 use crate::models::User;
-use crate::services::db::DbPool;
+use crate::services::db::Database;
+use crate::services::two_factor_service::{self, TwoFactorConfig};
 use crate::services::user_service;
 
+/// Marks a session that started with single sign-on while
+/// `oidc.trust_provider_mfa` is on. The identity provider is trusted to have
+/// checked a second factor, so such sessions do not need the blog's own.
+pub const SSO_SESSION_COOKIE: &str = "sso_session";
+
 pub struct AuthenticatedUser(pub User);
 
 #[rocket::async_trait]
@@ -26,24 +36,74 @@ impl<'r> FromRequest<'r> for AuthenticatedUser {
             Err(_) => return Outcome::Forward(Status::Unauthorized),
         };
 
-        let pool = match request.rocket().state::<DbPool>() {
-            Some(pool) => pool,
+        let db = match request.rocket().state::<Database>() {
+            Some(db) => db,
             None => return Outcome::Error((Status::InternalServerError, ())),
         };
 
-        let user = match user_service::get_user_by_id(pool, user_id).await {
-            Ok(Some(user)) => user,
+        let user = match user_service::get_user_by_id(db, user_id).await {
+            Ok(Some(user)) if !user.disabled => user,
             _ => return Outcome::Forward(Status::Unauthorized),
         };
 
+        // Sessions from before two-factor authentication became required for
+        // the user's role end here; signing in again leads through setup.
+        if let Some(config) = request.rocket().state::<TwoFactorConfig>() {
+            if config.required_for(user.role)
+                && request.cookies().get_private(SSO_SESSION_COOKIE).is_none()
+                && !two_factor_service::is_enabled(db, user.id).await.unwrap_or(false)
+            {
+                return Outcome::Forward(Status::Unauthorized);
+            }
+        }
+
         Outcome::Success(AuthenticatedUser(user))
     }
 }
 
-pub fn hash_password(password: &str) -> Result<String> {
-    Ok(hash(password, DEFAULT_COST)?)
+/// How many bcrypt jobs may run at once: one per CPU.
+pub fn max_concurrent_hashes() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(4)
+}
+
+/// Caps concurrent bcrypt jobs so a burst of logins cannot take over the
+/// blocking thread pool that SQLite calls also run on.
+static HASH_PERMITS: LazyLock<Semaphore> = LazyLock::new(|| Semaphore::new(max_concurrent_hashes()));
+
+async fn run_bcrypt<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> bcrypt::BcryptResult<T> + Send + 'static,
+{
+    let _permit = HASH_PERMITS.acquire().await?;
+    Ok(tokio::task::spawn_blocking(move || {
+        let _running = RunningHash::start();
+        f()
+    })
+    .await??)
 }
 
-pub fn verify_password(password: &str, hash: &str) -> Result<bool> {
-    Ok(verify(password, hash)?)
+pub async fn hash_password(password: &str) -> Result<String> {
+    let password = password.to_string();
+    run_bcrypt(move || hash(password, DEFAULT_COST)).await
+}
+
+pub async fn verify_password(password: &str, hash: &str) -> Result<bool> {
+    let (password, hash) = (password.to_string(), hash.to_string());
+    run_bcrypt(move || verify(password, &hash)).await
+}
+
+/// A hash of a random password, checked in place of a missing one.
+static DUMMY_HASH: OnceCell<String> = OnceCell::const_new();
+
+/// Like [`verify_password`], but with no hash, e.g. for an unknown
+/// username, it checks against a dummy one and fails. Either way a bcrypt
+/// check runs, so the time taken does not tell whether the account exists.
+pub async fn verify_password_or_dummy(password: &str, hash: Option<&str>) -> Result<bool> {
+    if let Some(hash) = hash {
+        return verify_password(password, hash).await;
+    }
+    let dummy = DUMMY_HASH.get_or_try_init(|| async { hash_password(&Uuid::new_v4().to_string()).await }).await?;
+    verify_password(password, dummy).await?;
+    Ok(false)
 }
@@ -0,0 +1,28 @@
+use rocket::form::FromForm;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+/// A post a user saved to read later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub user_id: Uuid,
+    pub post_id: Uuid,
+    /// Empty for bookmarks in no folder.
+    pub folder: String,
+    pub note: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Bookmark form on the post and bookmarks pages. The index page sends it
+/// empty.
+#[derive(Debug, Default, FromForm, Validate)]
+pub struct SaveBookmark {
+    #[field(default = String::new())]
+    #[validate(length(max = 50, message = "Folder names must be at most 50 characters long"))]
+    pub folder: String,
+    #[field(default = String::new())]
+    #[validate(length(max = 1000, message = "Notes must be at most 1000 characters long"))]
+    pub note: String,
+}
@@ -3,15 +3,26 @@ use uuid::Uuid;
 use validator::Validate;
 use rocket::form::FromForm;
 
+use crate::models::reaction::Reactions;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Comment {
     pub id: Uuid,
     pub content: String,
     pub post_id: Uuid,
+    /// The comment this one replies to, if any.
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
     pub author_id: Uuid,
     pub author: String,
+    /// Name of the visitor who wrote an imported comment without an
+    /// account; `author` is then the shared guest account.
+    #[serde(default)]
+    pub guest_name: Option<String>,
     pub created_at: String,
     pub updated_at: String,
+    #[serde(default)]
+    pub reactions: Reactions,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate, FromForm)]
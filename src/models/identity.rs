@@ -0,0 +1,14 @@
+use serde::Serialize;
+
+/// An account at an external identity provider, linked to a local user.
+/// The provider's issuer and subject together identify it for good; the
+/// email is only what the provider last said.
+#[derive(Debug, Clone, Serialize)]
+pub struct UserIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub user_id: uuid::Uuid,
+    pub email: Option<String>,
+    pub created_at: String,
+    pub last_login_at: String,
+}
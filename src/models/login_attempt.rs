@@ -0,0 +1,83 @@
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// How a login attempt on an account ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginOutcome {
+    Success,
+    WrongPassword,
+    /// The password was right but the second step's code was not.
+    WrongCode,
+    /// Refused without checking the password after too many failures.
+    Locked,
+    /// The password was right but the account is disabled.
+    Disabled,
+}
+
+impl LoginOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Success => "success",
+            LoginOutcome::WrongPassword => "wrong_password",
+            LoginOutcome::WrongCode => "wrong_code",
+            LoginOutcome::Locked => "locked",
+            LoginOutcome::Disabled => "disabled",
+        }
+    }
+}
+
+impl FromStr for LoginOutcome {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success" => Ok(LoginOutcome::Success),
+            "wrong_password" => Ok(LoginOutcome::WrongPassword),
+            "wrong_code" => Ok(LoginOutcome::WrongCode),
+            "locked" => Ok(LoginOutcome::Locked),
+            "disabled" => Ok(LoginOutcome::Disabled),
+            _ => Err(anyhow::anyhow!("Unknown login outcome: {}", s)),
+        }
+    }
+}
+
+/// A login attempt on an existing account, kept for its owner to review.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoginAttempt {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: LoginOutcome,
+    pub created_at: String,
+}
+
+/// Longest user agent kept with an attempt.
+const MAX_USER_AGENT_LEN: usize = 255;
+
+/// Where a login comes from. The address is the one Rocket reports, which
+/// honours its `ip_header` setting behind a proxy.
+#[derive(Debug, Clone, Default)]
+pub struct LoginClient {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LoginClient {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user_agent = request
+            .headers()
+            .get_one("User-Agent")
+            .map(|agent| agent.chars().take(MAX_USER_AGENT_LEN).collect());
+        Outcome::Success(LoginClient { ip: request.client_ip(), user_agent })
+    }
+}
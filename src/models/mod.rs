@@ -1,5 +1,14 @@
+pub mod bookmark;
 pub mod comment;
+pub mod identity;
+pub mod login_attempt;
 pub mod post;
+pub mod profile;
+pub mod reaction;
+pub mod series;
+pub mod tag;
+pub mod token;
+pub mod two_factor;
 pub mod user;
 pub mod auth;
 
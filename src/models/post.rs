@@ -1,17 +1,98 @@
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
 use uuid::Uuid;
 use validator::Validate;
-use rocket::form::FromForm;
+use rocket::form::{FromForm, FromFormField};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use crate::markdown::{self, Heading};
+use crate::models::reaction::Reactions;
+
+#[derive(Debug, Deserialize, Clone)]
 pub struct Post {
     pub id: Uuid,
     pub title: String,
+    /// URL-friendly name, set by imports, archive imports and directory sync.
+    /// Posts written on the site have none.
+    #[serde(default)]
+    pub slug: Option<String>,
     pub content: String,
     pub author_id: Uuid,
     pub author: String,
     pub created_at: String,
     pub updated_at: String,
+    /// Title for search results and link previews, in place of `title`.
+    #[serde(default)]
+    pub seo_title: Option<String>,
+    /// Description for search results and link previews, in place of one
+    /// taken from the content.
+    #[serde(default)]
+    pub seo_description: Option<String>,
+    /// Summary for post listings, in place of one taken from the content.
+    #[serde(default)]
+    pub excerpt: Option<String>,
+    /// Maintained by the repository as readers react; ignored on insert.
+    #[serde(default)]
+    pub reactions: Reactions,
+}
+
+impl Post {
+    /// The author's excerpt, or else the content up to a `<!--more-->`
+    /// marker or its opening words.
+    pub fn summary(&self) -> String {
+        self.excerpt.clone().unwrap_or_else(|| markdown::excerpt(&self.content))
+    }
+
+    pub fn word_count(&self) -> usize {
+        markdown::word_count(&self.content)
+    }
+
+    pub fn reading_minutes(&self) -> usize {
+        markdown::reading_minutes(self.word_count())
+    }
+
+    /// The content's headings with their anchors.
+    pub fn toc(&self) -> Vec<Heading> {
+        markdown::headings(&self.content)
+    }
+}
+
+/// Alongside the stored fields, a post carries what is derived from its
+/// content (`summary`, `word_count`, `reading_minutes` and `toc`) so
+/// templates and JSON readers need not work it out. Deserializing ignores
+/// them.
+impl Serialize for Post {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let word_count = self.word_count();
+        let mut post = serializer.serialize_struct("Post", 16)?;
+        post.serialize_field("id", &self.id)?;
+        post.serialize_field("title", &self.title)?;
+        post.serialize_field("slug", &self.slug)?;
+        post.serialize_field("content", &self.content)?;
+        post.serialize_field("author_id", &self.author_id)?;
+        post.serialize_field("author", &self.author)?;
+        post.serialize_field("created_at", &self.created_at)?;
+        post.serialize_field("updated_at", &self.updated_at)?;
+        post.serialize_field("seo_title", &self.seo_title)?;
+        post.serialize_field("seo_description", &self.seo_description)?;
+        post.serialize_field("excerpt", &self.excerpt)?;
+        post.serialize_field("reactions", &self.reactions)?;
+        post.serialize_field("summary", &self.summary())?;
+        post.serialize_field("word_count", &word_count)?;
+        post.serialize_field("reading_minutes", &markdown::reading_minutes(word_count))?;
+        post.serialize_field("toc", &self.toc())?;
+        post.end()
+    }
+}
+
+/// Orders of the post listing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromFormField)]
+pub enum PostSort {
+    #[default]
+    #[field(value = "newest")]
+    Newest,
+    /// Most reactions of any kind first, newest first among equals.
+    #[field(value = "liked")]
+    MostLiked,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate, FromForm)]
@@ -21,3 +102,51 @@ pub struct CreatePost {
     #[validate(length(min = 1))]
     pub content: String,
 }
+
+/// Search engine overrides form. Empty fields fall back to the post's
+/// title and the start of its content.
+#[derive(Debug, FromForm, Validate)]
+pub struct UpdatePostSeo {
+    #[validate(length(max = 70, message = "SEO title must be at most 70 characters long"))]
+    pub seo_title: String,
+    #[validate(length(max = 300, message = "SEO description must be at most 300 characters long"))]
+    pub seo_description: String,
+}
+
+/// Listing excerpt form. An empty excerpt falls back to one taken from the
+/// content.
+#[derive(Debug, FromForm, Validate)]
+pub struct UpdatePostExcerpt {
+    #[validate(length(max = 1000, message = "Excerpt must be at most 1000 characters long"))]
+    pub excerpt: String,
+}
+
+/// Links a post to the Markdown file it is synchronised with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostSource {
+    pub post_id: Uuid,
+    /// Canonical path of the directory or bare repository the file is in;
+    /// empty for files recorded before roots were kept.
+    pub root: String,
+    /// Path relative to the synchronised directory, with `/` separators.
+    pub path: String,
+    /// SHA-256 of the file as last read or written.
+    pub content_hash: String,
+    /// The post's `updated_at` at that time, to detect edits made on the web.
+    pub post_updated_at: String,
+    pub synced_at: String,
+}
+
+/// Lowercases `text` and joins its alphanumeric runs with hyphens, e.g.
+/// "Hello, World!" becomes "hello-world".
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
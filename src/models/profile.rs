@@ -0,0 +1,75 @@
+use rocket::form::FromForm;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+/// Public details an author shows on their page. Users who never edited
+/// their profile have the default, empty one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    pub user_id: Uuid,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+    pub website: Option<String>,
+    pub social_links: Vec<String>,
+    pub updated_at: String,
+}
+
+/// Profile form. Empty fields clear the value; social links are one URL
+/// per line.
+#[derive(Debug, FromForm, Validate)]
+pub struct UpdateProfile {
+    #[validate(length(max = 100, message = "Display name must be at most 100 characters long"))]
+    pub display_name: String,
+    #[validate(length(max = 2000, message = "Bio must be at most 2000 characters long"))]
+    pub bio: String,
+    #[validate(custom = "validate_optional_url")]
+    pub avatar_url: String,
+    #[validate(custom = "validate_optional_url")]
+    pub website: String,
+    #[validate(custom = "validate_url_lines")]
+    pub social_links: String,
+}
+
+impl UpdateProfile {
+    pub fn social_links(&self) -> Vec<String> {
+        self.social_links
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Only http(s) links are shown, so a profile cannot carry `javascript:` URLs.
+fn is_web_url(value: &str) -> bool {
+    (value.starts_with("https://") || value.starts_with("http://"))
+        && value.len() <= 500
+        && !value.contains(char::is_whitespace)
+}
+
+fn validate_optional_url(value: &str) -> Result<(), ValidationError> {
+    let value = value.trim();
+    if value.is_empty() || is_web_url(value) {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("url");
+    error.message = Some("Links must be http:// or https:// URLs".into());
+    Err(error)
+}
+
+fn validate_url_lines(value: &str) -> Result<(), ValidationError> {
+    let mut count = 0;
+    for line in value.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        validate_optional_url(line)?;
+        count += 1;
+    }
+    if count > 10 {
+        let mut error = ValidationError::new("social_links");
+        error.message = Some("At most 10 social links are allowed".into());
+        return Err(error);
+    }
+    Ok(())
+}
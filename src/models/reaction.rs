@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// A post's or comment's reactions counted by kind, kept on the post or
+/// comment itself so listings need not count them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reactions {
+    pub counts: BTreeMap<String, i64>,
+    /// Reactions of every kind, including ones no longer offered.
+    pub total: i64,
+}
+
+impl Reactions {
+    pub fn count(&self, kind: &str) -> i64 {
+        self.counts.get(kind).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReactionTarget {
+    Post,
+    Comment,
+}
+
+/// One user's reaction of one kind to a post or comment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    pub target: ReactionTarget,
+    pub target_id: Uuid,
+    pub user_id: Uuid,
+    pub kind: String,
+    pub created_at: String,
+}
+
+/// The kinds a user has reacted with to a post and to its comments.
+#[derive(Debug, Default)]
+pub struct UserReactions {
+    pub post: Vec<String>,
+    pub comments: HashMap<Uuid, Vec<String>>,
+}
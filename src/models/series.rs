@@ -0,0 +1,28 @@
+use rocket::form::FromForm;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+/// An ordered run of one author's posts, such as a multi-part tutorial.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Series {
+    pub id: Uuid,
+    pub title: String,
+    pub slug: String,
+    pub author_id: Uuid,
+    pub author: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Series form on the post edit page. A `new_series` title starts a series
+/// and wins over `series`; both empty takes the post out of its series.
+#[derive(Debug, FromForm, Validate)]
+pub struct UpdatePostSeries {
+    /// Id of one of the author's series.
+    pub series: String,
+    #[validate(length(max = 100, message = "Series title must be at most 100 characters long"))]
+    pub new_series: String,
+    /// Part number, counting from 1. The post goes last when missing.
+    pub position: Option<usize>,
+}
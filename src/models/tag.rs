@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub id: Uuid,
+    pub name: String,
+    pub slug: String,
+}
@@ -0,0 +1,69 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// What a [`UserToken`] may be used for. A token only works for its own
+/// purpose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenPurpose {
+    PasswordReset,
+    /// Confirms the address in [`UserToken::email`], either the current one
+    /// or one the user wants to switch to.
+    EmailVerification,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::PasswordReset => "password_reset",
+            TokenPurpose::EmailVerification => "email_verification",
+        }
+    }
+}
+
+impl FromStr for TokenPurpose {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "password_reset" => Ok(TokenPurpose::PasswordReset),
+            "email_verification" => Ok(TokenPurpose::EmailVerification),
+            _ => Err(anyhow::anyhow!("Unknown token purpose: {}", s)),
+        }
+    }
+}
+
+/// A single-use secret sent to a user, e.g. in a password reset link. Only
+/// its hash is stored, so a leaked database does not hand out working links.
+#[derive(Debug, Clone)]
+pub struct UserToken {
+    pub token_hash: String,
+    pub user_id: Uuid,
+    pub purpose: TokenPurpose,
+    /// The address an email verification token confirms.
+    pub email: Option<String>,
+    /// RFC 3339 UTC with whole seconds, so it compares as a string.
+    pub expires_at: String,
+    pub created_at: String,
+}
+
+/// Returns a new random token, hex encoded, and its hash.
+pub fn generate_token() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Token timestamps compare as strings, so they always use this format.
+pub fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
@@ -0,0 +1,33 @@
+use rocket::form::FromForm;
+use uuid::Uuid;
+
+/// A user's authenticator secret. The enrolment stays pending, and does not
+/// protect the account, until a code from the app is confirmed.
+#[derive(Debug, Clone)]
+pub struct TwoFactor {
+    pub user_id: Uuid,
+    /// Base32. Kept in the clear because codes are computed from it.
+    pub secret: String,
+    pub enabled: bool,
+    /// The time step of the last accepted code; it and older ones are
+    /// refused, so a code works only once.
+    pub last_used_step: Option<i64>,
+    /// Wrong codes since the last right one.
+    pub failed_attempts: i32,
+    /// RFC 3339 UTC; codes are refused until then.
+    pub locked_until: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A code from the authenticator app or a recovery code.
+#[derive(Debug, FromForm)]
+pub struct TwoFactorCode {
+    pub code: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct DisableTwoFactor {
+    pub password: String,
+    pub code: String,
+}
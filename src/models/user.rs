@@ -1,23 +1,95 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 use rocket::form::FromForm;
+use std::fmt;
+use std::str::FromStr;
+
+/// Ordered from least to most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "editor" => Ok(Role::Editor),
+            "admin" => Ok(Role::Admin),
+            _ => Err(anyhow::anyhow!("Unknown role: {}", s)),
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
     pub id: Uuid,
     pub username: String,
     pub password_hash: String,
+    pub role: Role,
+    pub disabled: bool,
+    /// Stored lowercased; see [`normalize_email`].
+    #[serde(default)]
+    pub email: Option<String>,
+    /// Whether the owner of `email` has confirmed it. Cleared when the
+    /// address changes without confirmation.
+    #[serde(default)]
+    pub email_verified: bool,
     pub created_at: String,
     pub updated_at: String,
 }
 
+/// Email addresses are compared case-insensitively, so they are stored
+/// trimmed and lowercased. Blank input means no address.
+pub fn normalize_email(email: &str) -> Option<String> {
+    Some(email.trim().to_lowercase()).filter(|email| !email.is_empty())
+}
+
+/// Accepts a blank value or something shaped like an address; whether it
+/// is deliverable only shows when mail is sent to it.
+fn validate_optional_email(value: &str) -> Result<(), ValidationError> {
+    let value = value.trim();
+    if value.is_empty() || validator::validate_email(value) {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("email");
+    error.message = Some("Please enter a valid email address".into());
+    Err(error)
+}
+
 #[derive(Debug, FromForm, Validate)]
 pub struct CreateUser {
     #[validate(length(min = 3, message = "Username must be at least 3 characters long"))]
     pub username: String,
     #[validate(length(min = 6, message = "Password must be at least 6 characters long"))]
     pub password: String,
+    /// Optional; needed to reset a forgotten password.
+    #[field(default = String::new())]
+    #[validate(custom = "validate_optional_email")]
+    pub email: String,
 }
 
 #[derive(Debug, FromForm)]
@@ -38,3 +110,24 @@ pub struct UpdatePassword {
     #[validate(length(min = 6, message = "New password must be at least 6 characters long"))]
     pub new_password: String,
 }
+
+#[derive(Debug, FromForm, Validate)]
+pub struct UpdateEmail {
+    #[validate(custom = "validate_optional_email")]
+    pub email: String,
+}
+
+#[derive(Debug, FromForm, Validate)]
+pub struct ForgotPassword {
+    #[validate(email(message = "Please enter a valid email address"))]
+    pub email: String,
+}
+
+#[derive(Debug, FromForm, Validate)]
+pub struct ResetPassword {
+    pub token: String,
+    #[validate(length(min = 6, message = "New password must be at least 6 characters long"))]
+    pub new_password: String,
+    #[validate(must_match(other = "new_password", message = "Passwords do not match"))]
+    pub confirm_password: String,
+}
@@ -0,0 +1,121 @@
+//! Requests to the identity provider's endpoints, made with `ureq` on the
+//! blocking pool. Plain `http` is only allowed to loopback addresses, for
+//! local providers and tests.
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rocket::http::RawStr;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::io::Read;
+use std::time::Duration;
+use url::{Host, Url};
+
+/// Larger responses are refused; discovery documents and key sets are a
+/// few kilobytes.
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Fetches `url` and parses the JSON it returns.
+pub async fn get_json<T: DeserializeOwned>(url: &str, timeout: Duration) -> Result<T> {
+    let url = parse_url(url)?;
+    let body = tokio::task::spawn_blocking(move || {
+        let request = agent(timeout).request_url("GET", &url);
+        read_body(&url, request.call())
+    })
+    .await??;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Posts `form` as `application/x-www-form-urlencoded`, with HTTP Basic
+/// credentials if given, and parses the JSON reply.
+pub async fn post_form<T: DeserializeOwned>(
+    url: &str,
+    form: &[(&str, &str)],
+    basic_auth: Option<(&str, &str)>,
+    timeout: Duration,
+) -> Result<T> {
+    let url = parse_url(url)?;
+    let form: Vec<(String, String)> = form.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    // RFC 6749 section 2.3.1: both halves are form-encoded first
+    let authorization = basic_auth.map(|(user, password)| {
+        let credentials = format!(
+            "{}:{}",
+            RawStr::new(user).percent_encode(),
+            RawStr::new(password).percent_encode()
+        );
+        format!("Basic {}", BASE64.encode(credentials))
+    });
+    let body = tokio::task::spawn_blocking(move || {
+        let mut request = agent(timeout).request_url("POST", &url);
+        if let Some(authorization) = &authorization {
+            request = request.set("Authorization", authorization);
+        }
+        let form: Vec<(&str, &str)> = form.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+        read_body(&url, request.send_form(&form))
+    })
+    .await??;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+fn parse_url(url: &str) -> Result<Url> {
+    let parsed = Url::parse(url).with_context(|| format!("Invalid URL {}", url))?;
+    let loopback = match parsed.host() {
+        Some(Host::Domain(domain)) => domain == "localhost",
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        None => bail!("URL has no host: {}", url),
+    };
+    match parsed.scheme() {
+        "https" => Ok(parsed),
+        "http" if loopback => Ok(parsed),
+        _ => Err(anyhow!("{} must use https", url)),
+    }
+}
+
+/// A client that does not follow redirects, so a provider cannot send
+/// requests on to another host or downgrade them to plain `http`.
+fn agent(timeout: Duration) -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .redirects(0)
+        .user_agent("blog")
+        .build()
+}
+
+/// The `error` and `error_description` of an OAuth error reply.
+#[derive(Deserialize)]
+struct ErrorReply {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// Returns the body of a successful reply, or the provider's error.
+fn read_body(url: &Url, result: Result<ureq::Response, ureq::Error>) -> Result<Vec<u8>> {
+    let (status, response) = match result {
+        Ok(response) => (response.status(), response),
+        Err(ureq::Error::Status(status, response)) => (status, response),
+        Err(e) => return Err(anyhow!(e).context(format!("Could not reach {}", url))),
+    };
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_end(&mut body)
+        .with_context(|| format!("Bad response from {}", url))?;
+    if body.len() as u64 > MAX_BODY_BYTES {
+        bail!("Response from {} is too large", url);
+    }
+    if !(200..300).contains(&status) {
+        return match serde_json::from_slice::<ErrorReply>(&body) {
+            Ok(reply) => Err(anyhow!(
+                "{} returned {}: {}",
+                url,
+                reply.error,
+                reply.error_description.unwrap_or_default()
+            )),
+            Err(_) => Err(anyhow!("{} returned HTTP {}", url, status)),
+        };
+    }
+    Ok(body)
+}
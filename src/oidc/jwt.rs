@@ -0,0 +1,151 @@
+//! Verifying signed JSON Web Tokens (RFC 7515 compact form) against a JSON
+//! Web Key Set. Only the asymmetric algorithms identity providers use are
+//! accepted; `none` and shared-secret `HS*` tokens are refused.
+
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Header {
+    pub alg: String,
+    pub kid: Option<String>,
+}
+
+/// A public key from the provider's `jwks_uri`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+    #[serde(default, rename = "use", skip_serializing_if = "Option::is_none")]
+    pub key_use: Option<String>,
+    /// RSA modulus and exponent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    /// Elliptic curve name and point.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+impl JwkSet {
+    /// The signing key a token's header names. A header without `kid` only
+    /// matches when the set has a single usable key.
+    pub fn find(&self, header: &Header) -> Option<&Jwk> {
+        let mut candidates = self.keys.iter().filter(|key| {
+            key.key_use.as_deref().is_none_or(|key_use| key_use == "sig")
+                && key.alg.as_deref().is_none_or(|alg| alg == header.alg)
+                && key.kty == key_type(&header.alg).unwrap_or_default()
+        });
+        match &header.kid {
+            Some(kid) => candidates.find(|key| key.kid.as_ref() == Some(kid)),
+            None => {
+                let key = candidates.next()?;
+                candidates.next().is_none().then_some(key)
+            }
+        }
+    }
+}
+
+fn key_type(alg: &str) -> Option<&'static str> {
+    match alg {
+        "RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512" => Some("RSA"),
+        "ES256" | "ES384" => Some("EC"),
+        _ => None,
+    }
+}
+
+fn decode_part(part: &str) -> Result<Vec<u8>> {
+    BASE64URL
+        .decode(part)
+        .map_err(|_| anyhow!("Token is not valid base64url"))
+}
+
+/// Splits a compact token into its header, signing input, payload and
+/// signature.
+fn split(token: &str) -> Result<(Header, &str, Vec<u8>, Vec<u8>)> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        bail!("Token is not a signed JWT");
+    };
+    let signing_input = &token[..header.len() + 1 + payload.len()];
+    let header: Header = serde_json::from_slice(&decode_part(header)?)?;
+    Ok((header, signing_input, decode_part(payload)?, decode_part(signature)?))
+}
+
+/// Reads a token's header without checking anything, to pick its key.
+pub fn decode_header(token: &str) -> Result<Header> {
+    Ok(split(token)?.0)
+}
+
+/// Checks the signature of `token` with `key` and returns its claims.
+/// Claims such as `exp` and `aud` are left to the caller.
+pub fn verify<T: DeserializeOwned>(token: &str, key: &Jwk) -> Result<T> {
+    let (header, signing_input, payload, signature) = split(token)?;
+    if key_type(&header.alg).is_none() {
+        bail!("Tokens signed with {} are not accepted", header.alg);
+    }
+    if key_type(&header.alg) != Some(key.kty.as_str()) || key.alg.as_deref().is_some_and(|alg| alg != header.alg) {
+        bail!("Key does not match the token's algorithm {}", header.alg);
+    }
+    let component = |value: &Option<String>, name: &str| {
+        value
+            .as_deref()
+            .ok_or_else(|| anyhow!("Key has no {}", name))
+            .and_then(decode_part)
+    };
+
+    let valid = match key.kty.as_str() {
+        "RSA" => {
+            let params: &signature::RsaParameters = match header.alg.as_str() {
+                "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                _ => &signature::RSA_PSS_2048_8192_SHA512,
+            };
+            let (n, e) = (component(&key.n, "n")?, component(&key.e, "e")?);
+            RsaPublicKeyComponents { n: &n, e: &e }
+                .verify(params, signing_input.as_bytes(), &signature)
+                .is_ok()
+        }
+        _ => {
+            let (algorithm, curve): (&signature::EcdsaVerificationAlgorithm, _) = match header.alg.as_str() {
+                "ES256" => (&signature::ECDSA_P256_SHA256_FIXED, "P-256"),
+                _ => (&signature::ECDSA_P384_SHA384_FIXED, "P-384"),
+            };
+            if key.crv.as_deref() != Some(curve) {
+                bail!("Key is not on curve {}", curve);
+            }
+            // Uncompressed point: 0x04 || x || y
+            let mut point = vec![0x04];
+            point.extend(component(&key.x, "x")?);
+            point.extend(component(&key.y, "y")?);
+            UnparsedPublicKey::new(algorithm, point)
+                .verify(signing_input.as_bytes(), &signature)
+                .is_ok()
+        }
+    };
+    if !valid {
+        bail!("Token signature is invalid");
+    }
+    Ok(serde_json::from_slice(&payload)?)
+}
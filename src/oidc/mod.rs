@@ -0,0 +1,365 @@
+//! OpenID Connect single sign-on: the authorization code flow with PKCE
+//! against one identity provider, found through discovery, with ID tokens
+//! checked against the provider's published keys.
+
+pub mod http;
+pub mod jwt;
+
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rocket::form::FromForm;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::instrument;
+
+use crate::models::user::Role;
+use jwt::JwkSet;
+
+/// Allowed difference between our clock and the provider's.
+const CLOCK_SKEW_SECS: i64 = 60;
+/// Discovery results are fetched again after this long.
+const METADATA_TTL: Duration = Duration::from_secs(3600);
+/// The key set is fetched again for an unknown key ID at most this often.
+const KEYS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OidcConfig {
+    /// The provider's issuer URL; single sign-on is off while it is empty.
+    pub issuer: String,
+    pub client_id: String,
+    /// Sent with HTTP Basic authentication. Leave unset for a public client.
+    pub client_secret: Option<String>,
+    /// Shown on the login button, e.g. "Acme SSO".
+    pub display_name: String,
+    pub scopes: Vec<String>,
+    /// Claim that names new accounts; falls back to the email's local part.
+    pub username_claim: String,
+    /// Claim holding group or role names; a dotted path reaches into
+    /// objects, e.g. `realm_access.roles`.
+    pub role_claim: String,
+    /// Claim values and the roles they grant. When not empty, each sign-in
+    /// sets the user's role to the highest one matched, or `default_role`.
+    pub roles: BTreeMap<String, Role>,
+    pub default_role: Role,
+    /// Create an account on first sign-in when no account is linked.
+    pub auto_provision: bool,
+    /// Link a first sign-in to the account whose verified address matches
+    /// the provider's verified email claim.
+    pub link_by_email: bool,
+    /// Trust the provider to have checked a second factor, so signing in
+    /// through it skips the blog's two-factor step, even for roles that
+    /// require one. Off, accounts with two-factor authentication on are
+    /// asked for a code after the provider.
+    pub trust_provider_mfa: bool,
+    pub timeout_secs: u64,
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        OidcConfig {
+            issuer: String::new(),
+            client_id: String::new(),
+            client_secret: None,
+            display_name: "Single sign-on".to_string(),
+            scopes: vec!["openid".to_string(), "profile".to_string(), "email".to_string()],
+            username_claim: "preferred_username".to_string(),
+            role_claim: "groups".to_string(),
+            roles: BTreeMap::new(),
+            default_role: Role::User,
+            auto_provision: true,
+            link_by_email: false,
+            trust_provider_mfa: false,
+            timeout_secs: 10,
+        }
+    }
+}
+
+impl OidcConfig {
+    pub fn enabled(&self) -> bool {
+        !self.issuer.is_empty() && !self.client_id.is_empty()
+    }
+}
+
+/// The parts of the discovery document the flow needs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    pub fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+/// Claims of a verified ID token.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Claims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: Audience,
+    pub azp: Option<String>,
+    pub exp: i64,
+    pub iat: Option<i64>,
+    pub nonce: Option<String>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl Claims {
+    /// Follows a dotted path such as `realm_access.roles`.
+    fn lookup(&self, path: &str) -> Option<&Value> {
+        let mut parts = path.split('.');
+        let mut value = self.other.get(parts.next()?)?;
+        for part in parts {
+            value = value.get(part)?;
+        }
+        Some(value)
+    }
+
+    pub fn string(&self, path: &str) -> Option<&str> {
+        self.lookup(path)?.as_str().filter(|value| !value.is_empty())
+    }
+
+    /// A claim that may hold one string or a list of them.
+    pub fn strings(&self, path: &str) -> Vec<&str> {
+        match self.lookup(path) {
+            Some(Value::String(value)) => vec![value.as_str()],
+            Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn email(&self) -> Option<&str> {
+        self.string("email")
+    }
+
+    /// Some providers send `email_verified` as a string.
+    pub fn email_verified(&self) -> bool {
+        matches!(self.lookup("email_verified"), Some(Value::Bool(true)))
+            || self.string("email_verified") == Some("true")
+    }
+}
+
+/// What has to survive the trip to the provider and back; kept in a
+/// private cookie.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthRequest {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+/// The query the provider sends the browser back with: a code, or an
+/// error, plus the state from the request.
+#[derive(Debug, FromForm)]
+pub struct AuthResponse {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+fn random_string() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    BASE64URL.encode(bytes)
+}
+
+/// The S256 PKCE challenge for a verifier (RFC 7636).
+pub fn code_challenge(code_verifier: &str) -> String {
+    BASE64URL.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+struct Provider {
+    metadata: ProviderMetadata,
+    keys: JwkSet,
+    metadata_fetched: Instant,
+    keys_fetched: Instant,
+}
+
+/// The relying party. Discovery and key set results are cached and shared
+/// between requests.
+pub struct Client {
+    config: OidcConfig,
+    provider: RwLock<Option<Arc<Provider>>>,
+}
+
+impl Client {
+    pub fn new(config: OidcConfig) -> Self {
+        Client {
+            config,
+            provider: RwLock::new(None),
+        }
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled()
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.config.timeout_secs)
+    }
+
+    async fn fetch_keys(&self, metadata: &ProviderMetadata) -> Result<JwkSet> {
+        http::get_json(&metadata.jwks_uri, self.timeout()).await
+    }
+
+    async fn provider(&self) -> Result<Arc<Provider>> {
+        if let Some(provider) = self.provider.read().await.as_ref() {
+            if provider.metadata_fetched.elapsed() < METADATA_TTL {
+                return Ok(provider.clone());
+            }
+        }
+
+        if !self.enabled() {
+            bail!("Single sign-on is not configured");
+        }
+        let issuer = self.config.issuer.trim_end_matches('/');
+        let url = format!("{}/.well-known/openid-configuration", issuer);
+        let metadata: ProviderMetadata = http::get_json(&url, self.timeout()).await?;
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            bail!("Discovery document is for issuer {}, not {}", metadata.issuer, issuer);
+        }
+        let keys = self.fetch_keys(&metadata).await?;
+        let now = Instant::now();
+        let provider = Arc::new(Provider {
+            metadata,
+            keys,
+            metadata_fetched: now,
+            keys_fetched: now,
+        });
+        *self.provider.write().await = Some(provider.clone());
+        Ok(provider)
+    }
+
+    /// Starts a sign-in. Returns the provider URL to send the browser to
+    /// and the values needed to finish.
+    #[instrument(skip_all, err)]
+    pub async fn authorization_request(&self, redirect_uri: &str) -> Result<(String, AuthRequest)> {
+        let provider = self.provider().await?;
+        let request = AuthRequest {
+            state: random_string(),
+            nonce: random_string(),
+            code_verifier: random_string(),
+        };
+        let mut url = url::Url::parse(&provider.metadata.authorization_endpoint)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", &self.config.scopes.join(" "))
+            .append_pair("state", &request.state)
+            .append_pair("nonce", &request.nonce)
+            .append_pair("code_challenge", &code_challenge(&request.code_verifier))
+            .append_pair("code_challenge_method", "S256");
+        Ok((url.to_string(), request))
+    }
+
+    /// Exchanges the code the provider sent back for an ID token and
+    /// returns its verified claims.
+    #[instrument(skip_all, err)]
+    pub async fn finish(&self, code: &str, request: &AuthRequest, redirect_uri: &str) -> Result<Claims> {
+        let provider = self.provider().await?;
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", request.code_verifier.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+        ];
+        let basic_auth = self
+            .config
+            .client_secret
+            .as_deref()
+            .map(|secret| (self.config.client_id.as_str(), secret));
+        let tokens: TokenResponse =
+            http::post_form(&provider.metadata.token_endpoint, &form, basic_auth, self.timeout()).await?;
+        let id_token = tokens
+            .id_token
+            .ok_or_else(|| anyhow!("The provider did not return an ID token"))?;
+        self.verify_id_token(&id_token, &request.nonce).await
+    }
+
+    /// Checks an ID token's signature, issuer, audience, expiry and nonce.
+    pub async fn verify_id_token(&self, id_token: &str, nonce: &str) -> Result<Claims> {
+        let header = jwt::decode_header(id_token)?;
+        let mut provider = self.provider().await?;
+        if provider.keys.find(&header).is_none() && provider.keys_fetched.elapsed() >= KEYS_MIN_REFRESH {
+            // The provider may have rotated its keys
+            let keys = self.fetch_keys(&provider.metadata).await?;
+            provider = Arc::new(Provider {
+                metadata: provider.metadata.clone(),
+                keys,
+                metadata_fetched: provider.metadata_fetched,
+                keys_fetched: Instant::now(),
+            });
+            *self.provider.write().await = Some(provider.clone());
+        }
+        let key = provider
+            .keys
+            .find(&header)
+            .ok_or_else(|| anyhow!("ID token is signed with an unknown key"))?;
+        let claims: Claims = jwt::verify(id_token, key)?;
+        validate(
+            &claims,
+            &provider.metadata.issuer,
+            &self.config.client_id,
+            nonce,
+            chrono::Utc::now().timestamp(),
+        )?;
+        Ok(claims)
+    }
+}
+
+/// The ID token checks of OpenID Connect Core section 3.1.3.7 that do not
+/// involve the signature.
+pub fn validate(claims: &Claims, issuer: &str, client_id: &str, nonce: &str, now: i64) -> Result<()> {
+    if claims.iss != issuer {
+        bail!("ID token is from issuer {}, not {}", claims.iss, issuer);
+    }
+    if !claims.aud.contains(client_id) || claims.azp.as_deref().is_some_and(|azp| azp != client_id) {
+        bail!("ID token is not meant for this client");
+    }
+    if claims.exp + CLOCK_SKEW_SECS <= now {
+        bail!("ID token has expired");
+    }
+    if claims.iat.is_some_and(|iat| iat - CLOCK_SKEW_SECS > now) {
+        bail!("ID token is issued in the future");
+    }
+    if claims.nonce.as_deref() != Some(nonce) {
+        bail!("ID token nonce does not match");
+    }
+    Ok(())
+}
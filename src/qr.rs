@@ -0,0 +1,25 @@
+//! QR codes for two-factor enrolment links, encoded by the `qrcode` crate
+//! at error correction level M. Codes are drawn as SVG so pages need no
+//! image library or script.
+
+use anyhow::Result;
+use qrcode::{Color, EcLevel, QrCode};
+
+/// Light modules around the symbol, as the standard asks.
+const QUIET_ZONE: usize = 4;
+
+/// An SVG of `data` drawing one unit per module, to be sized by the page.
+pub fn svg(data: &[u8]) -> Result<String> {
+    let code = QrCode::with_error_correction_level(data, EcLevel::M)?;
+    let size = code.width();
+    let extent = size + QUIET_ZONE * 2;
+    let mut path = String::new();
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            path.push_str(&format!("M{},{}h1v1h-1z", i % size + QUIET_ZONE, i / size + QUIET_ZONE));
+        }
+    }
+    Ok(format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {extent} {extent}" shape-rendering="crispEdges"><rect width="{extent}" height="{extent}" fill="#fff"/><path d="{path}" fill="#000"/></svg>"##
+    ))
+}
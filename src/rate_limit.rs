@@ -0,0 +1,412 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rocket::http::Method;
+use rusqlite::{params, OptionalExtension, TransactionBehavior};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use crate::services::login_service::address_key;
+
+/// Buckets that have refilled completely are dropped every this many takes.
+const SWEEP_EVERY: u64 = 1000;
+
+/// Where bucket levels are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// In this process only.
+    Memory,
+    /// In a SQLite file, shared by every process that points at it.
+    Sqlite,
+}
+
+/// A token bucket: `burst` requests at once, refilled at `per_minute`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Quota {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+/// Requests matched by any of `routes` share one bucket per client address
+/// and one per signed-in user. Routes are written `METHOD /path`, or just
+/// `/path` for any method; `*` matches one path segment and a trailing `**`
+/// the rest of the path.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RouteGroup {
+    pub routes: Vec<String>,
+    pub per_ip: Option<Quota>,
+    pub per_user: Option<Quota>,
+}
+
+/// Limits on request rates, from the `rate_limit` table of `Rocket.toml`. A
+/// request counts against every group it matches.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub store: StoreKind,
+    /// The database file for the `sqlite` store.
+    pub path: PathBuf,
+    /// Client addresses that are never limited, e.g. a monitoring probe.
+    pub exempt_ips: Vec<IpAddr>,
+    pub groups: BTreeMap<String, RouteGroup>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let group = |routes: &[&str], per_ip, per_user| RouteGroup {
+            routes: routes.iter().map(|route| route.to_string()).collect(),
+            per_ip: Some(per_ip),
+            per_user,
+        };
+        let groups = BTreeMap::from([
+            (
+                "site".to_string(),
+                group(&["/**"], Quota { burst: 120, per_minute: 120 }, None),
+            ),
+            (
+                "accounts".to_string(),
+                group(
+                    &["POST /register", "POST /login", "POST /forgot-password", "POST /reset-password"],
+                    Quota { burst: 10, per_minute: 5 },
+                    None,
+                ),
+            ),
+            (
+                "comments".to_string(),
+                group(
+                    &["POST /posts/*/comments"],
+                    Quota { burst: 10, per_minute: 4 },
+                    Some(Quota { burst: 5, per_minute: 2 }),
+                ),
+            ),
+            (
+                "reactions".to_string(),
+                group(
+                    &["/posts/*/reactions/*", "/posts/*/comments/*/reactions/*"],
+                    Quota { burst: 60, per_minute: 30 },
+                    Some(Quota { burst: 30, per_minute: 20 }),
+                ),
+            ),
+        ]);
+        RateLimitConfig {
+            enabled: true,
+            store: StoreKind::Memory,
+            path: PathBuf::from("rate_limits.db"),
+            exempt_ips: Vec::new(),
+            groups,
+        }
+    }
+}
+
+/// A bucket's level and when it was last taken from, in Unix seconds.
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    tokens: f64,
+    updated_at: f64,
+}
+
+impl Quota {
+    fn refill_rate(&self) -> f64 {
+        self.per_minute as f64 / 60.0
+    }
+
+    /// Takes a token from a bucket last left at `level`. Returns the new
+    /// level and whether there was a token to take.
+    fn take(&self, level: Option<Level>, now: f64) -> (Level, bool) {
+        let burst = self.burst as f64;
+        let tokens = match level {
+            Some(level) => (level.tokens + (now - level.updated_at).max(0.0) * self.refill_rate()).min(burst),
+            None => burst,
+        };
+        if tokens >= 1.0 {
+            (Level { tokens: tokens - 1.0, updated_at: now }, true)
+        } else {
+            (Level { tokens, updated_at: now }, false)
+        }
+    }
+
+    /// When a bucket at `level` will be full again.
+    fn full_at(&self, level: Level) -> f64 {
+        match self.refill_rate() {
+            rate if rate > 0.0 => level.updated_at + (self.burst as f64 - level.tokens).max(0.0) / rate,
+            _ => f64::INFINITY,
+        }
+    }
+
+    fn decision(&self, group: &str, level: Level, allowed: bool) -> Decision {
+        let rate = self.refill_rate();
+        let wait = |tokens: f64| match rate {
+            rate if rate > 0.0 => (tokens.max(0.0) / rate).ceil() as u64,
+            _ => u64::MAX,
+        };
+        Decision {
+            group: group.to_string(),
+            allowed,
+            limit: self.burst,
+            remaining: level.tokens.floor() as u32,
+            reset_secs: wait(self.burst as f64 - level.tokens),
+            retry_after_secs: if allowed { 0 } else { wait(1.0 - level.tokens).max(1) },
+        }
+    }
+}
+
+/// Keeps bucket levels between requests.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Takes a token from the bucket at `key`, refilling it first. Returns
+    /// its level afterwards and whether a token was taken.
+    async fn take(&self, key: &str, quota: Quota, now: f64) -> Result<(f64, bool)>;
+}
+
+struct MemoryEntry {
+    level: Level,
+    full_at: f64,
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: Mutex<(HashMap<String, MemoryEntry>, u64)>,
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, quota: Quota, now: f64) -> Result<(f64, bool)> {
+        let mut guard = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let (buckets, takes) = &mut *guard;
+        *takes += 1;
+        if takes.is_multiple_of(SWEEP_EVERY) {
+            buckets.retain(|_, entry| entry.full_at > now);
+        }
+        let (level, allowed) = quota.take(buckets.get(key).map(|entry| entry.level), now);
+        buckets.insert(key.to_string(), MemoryEntry { level, full_at: quota.full_at(level) });
+        Ok((level.tokens, allowed))
+    }
+}
+
+const SQLITE_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS rate_limits (
+    key TEXT PRIMARY KEY,
+    tokens REAL NOT NULL,
+    updated_at REAL NOT NULL,
+    full_at REAL NOT NULL
+)";
+
+/// Bucket levels in a SQLite file, for several server processes on one host
+/// to share.
+pub struct SqliteStore {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<SqliteStore> {
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA synchronous=OFF; PRAGMA busy_timeout=1000;")
+        });
+        let pool = Pool::builder()
+            .max_size(4)
+            .build(manager)
+            .with_context(|| format!("Failed to open rate limit store {}", path.display()))?;
+        pool.get()?.execute(SQLITE_SCHEMA, [])?;
+        Ok(SqliteStore { pool })
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for SqliteStore {
+    async fn take(&self, key: &str, quota: Quota, now: f64) -> Result<(f64, bool)> {
+        let pool = self.pool.clone();
+        let key = key.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get()?;
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let level = tx
+                .query_row(
+                    "SELECT tokens, updated_at FROM rate_limits WHERE key = ?1",
+                    params![key],
+                    |row| Ok(Level { tokens: row.get(0)?, updated_at: row.get(1)? }),
+                )
+                .optional()?;
+            let (level, allowed) = quota.take(level, now);
+            tx.execute(
+                "INSERT INTO rate_limits (key, tokens, updated_at, full_at) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(key) DO UPDATE SET tokens = ?2, updated_at = ?3, full_at = ?4",
+                params![key, level.tokens, level.updated_at, quota.full_at(level)],
+            )?;
+            // Sweeps about one take in SWEEP_EVERY, without a counter to
+            // share between processes
+            if rand::random::<u64>().is_multiple_of(SWEEP_EVERY) {
+                tx.execute("DELETE FROM rate_limits WHERE full_at <= ?1", params![now])?;
+            }
+            tx.commit()?;
+            Ok((level.tokens, allowed))
+        })
+        .await?
+    }
+}
+
+/// The outcome for the bucket that mattered most to a request: the one that
+/// refused it, or else the one closest to running out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    pub group: String,
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset_secs: u64,
+    /// Seconds until the next request can pass, when refused.
+    pub retry_after_secs: u64,
+}
+
+/// A `METHOD /path` pattern from a route group.
+#[derive(Debug, Clone)]
+struct RoutePattern {
+    method: Option<Method>,
+    segments: Vec<String>,
+}
+
+impl FromStr for RoutePattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (method, path) = match s.trim().split_once(' ') {
+            Some((method, path)) => {
+                let method = Method::from_str(&method.to_ascii_uppercase())
+                    .map_err(|_| anyhow::anyhow!("Unknown method in route {:?}", s))?;
+                (Some(method), path.trim())
+            }
+            None => (None, s.trim()),
+        };
+        if !path.starts_with('/') {
+            bail!("Route {:?} must start with /", s);
+        }
+        let segments: Vec<String> = path.split('/').filter(|segment| !segment.is_empty()).map(String::from).collect();
+        if segments.iter().rev().skip(1).any(|segment| segment == "**") {
+            bail!("** must come last in route {:?}", s);
+        }
+        Ok(RoutePattern { method, segments })
+    }
+}
+
+impl RoutePattern {
+    fn matches(&self, method: Method, path: &[&str]) -> bool {
+        if self.method.is_some_and(|expected| expected != method) {
+            return false;
+        }
+        let mut path = path.iter();
+        for segment in &self.segments {
+            match (segment.as_str(), path.next()) {
+                ("**", _) => return true,
+                (_, None) => return false,
+                ("*", Some(_)) => {}
+                (expected, Some(actual)) if expected == *actual => {}
+                _ => return false,
+            }
+        }
+        path.next().is_none()
+    }
+}
+
+struct Group {
+    name: String,
+    patterns: Vec<RoutePattern>,
+    per_ip: Option<Quota>,
+    per_user: Option<Quota>,
+}
+
+/// Applies the configured groups' buckets to requests.
+pub struct RateLimiter {
+    groups: Vec<Group>,
+    exempt_ips: Vec<IpAddr>,
+    store: Box<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Result<RateLimiter> {
+        let store: Box<dyn RateLimitStore> = match config.store {
+            StoreKind::Memory => Box::new(MemoryStore::default()),
+            StoreKind::Sqlite => Box::new(SqliteStore::open(&config.path)?),
+        };
+        RateLimiter::with_store(config, store)
+    }
+
+    pub fn with_store(config: &RateLimitConfig, store: Box<dyn RateLimitStore>) -> Result<RateLimiter> {
+        let mut groups = Vec::new();
+        for (name, group) in &config.groups {
+            let patterns = group
+                .routes
+                .iter()
+                .map(|route| route.parse())
+                .collect::<Result<Vec<RoutePattern>>>()
+                .with_context(|| format!("Invalid rate limit group {}", name))?;
+            for quota in group.per_ip.iter().chain(&group.per_user) {
+                if quota.burst == 0 {
+                    bail!("Rate limit group {} needs a burst of at least 1", name);
+                }
+            }
+            groups.push(Group { name: name.clone(), patterns, per_ip: group.per_ip, per_user: group.per_user });
+        }
+        Ok(RateLimiter { groups, exempt_ips: config.exempt_ips.clone(), store })
+    }
+
+    /// Counts a request against every group it matches. Returns `None` when
+    /// no bucket applies to it.
+    pub async fn check(
+        &self,
+        method: Method,
+        path: &str,
+        ip: Option<IpAddr>,
+        user_id: Option<Uuid>,
+    ) -> Result<Option<Decision>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+        self.check_at(method, path, ip, user_id, now).await
+    }
+
+    /// `check` as of `now`, in Unix seconds.
+    pub async fn check_at(
+        &self,
+        method: Method,
+        path: &str,
+        ip: Option<IpAddr>,
+        user_id: Option<Uuid>,
+        now: f64,
+    ) -> Result<Option<Decision>> {
+        if ip.is_some_and(|ip| self.exempt_ips.contains(&ip)) {
+            return Ok(None);
+        }
+        let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+
+        let mut decision: Option<Decision> = None;
+        for group in &self.groups {
+            if !group.patterns.iter().any(|pattern| pattern.matches(method, &segments)) {
+                continue;
+            }
+            let buckets = [
+                group.per_ip.zip(ip.map(|ip| format!("{}:{}", group.name, address_key(ip)))),
+                group.per_user.zip(user_id.map(|id| format!("{}:user:{}", group.name, id))),
+            ];
+            for (quota, key) in buckets.into_iter().flatten() {
+                let (tokens, allowed) = self.store.take(&key, quota, now).await?;
+                let candidate = quota.decision(&group.name, Level { tokens, updated_at: now }, allowed);
+                let replace = match &decision {
+                    None => true,
+                    Some(current) if current.allowed != candidate.allowed => !candidate.allowed,
+                    Some(current) if !current.allowed => candidate.retry_after_secs > current.retry_after_secs,
+                    Some(current) => candidate.remaining < current.remaining,
+                };
+                if replace {
+                    decision = Some(candidate);
+                }
+            }
+        }
+        Ok(decision)
+    }
+}
@@ -0,0 +1,301 @@
+pub mod postgres;
+pub mod schema;
+pub mod sqlite;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::models::bookmark::Bookmark;
+use crate::models::comment::Comment;
+use crate::models::identity::UserIdentity;
+use crate::models::login_attempt::LoginAttempt;
+use crate::models::post::{Post, PostSort, PostSource};
+use crate::models::profile::Profile;
+use crate::models::reaction::{Reaction, ReactionTarget, Reactions, UserReactions};
+use crate::models::series::Series;
+use crate::models::tag::Tag;
+use crate::models::token::{TokenPurpose, UserToken};
+use crate::models::two_factor::TwoFactor;
+use crate::models::user::Role;
+use crate::models::User;
+
+pub use schema::SCHEMA_VERSION;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SiteCounts {
+    pub users: i64,
+    pub posts: i64,
+    pub comments: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStatus {
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub max_size: u32,
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn insert_user(&self, user: &User) -> Result<()>;
+    async fn find_user_by_id(&self, id: Uuid) -> Result<Option<User>>;
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>>;
+    /// `email` must already be normalised.
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>>;
+    async fn list_users(&self) -> Result<Vec<User>>;
+    async fn update_username(&self, id: Uuid, username: &str, updated_at: &str) -> Result<bool>;
+    async fn update_password_hash(&self, id: Uuid, password_hash: &str, updated_at: &str) -> Result<bool>;
+    async fn set_user_role(&self, id: Uuid, role: Role, updated_at: &str) -> Result<bool>;
+    async fn set_user_disabled(&self, id: Uuid, disabled: bool, updated_at: &str) -> Result<bool>;
+    async fn set_user_email(&self, id: Uuid, email: Option<&str>, verified: bool, updated_at: &str) -> Result<bool>;
+    /// Also takes the user's reactions out of the counts they were in.
+    async fn delete_user(&self, id: Uuid) -> Result<bool>;
+}
+
+/// `Post::author` is filled in by reads and ignored by `insert_post`.
+#[async_trait]
+pub trait PostRepository: Send + Sync {
+    async fn insert_post(&self, post: &Post) -> Result<()>;
+    async fn list_posts(&self, sort: PostSort) -> Result<Vec<Post>>;
+    async fn list_posts_by_author(&self, author_id: Uuid) -> Result<Vec<Post>>;
+    async fn find_post(&self, id: Uuid) -> Result<Option<Post>>;
+    /// The posts with these ids that exist, in no particular order.
+    async fn list_posts_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Post>>;
+    async fn find_post_by_slug(&self, slug: &str) -> Result<Option<Post>>;
+    async fn update_post(&self, id: Uuid, title: &str, content: &str, updated_at: &str) -> Result<bool>;
+    /// Sets the fields a post's editor does not touch: its slug and dates.
+    async fn update_post_metadata(
+        &self,
+        id: Uuid,
+        slug: Option<&str>,
+        created_at: &str,
+        updated_at: &str,
+    ) -> Result<bool>;
+    /// Sets the search engine overrides, leaving `updated_at` alone.
+    async fn update_post_seo(&self, id: Uuid, seo_title: Option<&str>, seo_description: Option<&str>) -> Result<bool>;
+    /// Sets the author's excerpt, leaving `updated_at` alone.
+    async fn update_post_excerpt(&self, id: Uuid, excerpt: Option<&str>) -> Result<bool>;
+    async fn delete_post(&self, id: Uuid) -> Result<bool>;
+}
+
+/// `Comment::author` is filled in by reads and ignored by `insert_comment`.
+#[async_trait]
+pub trait CommentRepository: Send + Sync {
+    async fn insert_comment(&self, comment: &Comment) -> Result<()>;
+    async fn list_comments(&self) -> Result<Vec<Comment>>;
+    async fn list_post_comments(&self, post_id: Uuid) -> Result<Vec<Comment>>;
+    /// The author's most recent comments, newest first.
+    async fn list_comments_by_author(&self, author_id: Uuid, limit: i64) -> Result<Vec<Comment>>;
+    async fn find_comment(&self, id: Uuid) -> Result<Option<Comment>>;
+    async fn update_comment(&self, id: Uuid, content: &str, updated_at: &str) -> Result<bool>;
+    async fn delete_comment(&self, id: Uuid) -> Result<bool>;
+}
+
+#[async_trait]
+pub trait TagRepository: Send + Sync {
+    async fn insert_tag(&self, tag: &Tag) -> Result<()>;
+    async fn find_tag_by_slug(&self, slug: &str) -> Result<Option<Tag>>;
+    async fn list_post_tags(&self, post_id: Uuid) -> Result<Vec<Tag>>;
+    /// Attaches a tag to a post; attaching it twice is a no-op.
+    async fn add_post_tag(&self, post_id: Uuid, tag_id: Uuid) -> Result<()>;
+    async fn remove_post_tags(&self, post_id: Uuid) -> Result<()>;
+}
+
+/// Series and the order of their posts. Rows go away with their author, and
+/// a post's place in a series with the post.
+#[async_trait]
+pub trait SeriesRepository: Send + Sync {
+    async fn insert_series(&self, series: &Series) -> Result<()>;
+    async fn find_series(&self, id: Uuid) -> Result<Option<Series>>;
+    async fn find_series_by_slug(&self, slug: &str) -> Result<Option<Series>>;
+    async fn list_series(&self) -> Result<Vec<Series>>;
+    async fn list_series_by_author(&self, author_id: Uuid) -> Result<Vec<Series>>;
+    async fn find_post_series(&self, post_id: Uuid) -> Result<Option<Series>>;
+    /// The series' posts in order.
+    async fn list_series_posts(&self, series_id: Uuid) -> Result<Vec<Post>>;
+    /// Makes `post_ids` the series' posts in this order, moving any that
+    /// were in another series, and sets the series' `updated_at`.
+    async fn set_series_posts(&self, series_id: Uuid, post_ids: &[Uuid], updated_at: &str) -> Result<()>;
+    async fn remove_series_post(&self, post_id: Uuid) -> Result<bool>;
+}
+
+/// Reactions to posts and comments, at most one of each kind per user. The
+/// post or comment keeps their counts, updated in the same transaction.
+/// Rows go away with their target or user.
+#[async_trait]
+pub trait ReactionRepository: Send + Sync {
+    /// Adds the user's reaction of this kind, or takes it back, and returns
+    /// the target's counts afterwards. Returns `None` if there is no such
+    /// target.
+    async fn set_reaction(
+        &self,
+        target: ReactionTarget,
+        target_id: Uuid,
+        user_id: Uuid,
+        kind: &str,
+        reacted: bool,
+        now: &str,
+    ) -> Result<Option<Reactions>>;
+    async fn list_user_reactions(&self, post_id: Uuid, user_id: Uuid) -> Result<UserReactions>;
+    /// Every reaction to a post, or to a comment, oldest first.
+    async fn list_reactions(&self, target: ReactionTarget) -> Result<Vec<Reaction>>;
+}
+
+/// Posts users saved for later. Rows go away with their user or post.
+#[async_trait]
+pub trait BookmarkRepository: Send + Sync {
+    /// Inserts the bookmark, or updates the folder, note and `updated_at`
+    /// of the user's existing one for the post.
+    async fn upsert_bookmark(&self, bookmark: &Bookmark) -> Result<()>;
+    async fn find_bookmark(&self, user_id: Uuid, post_id: Uuid) -> Result<Option<Bookmark>>;
+    /// The user's bookmarks, newest first.
+    async fn list_bookmarks(&self, user_id: Uuid) -> Result<Vec<Bookmark>>;
+    /// The posts the user bookmarked, in the order of `list_bookmarks`.
+    async fn list_bookmarked_posts(&self, user_id: Uuid) -> Result<Vec<Post>>;
+    async fn delete_bookmark(&self, user_id: Uuid, post_id: Uuid) -> Result<bool>;
+}
+
+#[async_trait]
+pub trait ProfileRepository: Send + Sync {
+    async fn find_profile(&self, user_id: Uuid) -> Result<Option<Profile>>;
+    /// Inserts or replaces the profile of `profile.user_id`.
+    async fn upsert_profile(&self, profile: &Profile) -> Result<()>;
+}
+
+/// Source files of posts managed by Markdown synchronisation. Rows go away
+/// with their post.
+#[async_trait]
+pub trait PostSourceRepository: Send + Sync {
+    /// The sources recorded under `root`, by path.
+    async fn list_post_sources(&self, root: &str) -> Result<Vec<PostSource>>;
+    /// Inserts or replaces the source recorded for `source.post_id`.
+    async fn upsert_post_source(&self, source: &PostSource) -> Result<()>;
+}
+
+/// Posts created by importing another platform's export, keyed by
+/// `<platform>:<id in the export>`. Rows go away with their post.
+#[async_trait]
+pub trait PostOriginRepository: Send + Sync {
+    async fn find_post_origin(&self, origin: &str) -> Result<Option<Uuid>>;
+    async fn insert_post_origin(&self, origin: &str, post_id: Uuid) -> Result<()>;
+}
+
+/// Single-use tokens mailed to users. Rows go away with their user.
+#[async_trait]
+pub trait UserTokenRepository: Send + Sync {
+    async fn insert_user_token(&self, token: &UserToken) -> Result<()>;
+    /// Deletes the token with this hash and purpose and returns it, expired
+    /// or not, so it can be used at most once.
+    async fn take_user_token(&self, token_hash: &str, purpose: TokenPurpose) -> Result<Option<UserToken>>;
+    async fn delete_user_tokens(&self, user_id: Uuid, purpose: TokenPurpose) -> Result<()>;
+}
+
+/// Two-factor settings and hashed recovery codes. Rows go away with their
+/// user.
+#[async_trait]
+pub trait TwoFactorRepository: Send + Sync {
+    async fn find_two_factor(&self, user_id: Uuid) -> Result<Option<TwoFactor>>;
+    /// Inserts or replaces the settings of `two_factor.user_id`.
+    async fn upsert_two_factor(&self, two_factor: &TwoFactor) -> Result<()>;
+    async fn delete_two_factor(&self, user_id: Uuid) -> Result<bool>;
+    /// Records `step` as used unless it or a later one already was, so
+    /// concurrent requests cannot both accept a code. Returns false for a
+    /// replayed code.
+    async fn use_two_factor_step(&self, user_id: Uuid, step: i64, updated_at: &str) -> Result<bool>;
+    /// Counts a code attempt against an enabled, unlocked enrolment before
+    /// the code is checked, so parallel guesses cannot slip past the limit.
+    /// Returns the attempts since the last reset, or `None` while locked.
+    async fn begin_two_factor_attempt(&self, user_id: Uuid, now: &str) -> Result<Option<i32>>;
+    /// Resets the attempt count and locks code entry until `locked_until`,
+    /// or unlocks it.
+    async fn set_two_factor_lock(&self, user_id: Uuid, locked_until: Option<&str>, updated_at: &str) -> Result<()>;
+    /// Replaces all of the user's recovery codes.
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> Result<()>;
+    /// Deletes the code with this hash. Returns false if there was none.
+    async fn take_recovery_code(&self, user_id: Uuid, code_hash: &str) -> Result<bool>;
+    async fn count_recovery_codes(&self, user_id: Uuid) -> Result<i64>;
+}
+
+/// Links to accounts at an external identity provider. Rows go away with
+/// their user.
+#[async_trait]
+pub trait IdentityRepository: Send + Sync {
+    async fn find_identity(&self, issuer: &str, subject: &str) -> Result<Option<UserIdentity>>;
+    async fn list_user_identities(&self, user_id: Uuid) -> Result<Vec<UserIdentity>>;
+    /// Returns false when the identity is already linked.
+    async fn insert_identity(&self, identity: &UserIdentity) -> Result<bool>;
+    async fn touch_identity(&self, issuer: &str, subject: &str, email: Option<&str>, last_login_at: &str) -> Result<()>;
+}
+
+/// Counters of recent failed logins, keyed by account or client address,
+/// and a record of the attempts on each account. Attempt rows go away with
+/// their user.
+#[async_trait]
+pub trait LoginAttemptRepository: Send + Sync {
+    /// Counts an attempt against `key` before the password is checked, so
+    /// parallel guesses cannot slip past the limits. A count last raised
+    /// before `forget_before` starts again from one. Returns the count, or
+    /// `None` while `key` is locked.
+    async fn begin_login_attempt(&self, key: &str, now: &str, forget_before: &str) -> Result<Option<i32>>;
+    /// Takes back an attempt counted by `begin_login_attempt`.
+    async fn forgive_login_attempt(&self, key: &str) -> Result<()>;
+    async fn set_login_lock(&self, key: &str, locked_until: &str) -> Result<()>;
+    /// When the last lock on `key` ends, which may be in the past.
+    async fn find_login_lock(&self, key: &str) -> Result<Option<String>>;
+    /// Forgets the count and any lock. Returns false if there was neither.
+    async fn clear_login_failures(&self, key: &str) -> Result<bool>;
+    async fn insert_login_attempt(&self, attempt: &LoginAttempt) -> Result<()>;
+    /// The user's most recent attempts, newest first.
+    async fn list_login_attempts(&self, user_id: Uuid, limit: i64) -> Result<Vec<LoginAttempt>>;
+    /// Deletes attempts made, and counts last raised, before `before`,
+    /// keeping counts whose lock has not ended.
+    async fn prune_login_records(&self, before: &str) -> Result<()>;
+}
+
+/// Permanent redirects from paths of a previous site, keyed by the
+/// normalised old path.
+#[async_trait]
+pub trait RedirectRepository: Send + Sync {
+    /// Returns false when `from_path` already has a redirect.
+    async fn insert_redirect(&self, from_path: &str, to_path: &str, created_at: &str) -> Result<bool>;
+    async fn find_redirect(&self, from_path: &str) -> Result<Option<String>>;
+}
+
+/// A complete storage backend, plus the database-level operations used by
+/// health checks and metrics.
+#[async_trait]
+pub trait Repository:
+    UserRepository
+    + PostRepository
+    + CommentRepository
+    + TagRepository
+    + SeriesRepository
+    + ReactionRepository
+    + BookmarkRepository
+    + RedirectRepository
+    + PostSourceRepository
+    + PostOriginRepository
+    + ProfileRepository
+    + UserTokenRepository
+    + TwoFactorRepository
+    + IdentityRepository
+    + LoginAttemptRepository
+{
+    fn backend(&self) -> &'static str;
+    fn pool_status(&self) -> PoolStatus;
+    async fn ping(&self) -> Result<()>;
+    async fn schema_version(&self) -> Result<i32>;
+    async fn counts(&self) -> Result<SiteCounts>;
+    /// Rebuilds indexes and refreshes planner statistics.
+    async fn reindex(&self) -> Result<()>;
+    /// Reclaims free space.
+    async fn vacuum(&self) -> Result<()>;
+    /// Writes a consistent copy of the live database to `dest` without
+    /// blocking concurrent readers or writers.
+    async fn backup(&self, dest: &Path) -> Result<()>;
+    /// Replaces the live database with the contents of the database file at
+    /// `src`, then applies any migrations it is missing.
+    async fn restore(&self, src: &Path) -> Result<()>;
+}
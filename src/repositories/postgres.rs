@@ -0,0 +1,1367 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use sqlx::postgres::{PgConnectOptions, PgConnection, PgPool, PgPoolOptions, PgRow};
+use sqlx::Row;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::models::bookmark::Bookmark;
+use crate::models::comment::Comment;
+use crate::models::identity::UserIdentity;
+use crate::models::login_attempt::LoginAttempt;
+use crate::models::post::{Post, PostSort, PostSource};
+use crate::models::profile::Profile;
+use crate::models::reaction::{Reaction, ReactionTarget, Reactions, UserReactions};
+use crate::models::series::Series;
+use crate::models::tag::Tag;
+use crate::models::token::{TokenPurpose, UserToken};
+use crate::models::two_factor::TwoFactor;
+use crate::models::user::Role;
+use crate::models::User;
+use crate::repositories::schema::{post_order, reaction_tables, BASE_SCHEMA, MIGRATIONS, SCHEMA_VERSION};
+use crate::repositories::{
+    BookmarkRepository, CommentRepository, IdentityRepository, LoginAttemptRepository, PoolStatus, PostRepository,
+    PostOriginRepository, PostSourceRepository, ProfileRepository, ReactionRepository, RedirectRepository, Repository, SeriesRepository,
+    SiteCounts, TagRepository, TwoFactorRepository, UserRepository, UserTokenRepository,
+};
+use crate::services::db::DbConfig;
+
+/// Arbitrary key for the advisory lock that serialises migrations when
+/// several instances start at once.
+const MIGRATION_LOCK: i64 = 0x626c_6f67;
+
+/// Awaits a query and reports its latency the same way the SQLite profile
+/// hook does.
+async fn timed<T>(sql: &str, query: impl Future<Output = sqlx::Result<T>>) -> Result<T> {
+    let started = Instant::now();
+    let result = query.await;
+    crate::telemetry::log_sql(sql, started.elapsed());
+    Ok(result?)
+}
+
+fn parse_uuid(row: &PgRow, column: &str) -> Result<Uuid> {
+    Ok(Uuid::parse_str(row.try_get::<&str, _>(column)?)?)
+}
+
+/// Reads the counts by kind, stored as a JSON object, and their total.
+fn row_to_reactions(row: &PgRow) -> Result<Reactions> {
+    Ok(Reactions {
+        counts: serde_json::from_str(row.try_get("reactions")?)?,
+        total: row.try_get::<i32, _>("reaction_count")?.into(),
+    })
+}
+
+/// Counts the reactions to a post or comment again and stores the counts
+/// on it, which stays locked until the transaction ends. Returns `None` if
+/// there is no such target.
+async fn recount_reactions(conn: &mut PgConnection, target: ReactionTarget, id: &str) -> Result<Option<Reactions>> {
+    let (table, column, targets) = reaction_tables(target);
+    let lock = format!("SELECT id FROM {} WHERE id = $1 FOR UPDATE", targets);
+    if timed(&lock, sqlx::query(&lock).bind(id).fetch_optional(&mut *conn)).await?.is_none() {
+        return Ok(None);
+    }
+    let count = format!("SELECT kind, COUNT(*) AS n FROM {} WHERE {} = $1 GROUP BY kind", table, column);
+    let rows = timed(&count, sqlx::query(&count).bind(id).fetch_all(&mut *conn)).await?;
+    let counts = rows
+        .iter()
+        .map(|row| Ok((row.try_get("kind")?, row.try_get("n")?)))
+        .collect::<Result<BTreeMap<String, i64>>>()?;
+    let reactions = Reactions { total: counts.values().sum(), counts };
+    let update = format!("UPDATE {} SET reactions = $1, reaction_count = $2 WHERE id = $3", targets);
+    timed(&update, sqlx::query(&update)
+        .bind(serde_json::to_string(&reactions.counts)?)
+        .bind(reactions.total as i32)
+        .bind(id)
+        .execute(&mut *conn)).await?;
+    Ok(Some(reactions))
+}
+
+const SELECT_USERS: &str =
+    "SELECT id, username, password_hash, role, disabled, created_at, updated_at, email, email_verified FROM users";
+
+fn row_to_user(row: &PgRow) -> Result<User> {
+    Ok(User {
+        id: parse_uuid(row, "id")?,
+        username: row.try_get("username")?,
+        password_hash: row.try_get("password_hash")?,
+        role: row.try_get::<&str, _>("role")?.parse()?,
+        disabled: row.try_get::<i32, _>("disabled")? != 0,
+        email: row.try_get("email")?,
+        email_verified: row.try_get::<i32, _>("email_verified")? != 0,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn row_to_user_token(row: &PgRow) -> Result<UserToken> {
+    Ok(UserToken {
+        token_hash: row.try_get("token_hash")?,
+        user_id: parse_uuid(row, "user_id")?,
+        purpose: row.try_get::<&str, _>("purpose")?.parse()?,
+        email: row.try_get("email")?,
+        expires_at: row.try_get("expires_at")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+const SELECT_POSTS: &str =
+    "SELECT p.id, p.title, p.slug, p.content, p.author_id, u.username AS author, p.created_at, p.updated_at,
+            p.seo_title, p.seo_description, p.excerpt, p.reactions, p.reaction_count
+     FROM posts p
+     LEFT JOIN users u ON u.id = p.author_id";
+
+fn row_to_post(row: &PgRow) -> Result<Post> {
+    Ok(Post {
+        id: parse_uuid(row, "id")?,
+        title: row.try_get("title")?,
+        slug: row.try_get("slug")?,
+        content: row.try_get("content")?,
+        author_id: parse_uuid(row, "author_id")?,
+        author: row.try_get("author")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+        seo_title: row.try_get("seo_title")?,
+        seo_description: row.try_get("seo_description")?,
+        excerpt: row.try_get("excerpt")?,
+        reactions: row_to_reactions(row)?,
+    })
+}
+
+const SELECT_COMMENTS: &str =
+    "SELECT c.id, c.content, c.post_id, c.parent_id, c.author_id, u.username AS author, c.created_at, c.updated_at,
+            c.reactions, c.reaction_count, c.guest_name
+     FROM comments c
+     JOIN users u ON c.author_id = u.id";
+
+fn row_to_comment(row: &PgRow) -> Result<Comment> {
+    Ok(Comment {
+        id: parse_uuid(row, "id")?,
+        content: row.try_get("content")?,
+        post_id: parse_uuid(row, "post_id")?,
+        parent_id: row
+            .try_get::<Option<&str>, _>("parent_id")?
+            .map(Uuid::parse_str)
+            .transpose()?,
+        author_id: parse_uuid(row, "author_id")?,
+        author: row.try_get("author")?,
+        guest_name: row.try_get("guest_name")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+        reactions: row_to_reactions(row)?,
+    })
+}
+
+const SELECT_TAGS: &str = "SELECT t.id, t.name, t.slug FROM tags t";
+
+fn row_to_tag(row: &PgRow) -> Result<Tag> {
+    Ok(Tag {
+        id: parse_uuid(row, "id")?,
+        name: row.try_get("name")?,
+        slug: row.try_get("slug")?,
+    })
+}
+
+const SELECT_BOOKMARKS: &str =
+    "SELECT b.user_id, b.post_id, b.folder, b.note, b.created_at, b.updated_at FROM bookmarks b";
+
+fn row_to_bookmark(row: &PgRow) -> Result<Bookmark> {
+    Ok(Bookmark {
+        user_id: parse_uuid(row, "user_id")?,
+        post_id: parse_uuid(row, "post_id")?,
+        folder: row.try_get("folder")?,
+        note: row.try_get("note")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+const SELECT_PROFILES: &str =
+    "SELECT user_id, display_name, bio, avatar_url, website, social_links, updated_at FROM user_profiles";
+
+fn row_to_profile(row: &PgRow) -> Result<Profile> {
+    Ok(Profile {
+        user_id: parse_uuid(row, "user_id")?,
+        display_name: row.try_get("display_name")?,
+        bio: row.try_get("bio")?,
+        avatar_url: row.try_get("avatar_url")?,
+        website: row.try_get("website")?,
+        social_links: serde_json::from_str(row.try_get("social_links")?)?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+const SELECT_TWO_FACTOR: &str =
+    "SELECT user_id, secret, enabled, last_used_step, failed_attempts, locked_until, created_at, updated_at
+     FROM user_two_factor";
+
+fn row_to_two_factor(row: &PgRow) -> Result<TwoFactor> {
+    Ok(TwoFactor {
+        user_id: parse_uuid(row, "user_id")?,
+        secret: row.try_get("secret")?,
+        enabled: row.try_get::<i32, _>("enabled")? != 0,
+        last_used_step: row.try_get("last_used_step")?,
+        failed_attempts: row.try_get("failed_attempts")?,
+        locked_until: row.try_get("locked_until")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+const SELECT_IDENTITIES: &str =
+    "SELECT issuer, subject, user_id, email, created_at, last_login_at FROM user_identities";
+
+fn row_to_identity(row: &PgRow) -> Result<UserIdentity> {
+    Ok(UserIdentity {
+        issuer: row.try_get("issuer")?,
+        subject: row.try_get("subject")?,
+        user_id: parse_uuid(row, "user_id")?,
+        email: row.try_get("email")?,
+        created_at: row.try_get("created_at")?,
+        last_login_at: row.try_get("last_login_at")?,
+    })
+}
+
+const SELECT_LOGIN_ATTEMPTS: &str = "SELECT id, user_id, ip, user_agent, outcome, created_at FROM login_attempts";
+
+fn row_to_login_attempt(row: &PgRow) -> Result<LoginAttempt> {
+    Ok(LoginAttempt {
+        id: parse_uuid(row, "id")?,
+        user_id: parse_uuid(row, "user_id")?,
+        ip: row.try_get("ip")?,
+        user_agent: row.try_get("user_agent")?,
+        outcome: row.try_get::<&str, _>("outcome")?.parse()?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+const SELECT_POST_SOURCES: &str =
+    "SELECT post_id, root, path, content_hash, post_updated_at, synced_at FROM post_sources";
+
+fn row_to_post_source(row: &PgRow) -> Result<PostSource> {
+    Ok(PostSource {
+        post_id: parse_uuid(row, "post_id")?,
+        root: row.try_get("root")?,
+        path: row.try_get("path")?,
+        content_hash: row.try_get("content_hash")?,
+        post_updated_at: row.try_get("post_updated_at")?,
+        synced_at: row.try_get("synced_at")?,
+    })
+}
+
+const SELECT_SERIES: &str =
+    "SELECT s.id, s.title, s.slug, s.author_id, u.username AS author, s.created_at, s.updated_at
+     FROM series s
+     LEFT JOIN users u ON u.id = s.author_id";
+
+fn row_to_series(row: &PgRow) -> Result<Series> {
+    Ok(Series {
+        id: parse_uuid(row, "id")?,
+        title: row.try_get("title")?,
+        slug: row.try_get("slug")?,
+        author_id: parse_uuid(row, "author_id")?,
+        author: row.try_get("author")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+/// PostgreSQL backend on an sqlx pool. The driver is async, so unlike the
+/// SQLite backend nothing here needs the blocking thread pool.
+pub struct PostgresRepository {
+    pool: PgPool,
+    max_size: u32,
+}
+
+impl PostgresRepository {
+    pub async fn connect(url: &str, config: &DbConfig) -> Result<Self> {
+        Self::connect_with(PgConnectOptions::from_str(url)?, config).await
+    }
+
+    pub async fn connect_with(options: PgConnectOptions, config: &DbConfig) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(config.pool_size)
+            .acquire_timeout(Duration::from_secs(config.connection_timeout_secs))
+            .connect_with(options)
+            .await?;
+        let repository = PostgresRepository { pool, max_size: config.pool_size };
+        repository.migrate().await?;
+        Ok(repository)
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(MIGRATION_LOCK)
+            .execute(&mut *tx)
+            .await?;
+
+        for sql in BASE_SCHEMA {
+            sqlx::query(sql).execute(&mut *tx).await?;
+        }
+        sqlx::query("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")
+            .execute(&mut *tx)
+            .await?;
+
+        let current: Option<i32> = sqlx::query_scalar("SELECT version FROM schema_version")
+            .fetch_optional(&mut *tx)
+            .await?;
+        if current.is_none() {
+            sqlx::query("INSERT INTO schema_version (version) VALUES (0)")
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let current = current.unwrap_or(0);
+        for sql in MIGRATIONS.iter().skip(current.max(0) as usize) {
+            sqlx::raw_sql(sql).execute(&mut *tx).await?;
+        }
+        if current < SCHEMA_VERSION {
+            sqlx::query("UPDATE schema_version SET version = $1")
+                .bind(SCHEMA_VERSION)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepository for PostgresRepository {
+    async fn insert_user(&self, user: &User) -> Result<()> {
+        const SQL: &str = "INSERT INTO users (id, username, password_hash, role, disabled, email, email_verified, created_at, updated_at)
+                           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
+        timed(SQL, sqlx::query(SQL)
+            .bind(user.id.to_string())
+            .bind(&user.username)
+            .bind(&user.password_hash)
+            .bind(user.role.as_str())
+            .bind(user.disabled as i32)
+            .bind(&user.email)
+            .bind(user.email_verified as i32)
+            .bind(&user.created_at)
+            .bind(&user.updated_at)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn find_user_by_id(&self, id: Uuid) -> Result<Option<User>> {
+        let sql = format!("{} WHERE id = $1", SELECT_USERS);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_user).transpose()
+    }
+
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>> {
+        let sql = format!("{} WHERE username = $1", SELECT_USERS);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(username)
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_user).transpose()
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        let sql = format!("{} WHERE email = $1", SELECT_USERS);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(email)
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_user).transpose()
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>> {
+        let sql = format!("{} ORDER BY created_at", SELECT_USERS);
+        let rows = timed(&sql, sqlx::query(&sql).fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_user).collect()
+    }
+
+    async fn update_username(&self, id: Uuid, username: &str, updated_at: &str) -> Result<bool> {
+        const SQL: &str = "UPDATE users SET username = $1, updated_at = $2 WHERE id = $3";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(username)
+            .bind(updated_at)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn update_password_hash(&self, id: Uuid, password_hash: &str, updated_at: &str) -> Result<bool> {
+        const SQL: &str = "UPDATE users SET password_hash = $1, updated_at = $2 WHERE id = $3";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(password_hash)
+            .bind(updated_at)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_user_role(&self, id: Uuid, role: Role, updated_at: &str) -> Result<bool> {
+        const SQL: &str = "UPDATE users SET role = $1, updated_at = $2 WHERE id = $3";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(role.as_str())
+            .bind(updated_at)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_user_disabled(&self, id: Uuid, disabled: bool, updated_at: &str) -> Result<bool> {
+        const SQL: &str = "UPDATE users SET disabled = $1, updated_at = $2 WHERE id = $3";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(disabled as i32)
+            .bind(updated_at)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_user_email(&self, id: Uuid, email: Option<&str>, verified: bool, updated_at: &str) -> Result<bool> {
+        const SQL: &str = "UPDATE users SET email = $1, email_verified = $2, updated_at = $3 WHERE id = $4";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(email)
+            .bind(verified as i32)
+            .bind(updated_at)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_user(&self, id: Uuid) -> Result<bool> {
+        const SQL: &str = "DELETE FROM users WHERE id = $1";
+        let mut tx = self.pool.begin().await?;
+        let mut reacted = Vec::new();
+        for target in [ReactionTarget::Post, ReactionTarget::Comment] {
+            let (table, column, _) = reaction_tables(target);
+            let sql = format!("SELECT DISTINCT {} AS id FROM {} WHERE user_id = $1", column, table);
+            let rows = timed(&sql, sqlx::query(&sql).bind(id.to_string()).fetch_all(&mut *tx)).await?;
+            for row in rows {
+                reacted.push((target, row.try_get::<String, _>("id")?));
+            }
+        }
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(id.to_string())
+            .execute(&mut *tx)).await?;
+        for (target, target_id) in reacted {
+            recount_reactions(&mut tx, target, &target_id).await?;
+        }
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl PostRepository for PostgresRepository {
+    async fn insert_post(&self, post: &Post) -> Result<()> {
+        const SQL: &str = "INSERT INTO posts (id, title, slug, content, author_id, created_at, updated_at, seo_title, seo_description, excerpt)
+                           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
+        timed(SQL, sqlx::query(SQL)
+            .bind(post.id.to_string())
+            .bind(&post.title)
+            .bind(&post.slug)
+            .bind(&post.content)
+            .bind(post.author_id.to_string())
+            .bind(&post.created_at)
+            .bind(&post.updated_at)
+            .bind(&post.seo_title)
+            .bind(&post.seo_description)
+            .bind(&post.excerpt)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn list_posts(&self, sort: PostSort) -> Result<Vec<Post>> {
+        let sql = format!("{} ORDER BY {}", SELECT_POSTS, post_order(sort));
+        let rows = timed(&sql, sqlx::query(&sql).fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_post).collect()
+    }
+
+    async fn list_posts_by_author(&self, author_id: Uuid) -> Result<Vec<Post>> {
+        let sql = format!("{} WHERE p.author_id = $1 ORDER BY p.created_at DESC", SELECT_POSTS);
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(author_id.to_string())
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_post).collect()
+    }
+
+    async fn find_post(&self, id: Uuid) -> Result<Option<Post>> {
+        let sql = format!("{} WHERE p.id = $1", SELECT_POSTS);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_post).transpose()
+    }
+
+    async fn list_posts_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Post>> {
+        let sql = format!("{} WHERE p.id = ANY($1)", SELECT_POSTS);
+        let ids: Vec<String> = ids.iter().map(Uuid::to_string).collect();
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(ids)
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_post).collect()
+    }
+
+    async fn find_post_by_slug(&self, slug: &str) -> Result<Option<Post>> {
+        let sql = format!("{} WHERE p.slug = $1", SELECT_POSTS);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(slug)
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_post).transpose()
+    }
+
+    async fn update_post(&self, id: Uuid, title: &str, content: &str, updated_at: &str) -> Result<bool> {
+        const SQL: &str = "UPDATE posts SET title = $1, content = $2, updated_at = $3 WHERE id = $4";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(title)
+            .bind(content)
+            .bind(updated_at)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn update_post_metadata(
+        &self,
+        id: Uuid,
+        slug: Option<&str>,
+        created_at: &str,
+        updated_at: &str,
+    ) -> Result<bool> {
+        const SQL: &str = "UPDATE posts SET slug = $1, created_at = $2, updated_at = $3 WHERE id = $4";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(slug)
+            .bind(created_at)
+            .bind(updated_at)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn update_post_seo(&self, id: Uuid, seo_title: Option<&str>, seo_description: Option<&str>) -> Result<bool> {
+        const SQL: &str = "UPDATE posts SET seo_title = $1, seo_description = $2 WHERE id = $3";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(seo_title)
+            .bind(seo_description)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn update_post_excerpt(&self, id: Uuid, excerpt: Option<&str>) -> Result<bool> {
+        const SQL: &str = "UPDATE posts SET excerpt = $1 WHERE id = $2";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(excerpt)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_post(&self, id: Uuid) -> Result<bool> {
+        const SQL: &str = "DELETE FROM posts WHERE id = $1";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl CommentRepository for PostgresRepository {
+    async fn insert_comment(&self, comment: &Comment) -> Result<()> {
+        const SQL: &str = "INSERT INTO comments (id, content, post_id, parent_id, author_id, guest_name, created_at, updated_at)
+                           VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+        timed(SQL, sqlx::query(SQL)
+            .bind(comment.id.to_string())
+            .bind(&comment.content)
+            .bind(comment.post_id.to_string())
+            .bind(comment.parent_id.map(|id| id.to_string()))
+            .bind(comment.author_id.to_string())
+            .bind(&comment.guest_name)
+            .bind(&comment.created_at)
+            .bind(&comment.updated_at)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn list_comments(&self) -> Result<Vec<Comment>> {
+        let sql = format!("{} ORDER BY c.created_at", SELECT_COMMENTS);
+        let rows = timed(&sql, sqlx::query(&sql).fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_comment).collect()
+    }
+
+    async fn list_post_comments(&self, post_id: Uuid) -> Result<Vec<Comment>> {
+        let sql = format!("{} WHERE c.post_id = $1 ORDER BY c.created_at DESC", SELECT_COMMENTS);
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(post_id.to_string())
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_comment).collect()
+    }
+
+    async fn list_comments_by_author(&self, author_id: Uuid, limit: i64) -> Result<Vec<Comment>> {
+        let sql = format!("{} WHERE c.author_id = $1 ORDER BY c.created_at DESC LIMIT $2", SELECT_COMMENTS);
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(author_id.to_string())
+            .bind(limit)
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_comment).collect()
+    }
+
+    async fn find_comment(&self, id: Uuid) -> Result<Option<Comment>> {
+        let sql = format!("{} WHERE c.id = $1", SELECT_COMMENTS);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_comment).transpose()
+    }
+
+    async fn update_comment(&self, id: Uuid, content: &str, updated_at: &str) -> Result<bool> {
+        const SQL: &str = "UPDATE comments SET content = $1, updated_at = $2 WHERE id = $3";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(content)
+            .bind(updated_at)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_comment(&self, id: Uuid) -> Result<bool> {
+        const SQL: &str = "DELETE FROM comments WHERE id = $1";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl TagRepository for PostgresRepository {
+    async fn insert_tag(&self, tag: &Tag) -> Result<()> {
+        const SQL: &str = "INSERT INTO tags (id, name, slug) VALUES ($1, $2, $3)";
+        timed(SQL, sqlx::query(SQL)
+            .bind(tag.id.to_string())
+            .bind(&tag.name)
+            .bind(&tag.slug)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn find_tag_by_slug(&self, slug: &str) -> Result<Option<Tag>> {
+        let sql = format!("{} WHERE t.slug = $1", SELECT_TAGS);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(slug)
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_tag).transpose()
+    }
+
+    async fn list_post_tags(&self, post_id: Uuid) -> Result<Vec<Tag>> {
+        let sql = format!(
+            "{} JOIN post_tags pt ON pt.tag_id = t.id WHERE pt.post_id = $1 ORDER BY t.name",
+            SELECT_TAGS
+        );
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(post_id.to_string())
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_tag).collect()
+    }
+
+    async fn add_post_tag(&self, post_id: Uuid, tag_id: Uuid) -> Result<()> {
+        const SQL: &str = "INSERT INTO post_tags (post_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING";
+        timed(SQL, sqlx::query(SQL)
+            .bind(post_id.to_string())
+            .bind(tag_id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn remove_post_tags(&self, post_id: Uuid) -> Result<()> {
+        const SQL: &str = "DELETE FROM post_tags WHERE post_id = $1";
+        timed(SQL, sqlx::query(SQL)
+            .bind(post_id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SeriesRepository for PostgresRepository {
+    async fn insert_series(&self, series: &Series) -> Result<()> {
+        const SQL: &str = "INSERT INTO series (id, title, slug, author_id, created_at, updated_at)
+                           VALUES ($1, $2, $3, $4, $5, $6)";
+        timed(SQL, sqlx::query(SQL)
+            .bind(series.id.to_string())
+            .bind(&series.title)
+            .bind(&series.slug)
+            .bind(series.author_id.to_string())
+            .bind(&series.created_at)
+            .bind(&series.updated_at)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn find_series(&self, id: Uuid) -> Result<Option<Series>> {
+        let sql = format!("{} WHERE s.id = $1", SELECT_SERIES);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_series).transpose()
+    }
+
+    async fn find_series_by_slug(&self, slug: &str) -> Result<Option<Series>> {
+        let sql = format!("{} WHERE s.slug = $1", SELECT_SERIES);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(slug)
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_series).transpose()
+    }
+
+    async fn list_series(&self) -> Result<Vec<Series>> {
+        let sql = format!("{} ORDER BY s.title", SELECT_SERIES);
+        let rows = timed(&sql, sqlx::query(&sql).fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_series).collect()
+    }
+
+    async fn list_series_by_author(&self, author_id: Uuid) -> Result<Vec<Series>> {
+        let sql = format!("{} WHERE s.author_id = $1 ORDER BY s.title", SELECT_SERIES);
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(author_id.to_string())
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_series).collect()
+    }
+
+    async fn find_post_series(&self, post_id: Uuid) -> Result<Option<Series>> {
+        let sql = format!("{} JOIN series_posts sp ON sp.series_id = s.id WHERE sp.post_id = $1", SELECT_SERIES);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(post_id.to_string())
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_series).transpose()
+    }
+
+    async fn list_series_posts(&self, series_id: Uuid) -> Result<Vec<Post>> {
+        let sql = format!(
+            "{} JOIN series_posts sp ON sp.post_id = p.id WHERE sp.series_id = $1 ORDER BY sp.position",
+            SELECT_POSTS
+        );
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(series_id.to_string())
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_post).collect()
+    }
+
+    async fn set_series_posts(&self, series_id: Uuid, post_ids: &[Uuid], updated_at: &str) -> Result<()> {
+        const DELETE: &str = "DELETE FROM series_posts WHERE series_id = $1";
+        const INSERT: &str = "INSERT INTO series_posts (post_id, series_id, position) VALUES ($1, $2, $3)
+                              ON CONFLICT (post_id) DO UPDATE SET series_id = excluded.series_id, position = excluded.position";
+        const TOUCH: &str = "UPDATE series SET updated_at = $1 WHERE id = $2";
+        let mut tx = self.pool.begin().await?;
+        timed(DELETE, sqlx::query(DELETE).bind(series_id.to_string()).execute(&mut *tx)).await?;
+        for (position, post_id) in post_ids.iter().enumerate() {
+            timed(INSERT, sqlx::query(INSERT)
+                .bind(post_id.to_string())
+                .bind(series_id.to_string())
+                .bind(position as i32 + 1)
+                .execute(&mut *tx)).await?;
+        }
+        timed(TOUCH, sqlx::query(TOUCH)
+            .bind(updated_at)
+            .bind(series_id.to_string())
+            .execute(&mut *tx)).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn remove_series_post(&self, post_id: Uuid) -> Result<bool> {
+        const SQL: &str = "DELETE FROM series_posts WHERE post_id = $1";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(post_id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl ReactionRepository for PostgresRepository {
+    async fn set_reaction(
+        &self,
+        target: ReactionTarget,
+        target_id: Uuid,
+        user_id: Uuid,
+        kind: &str,
+        reacted: bool,
+        now: &str,
+    ) -> Result<Option<Reactions>> {
+        let (table, column, targets) = reaction_tables(target);
+        let target_id = target_id.to_string();
+        let mut tx = self.pool.begin().await?;
+        // Lock the target first so concurrent reactions are counted one
+        // after the other.
+        let lock = format!("SELECT id FROM {} WHERE id = $1 FOR UPDATE", targets);
+        if timed(&lock, sqlx::query(&lock).bind(&target_id).fetch_optional(&mut *tx)).await?.is_none() {
+            return Ok(None);
+        }
+        if reacted {
+            let sql = format!(
+                "INSERT INTO {} ({}, user_id, kind, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+                table, column
+            );
+            timed(&sql, sqlx::query(&sql)
+                .bind(&target_id)
+                .bind(user_id.to_string())
+                .bind(kind)
+                .bind(now)
+                .execute(&mut *tx)).await?;
+        } else {
+            let sql = format!("DELETE FROM {} WHERE {} = $1 AND user_id = $2 AND kind = $3", table, column);
+            timed(&sql, sqlx::query(&sql)
+                .bind(&target_id)
+                .bind(user_id.to_string())
+                .bind(kind)
+                .execute(&mut *tx)).await?;
+        }
+        let reactions = recount_reactions(&mut tx, target, &target_id).await?;
+        tx.commit().await?;
+        Ok(reactions)
+    }
+
+    async fn list_user_reactions(&self, post_id: Uuid, user_id: Uuid) -> Result<UserReactions> {
+        const POST: &str = "SELECT kind FROM post_reactions WHERE post_id = $1 AND user_id = $2 ORDER BY kind";
+        const COMMENTS: &str = "SELECT r.comment_id, r.kind FROM comment_reactions r
+                                JOIN comments c ON c.id = r.comment_id
+                                WHERE c.post_id = $1 AND r.user_id = $2
+                                ORDER BY r.kind";
+        let rows = timed(POST, sqlx::query(POST)
+            .bind(post_id.to_string())
+            .bind(user_id.to_string())
+            .fetch_all(&self.pool)).await?;
+        let mut reactions = UserReactions {
+            post: rows.iter().map(|row| row.try_get("kind")).collect::<sqlx::Result<_>>()?,
+            ..UserReactions::default()
+        };
+        let rows = timed(COMMENTS, sqlx::query(COMMENTS)
+            .bind(post_id.to_string())
+            .bind(user_id.to_string())
+            .fetch_all(&self.pool)).await?;
+        for row in &rows {
+            reactions.comments.entry(parse_uuid(row, "comment_id")?).or_default().push(row.try_get("kind")?);
+        }
+        Ok(reactions)
+    }
+
+    async fn list_reactions(&self, target: ReactionTarget) -> Result<Vec<Reaction>> {
+        let (table, column, _) = reaction_tables(target);
+        let sql = format!(
+            "SELECT {} AS target_id, user_id, kind, created_at FROM {} ORDER BY created_at",
+            column, table
+        );
+        let rows = timed(&sql, sqlx::query(&sql).fetch_all(&self.pool)).await?;
+        rows.iter()
+            .map(|row| {
+                Ok(Reaction {
+                    target,
+                    target_id: parse_uuid(row, "target_id")?,
+                    user_id: parse_uuid(row, "user_id")?,
+                    kind: row.try_get("kind")?,
+                    created_at: row.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl BookmarkRepository for PostgresRepository {
+    async fn upsert_bookmark(&self, bookmark: &Bookmark) -> Result<()> {
+        const SQL: &str = "INSERT INTO bookmarks (user_id, post_id, folder, note, created_at, updated_at)
+                           VALUES ($1, $2, $3, $4, $5, $6)
+                           ON CONFLICT (user_id, post_id) DO UPDATE SET
+                               folder = excluded.folder,
+                               note = excluded.note,
+                               updated_at = excluded.updated_at";
+        timed(SQL, sqlx::query(SQL)
+            .bind(bookmark.user_id.to_string())
+            .bind(bookmark.post_id.to_string())
+            .bind(&bookmark.folder)
+            .bind(&bookmark.note)
+            .bind(&bookmark.created_at)
+            .bind(&bookmark.updated_at)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn find_bookmark(&self, user_id: Uuid, post_id: Uuid) -> Result<Option<Bookmark>> {
+        let sql = format!("{} WHERE b.user_id = $1 AND b.post_id = $2", SELECT_BOOKMARKS);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(user_id.to_string())
+            .bind(post_id.to_string())
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_bookmark).transpose()
+    }
+
+    async fn list_bookmarks(&self, user_id: Uuid) -> Result<Vec<Bookmark>> {
+        let sql = format!("{} WHERE b.user_id = $1 ORDER BY b.created_at DESC, b.post_id", SELECT_BOOKMARKS);
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(user_id.to_string())
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_bookmark).collect()
+    }
+
+    async fn list_bookmarked_posts(&self, user_id: Uuid) -> Result<Vec<Post>> {
+        let sql = format!(
+            "{} JOIN bookmarks b ON b.post_id = p.id WHERE b.user_id = $1 ORDER BY b.created_at DESC, b.post_id",
+            SELECT_POSTS
+        );
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(user_id.to_string())
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_post).collect()
+    }
+
+    async fn delete_bookmark(&self, user_id: Uuid, post_id: Uuid) -> Result<bool> {
+        const SQL: &str = "DELETE FROM bookmarks WHERE user_id = $1 AND post_id = $2";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(user_id.to_string())
+            .bind(post_id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl ProfileRepository for PostgresRepository {
+    async fn find_profile(&self, user_id: Uuid) -> Result<Option<Profile>> {
+        let sql = format!("{} WHERE user_id = $1", SELECT_PROFILES);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(user_id.to_string())
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_profile).transpose()
+    }
+
+    async fn upsert_profile(&self, profile: &Profile) -> Result<()> {
+        const SQL: &str = "INSERT INTO user_profiles (user_id, display_name, bio, avatar_url, website, social_links, updated_at)
+                           VALUES ($1, $2, $3, $4, $5, $6, $7)
+                           ON CONFLICT (user_id) DO UPDATE SET display_name = excluded.display_name,
+                               bio = excluded.bio,
+                               avatar_url = excluded.avatar_url,
+                               website = excluded.website,
+                               social_links = excluded.social_links,
+                               updated_at = excluded.updated_at";
+        timed(SQL, sqlx::query(SQL)
+            .bind(profile.user_id.to_string())
+            .bind(&profile.display_name)
+            .bind(&profile.bio)
+            .bind(&profile.avatar_url)
+            .bind(&profile.website)
+            .bind(serde_json::to_string(&profile.social_links)?)
+            .bind(&profile.updated_at)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserTokenRepository for PostgresRepository {
+    async fn insert_user_token(&self, token: &UserToken) -> Result<()> {
+        const SQL: &str = "INSERT INTO user_tokens (token_hash, user_id, purpose, expires_at, created_at, email)
+                           VALUES ($1, $2, $3, $4, $5, $6)";
+        timed(SQL, sqlx::query(SQL)
+            .bind(&token.token_hash)
+            .bind(token.user_id.to_string())
+            .bind(token.purpose.as_str())
+            .bind(&token.expires_at)
+            .bind(&token.created_at)
+            .bind(&token.email)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn take_user_token(&self, token_hash: &str, purpose: TokenPurpose) -> Result<Option<UserToken>> {
+        const SQL: &str = "DELETE FROM user_tokens WHERE token_hash = $1 AND purpose = $2
+                           RETURNING token_hash, user_id, purpose, expires_at, created_at, email";
+        let row = timed(SQL, sqlx::query(SQL)
+            .bind(token_hash)
+            .bind(purpose.as_str())
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_user_token).transpose()
+    }
+
+    async fn delete_user_tokens(&self, user_id: Uuid, purpose: TokenPurpose) -> Result<()> {
+        const SQL: &str = "DELETE FROM user_tokens WHERE user_id = $1 AND purpose = $2";
+        timed(SQL, sqlx::query(SQL)
+            .bind(user_id.to_string())
+            .bind(purpose.as_str())
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for PostgresRepository {
+    async fn find_two_factor(&self, user_id: Uuid) -> Result<Option<TwoFactor>> {
+        let sql = format!("{} WHERE user_id = $1", SELECT_TWO_FACTOR);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(user_id.to_string())
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_two_factor).transpose()
+    }
+
+    async fn upsert_two_factor(&self, two_factor: &TwoFactor) -> Result<()> {
+        const SQL: &str = "INSERT INTO user_two_factor
+                               (user_id, secret, enabled, last_used_step, failed_attempts, locked_until, created_at, updated_at)
+                           VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                           ON CONFLICT (user_id) DO UPDATE SET secret = excluded.secret,
+                               enabled = excluded.enabled,
+                               last_used_step = excluded.last_used_step,
+                               failed_attempts = excluded.failed_attempts,
+                               locked_until = excluded.locked_until,
+                               created_at = excluded.created_at,
+                               updated_at = excluded.updated_at";
+        timed(SQL, sqlx::query(SQL)
+            .bind(two_factor.user_id.to_string())
+            .bind(&two_factor.secret)
+            .bind(two_factor.enabled as i32)
+            .bind(two_factor.last_used_step)
+            .bind(two_factor.failed_attempts)
+            .bind(&two_factor.locked_until)
+            .bind(&two_factor.created_at)
+            .bind(&two_factor.updated_at)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn delete_two_factor(&self, user_id: Uuid) -> Result<bool> {
+        const SQL: &str = "DELETE FROM user_two_factor WHERE user_id = $1";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(user_id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn use_two_factor_step(&self, user_id: Uuid, step: i64, updated_at: &str) -> Result<bool> {
+        const SQL: &str = "UPDATE user_two_factor SET last_used_step = $1, updated_at = $2
+                           WHERE user_id = $3 AND (last_used_step IS NULL OR last_used_step < $1)";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(step)
+            .bind(updated_at)
+            .bind(user_id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn begin_two_factor_attempt(&self, user_id: Uuid, now: &str) -> Result<Option<i32>> {
+        const SQL: &str = "UPDATE user_two_factor SET failed_attempts = failed_attempts + 1, updated_at = $1
+                           WHERE user_id = $2 AND enabled = 1 AND (locked_until IS NULL OR locked_until <= $1)
+                           RETURNING failed_attempts";
+        let row = timed(SQL, sqlx::query(SQL)
+            .bind(now)
+            .bind(user_id.to_string())
+            .fetch_optional(&self.pool)).await?;
+        Ok(row.map(|row| row.try_get(0)).transpose()?)
+    }
+
+    async fn set_two_factor_lock(&self, user_id: Uuid, locked_until: Option<&str>, updated_at: &str) -> Result<()> {
+        const SQL: &str = "UPDATE user_two_factor SET failed_attempts = 0, locked_until = $1, updated_at = $2
+                           WHERE user_id = $3";
+        timed(SQL, sqlx::query(SQL)
+            .bind(locked_until)
+            .bind(updated_at)
+            .bind(user_id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> Result<()> {
+        const DELETE: &str = "DELETE FROM recovery_codes WHERE user_id = $1";
+        const INSERT: &str = "INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)";
+        let mut tx = self.pool.begin().await?;
+        timed(DELETE, sqlx::query(DELETE).bind(user_id.to_string()).execute(&mut *tx)).await?;
+        for code_hash in code_hashes {
+            timed(INSERT, sqlx::query(INSERT)
+                .bind(user_id.to_string())
+                .bind(code_hash)
+                .execute(&mut *tx)).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn take_recovery_code(&self, user_id: Uuid, code_hash: &str) -> Result<bool> {
+        const SQL: &str = "DELETE FROM recovery_codes WHERE user_id = $1 AND code_hash = $2";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(user_id.to_string())
+            .bind(code_hash)
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn count_recovery_codes(&self, user_id: Uuid) -> Result<i64> {
+        const SQL: &str = "SELECT COUNT(*) FROM recovery_codes WHERE user_id = $1";
+        let row = timed(SQL, sqlx::query(SQL)
+            .bind(user_id.to_string())
+            .fetch_one(&self.pool)).await?;
+        Ok(row.try_get(0)?)
+    }
+}
+
+#[async_trait]
+impl PostSourceRepository for PostgresRepository {
+    async fn list_post_sources(&self, root: &str) -> Result<Vec<PostSource>> {
+        let sql = format!("{} WHERE root = $1 ORDER BY path", SELECT_POST_SOURCES);
+        let rows = timed(&sql, sqlx::query(&sql).bind(root).fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_post_source).collect()
+    }
+
+    async fn upsert_post_source(&self, source: &PostSource) -> Result<()> {
+        const SQL: &str = "INSERT INTO post_sources (post_id, root, path, content_hash, post_updated_at, synced_at)
+                           VALUES ($1, $2, $3, $4, $5, $6)
+                           ON CONFLICT (post_id) DO UPDATE SET root = excluded.root,
+                               path = excluded.path,
+                               content_hash = excluded.content_hash,
+                               post_updated_at = excluded.post_updated_at,
+                               synced_at = excluded.synced_at";
+        timed(SQL, sqlx::query(SQL)
+            .bind(source.post_id.to_string())
+            .bind(&source.root)
+            .bind(&source.path)
+            .bind(&source.content_hash)
+            .bind(&source.post_updated_at)
+            .bind(&source.synced_at)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for PostgresRepository {
+    async fn find_identity(&self, issuer: &str, subject: &str) -> Result<Option<UserIdentity>> {
+        let sql = format!("{} WHERE issuer = $1 AND subject = $2", SELECT_IDENTITIES);
+        let row = timed(&sql, sqlx::query(&sql)
+            .bind(issuer)
+            .bind(subject)
+            .fetch_optional(&self.pool)).await?;
+        row.as_ref().map(row_to_identity).transpose()
+    }
+
+    async fn list_user_identities(&self, user_id: Uuid) -> Result<Vec<UserIdentity>> {
+        let sql = format!("{} WHERE user_id = $1 ORDER BY created_at", SELECT_IDENTITIES);
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(user_id.to_string())
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_identity).collect()
+    }
+
+    async fn insert_identity(&self, identity: &UserIdentity) -> Result<bool> {
+        const SQL: &str = "INSERT INTO user_identities (issuer, subject, user_id, email, created_at, last_login_at)
+                           VALUES ($1, $2, $3, $4, $5, $6)
+                           ON CONFLICT DO NOTHING";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(&identity.issuer)
+            .bind(&identity.subject)
+            .bind(identity.user_id.to_string())
+            .bind(&identity.email)
+            .bind(&identity.created_at)
+            .bind(&identity.last_login_at)
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn touch_identity(&self, issuer: &str, subject: &str, email: Option<&str>, last_login_at: &str) -> Result<()> {
+        const SQL: &str = "UPDATE user_identities SET email = $1, last_login_at = $2 WHERE issuer = $3 AND subject = $4";
+        timed(SQL, sqlx::query(SQL)
+            .bind(email)
+            .bind(last_login_at)
+            .bind(issuer)
+            .bind(subject)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepository for PostgresRepository {
+    async fn begin_login_attempt(&self, key: &str, now: &str, forget_before: &str) -> Result<Option<i32>> {
+        const SQL: &str = "INSERT INTO login_failures (key, failures, locked_until, last_failure_at) VALUES ($1, 1, NULL, $2)
+                           ON CONFLICT (key) DO UPDATE SET
+                               failures = CASE WHEN login_failures.last_failure_at < $3 THEN 1
+                                               ELSE login_failures.failures + 1 END,
+                               last_failure_at = $2
+                           WHERE login_failures.locked_until IS NULL OR login_failures.locked_until <= $2
+                           RETURNING failures";
+        let row = timed(SQL, sqlx::query(SQL)
+            .bind(key)
+            .bind(now)
+            .bind(forget_before)
+            .fetch_optional(&self.pool)).await?;
+        Ok(row.map(|row| row.try_get(0)).transpose()?)
+    }
+
+    async fn forgive_login_attempt(&self, key: &str) -> Result<()> {
+        const SQL: &str = "UPDATE login_failures SET failures = failures - 1 WHERE key = $1 AND failures > 0";
+        timed(SQL, sqlx::query(SQL).bind(key).execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn set_login_lock(&self, key: &str, locked_until: &str) -> Result<()> {
+        const SQL: &str = "UPDATE login_failures SET locked_until = $1 WHERE key = $2";
+        timed(SQL, sqlx::query(SQL)
+            .bind(locked_until)
+            .bind(key)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn find_login_lock(&self, key: &str) -> Result<Option<String>> {
+        const SQL: &str = "SELECT locked_until FROM login_failures WHERE key = $1";
+        let locked_until: Option<Option<String>> = timed(SQL, sqlx::query_scalar(SQL)
+            .bind(key)
+            .fetch_optional(&self.pool)).await?;
+        Ok(locked_until.flatten())
+    }
+
+    async fn clear_login_failures(&self, key: &str) -> Result<bool> {
+        const SQL: &str = "DELETE FROM login_failures WHERE key = $1";
+        let result = timed(SQL, sqlx::query(SQL).bind(key).execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn insert_login_attempt(&self, attempt: &LoginAttempt) -> Result<()> {
+        const SQL: &str = "INSERT INTO login_attempts (id, user_id, ip, user_agent, outcome, created_at)
+                           VALUES ($1, $2, $3, $4, $5, $6)";
+        timed(SQL, sqlx::query(SQL)
+            .bind(attempt.id.to_string())
+            .bind(attempt.user_id.to_string())
+            .bind(&attempt.ip)
+            .bind(&attempt.user_agent)
+            .bind(attempt.outcome.as_str())
+            .bind(&attempt.created_at)
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn list_login_attempts(&self, user_id: Uuid, limit: i64) -> Result<Vec<LoginAttempt>> {
+        let sql = format!("{} WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2", SELECT_LOGIN_ATTEMPTS);
+        let rows = timed(&sql, sqlx::query(&sql)
+            .bind(user_id.to_string())
+            .bind(limit)
+            .fetch_all(&self.pool)).await?;
+        rows.iter().map(row_to_login_attempt).collect()
+    }
+
+    async fn prune_login_records(&self, before: &str) -> Result<()> {
+        const ATTEMPTS: &str = "DELETE FROM login_attempts WHERE created_at < $1";
+        const FAILURES: &str = "DELETE FROM login_failures
+                                WHERE last_failure_at < $1 AND (locked_until IS NULL OR locked_until < $1)";
+        timed(ATTEMPTS, sqlx::query(ATTEMPTS).bind(before).execute(&self.pool)).await?;
+        timed(FAILURES, sqlx::query(FAILURES).bind(before).execute(&self.pool)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PostOriginRepository for PostgresRepository {
+    async fn find_post_origin(&self, origin: &str) -> Result<Option<Uuid>> {
+        const SQL: &str = "SELECT post_id FROM post_origins WHERE origin = $1";
+        let post_id: Option<String> = timed(SQL, sqlx::query_scalar(SQL)
+            .bind(origin)
+            .fetch_optional(&self.pool)).await?;
+        Ok(post_id.map(|id| Uuid::parse_str(&id)).transpose()?)
+    }
+
+    async fn insert_post_origin(&self, origin: &str, post_id: Uuid) -> Result<()> {
+        const SQL: &str = "INSERT INTO post_origins (origin, post_id) VALUES ($1, $2)";
+        timed(SQL, sqlx::query(SQL)
+            .bind(origin)
+            .bind(post_id.to_string())
+            .execute(&self.pool)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RedirectRepository for PostgresRepository {
+    async fn insert_redirect(&self, from_path: &str, to_path: &str, created_at: &str) -> Result<bool> {
+        const SQL: &str = "INSERT INTO redirects (from_path, to_path, created_at) VALUES ($1, $2, $3)
+                           ON CONFLICT DO NOTHING";
+        let result = timed(SQL, sqlx::query(SQL)
+            .bind(from_path)
+            .bind(to_path)
+            .bind(created_at)
+            .execute(&self.pool)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn find_redirect(&self, from_path: &str) -> Result<Option<String>> {
+        const SQL: &str = "SELECT to_path FROM redirects WHERE from_path = $1";
+        Ok(timed(SQL, sqlx::query_scalar(SQL)
+            .bind(from_path)
+            .fetch_optional(&self.pool)).await?)
+    }
+}
+
+#[async_trait]
+impl Repository for PostgresRepository {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        PoolStatus {
+            connections: self.pool.size(),
+            idle_connections: self.pool.num_idle() as u32,
+            max_size: self.max_size,
+        }
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn schema_version(&self) -> Result<i32> {
+        Ok(sqlx::query_scalar("SELECT version FROM schema_version")
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    async fn counts(&self) -> Result<SiteCounts> {
+        let row = sqlx::query(
+            "SELECT (SELECT COUNT(*) FROM users), (SELECT COUNT(*) FROM posts), (SELECT COUNT(*) FROM comments)",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(SiteCounts {
+            users: row.try_get(0)?,
+            posts: row.try_get(1)?,
+            comments: row.try_get(2)?,
+        })
+    }
+
+    async fn reindex(&self) -> Result<()> {
+        for table in ["users", "posts", "comments"] {
+            sqlx::raw_sql(&format!("REINDEX TABLE {}; ANALYZE {};", table, table))
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn vacuum(&self) -> Result<()> {
+        // VACUUM cannot run inside a transaction block, so issue it on its own.
+        sqlx::raw_sql("VACUUM").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn backup(&self, _dest: &Path) -> Result<()> {
+        bail!("Online backups are only supported for SQLite; use pg_dump for PostgreSQL")
+    }
+
+    async fn restore(&self, _src: &Path) -> Result<()> {
+        bail!("Restore is only supported for SQLite; use pg_restore for PostgreSQL")
+    }
+}
@@ -0,0 +1,242 @@
+//! Schema shared by every backend. Statements are kept to the subset of SQL
+//! that SQLite and PostgreSQL both accept (TEXT ids and timestamps, plain
+//! foreign keys) so one migration list serves both.
+
+use crate::models::post::PostSort;
+use crate::models::reaction::ReactionTarget;
+
+/// Base tables, applied idempotently on every start.
+pub const BASE_SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS users (
+        id TEXT PRIMARY KEY,
+        username TEXT UNIQUE NOT NULL,
+        password_hash TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS posts (
+        id TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        content TEXT NOT NULL,
+        author_id TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE CASCADE
+    )",
+    "CREATE TABLE IF NOT EXISTS comments (
+        id TEXT PRIMARY KEY,
+        content TEXT NOT NULL,
+        post_id TEXT NOT NULL,
+        author_id TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        FOREIGN KEY (post_id) REFERENCES posts(id) ON DELETE CASCADE,
+        FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE CASCADE
+    )",
+];
+
+/// Schema changes applied on top of the base tables, oldest first. Each
+/// backend records how many of them a database has applied.
+pub const MIGRATIONS: &[&str] = &[
+    // 1: account roles and disabling
+    "ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
+     ALTER TABLE users ADD COLUMN disabled INTEGER NOT NULL DEFAULT 0;",
+    // 2: post slugs, threaded comments, tags and redirects from old URLs
+    "ALTER TABLE posts ADD COLUMN slug TEXT;
+     CREATE UNIQUE INDEX IF NOT EXISTS posts_slug ON posts (slug);
+     ALTER TABLE comments ADD COLUMN parent_id TEXT REFERENCES comments(id) ON DELETE CASCADE;
+     CREATE TABLE IF NOT EXISTS tags (
+         id TEXT PRIMARY KEY,
+         name TEXT NOT NULL,
+         slug TEXT UNIQUE NOT NULL
+     );
+     CREATE TABLE IF NOT EXISTS post_tags (
+         post_id TEXT NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+         tag_id TEXT NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+         PRIMARY KEY (post_id, tag_id)
+     );
+     CREATE INDEX IF NOT EXISTS post_tags_tag ON post_tags (tag_id);
+     CREATE TABLE IF NOT EXISTS redirects (
+         from_path TEXT PRIMARY KEY,
+         to_path TEXT NOT NULL,
+         created_at TEXT NOT NULL
+     );",
+    // 3: posts synchronised from Markdown files
+    "CREATE TABLE IF NOT EXISTS post_sources (
+         post_id TEXT PRIMARY KEY REFERENCES posts(id) ON DELETE CASCADE,
+         path TEXT UNIQUE NOT NULL,
+         content_hash TEXT NOT NULL,
+         post_updated_at TEXT NOT NULL,
+         synced_at TEXT NOT NULL
+     );",
+    // 4: author profiles
+    "CREATE TABLE IF NOT EXISTS user_profiles (
+         user_id TEXT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+         display_name TEXT,
+         bio TEXT,
+         avatar_url TEXT,
+         website TEXT,
+         social_links TEXT NOT NULL DEFAULT '[]',
+         updated_at TEXT NOT NULL
+     );
+     CREATE INDEX IF NOT EXISTS comments_author ON comments (author_id, created_at);
+     CREATE INDEX IF NOT EXISTS posts_author ON posts (author_id, created_at);",
+    // 5: email addresses and single-use tokens such as password resets
+    "ALTER TABLE users ADD COLUMN email TEXT;
+     CREATE UNIQUE INDEX IF NOT EXISTS users_email ON users (email);
+     CREATE TABLE IF NOT EXISTS user_tokens (
+         token_hash TEXT PRIMARY KEY,
+         user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+         purpose TEXT NOT NULL,
+         expires_at TEXT NOT NULL,
+         created_at TEXT NOT NULL
+     );
+     CREATE INDEX IF NOT EXISTS user_tokens_user ON user_tokens (user_id, purpose);",
+    // 6: email verification; a verification token names the address it confirms
+    "ALTER TABLE users ADD COLUMN email_verified INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE user_tokens ADD COLUMN email TEXT;",
+    // 7: two-factor authentication
+    "CREATE TABLE IF NOT EXISTS user_two_factor (
+         user_id TEXT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+         secret TEXT NOT NULL,
+         enabled INTEGER NOT NULL DEFAULT 0,
+         last_used_step BIGINT,
+         failed_attempts INTEGER NOT NULL DEFAULT 0,
+         locked_until TEXT,
+         created_at TEXT NOT NULL,
+         updated_at TEXT NOT NULL
+     );
+     CREATE TABLE IF NOT EXISTS recovery_codes (
+         user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+         code_hash TEXT NOT NULL,
+         PRIMARY KEY (user_id, code_hash)
+     );",
+    // 8: accounts at an external identity provider, for single sign-on
+    "CREATE TABLE IF NOT EXISTS user_identities (
+         issuer TEXT NOT NULL,
+         subject TEXT NOT NULL,
+         user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+         email TEXT,
+         created_at TEXT NOT NULL,
+         last_login_at TEXT NOT NULL,
+         PRIMARY KEY (issuer, subject)
+     );
+     CREATE INDEX IF NOT EXISTS user_identities_user ON user_identities (user_id);",
+    // 9: failed login counters, keyed by account or client address, and a
+    // record of login attempts on each account
+    "CREATE TABLE IF NOT EXISTS login_failures (
+         key TEXT PRIMARY KEY,
+         failures INTEGER NOT NULL,
+         locked_until TEXT,
+         last_failure_at TEXT NOT NULL
+     );
+     CREATE INDEX IF NOT EXISTS login_failures_last ON login_failures (last_failure_at);
+     CREATE TABLE IF NOT EXISTS login_attempts (
+         id TEXT PRIMARY KEY,
+         user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+         ip TEXT,
+         user_agent TEXT,
+         outcome TEXT NOT NULL,
+         created_at TEXT NOT NULL
+     );
+     CREATE INDEX IF NOT EXISTS login_attempts_user ON login_attempts (user_id, created_at);
+     CREATE INDEX IF NOT EXISTS login_attempts_created ON login_attempts (created_at);",
+    // 10: search engine title and description overrides for posts
+    "ALTER TABLE posts ADD COLUMN seo_title TEXT;
+     ALTER TABLE posts ADD COLUMN seo_description TEXT;",
+    // 11: author-written post excerpts
+    "ALTER TABLE posts ADD COLUMN excerpt TEXT;",
+    // 12: series of posts; a post belongs to at most one
+    "CREATE TABLE IF NOT EXISTS series (
+         id TEXT PRIMARY KEY,
+         title TEXT NOT NULL,
+         slug TEXT UNIQUE NOT NULL,
+         author_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+         created_at TEXT NOT NULL,
+         updated_at TEXT NOT NULL
+     );
+     CREATE TABLE IF NOT EXISTS series_posts (
+         post_id TEXT PRIMARY KEY REFERENCES posts(id) ON DELETE CASCADE,
+         series_id TEXT NOT NULL REFERENCES series(id) ON DELETE CASCADE,
+         position INTEGER NOT NULL
+     );
+     CREATE INDEX IF NOT EXISTS series_posts_series ON series_posts (series_id, position);
+     CREATE INDEX IF NOT EXISTS series_author ON series (author_id);",
+    // 13: reactions to posts and comments, one of each kind per user, with
+    // their counts kept on the post or comment
+    "ALTER TABLE posts ADD COLUMN reactions TEXT NOT NULL DEFAULT '{}';
+     ALTER TABLE posts ADD COLUMN reaction_count INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE comments ADD COLUMN reactions TEXT NOT NULL DEFAULT '{}';
+     ALTER TABLE comments ADD COLUMN reaction_count INTEGER NOT NULL DEFAULT 0;
+     CREATE INDEX IF NOT EXISTS posts_reaction_count ON posts (reaction_count, created_at);
+     CREATE TABLE IF NOT EXISTS post_reactions (
+         post_id TEXT NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+         user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+         kind TEXT NOT NULL,
+         created_at TEXT NOT NULL,
+         PRIMARY KEY (post_id, user_id, kind)
+     );
+     CREATE INDEX IF NOT EXISTS post_reactions_user ON post_reactions (user_id);
+     CREATE TABLE IF NOT EXISTS comment_reactions (
+         comment_id TEXT NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+         user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+         kind TEXT NOT NULL,
+         created_at TEXT NOT NULL,
+         PRIMARY KEY (comment_id, user_id, kind)
+     );
+     CREATE INDEX IF NOT EXISTS comment_reactions_user ON comment_reactions (user_id);",
+    // 14: posts readers saved for later, filed in folders
+    "CREATE TABLE IF NOT EXISTS bookmarks (
+         user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+         post_id TEXT NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+         folder TEXT NOT NULL DEFAULT '',
+         note TEXT,
+         created_at TEXT NOT NULL,
+         updated_at TEXT NOT NULL,
+         PRIMARY KEY (user_id, post_id)
+     );
+     CREATE INDEX IF NOT EXISTS bookmarks_user ON bookmarks (user_id, created_at);
+     CREATE INDEX IF NOT EXISTS bookmarks_post ON bookmarks (post_id);",
+    // 15: where imported posts came from, and the names of guests whose
+    // comments were imported
+    "CREATE TABLE IF NOT EXISTS post_origins (
+         origin TEXT PRIMARY KEY,
+         post_id TEXT NOT NULL REFERENCES posts(id) ON DELETE CASCADE
+     );
+     CREATE INDEX IF NOT EXISTS post_origins_post ON post_origins (post_id);
+     ALTER TABLE comments ADD COLUMN guest_name TEXT;",
+    // 16: the directory or repository each synchronised file belongs to.
+    // Paths are only unique within one; earlier rows get an empty root.
+    "CREATE TABLE IF NOT EXISTS post_sources_by_root (
+         post_id TEXT PRIMARY KEY REFERENCES posts(id) ON DELETE CASCADE,
+         root TEXT NOT NULL,
+         path TEXT NOT NULL,
+         content_hash TEXT NOT NULL,
+         post_updated_at TEXT NOT NULL,
+         synced_at TEXT NOT NULL,
+         UNIQUE (root, path)
+     );
+     INSERT INTO post_sources_by_root (post_id, root, path, content_hash, post_updated_at, synced_at)
+         SELECT post_id, '', path, content_hash, post_updated_at, synced_at FROM post_sources;
+     DROP TABLE post_sources;
+     ALTER TABLE post_sources_by_root RENAME TO post_sources;",
+];
+
+pub const SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32;
+
+/// The table of reactions to `target`, the column naming the target in it,
+/// and the table of the targets, which keeps their counts.
+pub fn reaction_tables(target: ReactionTarget) -> (&'static str, &'static str, &'static str) {
+    match target {
+        ReactionTarget::Post => ("post_reactions", "post_id", "posts"),
+        ReactionTarget::Comment => ("comment_reactions", "comment_id", "comments"),
+    }
+}
+
+/// `ORDER BY` terms for posts aliased `p`.
+pub fn post_order(sort: PostSort) -> &'static str {
+    match sort {
+        PostSort::Newest => "p.created_at DESC",
+        PostSort::MostLiked => "p.reaction_count DESC, p.created_at DESC",
+    }
+}
@@ -0,0 +1,1601 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction, TransactionBehavior};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::models::bookmark::Bookmark;
+use crate::models::comment::Comment;
+use crate::models::identity::UserIdentity;
+use crate::models::login_attempt::LoginAttempt;
+use crate::models::post::{Post, PostSort, PostSource};
+use crate::models::profile::Profile;
+use crate::models::reaction::{Reaction, ReactionTarget, Reactions, UserReactions};
+use crate::models::series::Series;
+use crate::models::tag::Tag;
+use crate::models::token::{TokenPurpose, UserToken};
+use crate::models::two_factor::TwoFactor;
+use crate::models::user::Role;
+use crate::models::User;
+use crate::repositories::schema::{post_order, reaction_tables, BASE_SCHEMA, MIGRATIONS, SCHEMA_VERSION};
+use crate::repositories::{
+    BookmarkRepository, CommentRepository, IdentityRepository, LoginAttemptRepository, PoolStatus, PostRepository,
+    PostOriginRepository, PostSourceRepository, ProfileRepository, ReactionRepository, RedirectRepository, Repository, SeriesRepository,
+    SiteCounts, TagRepository, TwoFactorRepository, UserRepository, UserTokenRepository,
+};
+use crate::services::db::DbConfig;
+
+pub type DbPool = Pool<SqliteConnectionManager>;
+
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub fn init_db(conn: &Connection) -> rusqlite::Result<()> {
+    for sql in BASE_SCHEMA {
+        conn.execute(sql, [])?;
+    }
+    migrate(conn)
+}
+
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<i32> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    if schema_version(conn)? >= SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+    // Re-read inside the transaction in case another connection got here first.
+    let current = schema_version(&tx)?;
+    for sql in MIGRATIONS.iter().skip(current.max(0) as usize) {
+        tx.execute_batch(sql)?;
+    }
+    tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    tx.commit()
+}
+
+pub fn create_pool(db_path: &str, config: &DbConfig) -> DbPool {
+    let busy_timeout_ms = config.busy_timeout_ms;
+    let manager = SqliteConnectionManager::file(db_path)
+        .with_init(move |conn| {
+            conn.execute_batch(&format!(
+                "PRAGMA foreign_keys=ON;
+                 PRAGMA journal_mode=WAL;
+                 PRAGMA synchronous=NORMAL;
+                 PRAGMA busy_timeout={};",
+                busy_timeout_ms
+            ))?;
+            conn.profile(Some(crate::telemetry::log_sql));
+            init_db(conn)
+        });
+
+    Pool::builder()
+        .max_size(config.pool_size)
+        .connection_timeout(Duration::from_secs(config.connection_timeout_secs))
+        .build(manager)
+        .expect("Failed to create pool")
+}
+
+/// Runs `f` with a pooled connection on Tokio's blocking thread pool so that
+/// SQLite work never stalls the async workers. The caller's tracing span is
+/// carried over so SQL events stay attached to the service call.
+pub async fn run<T, F>(pool: &DbPool, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Connection) -> Result<T> + Send + 'static,
+{
+    let pool = pool.clone();
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        let conn = pool.get()?;
+        f(&conn)
+    })
+    .await?
+}
+
+fn parse_uuid(row: &Row, idx: usize) -> rusqlite::Result<Uuid> {
+    let value: String = row.get(idx)?;
+    Uuid::parse_str(&value).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+        idx,
+        rusqlite::types::Type::Text,
+        Box::new(e),
+    ))
+}
+
+fn parse_optional_uuid(row: &Row, idx: usize) -> rusqlite::Result<Option<Uuid>> {
+    match row.get_ref(idx)? {
+        rusqlite::types::ValueRef::Null => Ok(None),
+        _ => parse_uuid(row, idx).map(Some),
+    }
+}
+
+/// Reads the counts by kind at `idx`, stored as a JSON object, and the
+/// total after them.
+fn parse_reactions(row: &Row, idx: usize) -> rusqlite::Result<Reactions> {
+    let counts: String = row.get(idx)?;
+    Ok(Reactions {
+        counts: serde_json::from_str(&counts).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+            idx,
+            rusqlite::types::Type::Text,
+            Box::new(e),
+        ))?,
+        total: row.get(idx + 1)?,
+    })
+}
+
+/// Counts the reactions to a post or comment again and stores the counts
+/// on it. Returns `None` if there is no such target.
+fn recount_reactions(conn: &Connection, target: ReactionTarget, id: &str) -> Result<Option<Reactions>> {
+    let (table, column, targets) = reaction_tables(target);
+    let mut stmt = conn.prepare(&format!("SELECT kind, COUNT(*) FROM {} WHERE {} = ?1 GROUP BY kind", table, column))?;
+    let counts: BTreeMap<String, i64> = collect(stmt.query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))?)?
+        .into_iter()
+        .collect();
+    let reactions = Reactions { total: counts.values().sum(), counts };
+    let rows = conn.execute(
+        &format!("UPDATE {} SET reactions = ?1, reaction_count = ?2 WHERE id = ?3", targets),
+        params![serde_json::to_string(&reactions.counts)?, reactions.total, id],
+    )?;
+    Ok((rows > 0).then_some(reactions))
+}
+
+const SELECT_USERS: &str =
+    "SELECT id, username, password_hash, role, disabled, created_at, updated_at, email, email_verified FROM users";
+
+fn row_to_user(row: &Row) -> rusqlite::Result<User> {
+    let role: String = row.get(3)?;
+    Ok(User {
+        id: parse_uuid(row, 0)?,
+        username: row.get(1)?,
+        password_hash: row.get(2)?,
+        role: role.parse().map_err(|e: anyhow::Error| rusqlite::Error::FromSqlConversionFailure(
+            3,
+            rusqlite::types::Type::Text,
+            e.into(),
+        ))?,
+        disabled: row.get(4)?,
+        email: row.get(7)?,
+        email_verified: row.get(8)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+fn row_to_user_token(row: &Row) -> rusqlite::Result<UserToken> {
+    let purpose: String = row.get(2)?;
+    Ok(UserToken {
+        token_hash: row.get(0)?,
+        user_id: parse_uuid(row, 1)?,
+        purpose: purpose.parse().map_err(|e: anyhow::Error| rusqlite::Error::FromSqlConversionFailure(
+            2,
+            rusqlite::types::Type::Text,
+            e.into(),
+        ))?,
+        email: row.get(5)?,
+        expires_at: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+const SELECT_POSTS: &str =
+    "SELECT p.id, p.title, p.slug, p.content, p.author_id, u.username, p.created_at, p.updated_at,
+            p.seo_title, p.seo_description, p.excerpt, p.reactions, p.reaction_count
+     FROM posts p
+     LEFT JOIN users u ON u.id = p.author_id";
+
+fn row_to_post(row: &Row) -> rusqlite::Result<Post> {
+    Ok(Post {
+        id: parse_uuid(row, 0)?,
+        title: row.get(1)?,
+        slug: row.get(2)?,
+        content: row.get(3)?,
+        author_id: parse_uuid(row, 4)?,
+        author: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+        seo_title: row.get(8)?,
+        seo_description: row.get(9)?,
+        excerpt: row.get(10)?,
+        reactions: parse_reactions(row, 11)?,
+    })
+}
+
+const SELECT_COMMENTS: &str =
+    "SELECT c.id, c.content, c.post_id, c.parent_id, c.author_id, u.username as author, c.created_at, c.updated_at,
+            c.reactions, c.reaction_count, c.guest_name
+     FROM comments c
+     JOIN users u ON c.author_id = u.id";
+
+fn row_to_comment(row: &Row) -> rusqlite::Result<Comment> {
+    Ok(Comment {
+        id: parse_uuid(row, 0)?,
+        content: row.get(1)?,
+        post_id: parse_uuid(row, 2)?,
+        parent_id: parse_optional_uuid(row, 3)?,
+        author_id: parse_uuid(row, 4)?,
+        author: row.get(5)?,
+        guest_name: row.get(10)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+        reactions: parse_reactions(row, 8)?,
+    })
+}
+
+const SELECT_TAGS: &str = "SELECT t.id, t.name, t.slug FROM tags t";
+
+fn row_to_tag(row: &Row) -> rusqlite::Result<Tag> {
+    Ok(Tag {
+        id: parse_uuid(row, 0)?,
+        name: row.get(1)?,
+        slug: row.get(2)?,
+    })
+}
+
+const SELECT_SERIES: &str =
+    "SELECT s.id, s.title, s.slug, s.author_id, u.username, s.created_at, s.updated_at
+     FROM series s
+     LEFT JOIN users u ON u.id = s.author_id";
+
+fn row_to_series(row: &Row) -> rusqlite::Result<Series> {
+    Ok(Series {
+        id: parse_uuid(row, 0)?,
+        title: row.get(1)?,
+        slug: row.get(2)?,
+        author_id: parse_uuid(row, 3)?,
+        author: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+const SELECT_BOOKMARKS: &str =
+    "SELECT b.user_id, b.post_id, b.folder, b.note, b.created_at, b.updated_at FROM bookmarks b";
+
+fn row_to_bookmark(row: &Row) -> rusqlite::Result<Bookmark> {
+    Ok(Bookmark {
+        user_id: parse_uuid(row, 0)?,
+        post_id: parse_uuid(row, 1)?,
+        folder: row.get(2)?,
+        note: row.get(3)?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    })
+}
+
+const SELECT_PROFILES: &str =
+    "SELECT user_id, display_name, bio, avatar_url, website, social_links, updated_at FROM user_profiles";
+
+fn row_to_profile(row: &Row) -> rusqlite::Result<Profile> {
+    let social_links: String = row.get(5)?;
+    Ok(Profile {
+        user_id: parse_uuid(row, 0)?,
+        display_name: row.get(1)?,
+        bio: row.get(2)?,
+        avatar_url: row.get(3)?,
+        website: row.get(4)?,
+        social_links: serde_json::from_str(&social_links).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(5, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        updated_at: row.get(6)?,
+    })
+}
+
+const SELECT_TWO_FACTOR: &str =
+    "SELECT user_id, secret, enabled, last_used_step, failed_attempts, locked_until, created_at, updated_at
+     FROM user_two_factor";
+
+fn row_to_two_factor(row: &Row) -> rusqlite::Result<TwoFactor> {
+    Ok(TwoFactor {
+        user_id: parse_uuid(row, 0)?,
+        secret: row.get(1)?,
+        enabled: row.get(2)?,
+        last_used_step: row.get(3)?,
+        failed_attempts: row.get(4)?,
+        locked_until: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+const SELECT_IDENTITIES: &str =
+    "SELECT issuer, subject, user_id, email, created_at, last_login_at FROM user_identities";
+
+fn row_to_identity(row: &Row) -> rusqlite::Result<UserIdentity> {
+    Ok(UserIdentity {
+        issuer: row.get(0)?,
+        subject: row.get(1)?,
+        user_id: parse_uuid(row, 2)?,
+        email: row.get(3)?,
+        created_at: row.get(4)?,
+        last_login_at: row.get(5)?,
+    })
+}
+
+const SELECT_LOGIN_ATTEMPTS: &str = "SELECT id, user_id, ip, user_agent, outcome, created_at FROM login_attempts";
+
+fn row_to_login_attempt(row: &Row) -> rusqlite::Result<LoginAttempt> {
+    let outcome: String = row.get(4)?;
+    Ok(LoginAttempt {
+        id: parse_uuid(row, 0)?,
+        user_id: parse_uuid(row, 1)?,
+        ip: row.get(2)?,
+        user_agent: row.get(3)?,
+        outcome: outcome.parse().map_err(|e: anyhow::Error| rusqlite::Error::FromSqlConversionFailure(
+            4,
+            rusqlite::types::Type::Text,
+            e.into(),
+        ))?,
+        created_at: row.get(5)?,
+    })
+}
+
+const SELECT_POST_SOURCES: &str =
+    "SELECT post_id, root, path, content_hash, post_updated_at, synced_at FROM post_sources";
+
+fn row_to_post_source(row: &Row) -> rusqlite::Result<PostSource> {
+    Ok(PostSource {
+        post_id: parse_uuid(row, 0)?,
+        root: row.get(1)?,
+        path: row.get(2)?,
+        content_hash: row.get(3)?,
+        post_updated_at: row.get(4)?,
+        synced_at: row.get(5)?,
+    })
+}
+
+fn collect<T>(rows: impl Iterator<Item = rusqlite::Result<T>>) -> Result<Vec<T>> {
+    let mut result = Vec::new();
+    for row in rows {
+        result.push(row?);
+    }
+    Ok(result)
+}
+
+/// SQLite backend on an r2d2 pool. Every call runs through [`run`].
+pub struct SqliteRepository {
+    pool: DbPool,
+}
+
+impl SqliteRepository {
+    pub fn new(pool: DbPool) -> Self {
+        SqliteRepository { pool }
+    }
+
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+}
+
+#[async_trait]
+impl UserRepository for SqliteRepository {
+    async fn insert_user(&self, user: &User) -> Result<()> {
+        let (id, username, password_hash, role, disabled, email, email_verified, created_at, updated_at) = (
+            user.id.to_string(),
+            user.username.clone(),
+            user.password_hash.clone(),
+            user.role.as_str(),
+            user.disabled,
+            user.email.clone(),
+            user.email_verified,
+            user.created_at.clone(),
+            user.updated_at.clone(),
+        );
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO users (id, username, password_hash, role, disabled, email, email_verified, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![id, username, password_hash, role, disabled, email, email_verified, created_at, updated_at],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn find_user_by_id(&self, id: Uuid) -> Result<Option<User>> {
+        run(&self.pool, move |conn| {
+            let user = conn.query_row(
+                &format!("{} WHERE id = ?", SELECT_USERS),
+                params![id.to_string()],
+                row_to_user,
+            ).optional()?;
+            Ok(user)
+        }).await
+    }
+
+    async fn find_user_by_username(&self, username: &str) -> Result<Option<User>> {
+        let username = username.to_string();
+        run(&self.pool, move |conn| {
+            let user = conn.query_row(
+                &format!("{} WHERE username = ?", SELECT_USERS),
+                params![username],
+                row_to_user,
+            ).optional()?;
+            Ok(user)
+        }).await
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        let email = email.to_string();
+        run(&self.pool, move |conn| {
+            let user = conn.query_row(
+                &format!("{} WHERE email = ?", SELECT_USERS),
+                params![email],
+                row_to_user,
+            ).optional()?;
+            Ok(user)
+        }).await
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>> {
+        run(&self.pool, |conn| {
+            let mut stmt = conn.prepare(&format!("{} ORDER BY created_at", SELECT_USERS))?;
+            let users = stmt.query_map([], row_to_user)?;
+            collect(users)
+        }).await
+    }
+
+    async fn update_username(&self, id: Uuid, username: &str, updated_at: &str) -> Result<bool> {
+        let (username, updated_at) = (username.to_string(), updated_at.to_string());
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE users SET username = ?, updated_at = ? WHERE id = ?",
+                params![username, updated_at, id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn update_password_hash(&self, id: Uuid, password_hash: &str, updated_at: &str) -> Result<bool> {
+        let (password_hash, updated_at) = (password_hash.to_string(), updated_at.to_string());
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE users SET password_hash = ?, updated_at = ? WHERE id = ?",
+                params![password_hash, updated_at, id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn set_user_role(&self, id: Uuid, role: Role, updated_at: &str) -> Result<bool> {
+        let updated_at = updated_at.to_string();
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE users SET role = ?, updated_at = ? WHERE id = ?",
+                params![role.as_str(), updated_at, id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn set_user_disabled(&self, id: Uuid, disabled: bool, updated_at: &str) -> Result<bool> {
+        let updated_at = updated_at.to_string();
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE users SET disabled = ?, updated_at = ? WHERE id = ?",
+                params![disabled, updated_at, id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn set_user_email(&self, id: Uuid, email: Option<&str>, verified: bool, updated_at: &str) -> Result<bool> {
+        let (email, updated_at) = (email.map(str::to_string), updated_at.to_string());
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE users SET email = ?, email_verified = ?, updated_at = ? WHERE id = ?",
+                params![email, verified, updated_at, id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn delete_user(&self, id: Uuid) -> Result<bool> {
+        run(&self.pool, move |conn| {
+            let id = id.to_string();
+            let tx = conn.unchecked_transaction()?;
+            let mut reacted = Vec::new();
+            for target in [ReactionTarget::Post, ReactionTarget::Comment] {
+                let (table, column, _) = reaction_tables(target);
+                let mut stmt = tx.prepare(&format!("SELECT DISTINCT {} FROM {} WHERE user_id = ?1", column, table))?;
+                let ids: Vec<String> = collect(stmt.query_map([&id], |row| row.get(0))?)?;
+                reacted.extend(ids.into_iter().map(|target_id| (target, target_id)));
+            }
+            let rows = tx.execute("DELETE FROM users WHERE id = ?1", [&id])?;
+            for (target, target_id) in reacted {
+                recount_reactions(&tx, target, &target_id)?;
+            }
+            tx.commit()?;
+            Ok(rows > 0)
+        }).await
+    }
+}
+
+#[async_trait]
+impl PostRepository for SqliteRepository {
+    async fn insert_post(&self, post: &Post) -> Result<()> {
+        let post = post.clone();
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO posts (id, title, slug, content, author_id, created_at, updated_at, seo_title, seo_description, excerpt)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    post.id.to_string(),
+                    post.title,
+                    post.slug,
+                    post.content,
+                    post.author_id.to_string(),
+                    post.created_at,
+                    post.updated_at,
+                    post.seo_title,
+                    post.seo_description,
+                    post.excerpt,
+                ],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn list_posts(&self, sort: PostSort) -> Result<Vec<Post>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!("{} ORDER BY {}", SELECT_POSTS, post_order(sort)))?;
+            let posts = stmt.query_map([], row_to_post)?;
+            collect(posts)
+        }).await
+    }
+
+    async fn list_posts_by_author(&self, author_id: Uuid) -> Result<Vec<Post>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "{} WHERE p.author_id = ?1 ORDER BY p.created_at DESC",
+                SELECT_POSTS
+            ))?;
+            let posts = stmt.query_map([author_id.to_string()], row_to_post)?;
+            collect(posts)
+        }).await
+    }
+
+    async fn find_post(&self, id: Uuid) -> Result<Option<Post>> {
+        run(&self.pool, move |conn| {
+            let post = conn.query_row(
+                &format!("{} WHERE p.id = ?1", SELECT_POSTS),
+                [id.to_string()],
+                row_to_post,
+            ).optional()?;
+            Ok(post)
+        }).await
+    }
+
+    async fn list_posts_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Post>> {
+        let ids = serde_json::to_string(ids)?;
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "{} WHERE p.id IN (SELECT value FROM json_each(?1))",
+                SELECT_POSTS
+            ))?;
+            let posts = stmt.query_map([ids], row_to_post)?;
+            collect(posts)
+        }).await
+    }
+
+    async fn find_post_by_slug(&self, slug: &str) -> Result<Option<Post>> {
+        let slug = slug.to_string();
+        run(&self.pool, move |conn| {
+            let post = conn.query_row(
+                &format!("{} WHERE p.slug = ?1", SELECT_POSTS),
+                [slug],
+                row_to_post,
+            ).optional()?;
+            Ok(post)
+        }).await
+    }
+
+    async fn update_post(&self, id: Uuid, title: &str, content: &str, updated_at: &str) -> Result<bool> {
+        let (title, content, updated_at) = (title.to_string(), content.to_string(), updated_at.to_string());
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE posts SET title = ?1, content = ?2, updated_at = ?3 WHERE id = ?4",
+                params![title, content, updated_at, id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn update_post_metadata(
+        &self,
+        id: Uuid,
+        slug: Option<&str>,
+        created_at: &str,
+        updated_at: &str,
+    ) -> Result<bool> {
+        let (slug, created_at, updated_at) = (slug.map(str::to_string), created_at.to_string(), updated_at.to_string());
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE posts SET slug = ?1, created_at = ?2, updated_at = ?3 WHERE id = ?4",
+                params![slug, created_at, updated_at, id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn update_post_seo(&self, id: Uuid, seo_title: Option<&str>, seo_description: Option<&str>) -> Result<bool> {
+        let (seo_title, seo_description) = (seo_title.map(str::to_string), seo_description.map(str::to_string));
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE posts SET seo_title = ?1, seo_description = ?2 WHERE id = ?3",
+                params![seo_title, seo_description, id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn update_post_excerpt(&self, id: Uuid, excerpt: Option<&str>) -> Result<bool> {
+        let excerpt = excerpt.map(str::to_string);
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE posts SET excerpt = ?1 WHERE id = ?2",
+                params![excerpt, id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn delete_post(&self, id: Uuid) -> Result<bool> {
+        run(&self.pool, move |conn| {
+            let rows = conn.execute("DELETE FROM posts WHERE id = ?1", params![id.to_string()])?;
+            Ok(rows > 0)
+        }).await
+    }
+}
+
+#[async_trait]
+impl CommentRepository for SqliteRepository {
+    async fn insert_comment(&self, comment: &Comment) -> Result<()> {
+        let (id, content, post_id, parent_id, author_id, guest_name, created_at, updated_at) = (
+            comment.id.to_string(),
+            comment.content.clone(),
+            comment.post_id.to_string(),
+            comment.parent_id.map(|id| id.to_string()),
+            comment.author_id.to_string(),
+            comment.guest_name.clone(),
+            comment.created_at.clone(),
+            comment.updated_at.clone(),
+        );
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO comments (id, content, post_id, parent_id, author_id, guest_name, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![id, content, post_id, parent_id, author_id, guest_name, created_at, updated_at],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn list_comments(&self) -> Result<Vec<Comment>> {
+        run(&self.pool, |conn| {
+            let mut stmt = conn.prepare(&format!("{} ORDER BY c.created_at", SELECT_COMMENTS))?;
+            let comments = stmt.query_map([], row_to_comment)?;
+            collect(comments)
+        }).await
+    }
+
+    async fn list_post_comments(&self, post_id: Uuid) -> Result<Vec<Comment>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "{} WHERE c.post_id = ? ORDER BY c.created_at DESC",
+                SELECT_COMMENTS
+            ))?;
+            let comments = stmt.query_map([post_id.to_string()], row_to_comment)?;
+            collect(comments)
+        }).await
+    }
+
+    async fn list_comments_by_author(&self, author_id: Uuid, limit: i64) -> Result<Vec<Comment>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "{} WHERE c.author_id = ?1 ORDER BY c.created_at DESC LIMIT ?2",
+                SELECT_COMMENTS
+            ))?;
+            let comments = stmt.query_map(params![author_id.to_string(), limit], row_to_comment)?;
+            collect(comments)
+        }).await
+    }
+
+    async fn find_comment(&self, id: Uuid) -> Result<Option<Comment>> {
+        run(&self.pool, move |conn| {
+            let comment = conn.query_row(
+                &format!("{} WHERE c.id = ?1", SELECT_COMMENTS),
+                [id.to_string()],
+                row_to_comment,
+            ).optional()?;
+            Ok(comment)
+        }).await
+    }
+
+    async fn update_comment(&self, id: Uuid, content: &str, updated_at: &str) -> Result<bool> {
+        let (content, updated_at) = (content.to_string(), updated_at.to_string());
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE comments SET content = ?1, updated_at = ?2 WHERE id = ?3",
+                params![content, updated_at, id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn delete_comment(&self, id: Uuid) -> Result<bool> {
+        run(&self.pool, move |conn| {
+            let rows = conn.execute("DELETE FROM comments WHERE id = ?1", [id.to_string()])?;
+            Ok(rows > 0)
+        }).await
+    }
+}
+
+#[async_trait]
+impl TagRepository for SqliteRepository {
+    async fn insert_tag(&self, tag: &Tag) -> Result<()> {
+        let (id, name, slug) = (tag.id.to_string(), tag.name.clone(), tag.slug.clone());
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO tags (id, name, slug) VALUES (?1, ?2, ?3)",
+                params![id, name, slug],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn find_tag_by_slug(&self, slug: &str) -> Result<Option<Tag>> {
+        let slug = slug.to_string();
+        run(&self.pool, move |conn| {
+            let tag = conn.query_row(
+                &format!("{} WHERE t.slug = ?1", SELECT_TAGS),
+                [slug],
+                row_to_tag,
+            ).optional()?;
+            Ok(tag)
+        }).await
+    }
+
+    async fn list_post_tags(&self, post_id: Uuid) -> Result<Vec<Tag>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "{} JOIN post_tags pt ON pt.tag_id = t.id WHERE pt.post_id = ?1 ORDER BY t.name",
+                SELECT_TAGS
+            ))?;
+            let tags = stmt.query_map([post_id.to_string()], row_to_tag)?;
+            collect(tags)
+        }).await
+    }
+
+    async fn add_post_tag(&self, post_id: Uuid, tag_id: Uuid) -> Result<()> {
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO post_tags (post_id, tag_id) VALUES (?1, ?2) ON CONFLICT DO NOTHING",
+                params![post_id.to_string(), tag_id.to_string()],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn remove_post_tags(&self, post_id: Uuid) -> Result<()> {
+        run(&self.pool, move |conn| {
+            conn.execute("DELETE FROM post_tags WHERE post_id = ?1", params![post_id.to_string()])?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl SeriesRepository for SqliteRepository {
+    async fn insert_series(&self, series: &Series) -> Result<()> {
+        let series = series.clone();
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO series (id, title, slug, author_id, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    series.id.to_string(),
+                    series.title,
+                    series.slug,
+                    series.author_id.to_string(),
+                    series.created_at,
+                    series.updated_at,
+                ],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn find_series(&self, id: Uuid) -> Result<Option<Series>> {
+        run(&self.pool, move |conn| {
+            let series = conn.query_row(
+                &format!("{} WHERE s.id = ?1", SELECT_SERIES),
+                [id.to_string()],
+                row_to_series,
+            ).optional()?;
+            Ok(series)
+        }).await
+    }
+
+    async fn find_series_by_slug(&self, slug: &str) -> Result<Option<Series>> {
+        let slug = slug.to_string();
+        run(&self.pool, move |conn| {
+            let series = conn.query_row(
+                &format!("{} WHERE s.slug = ?1", SELECT_SERIES),
+                [slug],
+                row_to_series,
+            ).optional()?;
+            Ok(series)
+        }).await
+    }
+
+    async fn list_series(&self) -> Result<Vec<Series>> {
+        run(&self.pool, |conn| {
+            let mut stmt = conn.prepare(&format!("{} ORDER BY s.title", SELECT_SERIES))?;
+            let series = stmt.query_map([], row_to_series)?;
+            collect(series)
+        }).await
+    }
+
+    async fn list_series_by_author(&self, author_id: Uuid) -> Result<Vec<Series>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!("{} WHERE s.author_id = ?1 ORDER BY s.title", SELECT_SERIES))?;
+            let series = stmt.query_map([author_id.to_string()], row_to_series)?;
+            collect(series)
+        }).await
+    }
+
+    async fn find_post_series(&self, post_id: Uuid) -> Result<Option<Series>> {
+        run(&self.pool, move |conn| {
+            let series = conn.query_row(
+                &format!("{} JOIN series_posts sp ON sp.series_id = s.id WHERE sp.post_id = ?1", SELECT_SERIES),
+                [post_id.to_string()],
+                row_to_series,
+            ).optional()?;
+            Ok(series)
+        }).await
+    }
+
+    async fn list_series_posts(&self, series_id: Uuid) -> Result<Vec<Post>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "{} JOIN series_posts sp ON sp.post_id = p.id WHERE sp.series_id = ?1 ORDER BY sp.position",
+                SELECT_POSTS
+            ))?;
+            let posts = stmt.query_map([series_id.to_string()], row_to_post)?;
+            collect(posts)
+        }).await
+    }
+
+    async fn set_series_posts(&self, series_id: Uuid, post_ids: &[Uuid], updated_at: &str) -> Result<()> {
+        let (post_ids, updated_at) = (post_ids.to_vec(), updated_at.to_string());
+        run(&self.pool, move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute("DELETE FROM series_posts WHERE series_id = ?1", [series_id.to_string()])?;
+            for (position, post_id) in post_ids.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO series_posts (post_id, series_id, position) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (post_id) DO UPDATE SET series_id = excluded.series_id, position = excluded.position",
+                    params![post_id.to_string(), series_id.to_string(), position as i64 + 1],
+                )?;
+            }
+            tx.execute(
+                "UPDATE series SET updated_at = ?1 WHERE id = ?2",
+                params![updated_at, series_id.to_string()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        }).await
+    }
+
+    async fn remove_series_post(&self, post_id: Uuid) -> Result<bool> {
+        run(&self.pool, move |conn| {
+            let rows = conn.execute("DELETE FROM series_posts WHERE post_id = ?1", [post_id.to_string()])?;
+            Ok(rows > 0)
+        }).await
+    }
+}
+
+#[async_trait]
+impl ReactionRepository for SqliteRepository {
+    async fn set_reaction(
+        &self,
+        target: ReactionTarget,
+        target_id: Uuid,
+        user_id: Uuid,
+        kind: &str,
+        reacted: bool,
+        now: &str,
+    ) -> Result<Option<Reactions>> {
+        let (target_id, user_id, kind, now) = (target_id.to_string(), user_id.to_string(), kind.to_string(), now.to_string());
+        run(&self.pool, move |conn| {
+            let (table, column, targets) = reaction_tables(target);
+            let tx = conn.unchecked_transaction()?;
+            if reacted {
+                tx.execute(
+                    &format!(
+                        "INSERT INTO {} ({}, user_id, kind, created_at)
+                         SELECT ?1, ?2, ?3, ?4 WHERE EXISTS (SELECT 1 FROM {} WHERE id = ?1)
+                         ON CONFLICT DO NOTHING",
+                        table, column, targets
+                    ),
+                    params![target_id, user_id, kind, now],
+                )?;
+            } else {
+                tx.execute(
+                    &format!("DELETE FROM {} WHERE {} = ?1 AND user_id = ?2 AND kind = ?3", table, column),
+                    params![target_id, user_id, kind],
+                )?;
+            }
+            let reactions = recount_reactions(&tx, target, &target_id)?;
+            tx.commit()?;
+            Ok(reactions)
+        }).await
+    }
+
+    async fn list_user_reactions(&self, post_id: Uuid, user_id: Uuid) -> Result<UserReactions> {
+        let (post_id, user_id) = (post_id.to_string(), user_id.to_string());
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare("SELECT kind FROM post_reactions WHERE post_id = ?1 AND user_id = ?2 ORDER BY kind")?;
+            let mut reactions = UserReactions {
+                post: collect(stmt.query_map(params![post_id, user_id], |row| row.get(0))?)?,
+                ..UserReactions::default()
+            };
+            let mut stmt = conn.prepare(
+                "SELECT r.comment_id, r.kind FROM comment_reactions r
+                 JOIN comments c ON c.id = r.comment_id
+                 WHERE c.post_id = ?1 AND r.user_id = ?2
+                 ORDER BY r.kind",
+            )?;
+            let rows = stmt.query_map(params![post_id, user_id], |row| Ok((parse_uuid(row, 0)?, row.get(1)?)))?;
+            for (comment_id, kind) in collect(rows)? {
+                reactions.comments.entry(comment_id).or_default().push(kind);
+            }
+            Ok(reactions)
+        }).await
+    }
+
+    async fn list_reactions(&self, target: ReactionTarget) -> Result<Vec<Reaction>> {
+        run(&self.pool, move |conn| {
+            let (table, column, _) = reaction_tables(target);
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {}, user_id, kind, created_at FROM {} ORDER BY created_at",
+                column, table
+            ))?;
+            let rows = stmt.query_map([], |row| {
+                Ok(Reaction {
+                    target,
+                    target_id: parse_uuid(row, 0)?,
+                    user_id: parse_uuid(row, 1)?,
+                    kind: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?;
+            collect(rows)
+        }).await
+    }
+}
+
+#[async_trait]
+impl BookmarkRepository for SqliteRepository {
+    async fn upsert_bookmark(&self, bookmark: &Bookmark) -> Result<()> {
+        let bookmark = bookmark.clone();
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO bookmarks (user_id, post_id, folder, note, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (user_id, post_id) DO UPDATE SET
+                     folder = excluded.folder,
+                     note = excluded.note,
+                     updated_at = excluded.updated_at",
+                params![
+                    bookmark.user_id.to_string(),
+                    bookmark.post_id.to_string(),
+                    bookmark.folder,
+                    bookmark.note,
+                    bookmark.created_at,
+                    bookmark.updated_at,
+                ],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn find_bookmark(&self, user_id: Uuid, post_id: Uuid) -> Result<Option<Bookmark>> {
+        run(&self.pool, move |conn| {
+            let bookmark = conn.query_row(
+                &format!("{} WHERE b.user_id = ?1 AND b.post_id = ?2", SELECT_BOOKMARKS),
+                params![user_id.to_string(), post_id.to_string()],
+                row_to_bookmark,
+            ).optional()?;
+            Ok(bookmark)
+        }).await
+    }
+
+    async fn list_bookmarks(&self, user_id: Uuid) -> Result<Vec<Bookmark>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "{} WHERE b.user_id = ?1 ORDER BY b.created_at DESC, b.post_id",
+                SELECT_BOOKMARKS
+            ))?;
+            let bookmarks = stmt.query_map([user_id.to_string()], row_to_bookmark)?;
+            collect(bookmarks)
+        }).await
+    }
+
+    async fn list_bookmarked_posts(&self, user_id: Uuid) -> Result<Vec<Post>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "{} JOIN bookmarks b ON b.post_id = p.id WHERE b.user_id = ?1 ORDER BY b.created_at DESC, b.post_id",
+                SELECT_POSTS
+            ))?;
+            let posts = stmt.query_map([user_id.to_string()], row_to_post)?;
+            collect(posts)
+        }).await
+    }
+
+    async fn delete_bookmark(&self, user_id: Uuid, post_id: Uuid) -> Result<bool> {
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "DELETE FROM bookmarks WHERE user_id = ?1 AND post_id = ?2",
+                params![user_id.to_string(), post_id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+}
+
+#[async_trait]
+impl ProfileRepository for SqliteRepository {
+    async fn find_profile(&self, user_id: Uuid) -> Result<Option<Profile>> {
+        run(&self.pool, move |conn| {
+            let profile = conn.query_row(
+                &format!("{} WHERE user_id = ?1", SELECT_PROFILES),
+                [user_id.to_string()],
+                row_to_profile,
+            ).optional()?;
+            Ok(profile)
+        }).await
+    }
+
+    async fn upsert_profile(&self, profile: &Profile) -> Result<()> {
+        let (user_id, display_name, bio, avatar_url, website, social_links, updated_at) = (
+            profile.user_id.to_string(),
+            profile.display_name.clone(),
+            profile.bio.clone(),
+            profile.avatar_url.clone(),
+            profile.website.clone(),
+            serde_json::to_string(&profile.social_links)?,
+            profile.updated_at.clone(),
+        );
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO user_profiles (user_id, display_name, bio, avatar_url, website, social_links, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT (user_id) DO UPDATE SET display_name = excluded.display_name,
+                     bio = excluded.bio,
+                     avatar_url = excluded.avatar_url,
+                     website = excluded.website,
+                     social_links = excluded.social_links,
+                     updated_at = excluded.updated_at",
+                params![user_id, display_name, bio, avatar_url, website, social_links, updated_at],
+            )?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl UserTokenRepository for SqliteRepository {
+    async fn insert_user_token(&self, token: &UserToken) -> Result<()> {
+        let (token_hash, user_id, purpose, email, expires_at, created_at) = (
+            token.token_hash.clone(),
+            token.user_id.to_string(),
+            token.purpose.as_str(),
+            token.email.clone(),
+            token.expires_at.clone(),
+            token.created_at.clone(),
+        );
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO user_tokens (token_hash, user_id, purpose, expires_at, created_at, email)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![token_hash, user_id, purpose, expires_at, created_at, email],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn take_user_token(&self, token_hash: &str, purpose: TokenPurpose) -> Result<Option<UserToken>> {
+        let token_hash = token_hash.to_string();
+        run(&self.pool, move |conn| {
+            let token = conn.query_row(
+                "DELETE FROM user_tokens WHERE token_hash = ?1 AND purpose = ?2
+                 RETURNING token_hash, user_id, purpose, expires_at, created_at, email",
+                params![token_hash, purpose.as_str()],
+                row_to_user_token,
+            ).optional()?;
+            Ok(token)
+        }).await
+    }
+
+    async fn delete_user_tokens(&self, user_id: Uuid, purpose: TokenPurpose) -> Result<()> {
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "DELETE FROM user_tokens WHERE user_id = ?1 AND purpose = ?2",
+                params![user_id.to_string(), purpose.as_str()],
+            )?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for SqliteRepository {
+    async fn find_two_factor(&self, user_id: Uuid) -> Result<Option<TwoFactor>> {
+        run(&self.pool, move |conn| {
+            let two_factor = conn.query_row(
+                &format!("{} WHERE user_id = ?1", SELECT_TWO_FACTOR),
+                [user_id.to_string()],
+                row_to_two_factor,
+            ).optional()?;
+            Ok(two_factor)
+        }).await
+    }
+
+    async fn upsert_two_factor(&self, two_factor: &TwoFactor) -> Result<()> {
+        let two_factor = two_factor.clone();
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO user_two_factor
+                     (user_id, secret, enabled, last_used_step, failed_attempts, locked_until, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                 ON CONFLICT (user_id) DO UPDATE SET secret = excluded.secret,
+                     enabled = excluded.enabled,
+                     last_used_step = excluded.last_used_step,
+                     failed_attempts = excluded.failed_attempts,
+                     locked_until = excluded.locked_until,
+                     created_at = excluded.created_at,
+                     updated_at = excluded.updated_at",
+                params![
+                    two_factor.user_id.to_string(),
+                    two_factor.secret,
+                    two_factor.enabled,
+                    two_factor.last_used_step,
+                    two_factor.failed_attempts,
+                    two_factor.locked_until,
+                    two_factor.created_at,
+                    two_factor.updated_at,
+                ],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn delete_two_factor(&self, user_id: Uuid) -> Result<bool> {
+        run(&self.pool, move |conn| {
+            let rows = conn.execute("DELETE FROM user_two_factor WHERE user_id = ?1", [user_id.to_string()])?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn use_two_factor_step(&self, user_id: Uuid, step: i64, updated_at: &str) -> Result<bool> {
+        let updated_at = updated_at.to_string();
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "UPDATE user_two_factor SET last_used_step = ?1, updated_at = ?2
+                 WHERE user_id = ?3 AND (last_used_step IS NULL OR last_used_step < ?1)",
+                params![step, updated_at, user_id.to_string()],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn begin_two_factor_attempt(&self, user_id: Uuid, now: &str) -> Result<Option<i32>> {
+        let now = now.to_string();
+        run(&self.pool, move |conn| {
+            let attempts = conn.query_row(
+                "UPDATE user_two_factor SET failed_attempts = failed_attempts + 1, updated_at = ?1
+                 WHERE user_id = ?2 AND enabled = 1 AND (locked_until IS NULL OR locked_until <= ?1)
+                 RETURNING failed_attempts",
+                params![now, user_id.to_string()],
+                |row| row.get(0),
+            ).optional()?;
+            Ok(attempts)
+        }).await
+    }
+
+    async fn set_two_factor_lock(&self, user_id: Uuid, locked_until: Option<&str>, updated_at: &str) -> Result<()> {
+        let (locked_until, updated_at) = (locked_until.map(str::to_string), updated_at.to_string());
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "UPDATE user_two_factor SET failed_attempts = 0, locked_until = ?1, updated_at = ?2 WHERE user_id = ?3",
+                params![locked_until, updated_at, user_id.to_string()],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> Result<()> {
+        let code_hashes = code_hashes.to_vec();
+        run(&self.pool, move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute("DELETE FROM recovery_codes WHERE user_id = ?1", [user_id.to_string()])?;
+            for code_hash in &code_hashes {
+                tx.execute(
+                    "INSERT INTO recovery_codes (user_id, code_hash) VALUES (?1, ?2)",
+                    params![user_id.to_string(), code_hash],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        }).await
+    }
+
+    async fn take_recovery_code(&self, user_id: Uuid, code_hash: &str) -> Result<bool> {
+        let code_hash = code_hash.to_string();
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "DELETE FROM recovery_codes WHERE user_id = ?1 AND code_hash = ?2",
+                params![user_id.to_string(), code_hash],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn count_recovery_codes(&self, user_id: Uuid) -> Result<i64> {
+        run(&self.pool, move |conn| {
+            let count = conn.query_row(
+                "SELECT COUNT(*) FROM recovery_codes WHERE user_id = ?1",
+                [user_id.to_string()],
+                |row| row.get(0),
+            )?;
+            Ok(count)
+        }).await
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for SqliteRepository {
+    async fn find_identity(&self, issuer: &str, subject: &str) -> Result<Option<UserIdentity>> {
+        let (issuer, subject) = (issuer.to_string(), subject.to_string());
+        run(&self.pool, move |conn| {
+            let identity = conn.query_row(
+                &format!("{} WHERE issuer = ?1 AND subject = ?2", SELECT_IDENTITIES),
+                params![issuer, subject],
+                row_to_identity,
+            ).optional()?;
+            Ok(identity)
+        }).await
+    }
+
+    async fn list_user_identities(&self, user_id: Uuid) -> Result<Vec<UserIdentity>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!("{} WHERE user_id = ?1 ORDER BY created_at", SELECT_IDENTITIES))?;
+            let identities = stmt.query_map([user_id.to_string()], row_to_identity)?;
+            collect(identities)
+        }).await
+    }
+
+    async fn insert_identity(&self, identity: &UserIdentity) -> Result<bool> {
+        let identity = identity.clone();
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "INSERT INTO user_identities (issuer, subject, user_id, email, created_at, last_login_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT DO NOTHING",
+                params![
+                    identity.issuer,
+                    identity.subject,
+                    identity.user_id.to_string(),
+                    identity.email,
+                    identity.created_at,
+                    identity.last_login_at,
+                ],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn touch_identity(&self, issuer: &str, subject: &str, email: Option<&str>, last_login_at: &str) -> Result<()> {
+        let (issuer, subject, email, last_login_at) =
+            (issuer.to_string(), subject.to_string(), email.map(str::to_string), last_login_at.to_string());
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "UPDATE user_identities SET email = ?1, last_login_at = ?2 WHERE issuer = ?3 AND subject = ?4",
+                params![email, last_login_at, issuer, subject],
+            )?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepository for SqliteRepository {
+    async fn begin_login_attempt(&self, key: &str, now: &str, forget_before: &str) -> Result<Option<i32>> {
+        let (key, now, forget_before) = (key.to_string(), now.to_string(), forget_before.to_string());
+        run(&self.pool, move |conn| {
+            let failures = conn.query_row(
+                "INSERT INTO login_failures (key, failures, locked_until, last_failure_at) VALUES (?1, 1, NULL, ?2)
+                 ON CONFLICT (key) DO UPDATE SET
+                     failures = CASE WHEN login_failures.last_failure_at < ?3 THEN 1
+                                     ELSE login_failures.failures + 1 END,
+                     last_failure_at = ?2
+                 WHERE login_failures.locked_until IS NULL OR login_failures.locked_until <= ?2
+                 RETURNING failures",
+                params![key, now, forget_before],
+                |row| row.get(0),
+            ).optional()?;
+            Ok(failures)
+        }).await
+    }
+
+    async fn forgive_login_attempt(&self, key: &str) -> Result<()> {
+        let key = key.to_string();
+        run(&self.pool, move |conn| {
+            conn.execute("UPDATE login_failures SET failures = failures - 1 WHERE key = ?1 AND failures > 0", [key])?;
+            Ok(())
+        }).await
+    }
+
+    async fn set_login_lock(&self, key: &str, locked_until: &str) -> Result<()> {
+        let (key, locked_until) = (key.to_string(), locked_until.to_string());
+        run(&self.pool, move |conn| {
+            conn.execute("UPDATE login_failures SET locked_until = ?1 WHERE key = ?2", params![locked_until, key])?;
+            Ok(())
+        }).await
+    }
+
+    async fn find_login_lock(&self, key: &str) -> Result<Option<String>> {
+        let key = key.to_string();
+        run(&self.pool, move |conn| {
+            let locked_until = conn.query_row(
+                "SELECT locked_until FROM login_failures WHERE key = ?1",
+                [key],
+                |row| row.get(0),
+            ).optional()?;
+            Ok(locked_until.flatten())
+        }).await
+    }
+
+    async fn clear_login_failures(&self, key: &str) -> Result<bool> {
+        let key = key.to_string();
+        run(&self.pool, move |conn| {
+            Ok(conn.execute("DELETE FROM login_failures WHERE key = ?1", [key])? > 0)
+        }).await
+    }
+
+    async fn insert_login_attempt(&self, attempt: &LoginAttempt) -> Result<()> {
+        let attempt = attempt.clone();
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO login_attempts (id, user_id, ip, user_agent, outcome, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    attempt.id.to_string(),
+                    attempt.user_id.to_string(),
+                    attempt.ip,
+                    attempt.user_agent,
+                    attempt.outcome.as_str(),
+                    attempt.created_at,
+                ],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn list_login_attempts(&self, user_id: Uuid, limit: i64) -> Result<Vec<LoginAttempt>> {
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "{} WHERE user_id = ?1 ORDER BY created_at DESC LIMIT ?2",
+                SELECT_LOGIN_ATTEMPTS
+            ))?;
+            let attempts = stmt.query_map(params![user_id.to_string(), limit], row_to_login_attempt)?;
+            collect(attempts)
+        }).await
+    }
+
+    async fn prune_login_records(&self, before: &str) -> Result<()> {
+        let before = before.to_string();
+        run(&self.pool, move |conn| {
+            conn.execute("DELETE FROM login_attempts WHERE created_at < ?1", [&before])?;
+            conn.execute(
+                "DELETE FROM login_failures WHERE last_failure_at < ?1 AND (locked_until IS NULL OR locked_until < ?1)",
+                [&before],
+            )?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl PostSourceRepository for SqliteRepository {
+    async fn list_post_sources(&self, root: &str) -> Result<Vec<PostSource>> {
+        let root = root.to_string();
+        run(&self.pool, move |conn| {
+            let mut stmt = conn.prepare(&format!("{} WHERE root = ?1 ORDER BY path", SELECT_POST_SOURCES))?;
+            let sources = stmt.query_map([root], row_to_post_source)?;
+            collect(sources)
+        }).await
+    }
+
+    async fn upsert_post_source(&self, source: &PostSource) -> Result<()> {
+        let (post_id, root, path, content_hash, post_updated_at, synced_at) = (
+            source.post_id.to_string(),
+            source.root.clone(),
+            source.path.clone(),
+            source.content_hash.clone(),
+            source.post_updated_at.clone(),
+            source.synced_at.clone(),
+        );
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO post_sources (post_id, root, path, content_hash, post_updated_at, synced_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (post_id) DO UPDATE SET root = excluded.root,
+                     path = excluded.path,
+                     content_hash = excluded.content_hash,
+                     post_updated_at = excluded.post_updated_at,
+                     synced_at = excluded.synced_at",
+                params![post_id, root, path, content_hash, post_updated_at, synced_at],
+            )?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl PostOriginRepository for SqliteRepository {
+    async fn find_post_origin(&self, origin: &str) -> Result<Option<Uuid>> {
+        let origin = origin.to_string();
+        run(&self.pool, move |conn| {
+            let post_id = conn.query_row(
+                "SELECT post_id FROM post_origins WHERE origin = ?1",
+                [origin],
+                |row| parse_uuid(row, 0),
+            ).optional()?;
+            Ok(post_id)
+        }).await
+    }
+
+    async fn insert_post_origin(&self, origin: &str, post_id: Uuid) -> Result<()> {
+        let (origin, post_id) = (origin.to_string(), post_id.to_string());
+        run(&self.pool, move |conn| {
+            conn.execute(
+                "INSERT INTO post_origins (origin, post_id) VALUES (?1, ?2)",
+                params![origin, post_id],
+            )?;
+            Ok(())
+        }).await
+    }
+}
+
+#[async_trait]
+impl RedirectRepository for SqliteRepository {
+    async fn insert_redirect(&self, from_path: &str, to_path: &str, created_at: &str) -> Result<bool> {
+        let (from_path, to_path, created_at) = (from_path.to_string(), to_path.to_string(), created_at.to_string());
+        run(&self.pool, move |conn| {
+            let rows = conn.execute(
+                "INSERT INTO redirects (from_path, to_path, created_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT DO NOTHING",
+                params![from_path, to_path, created_at],
+            )?;
+            Ok(rows > 0)
+        }).await
+    }
+
+    async fn find_redirect(&self, from_path: &str) -> Result<Option<String>> {
+        let from_path = from_path.to_string();
+        run(&self.pool, move |conn| {
+            let to_path = conn.query_row(
+                "SELECT to_path FROM redirects WHERE from_path = ?1",
+                [from_path],
+                |row| row.get(0),
+            ).optional()?;
+            Ok(to_path)
+        }).await
+    }
+}
+
+#[async_trait]
+impl Repository for SqliteRepository {
+    fn backend(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        let state = self.pool.state();
+        PoolStatus {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            max_size: self.pool.max_size(),
+        }
+    }
+
+    async fn ping(&self) -> Result<()> {
+        // Give up quickly when the pool is exhausted so a health probe
+        // never hangs for the full connection timeout.
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = pool.get_timeout(PING_TIMEOUT)?;
+            conn.query_row("SELECT 1", [], |row| row.get::<_, i32>(0))?;
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn schema_version(&self) -> Result<i32> {
+        run(&self.pool, |conn| Ok(schema_version(conn)?)).await
+    }
+
+    async fn counts(&self) -> Result<SiteCounts> {
+        run(&self.pool, |conn| {
+            let counts = conn.query_row(
+                "SELECT (SELECT COUNT(*) FROM users), (SELECT COUNT(*) FROM posts), (SELECT COUNT(*) FROM comments)",
+                [],
+                |row| {
+                    Ok(SiteCounts {
+                        users: row.get(0)?,
+                        posts: row.get(1)?,
+                        comments: row.get(2)?,
+                    })
+                },
+            )?;
+            Ok(counts)
+        }).await
+    }
+
+    async fn reindex(&self) -> Result<()> {
+        run(&self.pool, |conn| {
+            conn.execute_batch("REINDEX; ANALYZE;")?;
+            Ok(())
+        }).await
+    }
+
+    async fn vacuum(&self) -> Result<()> {
+        run(&self.pool, |conn| {
+            conn.execute_batch("VACUUM;")?;
+            Ok(())
+        }).await
+    }
+
+    async fn backup(&self, dest: &Path) -> Result<()> {
+        let dest = dest.to_path_buf();
+        run(&self.pool, move |conn| {
+            let mut target = Connection::open(&dest)?;
+            // Copy every page in a single step so the whole copy is read from
+            // one WAL snapshot; stepping in chunks would restart whenever a
+            // writer commits mid-backup.
+            copy_database(conn, &mut target)
+        }).await
+    }
+
+    async fn restore(&self, src: &Path) -> Result<()> {
+        let src = src.to_path_buf();
+        let pool = self.pool.clone();
+        let span = tracing::Span::current();
+        tokio::task::spawn_blocking(move || {
+            let _entered = span.enter();
+            let source = Connection::open(&src)?;
+            let mut conn = pool.get()?;
+            // The backup API writes the new pages in one transaction, so other
+            // pooled connections see either the old database or the new one.
+            copy_database(&source, &mut conn)?;
+            migrate(&conn)?;
+            Ok(())
+        })
+        .await?
+    }
+}
+
+fn copy_database(from: &Connection, to: &mut Connection) -> Result<()> {
+    match Backup::new(from, to)?.step(-1)? {
+        StepResult::Done => Ok(()),
+        other => bail!("database copy did not complete: {:?}", other),
+    }
+}
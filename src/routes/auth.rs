@@ -1,85 +1,512 @@
+use chrono::{Duration, Utc};
 use rocket::form::Form;
+use rocket::request::FlashMessage;
 use rocket::response::{Flash, Redirect};
 use rocket::State;
-use rocket::http::Cookie;
+use rocket::http::{Cookie, SameSite};
 use rocket_dyn_templates::{Template, context};
 use rocket::http::CookieJar;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
 use validator::Validate;
 use rocket::{get, post, uri};
 
-use crate::models::auth::{AuthenticatedUser, hash_password, verify_password};
-use crate::services::db::DbPool;
-use crate::services::user_service;
-use crate::models::user::{CreateUser, LoginUser};
+use crate::csrf::CsrfToken;
+use crate::email::{EmailConfig, Mailer, Verification};
+use crate::feeds::SiteConfig;
+use crate::metrics;
+use crate::models::auth::{AuthenticatedUser, SSO_SESSION_COOKIE, hash_password};
+use crate::models::login_attempt::{LoginClient, LoginOutcome};
+use crate::models::two_factor::TwoFactorCode;
+use crate::models::User;
+use crate::services::db::Database;
+use crate::services::login_service::{self, LoginConfig, LoginResult};
+use crate::services::two_factor_service::{self, LoginStep, TwoFactorConfig};
+use crate::oidc::{self, AuthRequest, AuthResponse};
+use crate::services::{email_verification_service, password_reset_service, sso_service, user_service};
+use crate::models::user::{CreateUser, ForgotPassword, LoginUser, ResetPassword};
+
+/// Holds `<user id>:<expiry as Unix time>` between the password and the
+/// second step of a login.
+pub(crate) const PENDING_LOGIN_COOKIE: &str = "pending_login";
+const PENDING_LOGIN_MINUTES: i64 = 10;
+
+/// Lax, so the cookie survives the redirect back from single sign-on.
+fn start_pending_login(cookies: &CookieJar<'_>, user_id: Uuid) {
+    let expires = (Utc::now() + Duration::minutes(PENDING_LOGIN_MINUTES)).timestamp();
+    let cookie = Cookie::build((PENDING_LOGIN_COOKIE, format!("{}:{}", user_id, expires))).same_site(SameSite::Lax);
+    cookies.add_private(cookie);
+}
+
+/// The user half way through logging in, if their time has not run out.
+async fn pending_login(cookies: &CookieJar<'_>, db: &Database) -> Option<User> {
+    let cookie = cookies.get_private(PENDING_LOGIN_COOKIE)?;
+    let (user_id, expires) = cookie.value().split_once(':')?;
+    if expires.parse::<i64>().ok()? < Utc::now().timestamp() {
+        return None;
+    }
+    let user_id = Uuid::parse_str(user_id).ok()?;
+    user_service::get_user_by_id(db, user_id).await.ok()?.filter(|user| !user.disabled)
+}
+
+/// Starts the session. Returns the new CSRF token that comes with it.
+fn finish_login(cookies: &CookieJar<'_>, user: &User) -> CsrfToken {
+    metrics::record_login(true);
+    cookies.remove_private(Cookie::from(PENDING_LOGIN_COOKIE));
+    cookies.remove_private(Cookie::from(SSO_SESSION_COOKIE));
+    cookies.add_private(Cookie::new("user_id", user.id.to_string()));
+    CsrfToken::renew(cookies)
+}
+
+/// Holds a single sign-on in progress between leaving for the identity
+/// provider and coming back.
+const SSO_REQUEST_COOKIE: &str = "sso_request";
+const SSO_REQUEST_MINUTES: i64 = 10;
+
+#[derive(Serialize, Deserialize)]
+struct PendingSso {
+    request: AuthRequest,
+    /// Set when a signed-in user is linking their account. Their session
+    /// cookie is not sent along when the provider redirects back.
+    link_user_id: Option<Uuid>,
+    expires: i64,
+}
+
+fn sso_redirect_uri(site: &SiteConfig) -> String {
+    format!("{}/login/sso/callback", site.base_url.trim_end_matches('/'))
+}
+
+/// Sends the browser to the identity provider, to sign in or, with
+/// `link_user_id`, to link an account.
+pub(crate) async fn start_sso(
+    client: &oidc::Client,
+    site: &SiteConfig,
+    cookies: &CookieJar<'_>,
+    link_user_id: Option<Uuid>,
+) -> Result<Redirect, String> {
+    if !client.enabled() {
+        return Err("Single sign-on is not set up".to_string());
+    }
+    let (url, request) = client.authorization_request(&sso_redirect_uri(site)).await.map_err(|e| {
+        tracing::warn!(error = %e, "single sign-on could not start");
+        format!("{} is not available right now", client.config().display_name)
+    })?;
+    let expires = (Utc::now() + Duration::minutes(SSO_REQUEST_MINUTES)).timestamp();
+    let pending = PendingSso { request, link_user_id, expires };
+    // Lax, so the cookie comes back with the provider's redirect
+    let cookie = Cookie::build((SSO_REQUEST_COOKIE, json!(pending).to_string())).same_site(SameSite::Lax);
+    cookies.add_private(cookie);
+    Ok(Redirect::to(url))
+}
+
+/// Sets the session cookie after single sign-on. It is Lax rather than the
+/// default Strict because the browser arrives from the provider's site,
+/// and a Strict cookie would be held back from the redirect that follows.
+/// With `skip_two_factor` the session is marked as having had its second
+/// factor checked by the provider.
+fn add_sso_session(cookies: &CookieJar<'_>, user: &User, skip_two_factor: bool) {
+    cookies.remove_private(Cookie::from(PENDING_LOGIN_COOKIE));
+    cookies.add_private(Cookie::build(("user_id", user.id.to_string())).same_site(SameSite::Lax));
+    if skip_two_factor {
+        cookies.add_private(Cookie::build((SSO_SESSION_COOKIE, "1")).same_site(SameSite::Lax));
+    }
+    CsrfToken::renew(cookies);
+}
+
+/// The login page with an error. Used instead of a flash message, since
+/// the flash cookie would not survive the redirect from the provider.
+fn sso_failed(client: &oidc::Client, cookies: &CookieJar<'_>, message: impl Into<String>) -> Template {
+    metrics::record_login(false);
+    Template::render("login", context! {
+        csrf_token: CsrfToken::from_cookies(cookies),
+        flash: json!({ "kind": "error", "message": message.into() }),
+        sso: client.enabled().then(|| client.config().display_name.clone()),
+    })
+}
+
+fn login_expired() -> Flash<Redirect> {
+    Flash::error(Redirect::to(uri!(login_page)), "Your login timed out. Please log in again.")
+}
 
 #[get("/register")]
-pub fn register_page(_user: Option<AuthenticatedUser>) -> Template {
+pub fn register_page(_user: Option<AuthenticatedUser>, csrf: CsrfToken, email: &State<EmailConfig>) -> Template {
     Template::render("register", context! {
-        user: _user.map(|u| u.0)
+        user: _user.map(|u| u.0),
+        csrf_token: csrf,
+        email_required: email.verification == Verification::Required,
     })
 }
 
 #[post("/register", data = "<user>")]
-pub async fn register(user: Form<CreateUser>, pool: &State<DbPool>, _cookies: &CookieJar<'_>) -> Result<Flash<Redirect>, Flash<Redirect>> {
+pub async fn register(
+    user: Form<CreateUser>,
+    db: &State<Database>,
+    mailer: &State<Mailer>,
+    site: &State<SiteConfig>,
+    email: &State<EmailConfig>,
+    _cookies: &CookieJar<'_>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
     if let Err(e) = user.validate() {
         return Err(Flash::error(Redirect::to("/register"), e.to_string()));
     }
+    if email.verification == Verification::Required && user.email.trim().is_empty() {
+        return Err(Flash::error(Redirect::to("/register"), "An email address is required"));
+    }
 
-    let password_hash = match hash_password(&user.password) {
+    let password_hash = match hash_password(&user.password).await {
         Ok(hash) => hash,
         Err(_) => return Err(Flash::error(Redirect::to("/register"), "Failed to hash password"))
     };
 
-    match user_service::create_user(pool, user.into_inner(), password_hash).await {
-        Ok(_) => Ok(Flash::success(Redirect::to("/login"), "Registration successful! Please login.")),
-        Err(_) => Err(Flash::error(Redirect::to("/register"), "Username already taken"))
+    let user = match user_service::create_user(db, user.into_inner(), password_hash).await {
+        Ok(user) => user,
+        Err(e) => return Err(Flash::error(Redirect::to("/register"), e.to_string()))
+    };
+
+    if email.verification == Verification::Off || user.email.is_none() {
+        return Ok(Flash::success(Redirect::to("/login"), "Registration successful! Please login."));
     }
+    // A slow mail server should not hold up registration; failures are
+    // logged by the service and the link can be sent again from the profile.
+    let (db, mailer, base_url) = (db.inner().clone(), mailer.inner().clone(), site.base_url.clone());
+    tokio::spawn(async move {
+        email_verification_service::send_verification(&db, &mailer, &base_url, &user).await.ok();
+    });
+    Ok(Flash::success(
+        Redirect::to("/login"),
+        "Registration successful! Check your email for a link to confirm your address, then login.",
+    ))
 }
 
 #[get("/login")]
-pub fn login_page(_user: Option<AuthenticatedUser>) -> Template {
+pub fn login_page(
+    _user: Option<AuthenticatedUser>,
+    csrf: CsrfToken,
+    flash: Option<FlashMessage<'_>>,
+    sso: &State<oidc::Client>,
+) -> Template {
     Template::render("login", context! {
-        user: _user.map(|u| u.0)
+        user: _user.map(|u| u.0),
+        csrf_token: csrf,
+        flash: flash,
+        sso: sso.enabled().then(|| sso.config().display_name.clone()),
     })
 }
 
+#[get("/login/sso")]
+pub async fn sso_login(
+    client: &State<oidc::Client>,
+    site: &State<SiteConfig>,
+    cookies: &CookieJar<'_>,
+) -> Result<Redirect, Flash<Redirect>> {
+    start_sso(client, site, cookies, None).await.map_err(|e| Flash::error(Redirect::to(uri!(login_page)), e))
+}
+
+#[get("/login/sso/callback?<response..>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn sso_callback(
+    response: AuthResponse,
+    login_client: LoginClient,
+    client: &State<oidc::Client>,
+    site: &State<SiteConfig>,
+    db: &State<Database>,
+    config: &State<LoginConfig>,
+    two_factor: &State<TwoFactorConfig>,
+    cookies: &CookieJar<'_>,
+) -> Result<Redirect, Template> {
+    let pending = cookies
+        .get_private(SSO_REQUEST_COOKIE)
+        .and_then(|cookie| serde_json::from_str::<PendingSso>(cookie.value()).ok())
+        .filter(|pending| pending.expires >= Utc::now().timestamp());
+    cookies.remove_private(Cookie::from(SSO_REQUEST_COOKIE));
+    let display_name = &client.config().display_name;
+    let pending = pending.ok_or_else(|| sso_failed(client, cookies, "Your sign-in timed out. Please try again."))?;
+    if let Some(error) = &response.error {
+        let reason = response.error_description.as_ref().unwrap_or(error);
+        return Err(sso_failed(client, cookies, format!("{} sign-in failed: {}", display_name, reason)));
+    }
+    let code = response
+        .code
+        .as_deref()
+        .filter(|_| response.state.as_deref() == Some(pending.request.state.as_str()))
+        .ok_or_else(|| sso_failed(client, cookies, format!("{} sign-in failed. Please try again.", display_name)))?;
+
+    let claims = client.finish(code, &pending.request, &sso_redirect_uri(site)).await.map_err(|e| {
+        tracing::warn!(error = %e, "single sign-on failed");
+        sso_failed(client, cookies, format!("{} sign-in failed. Please try again.", display_name))
+    })?;
+    let link_to = match pending.link_user_id {
+        Some(user_id) => match user_service::get_user_by_id(db, user_id).await {
+            Ok(Some(user)) => Some(user),
+            _ => return Err(sso_failed(client, cookies, "Your account could not be found")),
+        },
+        None => None,
+    };
+    let user = sso_service::sign_in(db, client.config(), &claims, link_to.as_ref())
+        .await
+        .map_err(|e| sso_failed(client, cookies, e.to_string()))?;
+
+    if link_to.is_some() {
+        add_sso_session(cookies, &user, false);
+        return Ok(Redirect::to(uri!(crate::routes::profile::profile_page)));
+    }
+    if client.config().trust_provider_mfa {
+        login_service::record_attempt(db, config, user.id, &login_client, LoginOutcome::Success).await.ok();
+        metrics::record_login(true);
+        add_sso_session(cookies, &user, true);
+        return Ok(Redirect::to("/"));
+    }
+
+    // Otherwise the blog's own second factor applies, as after a password
+    match two_factor_service::login_step(db, two_factor, &user).await {
+        Ok(LoginStep::Done) => {
+            login_service::record_attempt(db, config, user.id, &login_client, LoginOutcome::Success).await.ok();
+            metrics::record_login(true);
+            add_sso_session(cookies, &user, false);
+            Ok(Redirect::to("/"))
+        }
+        Ok(LoginStep::Code) => {
+            start_pending_login(cookies, user.id);
+            Ok(Redirect::to(uri!(two_factor_page)))
+        }
+        Ok(LoginStep::Enrol) => {
+            start_pending_login(cookies, user.id);
+            Ok(Redirect::to(uri!(two_factor_setup_page)))
+        }
+        Err(_) => Err(sso_failed(client, cookies, "An error occurred")),
+    }
+}
+
+/// "45 seconds" or "3 minutes", rounded up.
+fn wait_time(wait: Duration) -> String {
+    let seconds = wait.num_seconds().max(1);
+    if seconds < 60 {
+        format!("{} second{}", seconds, if seconds == 1 { "" } else { "s" })
+    } else {
+        let minutes = (seconds + 59) / 60;
+        format!("{} minute{}", minutes, if minutes == 1 { "" } else { "s" })
+    }
+}
+
 #[post("/login", data = "<credentials>")]
 pub async fn login(
     credentials: Form<LoginUser>,
-    pool: &State<DbPool>,
+    client: LoginClient,
+    db: &State<Database>,
+    mailer: &State<Mailer>,
+    config: &State<LoginConfig>,
+    two_factor: &State<TwoFactorConfig>,
     cookies: &CookieJar<'_>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    let user = match user_service::get_user_by_username(pool, &credentials.username).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            return Err(Flash::error(
-                Redirect::to(uri!(login_page)),
-                "Invalid username or password",
+    let failed = |message: String| {
+        metrics::record_login(false);
+        Flash::error(Redirect::to(uri!(login_page)), message)
+    };
+    let result = login_service::authenticate(db, mailer, config, &client, &credentials.username, &credentials.password);
+    let user = match result.await {
+        Ok(LoginResult::Success(user)) => user,
+        Ok(LoginResult::Disabled) => return Err(failed("This account has been disabled".to_string())),
+        Ok(LoginResult::Failed) => return Err(failed("Invalid username or password".to_string())),
+        Ok(LoginResult::Locked { retry_after }) => {
+            return Err(failed(format!("Too many failed logins. Try again in {}.", wait_time(retry_after))))
+        }
+        Err(_) => return Err(Flash::error(Redirect::to(uri!(login_page)), "An error occurred")),
+    };
+
+    match two_factor_service::login_step(db, two_factor, &user).await {
+        Ok(LoginStep::Done) => {
+            login_service::record_attempt(db, config, user.id, &client, LoginOutcome::Success).await.ok();
+            finish_login(cookies, &user);
+            Ok(Flash::success(Redirect::to("/"), "Successfully logged in"))
+        }
+        Ok(LoginStep::Code) => {
+            start_pending_login(cookies, user.id);
+            Ok(Flash::success(
+                Redirect::to(uri!(two_factor_page)),
+                "Enter the code from your authenticator app",
             ))
         }
-        Err(_) => {
-            return Err(Flash::error(
-                Redirect::to(uri!(login_page)),
-                "An error occurred",
+        Ok(LoginStep::Enrol) => {
+            start_pending_login(cookies, user.id);
+            Ok(Flash::success(
+                Redirect::to(uri!(two_factor_setup_page)),
+                "Your account needs two-factor authentication. Set it up to finish logging in.",
             ))
         }
-    };
+        Err(_) => Err(Flash::error(Redirect::to(uri!(login_page)), "An error occurred")),
+    }
+}
+
+#[get("/login/two-factor")]
+pub async fn two_factor_page(
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    db: &State<Database>,
+    cookies: &CookieJar<'_>,
+) -> Result<Template, Flash<Redirect>> {
+    pending_login(cookies, db).await.ok_or_else(login_expired)?;
+    Ok(Template::render("two_factor", context! {
+        flash: flash,
+        csrf_token: csrf,
+        title: "Two-Factor Authentication",
+    }))
+}
 
-    match verify_password(&credentials.password, &user.password_hash) {
-        Ok(true) => {
-            cookies.add_private(Cookie::new("user_id", user.id.to_string()));
+#[post("/login/two-factor", data = "<form>")]
+pub async fn two_factor_login(
+    form: Form<TwoFactorCode>,
+    client: LoginClient,
+    db: &State<Database>,
+    config: &State<LoginConfig>,
+    cookies: &CookieJar<'_>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let user = pending_login(cookies, db).await.ok_or_else(login_expired)?;
+    match two_factor_service::verify_code(db, user.id, &form.code).await {
+        Ok(()) => {
+            login_service::record_attempt(db, config, user.id, &client, LoginOutcome::Success).await.ok();
+            finish_login(cookies, &user);
             Ok(Flash::success(Redirect::to("/"), "Successfully logged in"))
         }
-        _ => Err(Flash::error(
-            Redirect::to(uri!(login_page)),
-            "Invalid username or password",
-        )),
+        Err(e) => {
+            login_service::record_attempt(db, config, user.id, &client, LoginOutcome::WrongCode).await.ok();
+            metrics::record_login(false);
+            Err(Flash::error(Redirect::to(uri!(two_factor_page)), e.to_string()))
+        }
     }
 }
 
+#[get("/login/two-factor/setup")]
+pub async fn two_factor_setup_page(
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    db: &State<Database>,
+    config: &State<TwoFactorConfig>,
+    cookies: &CookieJar<'_>,
+) -> Result<Template, Flash<Redirect>> {
+    let user = pending_login(cookies, db).await.ok_or_else(login_expired)?;
+    let enrolment = two_factor_service::begin_enrolment(db, config, &user)
+        .await
+        .map_err(|e| Flash::error(Redirect::to(uri!(login_page)), e.to_string()))?;
+    Ok(Template::render("two_factor_setup", context! {
+        flash: flash,
+        csrf_token: csrf,
+        enrolment: enrolment,
+        action: uri!(two_factor_setup).to_string(),
+        title: "Set Up Two-Factor Authentication",
+    }))
+}
+
+#[post("/login/two-factor/setup", data = "<form>")]
+pub async fn two_factor_setup(
+    form: Form<TwoFactorCode>,
+    client: LoginClient,
+    db: &State<Database>,
+    config: &State<LoginConfig>,
+    cookies: &CookieJar<'_>,
+) -> Result<Template, Flash<Redirect>> {
+    let user = pending_login(cookies, db).await.ok_or_else(login_expired)?;
+    let codes = two_factor_service::confirm_enrolment(db, user.id, &form.code)
+        .await
+        .map_err(|e| Flash::error(Redirect::to(uri!(two_factor_setup_page)), e.to_string()))?;
+    login_service::record_attempt(db, config, user.id, &client, LoginOutcome::Success).await.ok();
+    let csrf = finish_login(cookies, &user);
+    Ok(Template::render("recovery_codes", context! {
+        user: user,
+        csrf_token: csrf,
+        codes: codes,
+        next: "/",
+        title: "Recovery Codes",
+    }))
+}
+
 #[post("/logout")]
 pub fn logout(_cookies: &CookieJar<'_>) -> Flash<Redirect> {
     let cookie = rocket::http::Cookie::new("user_id", "");
     _cookies.remove_private(cookie);
+    _cookies.remove_private(Cookie::from(SSO_SESSION_COOKIE));
+    CsrfToken::renew(_cookies);
     Flash::success(Redirect::to("/"), "Logged out successfully!")
 }
+
+#[get("/forgot-password")]
+pub fn forgot_password_page(user: Option<AuthenticatedUser>, csrf: CsrfToken) -> Template {
+    Template::render("forgot_password", context! {
+        user: user.map(|u| u.0),
+        csrf_token: csrf,
+        title: "Forgot Password",
+    })
+}
+
+#[post("/forgot-password", data = "<form>")]
+pub async fn forgot_password(
+    form: Form<ForgotPassword>,
+    db: &State<Database>,
+    mailer: &State<Mailer>,
+    site: &State<SiteConfig>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    if let Err(e) = form.validate() {
+        return Err(Flash::error(Redirect::to(uri!(forgot_password_page)), e.to_string()));
+    }
+
+    // Sent in the background so the response time does not reveal whether
+    // the address belongs to an account. Failures are logged by the service.
+    let (db, mailer, base_url) = (db.inner().clone(), mailer.inner().clone(), site.base_url.clone());
+    let email = form.into_inner().email;
+    tokio::spawn(async move {
+        password_reset_service::request_reset(&db, &mailer, &base_url, &email).await.ok();
+    });
+
+    Ok(Flash::success(
+        Redirect::to(uri!(login_page)),
+        "If an account uses that address, a link to reset its password is on its way.",
+    ))
+}
+
+#[get("/reset-password?<token>")]
+pub fn reset_password_page(token: &str, user: Option<AuthenticatedUser>, csrf: CsrfToken) -> Template {
+    Template::render("reset_password", context! {
+        user: user.map(|u| u.0),
+        csrf_token: csrf,
+        token: token,
+        title: "Reset Password",
+    })
+}
+
+#[post("/reset-password", data = "<form>")]
+pub async fn reset_password(
+    form: Form<ResetPassword>,
+    db: &State<Database>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    if let Err(e) = form.validate() {
+        return Err(Flash::error(
+            Redirect::to(uri!(reset_password_page(token = &form.token))),
+            e.to_string(),
+        ));
+    }
+
+    match password_reset_service::reset_password(db, &form.token, &form.new_password).await {
+        Ok(()) => Ok(Flash::success(
+            Redirect::to(uri!(login_page)),
+            "Your password has been reset. Please log in.",
+        )),
+        Err(e) => Err(Flash::error(Redirect::to(uri!(forgot_password_page)), e.to_string())),
+    }
+}
+
+#[get("/verify-email?<token>")]
+pub async fn verify_email(
+    token: &str,
+    user: Option<AuthenticatedUser>,
+    db: &State<Database>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    // Signed-in users go back to their settings, everyone else to log in
+    let next = match user {
+        Some(_) => Redirect::to(uri!(crate::routes::profile::profile_page)),
+        None => Redirect::to(uri!(login_page)),
+    };
+    match email_verification_service::confirm_email(db, token).await {
+        Ok(email) => Ok(Flash::success(next, format!("{} is now verified", email))),
+        Err(e) => Err(Flash::error(next, e.to_string())),
+    }
+}
@@ -0,0 +1,58 @@
+use rocket::http::{ContentType, Status};
+use rocket::{get, uri, State};
+use rocket_dyn_templates::{Template, context};
+
+use crate::csrf::CsrfToken;
+use crate::feeds::{self, FeedEntry, SiteConfig};
+use crate::models::auth::AuthenticatedUser;
+use crate::models::post::Post;
+use crate::routes::server_error;
+use crate::services::db::Database;
+use crate::services::{profile_service, tag_service};
+
+/// Number of most recent posts in an author's feed.
+const FEED_SIZE: usize = 20;
+
+#[get("/authors/<username>")]
+pub async fn author_page(
+    username: &str,
+    user: Option<AuthenticatedUser>,
+    csrf: CsrfToken,
+    db: &State<Database>,
+) -> Result<Option<Template>, Status> {
+    let Some(author) = profile_service::get_author_page(db, username).await.map_err(server_error)? else {
+        return Ok(None);
+    };
+    let title = author.name.clone();
+    Ok(Some(Template::render("author", context! {
+        user: user.map(|u| u.0),
+        csrf_token: csrf,
+        feed_url: uri!(author_feed(username)).to_string(),
+        author: author,
+        title: title,
+    })))
+}
+
+#[get("/authors/<username>/feed.xml")]
+pub async fn author_feed(
+    username: &str,
+    db: &State<Database>,
+    site: &State<SiteConfig>,
+) -> Result<Option<(ContentType, Template)>, Status> {
+    let Some(author) = profile_service::get_author_page(db, username).await.map_err(server_error)? else {
+        return Ok(None);
+    };
+    let posts = &author.posts[..author.posts.len().min(FEED_SIZE)];
+    let mut entries = Vec::with_capacity(posts.len());
+    for post in posts {
+        let tags = tag_service::get_post_tags(db, post.id).await.map_err(server_error)?;
+        entries.push(FeedEntry { post, tags: tags.into_iter().map(|tag| tag.name).collect() });
+    }
+
+    let feed_path = uri!(author_feed(username)).to_string();
+    let title = format!("Posts by {}", author.name);
+    let context = feeds::atom_context(&site.base_url, &feed_path, &title, &entries, |post: &Post| {
+        format!("/posts/{}", post.id)
+    });
+    Ok(Some((ContentType::new("application", "atom+xml"), Template::render("feed", context))))
+}
@@ -0,0 +1,151 @@
+use rocket::form::Form;
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect, Responder};
+use rocket::{delete, get, put, uri, State};
+use rocket_dyn_templates::{context, Template};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::csrf::CsrfToken;
+use crate::feeds::SiteConfig;
+use crate::models::auth::AuthenticatedUser;
+use crate::models::bookmark::SaveBookmark;
+use crate::models::post::Post;
+use crate::routes::server_error;
+use crate::services::bookmark_service::{self, ExportedBookmark, SavedPost};
+use crate::services::db::Database;
+use crate::services::post_service;
+
+/// A response the browser saves as a file instead of showing it.
+#[derive(Responder)]
+pub struct Download<R> {
+    inner: (ContentType, R),
+    disposition: Header<'static>,
+}
+
+impl<R> Download<R> {
+    fn new(content_type: ContentType, body: R, filename: &str) -> Self {
+        Download {
+            inner: (content_type, body),
+            disposition: Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", filename)),
+        }
+    }
+}
+
+/// Where to go after saving or removing a bookmark: `back` when it is a
+/// path on this site, or else the post.
+fn back_to(back: Option<&str>, post_id: &str) -> String {
+    match back {
+        Some(path) if path.starts_with('/') && !path.starts_with("//") && !path.contains('\\') => path.to_string(),
+        _ => format!("/posts/{}", post_id),
+    }
+}
+
+fn post_path(post: &Post) -> String {
+    uri!(crate::routes::posts::get_post(post.id.to_string())).to_string()
+}
+
+#[get("/bookmarks?<folder>")]
+pub async fn bookmarks_page(
+    folder: Option<&str>,
+    flash: Option<FlashMessage<'_>>,
+    user: AuthenticatedUser,
+    csrf: CsrfToken,
+    db: &State<Database>,
+) -> Result<Template, Status> {
+    let saved = bookmark_service::get_bookmarks(db, user.0.id).await.map_err(server_error)?;
+    let folders = bookmark_service::folders(&saved);
+    let total = saved.len();
+    let shown: Vec<SavedPost> = match folder {
+        Some(folder) => saved.into_iter().filter(|entry| entry.bookmark.folder == folder).collect(),
+        None => saved,
+    };
+
+    Ok(Template::render("bookmarks", context! {
+        user: user.0,
+        csrf_token: csrf,
+        flash: flash,
+        bookmarks: shown,
+        folders: folders,
+        folder: folder,
+        total: total,
+        title: "Bookmarks",
+    }))
+}
+
+#[put("/posts/<id>/bookmark?<back>", data = "<form>")]
+pub async fn save_bookmark(
+    id: &str,
+    back: Option<&str>,
+    form: Form<SaveBookmark>,
+    user: AuthenticatedUser,
+    db: &State<Database>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let back = back_to(back, id);
+    let uuid = match Uuid::parse_str(id) {
+        Ok(uuid) => uuid,
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid post ID")),
+    };
+    if let Err(e) = form.validate() {
+        return Err(Flash::error(Redirect::to(back), e.to_string()));
+    }
+    match post_service::get_post(db, uuid).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(Flash::error(Redirect::to("/"), "Post not found")),
+        Err(_) => return Err(Flash::error(Redirect::to(back), "Failed to fetch post")),
+    }
+
+    match bookmark_service::save_bookmark(db, user.0.id, uuid, form.into_inner()).await {
+        Ok(_) => Ok(Flash::success(Redirect::to(back), "Bookmark saved")),
+        Err(_) => Err(Flash::error(Redirect::to(back), "Failed to save bookmark")),
+    }
+}
+
+#[delete("/posts/<id>/bookmark?<back>")]
+pub async fn remove_bookmark(
+    id: &str,
+    back: Option<&str>,
+    user: AuthenticatedUser,
+    db: &State<Database>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let back = back_to(back, id);
+    let uuid = match Uuid::parse_str(id) {
+        Ok(uuid) => uuid,
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid post ID")),
+    };
+
+    match bookmark_service::remove_bookmark(db, user.0.id, uuid).await {
+        Ok(_) => Ok(Flash::success(Redirect::to(back), "Bookmark removed")),
+        Err(_) => Err(Flash::error(Redirect::to(back), "Failed to remove bookmark")),
+    }
+}
+
+/// The user's bookmarks as JSON, most recently saved first.
+#[get("/bookmarks/export.json")]
+pub async fn export_json(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    site: &State<SiteConfig>,
+) -> Result<Download<String>, Status> {
+    let saved = bookmark_service::get_bookmarks(db, user.0.id).await.map_err(server_error)?;
+    let bookmarks: Vec<ExportedBookmark> = bookmark_service::export(&saved, &site.base_url, post_path);
+    let body = serde_json::to_string_pretty(&bookmarks).map_err(|e| server_error(e.into()))?;
+    Ok(Download::new(ContentType::JSON, body, "bookmarks.json"))
+}
+
+/// The user's bookmarks in the Netscape bookmark file format that browsers
+/// import, with a folder for each of theirs.
+#[get("/bookmarks/export.html")]
+pub async fn export_html(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    site: &State<SiteConfig>,
+) -> Result<Download<Template>, Status> {
+    let saved = bookmark_service::get_bookmarks(db, user.0.id).await.map_err(server_error)?;
+    let context = context! {
+        folders: bookmark_service::folders(&saved),
+        bookmarks: bookmark_service::export(&saved, &site.base_url, post_path),
+    };
+    Ok(Download::new(ContentType::HTML, Template::render("bookmarks_export", context), "bookmarks.html"))
+}
@@ -6,18 +6,27 @@ use rocket::{get, post, put, delete};
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::csrf::CsrfToken;
+use crate::email::EmailConfig;
 use crate::models::auth::AuthenticatedUser;
 use crate::models::comment::CreateComment;
-use crate::services::db::DbPool;
-use crate::services::comment_service;
+use crate::services::db::Database;
+use crate::services::{comment_service, email_verification_service};
 
 #[post("/posts/<post_id>/comments", data = "<comment>")]
-pub async fn create_comment(post_id: &str, user: AuthenticatedUser, comment: Form<CreateComment>, pool: &State<DbPool>) -> Result<Flash<Redirect>, Flash<Redirect>> {
+pub async fn create_comment(post_id: &str, user: AuthenticatedUser, comment: Form<CreateComment>, db: &State<Database>, email: &State<EmailConfig>) -> Result<Flash<Redirect>, Flash<Redirect>> {
     let uuid = match Uuid::parse_str(post_id) {
         Ok(uuid) => uuid,
         Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid post ID"))
     };
 
+    if !email_verification_service::can_comment(email.verification, &user.0) {
+        return Err(Flash::error(
+            Redirect::to(format!("/posts/{}", post_id)),
+            "Please verify your email address before commenting",
+        ));
+    }
+
     if let Err(e) = comment.validate() {
         return Err(Flash::error(
             Redirect::to(format!("/posts/{}", post_id)),
@@ -25,7 +34,7 @@ pub async fn create_comment(post_id: &str, user: AuthenticatedUser, comment: For
         ));
     }
 
-    match comment_service::create_comment(pool, comment.into_inner(), uuid, user.0.id).await {
+    match comment_service::create_comment(db, comment.into_inner(), uuid, user.0.id).await {
         Ok(_) => Ok(Flash::success(
             Redirect::to(format!("/posts/{}", post_id)),
             "Comment added successfully!",
@@ -42,14 +51,15 @@ pub async fn edit_comment_page(
     post_id: &str,
     comment_id: &str,
     user: AuthenticatedUser,
-    pool: &State<DbPool>,
+    csrf: CsrfToken,
+    db: &State<Database>,
 ) -> Result<Template, Flash<Redirect>> {
     let comment_id = match Uuid::parse_str(comment_id) {
         Ok(uuid) => uuid,
         Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid comment ID"))
     };
 
-    let comment = match comment_service::get_comment(pool, comment_id).await {
+    let comment = match comment_service::get_comment(db, comment_id).await {
         Ok(Some(comment)) => comment,
         Ok(None) => return Err(Flash::error(Redirect::to("/"), "Comment not found")),
         Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch comment"))
@@ -66,6 +76,7 @@ pub async fn edit_comment_page(
         "edit_comment",
         context! {
             user: user.0,
+            csrf_token: csrf,
             comment: comment,
         },
     ))
@@ -77,7 +88,7 @@ pub async fn update_comment(
     comment_id: &str,
     user: AuthenticatedUser,
     comment: Form<CreateComment>,
-    pool: &State<DbPool>,
+    db: &State<Database>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     let comment_id = match Uuid::parse_str(comment_id) {
         Ok(uuid) => uuid,
@@ -85,7 +96,7 @@ pub async fn update_comment(
     };
 
     // Verify comment exists and user owns it
-    let existing_comment = match comment_service::get_comment(pool, comment_id).await {
+    let existing_comment = match comment_service::get_comment(db, comment_id).await {
         Ok(Some(comment)) => comment,
         Ok(None) => return Err(Flash::error(Redirect::to("/"), "Comment not found")),
         Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch comment"))
@@ -105,7 +116,7 @@ pub async fn update_comment(
         ));
     }
 
-    match comment_service::update_comment(pool, comment_id, comment.content.clone()).await {
+    match comment_service::update_comment(db, comment_id, comment.content.clone()).await {
         Ok(_) => Ok(Flash::success(
             Redirect::to(format!("/posts/{}", post_id)),
             "Comment updated successfully!",
@@ -122,7 +133,7 @@ pub async fn delete_comment(
     post_id: &str,
     comment_id: &str,
     user: AuthenticatedUser,
-    pool: &State<DbPool>,
+    db: &State<Database>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     let comment_id = match Uuid::parse_str(comment_id) {
         Ok(uuid) => uuid,
@@ -130,7 +141,7 @@ pub async fn delete_comment(
     };
 
     // Verify comment exists and user owns it
-    let comment = match comment_service::get_comment(pool, comment_id).await {
+    let comment = match comment_service::get_comment(db, comment_id).await {
         Ok(Some(comment)) => comment,
         Ok(None) => return Err(Flash::error(Redirect::to("/"), "Comment not found")),
         Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch comment"))
@@ -143,7 +154,7 @@ pub async fn delete_comment(
         ));
     }
 
-    match comment_service::delete_comment(pool, comment_id).await {
+    match comment_service::delete_comment(db, comment_id).await {
         Ok(_) => Ok(Flash::success(
             Redirect::to(format!("/posts/{}", post_id)),
             "Comment deleted successfully!",
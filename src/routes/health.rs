@@ -0,0 +1,64 @@
+use rocket::http::Status;
+use rocket::serde::json::{json, Json, Value};
+use rocket::{get, State};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::services::db::Database;
+use crate::services::health_service;
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Directory that must be writable for the instance to be ready.
+    pub uploads_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: Option<String>,
+}
+
+impl CheckResult {
+    fn from_result<T: ToString>(name: &'static str, result: anyhow::Result<T>) -> Self {
+        match result {
+            Ok(value) => CheckResult { name, ok: true, detail: Some(value.to_string()) },
+            Err(e) => CheckResult { name, ok: false, detail: Some(e.to_string()) },
+        }
+    }
+}
+
+#[get("/healthz")]
+pub fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+#[get("/readyz")]
+pub async fn readyz(db: &State<Database>, config: &State<HealthConfig>) -> (Status, Json<Value>) {
+    let mut checks = vec![
+        CheckResult::from_result(
+            "database",
+            health_service::check_database(db).await.map(|_| "connected"),
+        ),
+        CheckResult::from_result(
+            "schema",
+            health_service::check_schema(db).await.map(|v| format!("version {}", v)),
+        ),
+    ];
+
+    if let Some(dir) = &config.uploads_dir {
+        checks.push(CheckResult::from_result(
+            "uploads_dir",
+            health_service::check_writable(dir).map(|_| dir.display().to_string()),
+        ));
+    }
+
+    let ready = checks.iter().all(|check| check.ok);
+    let status = if ready { Status::Ok } else { Status::ServiceUnavailable };
+    (status, Json(json!({
+        "status": if ready { "ready" } else { "unavailable" },
+        "checks": checks,
+    })))
+}
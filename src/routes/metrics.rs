@@ -0,0 +1,46 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{get, State};
+use subtle::ConstantTimeEq;
+
+use crate::metrics::{render, MetricsConfig};
+use crate::services::db::Database;
+use crate::services::stats_service;
+
+/// Request guard enforcing the `metrics` settings in `Rocket.toml`.
+pub struct MetricsAccess;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MetricsAccess {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let config = match request.rocket().state::<MetricsConfig>() {
+            Some(config) => config,
+            None => return Outcome::Error((Status::InternalServerError, ())),
+        };
+
+        if !config.allowed_ips.is_empty() {
+            match request.client_ip() {
+                Some(ip) if config.allowed_ips.contains(&ip) => {}
+                _ => return Outcome::Error((Status::Forbidden, ())),
+            }
+        }
+
+        if let Some(token) = &config.token {
+            let expected = format!("Bearer {}", token);
+            match request.headers().get_one("Authorization") {
+                Some(value) if bool::from(value.as_bytes().ct_eq(expected.as_bytes())) => {}
+                _ => return Outcome::Error((Status::Unauthorized, ())),
+            }
+        }
+
+        Outcome::Success(MetricsAccess)
+    }
+}
+
+#[get("/metrics")]
+pub async fn metrics(_access: MetricsAccess, db: &State<Database>) -> String {
+    let counts = stats_service::get_counts(db).await.ok();
+    render(db.pool_status(), counts)
+}
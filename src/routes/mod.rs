@@ -2,29 +2,111 @@ pub mod auth;
 pub mod posts;
 pub mod profile;
 pub mod comments;
+pub mod health;
+pub mod metrics;
+pub mod redirects;
+pub mod authors;
+pub mod seo;
+pub mod series;
+pub mod reactions;
+pub mod bookmarks;
 
+use rocket::http::Status;
+use rocket::response::{Flash, Redirect, Responder};
 use rocket::{routes, Route};
 
+use crate::fairings::request_log::in_request_span;
+
+/// Logs an error that keeps a page from being shown and answers 500.
+pub fn server_error(error: anyhow::Error) -> Status {
+    tracing::error!(error = %format!("{:#}", error), "failed to load page data");
+    Status::InternalServerError
+}
+
+/// Why a page was not shown: a redirect with a message for the reader, or
+/// an error status.
+#[derive(Responder)]
+pub enum PageError {
+    Redirect(Box<Flash<Redirect>>),
+    Status(Status),
+}
+
+impl From<Flash<Redirect>> for PageError {
+    fn from(redirect: Flash<Redirect>) -> Self {
+        PageError::Redirect(Box::new(redirect))
+    }
+}
+
+impl From<Status> for PageError {
+    fn from(status: Status) -> Self {
+        PageError::Status(status)
+    }
+}
+
+/// Every route of the site, each run inside its request's span.
 pub fn routes() -> Vec<Route> {
-    routes![
+    in_request_span(routes![
         auth::login_page,
         auth::login,
+        auth::two_factor_page,
+        auth::two_factor_login,
+        auth::two_factor_setup_page,
+        auth::two_factor_setup,
+        auth::sso_login,
+        auth::sso_callback,
         auth::register_page,
         auth::register,
         auth::logout,
+        auth::forgot_password_page,
+        auth::forgot_password,
+        auth::reset_password_page,
+        auth::reset_password,
+        auth::verify_email,
         posts::index,
         posts::new_post,
         posts::create_post,
         posts::get_post,
         posts::edit_post_page,
         posts::update_post,
+        posts::update_post_seo,
+        posts::update_post_excerpt,
+        posts::update_post_series,
         posts::delete_post,
         profile::profile_page,
         profile::update_username,
         profile::update_password,
+        profile::update_details,
+        profile::update_email,
+        profile::resend_verification,
+        profile::two_factor_page,
+        profile::enable_two_factor,
+        profile::regenerate_recovery_codes,
+        profile::disable_two_factor,
+        profile::link_sso,
+        profile::login_history,
+        authors::author_page,
+        authors::author_feed,
+        series::series_page,
+        series::series_feed,
         comments::create_comment,
         comments::edit_comment_page,
         comments::update_comment,
         comments::delete_comment,
-    ]
+        reactions::add_post_reaction,
+        reactions::remove_post_reaction,
+        reactions::add_comment_reaction,
+        reactions::remove_comment_reaction,
+        bookmarks::bookmarks_page,
+        bookmarks::save_bookmark,
+        bookmarks::remove_bookmark,
+        bookmarks::export_json,
+        bookmarks::export_html,
+        metrics::metrics,
+        health::healthz,
+        health::readyz,
+        seo::robots,
+        seo::sitemap_xml,
+        seo::sitemap_part,
+        redirects::redirect,
+    ])
 }
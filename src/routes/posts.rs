@@ -1,33 +1,76 @@
 use rocket::form::Form;
+use rocket::http::Status;
+use rocket::request::{FlashMessage, FromRequest, Outcome, Request};
 use rocket::response::{Flash, Redirect};
 use rocket::State;
 use rocket_dyn_templates::{Template, context};
 use rocket::{get, post, put, delete, uri};
+use std::collections::HashMap;
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::csrf::CsrfToken;
+use crate::email::EmailConfig;
+use crate::feeds::SiteConfig;
+use crate::markdown;
 use crate::models::auth::AuthenticatedUser;
-use crate::models::post::{CreatePost};
-use crate::services::db::DbPool;
-use crate::services::{post_service, comment_service};
+use crate::models::post::{CreatePost, PostSort, UpdatePostExcerpt, UpdatePostSeo};
+use crate::models::series::UpdatePostSeries;
+use crate::security_headers::CspNonce;
+use crate::seo;
+use crate::services::db::Database;
+use crate::routes::{series, server_error, PageError};
+use crate::services::reaction_service::{self, ReactionsConfig};
+use crate::services::{bookmark_service, post_service, comment_service, email_verification_service, series_service, tag_service};
 
-#[get("/")]
-pub async fn index(user: Option<AuthenticatedUser>, pool: &State<DbPool>) -> Template {
-    let posts = post_service::get_posts(pool)
-        .await
-        .unwrap_or_else(|_| vec![]);
+/// The settings that decide what readers can do on a post page.
+pub struct ReaderSettings<'r> {
+    pub email: &'r EmailConfig,
+    pub reactions: &'r ReactionsConfig,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReaderSettings<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let rocket = request.rocket();
+        match (rocket.state::<EmailConfig>(), rocket.state::<ReactionsConfig>()) {
+            (Some(email), Some(reactions)) => Outcome::Success(ReaderSettings { email, reactions }),
+            _ => Outcome::Error((Status::InternalServerError, ())),
+        }
+    }
+}
 
-    Template::render("index", context! {
+#[get("/?<sort>")]
+pub async fn index(
+    sort: Option<PostSort>,
+    user: Option<AuthenticatedUser>,
+    csrf: CsrfToken,
+    db: &State<Database>,
+) -> Result<Template, Status> {
+    let sort = sort.unwrap_or_default();
+    let posts = post_service::get_posts(db, sort).await.map_err(server_error)?;
+    let bookmarked = match &user {
+        Some(user) => bookmark_service::get_bookmarked_ids(db, user.0.id).await.map_err(server_error)?,
+        None => Default::default(),
+    };
+
+    Ok(Template::render("index", context! {
         user: user.map(|u| u.0),
+        csrf_token: csrf,
         posts: posts,
+        bookmarked: bookmarked,
+        most_liked: sort == PostSort::MostLiked,
         title: "Home",
-    })
+    }))
 }
 
 #[get("/posts/new")]
-pub fn new_post(user: AuthenticatedUser) -> Template {
+pub fn new_post(user: AuthenticatedUser, csrf: CsrfToken) -> Template {
     Template::render("new_post", context! {
         user: user.0,
+        csrf_token: csrf,
         title: "New Post",
     })
 }
@@ -36,7 +79,7 @@ pub fn new_post(user: AuthenticatedUser) -> Template {
 pub async fn create_post(
     post: Form<CreatePost>,
     user: AuthenticatedUser,
-    pool: &State<DbPool>,
+    db: &State<Database>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     if let Err(e) = post.validate() {
         return Err(Flash::error(
@@ -45,7 +88,7 @@ pub async fn create_post(
         ));
     }
 
-    match post_service::create_post(pool, post.into_inner(), user.0.id).await {
+    match post_service::create_post(db, post.into_inner(), user.0.id).await {
         Ok(_) => Ok(Flash::success(
             Redirect::to("/"),
             "Post created successfully!",
@@ -61,28 +104,77 @@ pub async fn create_post(
 pub async fn get_post(
     id: &str,
     user: Option<AuthenticatedUser>,
-    pool: &State<DbPool>,
-) -> Result<Template, Flash<Redirect>> {
+    csrf: CsrfToken,
+    nonce: CspNonce,
+    db: &State<Database>,
+    settings: ReaderSettings<'_>,
+    site: &State<SiteConfig>,
+) -> Result<Template, PageError> {
     let uuid = match Uuid::parse_str(id) {
         Ok(uuid) => uuid,
-        Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid post ID"))
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid post ID").into())
     };
 
-    let post = match post_service::get_post(pool, uuid).await {
+    let post = match post_service::get_post(db, uuid).await {
         Ok(Some(post)) => post,
-        Ok(None) => return Err(Flash::error(Redirect::to("/"), "Post not found")),
-        Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch post"))
+        Ok(None) => return Err(Flash::error(Redirect::to("/"), "Post not found").into()),
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch post").into())
     };
 
-    let comments = comment_service::get_post_comments(pool, uuid)
+    let comments = comment_service::get_post_comments(db, uuid).await.map_err(server_error)?;
+
+    let tags: Vec<String> = tag_service::get_post_tags(db, uuid)
         .await
-        .unwrap_or_else(|_| vec![]);
+        .map_err(server_error)?
+        .into_iter()
+        .map(|tag| tag.name)
+        .collect();
 
-    let title = post.title.clone();
+    let author_url = uri!(crate::routes::authors::author_page(&post.author)).to_string();
+    let base_url = site.base_url.trim_end_matches('/');
+    let meta = seo::post_meta(
+        &post,
+        &format!("{}{}", base_url, uri!(get_post(id))),
+        &format!("{}{}", base_url, author_url),
+        &tags,
+    );
+    let title = meta.title.clone();
+    let can_comment = user
+        .as_ref()
+        .is_some_and(|u| email_verification_service::can_comment(settings.email.verification, &u.0));
+    let mine = match &user {
+        Some(user) => reaction_service::get_user_reactions(db, uuid, user.0.id).await.map_err(server_error)?,
+        None => Default::default(),
+    };
+    let (bookmark, bookmark_folders) = match &user {
+        Some(user) => (
+            bookmark_service::get_bookmark(db, user.0.id, uuid).await.map_err(server_error)?,
+            bookmark_service::get_folder_names(db, user.0.id).await.map_err(server_error)?,
+        ),
+        None => (None, vec![]),
+    };
+    let comment_reactions: HashMap<Uuid, _> = comments
+        .iter()
+        .map(|comment| {
+            let kinds = mine.comments.get(&comment.id).map(Vec::as_slice).unwrap_or_default();
+            (comment.id, reaction_service::buttons(settings.reactions, &comment.reactions, kinds))
+        })
+        .collect();
     Ok(Template::render("post", context! {
         user: user.map(|u| u.0),
+        csrf_token: csrf,
+        csp_nonce: nonce,
+        can_comment: can_comment,
+        author_url: author_url,
+        sections: markdown::sections(&post.content),
+        series: series::post_series_nav(db, uuid).await.map_err(server_error)?,
+        reactions: reaction_service::buttons(settings.reactions, &post.reactions, &mine.post),
+        comment_reactions: comment_reactions,
+        bookmark: bookmark,
+        bookmark_folders: bookmark_folders,
         post: post,
         comments: comments,
+        seo: meta,
         title: &title,
     }))
 }
@@ -90,29 +182,39 @@ pub async fn get_post(
 #[get("/posts/<id>/edit")]
 pub async fn edit_post_page(
     id: &str,
+    flash: Option<FlashMessage<'_>>,
     user: AuthenticatedUser,
-    pool: &State<DbPool>,
-) -> Result<Template, Flash<Redirect>> {
+    csrf: CsrfToken,
+    db: &State<Database>,
+) -> Result<Template, PageError> {
     let uuid = match Uuid::parse_str(id) {
         Ok(uuid) => uuid,
-        Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid post ID"))
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid post ID").into())
     };
 
-    let post = match post_service::get_post(pool, uuid).await {
+    let post = match post_service::get_post(db, uuid).await {
         Ok(Some(post)) => post,
-        Ok(None) => return Err(Flash::error(Redirect::to("/"), "Post not found")),
-        Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch post"))
+        Ok(None) => return Err(Flash::error(Redirect::to("/"), "Post not found").into()),
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch post").into())
     };
 
     if post.author_id != user.0.id {
         return Err(Flash::error(
             Redirect::to(format!("/posts/{}", id)),
             "You don't have permission to edit this post",
-        ));
+        ).into());
     }
 
+    let series_options = series_service::list_author_series(db, user.0.id)
+        .await
+        .map_err(server_error)?;
+
     Ok(Template::render("edit_post", context! {
+        flash: flash,
         user: user.0,
+        csrf_token: csrf,
+        series: series::post_series_nav(db, uuid).await.map_err(server_error)?,
+        series_options: series_options,
         post: post,
         title: "Edit Post",
     }))
@@ -123,7 +225,7 @@ pub async fn update_post(
     id: &str,
     user: AuthenticatedUser,
     post: Form<CreatePost>,
-    pool: &State<DbPool>,
+    db: &State<Database>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     let uuid = match Uuid::parse_str(id) {
         Ok(uuid) => uuid,
@@ -131,7 +233,7 @@ pub async fn update_post(
     };
 
     // Verify post exists and user owns it
-    let existing_post = match post_service::get_post(pool, uuid).await {
+    let existing_post = match post_service::get_post(db, uuid).await {
         Ok(Some(post)) => post,
         Ok(None) => return Err(Flash::error(Redirect::to("/"), "Post not found")),
         Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch post"))
@@ -151,7 +253,7 @@ pub async fn update_post(
         ));
     }
 
-    match post_service::update_post(pool, uuid, post.title.clone(), post.content.clone()).await {
+    match post_service::update_post(db, uuid, post.title.clone(), post.content.clone()).await {
         Ok(_) => Ok(Flash::success(
             Redirect::to(format!("/posts/{}", id)),
             "Post updated successfully!",
@@ -163,11 +265,156 @@ pub async fn update_post(
     }
 }
 
+#[put("/posts/<id>/seo", data = "<seo>")]
+pub async fn update_post_seo(
+    id: &str,
+    user: AuthenticatedUser,
+    seo: Form<UpdatePostSeo>,
+    db: &State<Database>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let uuid = match Uuid::parse_str(id) {
+        Ok(uuid) => uuid,
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid post ID"))
+    };
+
+    let post = match post_service::get_post(db, uuid).await {
+        Ok(Some(post)) => post,
+        Ok(None) => return Err(Flash::error(Redirect::to("/"), "Post not found")),
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch post"))
+    };
+
+    if post.author_id != user.0.id {
+        return Err(Flash::error(
+            Redirect::to(format!("/posts/{}", id)),
+            "You don't have permission to edit this post",
+        ));
+    }
+
+    if let Err(e) = seo.validate() {
+        return Err(Flash::error(
+            Redirect::to(format!("/posts/{}/edit", id)),
+            e.to_string(),
+        ));
+    }
+
+    match post_service::set_post_seo(db, uuid, &seo).await {
+        Ok(_) => Ok(Flash::success(
+            Redirect::to(format!("/posts/{}/edit", id)),
+            "Search engine details updated",
+        )),
+        Err(_) => Err(Flash::error(
+            Redirect::to(format!("/posts/{}/edit", id)),
+            "Failed to update search engine details",
+        ))
+    }
+}
+
+#[put("/posts/<id>/excerpt", data = "<excerpt>")]
+pub async fn update_post_excerpt(
+    id: &str,
+    user: AuthenticatedUser,
+    excerpt: Form<UpdatePostExcerpt>,
+    db: &State<Database>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let uuid = match Uuid::parse_str(id) {
+        Ok(uuid) => uuid,
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid post ID"))
+    };
+
+    let post = match post_service::get_post(db, uuid).await {
+        Ok(Some(post)) => post,
+        Ok(None) => return Err(Flash::error(Redirect::to("/"), "Post not found")),
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch post"))
+    };
+
+    if post.author_id != user.0.id {
+        return Err(Flash::error(
+            Redirect::to(format!("/posts/{}", id)),
+            "You don't have permission to edit this post",
+        ));
+    }
+
+    if let Err(e) = excerpt.validate() {
+        return Err(Flash::error(
+            Redirect::to(format!("/posts/{}/edit", id)),
+            e.to_string(),
+        ));
+    }
+
+    match post_service::set_post_excerpt(db, uuid, &excerpt).await {
+        Ok(_) => Ok(Flash::success(
+            Redirect::to(format!("/posts/{}/edit", id)),
+            "Excerpt updated",
+        )),
+        Err(_) => Err(Flash::error(
+            Redirect::to(format!("/posts/{}/edit", id)),
+            "Failed to update excerpt",
+        ))
+    }
+}
+
+#[put("/posts/<id>/series", data = "<form>")]
+pub async fn update_post_series(
+    id: &str,
+    user: AuthenticatedUser,
+    form: Form<UpdatePostSeries>,
+    db: &State<Database>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let uuid = match Uuid::parse_str(id) {
+        Ok(uuid) => uuid,
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Invalid post ID"))
+    };
+
+    let post = match post_service::get_post(db, uuid).await {
+        Ok(Some(post)) => post,
+        Ok(None) => return Err(Flash::error(Redirect::to("/"), "Post not found")),
+        Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch post"))
+    };
+
+    if post.author_id != user.0.id {
+        return Err(Flash::error(
+            Redirect::to(format!("/posts/{}", id)),
+            "You don't have permission to edit this post",
+        ));
+    }
+
+    let edit_page = || Redirect::to(format!("/posts/{}/edit", id));
+    if let Err(e) = form.validate() {
+        return Err(Flash::error(edit_page(), e.to_string()));
+    }
+
+    let series = if !form.new_series.trim().is_empty() {
+        match series_service::create_series(db, user.0.id, &form.new_series).await {
+            Ok(series) => series,
+            Err(_) => return Err(Flash::error(edit_page(), "Series titles need letters or digits")),
+        }
+    } else if !form.series.is_empty() {
+        let series = match Uuid::parse_str(&form.series) {
+            Ok(series_id) => series_service::get_series(db, series_id).await.ok().flatten(),
+            Err(_) => None,
+        };
+        match series {
+            Some(series) if series.author_id == user.0.id => series,
+            _ => return Err(Flash::error(edit_page(), "Series not found")),
+        }
+    } else {
+        return match series_service::remove_from_series(db, uuid).await {
+            Ok(_) => Ok(Flash::success(edit_page(), "Removed from series")),
+            Err(_) => Err(Flash::error(edit_page(), "Failed to update series")),
+        };
+    };
+
+    match series_service::add_to_series(db, series.id, uuid, form.position).await {
+        Ok(_) => Ok(Flash::success(edit_page(), "Series updated")),
+        Err(_) => Err(Flash::error(edit_page(), "Failed to update series")),
+    }
+}
+
 #[delete("/posts/<id>")]
 pub async fn delete_post(
     id: &str,
     user: AuthenticatedUser,
-    pool: &State<DbPool>,
+    db: &State<Database>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     let uuid = match Uuid::parse_str(id) {
         Ok(uuid) => uuid,
@@ -175,7 +422,7 @@ pub async fn delete_post(
     };
 
     // Verify post exists and user owns it
-    let post = match post_service::get_post(pool, uuid).await {
+    let post = match post_service::get_post(db, uuid).await {
         Ok(Some(post)) => post,
         Ok(None) => return Err(Flash::error(Redirect::to("/"), "Post not found")),
         Err(_) => return Err(Flash::error(Redirect::to("/"), "Failed to fetch post"))
@@ -188,7 +435,7 @@ pub async fn delete_post(
         ));
     }
 
-    match post_service::delete_post(pool, uuid).await {
+    match post_service::delete_post(db, uuid).await {
         Ok(_) => Ok(Flash::success(
             Redirect::to("/"),
             "Post deleted successfully!",
@@ -2,28 +2,85 @@ use rocket::form::Form;
 use rocket::response::{Flash, Redirect};
 use rocket::State;
 use rocket_dyn_templates::{Template, context};
-use rocket::{get, put, uri};
+use rocket::request::FlashMessage;
+use rocket::{delete, get, post, put, uri};
 use validator::Validate;
 
+use crate::csrf::CsrfToken;
+use crate::email::{EmailConfig, Mailer, Verification};
+use crate::feeds::SiteConfig;
 use crate::models::auth::AuthenticatedUser;
-use crate::services::db::DbPool;
-use crate::services::user_service;
-use crate::models::user::{UpdateUsername, UpdatePassword};
-use rocket::http::CookieJar;
+use crate::oidc;
+use crate::routes::server_error;
+use crate::models::two_factor::{DisableTwoFactor, TwoFactorCode};
+use crate::services::two_factor_service::{self, TwoFactorConfig};
+use crate::services::db::Database;
+use crate::services::email_verification_service::{self, EmailChange};
+use crate::services::{login_service, profile_service, user_service};
+use crate::models::profile::UpdateProfile;
+use crate::models::user::{UpdateEmail, UpdateUsername, UpdatePassword};
+use rocket::http::{CookieJar, Status};
 
 #[get("/profile")]
-pub fn profile_page(user: AuthenticatedUser) -> Template {
-    Template::render("profile", context! {
+pub async fn profile_page(
+    user: AuthenticatedUser,
+    csrf: CsrfToken,
+    db: &State<Database>,
+    email: &State<EmailConfig>,
+    sso: &State<oidc::Client>,
+) -> Result<Template, Status> {
+    let profile = profile_service::get_profile(db, user.0.id).await.map_err(server_error)?;
+    let two_factor_enabled = two_factor_service::is_enabled(db, user.0.id).await.map_err(server_error)?;
+    let identities = db.list_user_identities(user.0.id).await.map_err(server_error)?;
+    Ok(Template::render("profile", context! {
         user: user.0,
+        csrf_token: csrf,
+        profile: profile,
+        two_factor_enabled: two_factor_enabled,
+        sso: sso.enabled().then(|| sso.config().display_name.clone()),
+        identities: identities,
+        email_verification: email.verification != Verification::Off,
         title: "Profile Settings",
-    })
+    }))
+}
+
+/// Recent login attempts on the account, so its owner can spot ones that
+/// were not theirs.
+#[get("/profile/logins")]
+pub async fn login_history(
+    user: AuthenticatedUser,
+    csrf: CsrfToken,
+    db: &State<Database>,
+) -> Result<Template, Flash<Redirect>> {
+    let attempts = login_service::history(db, user.0.id)
+        .await
+        .map_err(|e| Flash::error(Redirect::to(uri!(profile_page)), e.to_string()))?;
+    Ok(Template::render("login_history", context! {
+        user: user.0,
+        csrf_token: csrf,
+        attempts: attempts,
+        title: "Login Activity",
+    }))
+}
+
+/// Links the signed-in account to an account at the identity provider.
+#[post("/profile/sso")]
+pub async fn link_sso(
+    user: AuthenticatedUser,
+    client: &State<oidc::Client>,
+    site: &State<SiteConfig>,
+    cookies: &CookieJar<'_>,
+) -> Result<Redirect, Flash<Redirect>> {
+    crate::routes::auth::start_sso(client, site, cookies, Some(user.0.id))
+        .await
+        .map_err(|e| Flash::error(Redirect::to(uri!(profile_page)), e))
 }
 
 #[put("/profile/username", data = "<username>")]
 pub async fn update_username(
     username: Form<UpdateUsername>,
     mut user: AuthenticatedUser,
-    pool: &State<DbPool>,
+    db: &State<Database>,
     cookies: &CookieJar<'_>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     let username = username.into_inner();
@@ -35,7 +92,7 @@ pub async fn update_username(
         ));
     }
 
-    match user_service::update_username(pool, user.0.id, username.username).await {
+    match user_service::update_username(db, user.0.id, username.username).await {
         Ok(updated_user) => {
             // Update the user in the cookie
             user.0 = updated_user;
@@ -58,7 +115,7 @@ pub async fn update_username(
 pub async fn update_password(
     password: Form<UpdatePassword>,
     mut user: AuthenticatedUser,
-    pool: &State<DbPool>,
+    db: &State<Database>,
     cookies: &CookieJar<'_>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     let password = password.into_inner();
@@ -71,7 +128,7 @@ pub async fn update_password(
     }
 
     match user_service::update_password(
-        pool,
+        db,
         user.0.id,
         password.current_password,
         password.new_password,
@@ -94,3 +151,187 @@ pub async fn update_password(
         }
     }
 }
+
+#[put("/profile/details", data = "<details>")]
+pub async fn update_details(
+    details: Form<UpdateProfile>,
+    user: AuthenticatedUser,
+    db: &State<Database>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let details = details.into_inner();
+
+    if let Err(e) = details.validate() {
+        return Err(Flash::error(
+            Redirect::to(uri!(profile_page)),
+            e.to_string(),
+        ));
+    }
+
+    match profile_service::update_profile(db, user.0.id, details).await {
+        Ok(_) => Ok(Flash::success(
+            Redirect::to(uri!(profile_page)),
+            "Profile updated successfully",
+        )),
+        Err(e) => Err(Flash::error(
+            Redirect::to(uri!(profile_page)),
+            e.to_string(),
+        )),
+    }
+}
+
+#[put("/profile/email", data = "<email>")]
+pub async fn update_email(
+    email: Form<UpdateEmail>,
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    mailer: &State<Mailer>,
+    site: &State<SiteConfig>,
+    config: &State<EmailConfig>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    if let Err(e) = email.validate() {
+        return Err(Flash::error(
+            Redirect::to(uri!(profile_page)),
+            e.to_string(),
+        ));
+    }
+
+    match email_verification_service::change_email(db, mailer, &site.base_url, config.verification, &user.0, &email.email).await {
+        Ok(EmailChange::Applied(Some(_))) => Ok(Flash::success(
+            Redirect::to(uri!(profile_page)),
+            "Email updated successfully",
+        )),
+        Ok(EmailChange::Applied(None)) => Ok(Flash::success(
+            Redirect::to(uri!(profile_page)),
+            "Email removed",
+        )),
+        Ok(EmailChange::Pending(email)) => Ok(Flash::success(
+            Redirect::to(uri!(profile_page)),
+            format!("We sent a confirmation link to {}. Open it to finish the change.", email),
+        )),
+        Err(e) => Err(Flash::error(
+            Redirect::to(uri!(profile_page)),
+            e.to_string(),
+        )),
+    }
+}
+
+#[post("/profile/email/verification")]
+pub async fn resend_verification(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    mailer: &State<Mailer>,
+    site: &State<SiteConfig>,
+    config: &State<EmailConfig>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    if config.verification == Verification::Off {
+        return Err(Flash::error(
+            Redirect::to(uri!(profile_page)),
+            "Email verification is turned off",
+        ));
+    }
+
+    match email_verification_service::send_verification(db, mailer, &site.base_url, &user.0).await {
+        Ok(()) => Ok(Flash::success(
+            Redirect::to(uri!(profile_page)),
+            "We sent you a new confirmation link",
+        )),
+        Err(e) => Err(Flash::error(
+            Redirect::to(uri!(profile_page)),
+            e.to_string(),
+        )),
+    }
+}
+
+#[get("/profile/two-factor")]
+pub async fn two_factor_page(
+    flash: Option<FlashMessage<'_>>,
+    user: AuthenticatedUser,
+    csrf: CsrfToken,
+    db: &State<Database>,
+    config: &State<TwoFactorConfig>,
+) -> Result<Template, Flash<Redirect>> {
+    let error = |e: anyhow::Error| Flash::error(Redirect::to(uri!(profile_page)), e.to_string());
+    let status = two_factor_service::status(db, config, &user.0).await.map_err(error)?;
+    if status.enabled {
+        return Ok(Template::render("two_factor_settings", context! {
+            flash: flash,
+            user: user.0,
+            csrf_token: csrf,
+            status: status,
+            title: "Two-Factor Authentication",
+        }));
+    }
+
+    let enrolment = two_factor_service::begin_enrolment(db, config, &user.0).await.map_err(error)?;
+    Ok(Template::render("two_factor_setup", context! {
+        flash: flash,
+        user: user.0,
+        csrf_token: csrf,
+        enrolment: enrolment,
+        action: uri!(enable_two_factor).to_string(),
+        title: "Set Up Two-Factor Authentication",
+    }))
+}
+
+#[post("/profile/two-factor", data = "<form>")]
+pub async fn enable_two_factor(
+    form: Form<TwoFactorCode>,
+    user: AuthenticatedUser,
+    csrf: CsrfToken,
+    db: &State<Database>,
+) -> Result<Template, Flash<Redirect>> {
+    match two_factor_service::confirm_enrolment(db, user.0.id, &form.code).await {
+        Ok(codes) => Ok(Template::render("recovery_codes", context! {
+            user: user.0,
+            csrf_token: csrf,
+            codes: codes,
+            next: uri!(two_factor_page).to_string(),
+            title: "Recovery Codes",
+        })),
+        Err(e) => Err(Flash::error(
+            Redirect::to(uri!(two_factor_page)),
+            e.to_string(),
+        )),
+    }
+}
+
+#[post("/profile/two-factor/recovery-codes", data = "<form>")]
+pub async fn regenerate_recovery_codes(
+    form: Form<TwoFactorCode>,
+    user: AuthenticatedUser,
+    csrf: CsrfToken,
+    db: &State<Database>,
+) -> Result<Template, Flash<Redirect>> {
+    match two_factor_service::regenerate_recovery_codes(db, user.0.id, &form.code).await {
+        Ok(codes) => Ok(Template::render("recovery_codes", context! {
+            user: user.0,
+            csrf_token: csrf,
+            codes: codes,
+            next: uri!(two_factor_page).to_string(),
+            title: "Recovery Codes",
+        })),
+        Err(e) => Err(Flash::error(
+            Redirect::to(uri!(two_factor_page)),
+            e.to_string(),
+        )),
+    }
+}
+
+#[delete("/profile/two-factor", data = "<form>")]
+pub async fn disable_two_factor(
+    form: Form<DisableTwoFactor>,
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    config: &State<TwoFactorConfig>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    match two_factor_service::disable(db, config, &user.0, &form.password, &form.code).await {
+        Ok(()) => Ok(Flash::success(
+            Redirect::to(uri!(profile_page)),
+            "Two-factor authentication is off",
+        )),
+        Err(e) => Err(Flash::error(
+            Redirect::to(uri!(two_factor_page)),
+            e.to_string(),
+        )),
+    }
+}
@@ -0,0 +1,87 @@
+use rocket::http::Status;
+use rocket::serde::json::{json, Json, Value};
+use rocket::{delete, put, State};
+use uuid::Uuid;
+
+use crate::models::auth::AuthenticatedUser;
+use crate::models::reaction::ReactionTarget;
+use crate::services::db::Database;
+use crate::services::reaction_service::{self, ReactionsConfig};
+
+/// Adds or takes back a reaction and answers with the target's counts, for
+/// the post page to update in place. Requests carry the CSRF token in the
+/// `X-CSRF-Token` header.
+async fn react(
+    db: &Database,
+    config: &ReactionsConfig,
+    user: AuthenticatedUser,
+    target: ReactionTarget,
+    id: &str,
+    kind: &str,
+    reacted: bool,
+) -> (Status, Json<Value>) {
+    let id = match Uuid::parse_str(id) {
+        Ok(id) => id,
+        Err(_) => return (Status::BadRequest, Json(json!({ "error": "Invalid ID" }))),
+    };
+    if !config.offers(kind) {
+        return (Status::BadRequest, Json(json!({ "error": "Unknown reaction" })));
+    }
+
+    match reaction_service::react(db, target, id, user.0.id, kind, reacted).await {
+        Ok(Some(reactions)) => (Status::Ok, Json(json!({
+            "kind": kind,
+            "reacted": reacted,
+            "counts": reactions.counts,
+            "total": reactions.total,
+        }))),
+        Ok(None) => (Status::NotFound, Json(json!({ "error": "Not found" }))),
+        Err(_) => (Status::InternalServerError, Json(json!({ "error": "Failed to save reaction" }))),
+    }
+}
+
+#[put("/posts/<id>/reactions/<kind>")]
+pub async fn add_post_reaction(
+    id: &str,
+    kind: &str,
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    config: &State<ReactionsConfig>,
+) -> (Status, Json<Value>) {
+    react(db, config, user, ReactionTarget::Post, id, kind, true).await
+}
+
+#[delete("/posts/<id>/reactions/<kind>")]
+pub async fn remove_post_reaction(
+    id: &str,
+    kind: &str,
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    config: &State<ReactionsConfig>,
+) -> (Status, Json<Value>) {
+    react(db, config, user, ReactionTarget::Post, id, kind, false).await
+}
+
+#[put("/posts/<_post_id>/comments/<id>/reactions/<kind>")]
+pub async fn add_comment_reaction(
+    _post_id: &str,
+    id: &str,
+    kind: &str,
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    config: &State<ReactionsConfig>,
+) -> (Status, Json<Value>) {
+    react(db, config, user, ReactionTarget::Comment, id, kind, true).await
+}
+
+#[delete("/posts/<_post_id>/comments/<id>/reactions/<kind>")]
+pub async fn remove_comment_reaction(
+    _post_id: &str,
+    id: &str,
+    kind: &str,
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    config: &State<ReactionsConfig>,
+) -> (Status, Json<Value>) {
+    react(db, config, user, ReactionTarget::Comment, id, kind, false).await
+}
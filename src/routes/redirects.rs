@@ -0,0 +1,16 @@
+use rocket::http::uri::Origin;
+use rocket::response::Redirect;
+use rocket::{get, State};
+use std::path::PathBuf;
+
+use crate::services::db::Database;
+use crate::services::redirect_service;
+
+/// Sends old URLs, e.g. from an imported WordPress or Ghost site, to their
+/// new location. Ranked after every other route so it only sees paths
+/// nothing else handles.
+#[get("/<_path..>", rank = 20)]
+pub async fn redirect(_path: PathBuf, uri: &Origin<'_>, db: &State<Database>) -> Option<Redirect> {
+    let target = redirect_service::resolve(db, &uri.to_string()).await.ok()??;
+    Some(Redirect::moved(target))
+}
@@ -0,0 +1,72 @@
+use rocket::http::{ContentType, Status};
+use rocket::{get, uri, State};
+use rocket_dyn_templates::Template;
+use std::collections::BTreeMap;
+
+use crate::feeds::SiteConfig;
+use crate::models::post::PostSort;
+use crate::seo::{self, SeoConfig, Sitemap, SitemapUrl};
+use crate::services::db::Database;
+use crate::services::{post_service, series_service};
+
+#[get("/robots.txt")]
+pub fn robots(seo: &State<SeoConfig>, site: &State<SiteConfig>) -> String {
+    seo::robots_txt(seo, &site.base_url)
+}
+
+/// The home page, each post, each author with posts and each series, dated
+/// by their latest change.
+async fn sitemap(db: &Database, site: &SiteConfig, seo: &SeoConfig) -> Result<Sitemap, Status> {
+    let base_url = site.base_url.trim_end_matches('/');
+    let posts = post_service::get_posts(db, PostSort::Newest).await.map_err(|_| Status::InternalServerError)?;
+
+    let mut authors: BTreeMap<&str, &str> = BTreeMap::new();
+    for post in &posts {
+        let lastmod = authors.entry(&post.author).or_insert(&post.updated_at);
+        *lastmod = (*lastmod).max(post.updated_at.as_str());
+    }
+    let mut urls = vec![SitemapUrl {
+        loc: format!("{}/", base_url),
+        lastmod: posts.iter().map(|post| post.updated_at.clone()).max(),
+    }];
+    urls.extend(posts.iter().map(|post| SitemapUrl {
+        loc: format!("{}{}", base_url, uri!(crate::routes::posts::get_post(post.id.to_string()))),
+        lastmod: Some(post.updated_at.clone()),
+    }));
+    urls.extend(authors.iter().map(|(author, lastmod)| SitemapUrl {
+        loc: format!("{}{}", base_url, uri!(crate::routes::authors::author_page(*author))),
+        lastmod: Some(lastmod.to_string()),
+    }));
+    for series in series_service::list_series(db).await.map_err(|_| Status::InternalServerError)? {
+        let parts = series_service::get_series_posts(db, series.id).await.map_err(|_| Status::InternalServerError)?;
+        let lastmod = parts.iter().map(|post| &post.updated_at).chain([&series.updated_at]).max().cloned();
+        urls.push(SitemapUrl {
+            loc: format!("{}{}", base_url, uri!(crate::routes::series::series_page(&series.slug))),
+            lastmod,
+        });
+    }
+    Ok(Sitemap::new(base_url, urls, seo.sitemap_max_urls))
+}
+
+/// The whole sitemap, or an index of its parts once it outgrows one file.
+#[get("/sitemap.xml")]
+pub async fn sitemap_xml(
+    db: &State<Database>,
+    site: &State<SiteConfig>,
+    seo: &State<SeoConfig>,
+) -> Result<(ContentType, Template), Status> {
+    let (template, context) = sitemap(db, site, seo).await?.file("sitemap.xml").ok_or(Status::NotFound)?;
+    Ok((ContentType::XML, Template::render(template, context)))
+}
+
+#[get("/sitemaps/<file>")]
+pub async fn sitemap_part(
+    file: &str,
+    db: &State<Database>,
+    site: &State<SiteConfig>,
+    seo: &State<SeoConfig>,
+) -> Result<(ContentType, Template), Status> {
+    let path = format!("sitemaps/{}", file);
+    let (template, context) = sitemap(db, site, seo).await?.file(&path).ok_or(Status::NotFound)?;
+    Ok((ContentType::XML, Template::render(template, context)))
+}
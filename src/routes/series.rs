@@ -0,0 +1,73 @@
+use rocket::http::{ContentType, Status};
+use rocket::{get, uri, State};
+use rocket_dyn_templates::{Template, context};
+use uuid::Uuid;
+
+use crate::csrf::CsrfToken;
+use crate::feeds::{self, FeedEntry, SiteConfig};
+use crate::models::auth::AuthenticatedUser;
+use crate::models::post::Post;
+use crate::routes::server_error;
+use crate::services::db::Database;
+use crate::services::series_service::{self, SeriesEntry, SeriesNav};
+use crate::services::tag_service;
+
+fn post_url(post: &Post) -> String {
+    uri!(crate::routes::posts::get_post(post.id.to_string())).to_string()
+}
+
+/// The navigation for a post's page, if it is part of a series.
+pub async fn post_series_nav(db: &Database, post_id: Uuid) -> anyhow::Result<Option<SeriesNav>> {
+    let Some(series) = series_service::get_post_series(db, post_id).await? else {
+        return Ok(None);
+    };
+    let posts = series_service::get_series_posts(db, series.id).await?;
+    let series_url = uri!(series_page(&series.slug)).to_string();
+    Ok(series_service::series_nav(&series, &posts, post_id, series_url, post_url))
+}
+
+#[get("/series/<slug>")]
+pub async fn series_page(
+    slug: &str,
+    user: Option<AuthenticatedUser>,
+    csrf: CsrfToken,
+    db: &State<Database>,
+) -> Result<Option<Template>, Status> {
+    let Some(series) = series_service::get_series_by_slug(db, slug).await.map_err(server_error)? else {
+        return Ok(None);
+    };
+    let posts = series_service::get_series_posts(db, series.id).await.map_err(server_error)?;
+    let parts: Vec<SeriesEntry> = posts.iter().map(|post| SeriesEntry { url: post_url(post), post }).collect();
+    let title = series.title.clone();
+    Ok(Some(Template::render("series", context! {
+        user: user.map(|u| u.0),
+        csrf_token: csrf,
+        feed_url: uri!(series_feed(slug)).to_string(),
+        author_url: uri!(crate::routes::authors::author_page(&series.author)).to_string(),
+        series: series,
+        parts: parts,
+        title: title,
+    })))
+}
+
+/// Every part of the series, in order.
+#[get("/series/<slug>/feed.xml")]
+pub async fn series_feed(
+    slug: &str,
+    db: &State<Database>,
+    site: &State<SiteConfig>,
+) -> Result<Option<(ContentType, Template)>, Status> {
+    let Some(series) = series_service::get_series_by_slug(db, slug).await.map_err(server_error)? else {
+        return Ok(None);
+    };
+    let posts = series_service::get_series_posts(db, series.id).await.map_err(server_error)?;
+    let mut entries = Vec::with_capacity(posts.len());
+    for post in &posts {
+        let tags = tag_service::get_post_tags(db, post.id).await.map_err(server_error)?;
+        entries.push(FeedEntry { post, tags: tags.into_iter().map(|tag| tag.name).collect() });
+    }
+
+    let feed_path = uri!(series_feed(slug)).to_string();
+    let context = feeds::atom_context(&site.base_url, &feed_path, &series.title, &entries, post_url);
+    Ok(Some((ContentType::new("application", "atom+xml"), Template::render("feed", context))))
+}
@@ -0,0 +1,148 @@
+//! Security headers for every response, set by
+//! [`crate::fairings::SecurityHeaders`], and the per-response nonce that lets
+//! a page's own inline scripts past its Content-Security-Policy.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+
+/// Where browsers post Content-Security-Policy violations.
+pub const CSP_REPORT_PATH: &str = "/csp-reports";
+
+/// Headers added to every response, from the `security_headers` table of
+/// `Rocket.toml`. Headers a route sets itself are left alone.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SecurityHeadersConfig {
+    pub enabled: bool,
+    /// Content-Security-Policy directives and their sources. `script-src`
+    /// also gets each response's nonce.
+    pub csp: BTreeMap<String, String>,
+    /// Sends the policy as `Content-Security-Policy-Report-Only`, so
+    /// violations are reported but nothing is blocked.
+    pub csp_report_only: bool,
+    /// Asks browsers to post violations to `/csp-reports`, where they are
+    /// logged.
+    pub report_violations: bool,
+    /// Who may show the site in a frame: `'none'`, `'self'` or origins.
+    pub frame_ancestors: String,
+    /// `max-age` of Strict-Transport-Security. Zero leaves the header out;
+    /// only set it once the site is served over HTTPS.
+    pub hsts_max_age_secs: u64,
+    pub hsts_include_subdomains: bool,
+    pub hsts_preload: bool,
+    pub referrer_policy: String,
+    pub permissions_policy: String,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        let csp = [
+            ("default-src", "'self'"),
+            ("script-src", "'self'"),
+            ("style-src", "'self'"),
+            // Avatars may be hosted anywhere
+            ("img-src", "'self' https: data:"),
+            ("object-src", "'none'"),
+            ("base-uri", "'self'"),
+        ];
+        SecurityHeadersConfig {
+            enabled: true,
+            csp: csp.iter().map(|(name, sources)| (name.to_string(), sources.to_string())).collect(),
+            csp_report_only: false,
+            report_violations: true,
+            frame_ancestors: "'none'".to_string(),
+            hsts_max_age_secs: 0,
+            hsts_include_subdomains: false,
+            hsts_preload: false,
+            referrer_policy: "strict-origin-when-cross-origin".to_string(),
+            permissions_policy: "camera=(), microphone=(), geolocation=(), payment=()".to_string(),
+        }
+    }
+}
+
+impl SecurityHeadersConfig {
+    /// The Content-Security-Policy for a response with `nonce`.
+    pub fn content_security_policy(&self, nonce: &CspNonce) -> String {
+        let mut directives: Vec<String> = self
+            .csp
+            .iter()
+            .map(|(name, sources)| match name.as_str() {
+                "script-src" => format!("{} {} 'nonce-{}'", name, sources, nonce.0),
+                _ => format!("{} {}", name, sources),
+            })
+            .collect();
+        if !self.frame_ancestors.is_empty() {
+            directives.push(format!("frame-ancestors {}", self.frame_ancestors));
+        }
+        if self.report_violations {
+            directives.push(format!("report-uri {}", CSP_REPORT_PATH));
+            directives.push("report-to csp".to_string());
+        }
+        directives.join("; ")
+    }
+
+    /// The Strict-Transport-Security value, if it is turned on.
+    pub fn strict_transport_security(&self) -> Option<String> {
+        if self.hsts_max_age_secs == 0 {
+            return None;
+        }
+        let mut value = format!("max-age={}", self.hsts_max_age_secs);
+        if self.hsts_include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if self.hsts_preload {
+            value.push_str("; preload");
+        }
+        Some(value)
+    }
+
+    /// X-Frame-Options for browsers without `frame-ancestors`, where the
+    /// two can say the same thing.
+    pub fn frame_options(&self) -> Option<&'static str> {
+        match self.frame_ancestors.as_str() {
+            "'none'" => Some("DENY"),
+            "'self'" => Some("SAMEORIGIN"),
+            _ => None,
+        }
+    }
+}
+
+/// A random value that lets one response's inline scripts past the
+/// Content-Security-Policy. Templates put it in `<script nonce="...">` as
+/// `csp_nonce`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    /// The request's nonce, the same one each time it is asked for.
+    pub fn for_request(request: &Request<'_>) -> CspNonce {
+        request
+            .local_cache(|| {
+                let mut bytes = [0u8; 16];
+                OsRng.fill_bytes(&mut bytes);
+                CspNonce(BASE64URL.encode(bytes))
+            })
+            .clone()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CspNonce {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(CspNonce::for_request(request))
+    }
+}
@@ -0,0 +1,201 @@
+//! What search engines and link previews read: the sitemap, `robots.txt`
+//! and each post's Open Graph, Twitter Card and JSON-LD metadata. Shared by
+//! the server and the static site export.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::markdown;
+use crate::models::post::Post;
+
+/// Most URLs the sitemap protocol allows in one file.
+pub const SITEMAP_PROTOCOL_LIMIT: usize = 50_000;
+
+/// Longest description generated from a post's content.
+const DESCRIPTION_CHARS: usize = 160;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SeoConfig {
+    /// Most URLs in one sitemap file. Past it, `/sitemap.xml` becomes an
+    /// index of `/sitemaps/<n>.xml` files. Capped at the protocol's 50,000.
+    pub sitemap_max_urls: usize,
+    /// Whether crawlers are welcome at all; turn it off on staging servers.
+    pub allow_crawling: bool,
+    /// Paths crawlers are asked to stay out of.
+    pub disallow: Vec<String>,
+    /// Lines added to the end of `robots.txt` as they are, e.g. rules for
+    /// one crawler.
+    pub robots_extra: String,
+}
+
+impl Default for SeoConfig {
+    fn default() -> Self {
+        SeoConfig {
+            sitemap_max_urls: SITEMAP_PROTOCOL_LIMIT,
+            allow_crawling: true,
+            disallow: ["/login", "/register", "/logout", "/profile", "/bookmarks", "/posts/new", "/forgot-password", "/reset-password"]
+                .iter()
+                .map(|path| path.to_string())
+                .collect(),
+            robots_extra: String::new(),
+        }
+    }
+}
+
+/// The text of `robots.txt` for a site at `base_url`.
+pub fn robots_txt(config: &SeoConfig, base_url: &str) -> String {
+    let mut text = String::from("User-agent: *\n");
+    if config.allow_crawling {
+        for path in &config.disallow {
+            text.push_str(&format!("Disallow: {}\n", path));
+        }
+        text.push_str(&format!("\nSitemap: {}/sitemap.xml\n", base_url.trim_end_matches('/')));
+    } else {
+        text.push_str("Disallow: /\n");
+    }
+    if !config.robots_extra.is_empty() {
+        text.push('\n');
+        text.push_str(config.robots_extra.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SitemapUrl {
+    pub loc: String,
+    pub lastmod: Option<String>,
+}
+
+/// A site's URLs, split into as many sitemap files as they need.
+pub struct Sitemap {
+    base_url: String,
+    urls: Vec<SitemapUrl>,
+    max_urls: usize,
+}
+
+impl Sitemap {
+    pub fn new(base_url: &str, urls: Vec<SitemapUrl>, max_urls: usize) -> Self {
+        Sitemap {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            urls,
+            max_urls: max_urls.clamp(1, SITEMAP_PROTOCOL_LIMIT),
+        }
+    }
+
+    fn parts(&self) -> std::slice::Chunks<'_, SitemapUrl> {
+        self.urls.chunks(self.max_urls)
+    }
+
+    /// Paths of the sitemap's files relative to the site root, starting
+    /// with `sitemap.xml`.
+    pub fn paths(&self) -> Vec<String> {
+        let mut paths = vec!["sitemap.xml".to_string()];
+        if self.urls.len() > self.max_urls {
+            paths.extend((1..=self.parts().len()).map(|number| format!("sitemaps/{}.xml", number)));
+        }
+        paths
+    }
+
+    /// The template and context of the file at `path`, one of `paths`.
+    pub fn file(&self, path: &str) -> Option<(&'static str, Value)> {
+        if self.urls.len() <= self.max_urls {
+            return (path == "sitemap.xml").then(|| ("sitemap", json!({ "urls": self.urls })));
+        }
+        if path == "sitemap.xml" {
+            let sitemaps: Vec<Value> = (1..)
+                .zip(self.parts())
+                .map(|(number, part)| json!({
+                    "loc": format!("{}/sitemaps/{}.xml", self.base_url, number),
+                    "lastmod": part.iter().filter_map(|url| url.lastmod.as_deref()).max(),
+                }))
+                .collect();
+            return Some(("sitemap_index", json!({ "sitemaps": sitemaps })));
+        }
+        let number: usize = path.strip_prefix("sitemaps/")?.strip_suffix(".xml")?.parse().ok()?;
+        let part = self.parts().nth(number.checked_sub(1)?)?;
+        Some(("sitemap", json!({ "urls": part })))
+    }
+}
+
+/// Metadata for the head of a post's page, rendered by `base.html.tera`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PostMeta {
+    pub title: String,
+    pub description: String,
+    pub canonical_url: String,
+    pub author: String,
+    pub author_url: String,
+    pub published_time: String,
+    pub modified_time: String,
+    pub tags: Vec<String>,
+    /// The `BlogPosting` object, ready to go inside a `<script>` element.
+    pub json_ld: String,
+}
+
+/// Metadata for `post`, whose page is at the absolute `url`. The post's
+/// own SEO title and description win over its title, then its author's
+/// excerpt and content.
+pub fn post_meta(post: &Post, url: &str, author_url: &str, tags: &[String]) -> PostMeta {
+    let title = post.seo_title.clone().unwrap_or_else(|| post.title.clone());
+    let description = post
+        .seo_description
+        .clone()
+        .unwrap_or_else(|| describe(post.excerpt.as_deref().unwrap_or(&post.content)));
+    let json_ld = json!({
+        "@context": "https://schema.org",
+        "@type": "BlogPosting",
+        "headline": title,
+        "description": description,
+        "url": url,
+        "mainEntityOfPage": { "@type": "WebPage", "@id": url },
+        "datePublished": post.created_at,
+        "dateModified": post.updated_at,
+        "author": { "@type": "Person", "name": post.author, "url": author_url },
+        "keywords": tags,
+        "wordCount": post.word_count(),
+    });
+    PostMeta {
+        json_ld: script_json(&json_ld),
+        title,
+        description,
+        canonical_url: url.to_string(),
+        author: post.author.clone(),
+        author_url: author_url.to_string(),
+        published_time: post.created_at.clone(),
+        modified_time: post.updated_at.clone(),
+        tags: tags.to_vec(),
+    }
+}
+
+/// A plain-text description from the start of `content`: tags and Markdown
+/// markers dropped, whitespace collapsed, and cut at a word boundary.
+pub fn describe(content: &str) -> String {
+    let text = markdown::plain_text(content);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut description = String::new();
+    for word in words {
+        if description.chars().count() + word.chars().count() + 1 > DESCRIPTION_CHARS {
+            if description.is_empty() {
+                description.extend(word.chars().take(DESCRIPTION_CHARS - 1));
+            }
+            description.push('\u{2026}');
+            break;
+        }
+        if !description.is_empty() {
+            description.push(' ');
+        }
+        description.push_str(word);
+    }
+    description
+}
+
+/// JSON that cannot end the `<script>` element it is placed in.
+fn script_json(value: &Value) -> String {
+    value
+        .to_string()
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+}
@@ -0,0 +1,309 @@
+use crate::metrics;
+use crate::repositories::sqlite::schema_version;
+use crate::repositories::SCHEMA_VERSION;
+use crate::services::db::Database;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::instrument;
+
+const FILE_PREFIX: &str = "blog-";
+const PLAIN_SUFFIX: &str = ".db";
+const COMPRESSED_SUFFIX: &str = ".db.gz";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// Settings from the `backup` table in `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Directory backups are written to.
+    pub dir: PathBuf,
+    /// Gzip each backup once it has passed verification.
+    pub compress: bool,
+    /// Number of recent backups to keep. Zero keeps every backup.
+    pub keep_last: usize,
+    /// Remove backups older than this many days. The newest backup is
+    /// always kept regardless of age.
+    pub max_age_days: Option<u64>,
+    /// Seconds between scheduled backups while the server runs. Unset
+    /// disables scheduled backups.
+    pub interval_secs: Option<u64>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            dir: PathBuf::from("backups"),
+            compress: true,
+            keep_last: 7,
+            max_age_days: None,
+            interval_secs: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub created_at: String,
+    pub compressed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupReport {
+    pub backup: BackupInfo,
+    pub schema_version: i32,
+    /// Older backups deleted by the retention rules.
+    pub removed: Vec<PathBuf>,
+}
+
+/// Takes a hot backup of the live database into a timestamped file in
+/// `config.dir`, verifies it, optionally compresses it, then applies the
+/// retention rules. A backup that fails verification is deleted.
+#[instrument(skip_all, fields(dir = %config.dir.display()), err)]
+pub async fn create_backup(db: &Database, config: &BackupConfig) -> Result<BackupReport> {
+    let result = write_backup(db, config).await;
+    metrics::record_backup(result.is_ok());
+    let (backup, schema_version) = result?;
+
+    let removed = prune_backups(config).await?;
+    Ok(BackupReport { backup, schema_version, removed })
+}
+
+async fn write_backup(db: &Database, config: &BackupConfig) -> Result<(BackupInfo, i32)> {
+    fs::create_dir_all(&config.dir)
+        .with_context(|| format!("Failed to create {}", config.dir.display()))?;
+
+    let stem = format!("{}{}", FILE_PREFIX, Utc::now().format(TIMESTAMP_FORMAT));
+    let plain = config.dir.join(format!("{}{}", stem, PLAIN_SUFFIX));
+    let partial = with_suffix(&plain, ".partial");
+
+    if let Err(e) = db.backup(&partial).await {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+
+    let compress = config.compress;
+    blocking(move || {
+        let result = finish_backup(&partial, &plain, compress);
+        if result.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        result
+    })
+    .await
+}
+
+fn finish_backup(partial: &Path, plain: &Path, compress: bool) -> Result<(BackupInfo, i32)> {
+    let version = check_database(partial).context("Backup failed verification")?;
+
+    let path = if compress {
+        let compressed = with_suffix(plain, ".gz");
+        let staged = with_suffix(&compressed, ".partial");
+        let written = gzip(partial, &staged);
+        if written.is_err() {
+            let _ = fs::remove_file(&staged);
+        }
+        written?;
+        fs::rename(&staged, &compressed)?;
+        fs::remove_file(partial)?;
+        compressed
+    } else {
+        fs::rename(partial, plain)?;
+        plain.to_path_buf()
+    };
+
+    let info = backup_info(&path)?.context("Backup file disappeared")?;
+    Ok((info, version))
+}
+
+/// Lists the backups in `dir`, newest first.
+pub async fn list_backups(dir: &Path) -> Result<Vec<BackupInfo>> {
+    let dir = dir.to_path_buf();
+    blocking(move || read_backups(&dir)).await
+}
+
+fn read_backups(dir: &Path) -> Result<Vec<BackupInfo>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        if let Some(info) = backup_info(&entry?.path())? {
+            backups.push(info);
+        }
+    }
+    // File names embed a fixed-width UTC timestamp, so name order is age order.
+    backups.sort_by(|a, b| b.path.cmp(&a.path));
+    Ok(backups)
+}
+
+/// Describes `path` if it is a backup written by `create_backup`. Other
+/// files, even in the backup directory, are never listed or pruned.
+fn backup_info(path: &Path) -> Result<Option<BackupInfo>> {
+    let Some(created_at) = backup_timestamp(path) else {
+        return Ok(None);
+    };
+    let compressed = path.to_string_lossy().ends_with(COMPRESSED_SUFFIX);
+    Ok(Some(BackupInfo {
+        path: path.to_path_buf(),
+        size_bytes: fs::metadata(path)?.len(),
+        created_at: created_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        compressed,
+    }))
+}
+
+fn backup_timestamp(path: &Path) -> Option<DateTime<Utc>> {
+    let name = path.file_name()?.to_str()?;
+    let stamp = name.strip_prefix(FILE_PREFIX)?;
+    let stamp = stamp
+        .strip_suffix(COMPRESSED_SUFFIX)
+        .or_else(|| stamp.strip_suffix(PLAIN_SUFFIX))?;
+    let created_at = NaiveDateTime::parse_from_str(stamp, TIMESTAMP_FORMAT).ok()?;
+    Some(created_at.and_utc())
+}
+
+/// Deletes backups beyond `keep_last` or older than `max_age_days`. The
+/// newest backup always survives so retention can never remove every copy.
+#[instrument(skip_all, err)]
+pub async fn prune_backups(config: &BackupConfig) -> Result<Vec<PathBuf>> {
+    let config = config.clone();
+    blocking(move || {
+        let max_age = config.max_age_days.map(|days| chrono::Duration::days(days as i64));
+        let now = Utc::now();
+
+        let mut removed = Vec::new();
+        for (index, backup) in read_backups(&config.dir)?.into_iter().enumerate().skip(1) {
+            let over_count = config.keep_last > 0 && index >= config.keep_last;
+            let too_old = match (max_age, backup_timestamp(&backup.path)) {
+                (Some(max_age), Some(created_at)) => now - created_at > max_age,
+                _ => false,
+            };
+            if over_count || too_old {
+                fs::remove_file(&backup.path)?;
+                removed.push(backup.path);
+            }
+        }
+        Ok(removed)
+    })
+    .await
+}
+
+/// Runs SQLite's integrity check on a backup, decompressing it first if
+/// needed, and returns the schema version it was taken at.
+#[instrument(skip_all, fields(path = %path.display()), err)]
+pub async fn verify_backup(path: &Path) -> Result<i32> {
+    let path = path.to_path_buf();
+    blocking(move || {
+        let staged = stage(&path, ".verify")?;
+        let result = check_database(&staged);
+        remove_database(&staged);
+        result
+    })
+    .await
+}
+
+/// Replaces the live database with a backup. The backup is verified first
+/// and refused if it was taken by a newer build than this one; backups from
+/// older builds are migrated forward once restored.
+#[instrument(skip_all, fields(path = %path.display()), err)]
+pub async fn restore_backup(db: &Database, path: &Path) -> Result<i32> {
+    let path = path.to_path_buf();
+    let staged = blocking(move || {
+        let staged = stage(&path, ".restore")?;
+        let checked = check_database(&staged).and_then(|version| {
+            if version > SCHEMA_VERSION {
+                bail!(
+                    "Backup has schema version {}, but this build only supports up to {}",
+                    version,
+                    SCHEMA_VERSION
+                );
+            }
+            Ok(version)
+        });
+        match checked {
+            Ok(version) => Ok((staged, version)),
+            Err(e) => {
+                remove_database(&staged);
+                Err(e)
+            }
+        }
+    })
+    .await;
+    let (staged, version) = staged?;
+
+    let result = db.restore(&staged).await;
+    remove_database(&staged);
+    result.map(|()| version)
+}
+
+/// Copies a backup next to itself, decompressing it if needed, so it can be
+/// opened without touching the original file.
+fn stage(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let staged = with_suffix(path, suffix);
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut output = BufWriter::new(File::create(&staged)?);
+
+    let copied = if path.to_string_lossy().ends_with(".gz") {
+        io::copy(&mut GzDecoder::new(BufReader::new(file)), &mut output)
+    } else {
+        io::copy(&mut BufReader::new(file), &mut output)
+    };
+    if let Err(e) = copied.and_then(|_| output.flush()) {
+        let _ = fs::remove_file(&staged);
+        return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+    }
+    Ok(staged)
+}
+
+fn check_database(path: &Path) -> Result<i32> {
+    let conn = Connection::open(path)?;
+    let problems: Vec<String> = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    if problems != ["ok"] {
+        bail!("Integrity check failed: {}", problems.join("; "));
+    }
+    Ok(schema_version(&conn)?)
+}
+
+fn gzip(from: &Path, to: &Path) -> Result<()> {
+    let mut input = BufReader::new(File::open(from)?);
+    let mut encoder = GzEncoder::new(BufWriter::new(File::create(to)?), Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.flush()?;
+    Ok(())
+}
+
+fn remove_database(path: &Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(with_suffix(path, suffix));
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await?
+}
@@ -0,0 +1,130 @@
+use crate::models::bookmark::{Bookmark, SaveBookmark};
+use crate::models::post::Post;
+use crate::services::db::Database;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use tracing::instrument;
+use uuid::Uuid;
+
+/// A bookmarked post with the reader's folder and note.
+#[derive(Debug, Serialize)]
+pub struct SavedPost {
+    pub bookmark: Bookmark,
+    pub post: Post,
+}
+
+/// A folder on the bookmarks page. The unnamed folder holds bookmarks in no
+/// folder.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Folder {
+    pub name: String,
+    pub count: usize,
+}
+
+/// A bookmark as exported, with everything needed to find the post again.
+#[derive(Debug, Serialize)]
+pub struct ExportedBookmark {
+    pub title: String,
+    pub url: String,
+    pub author: String,
+    pub folder: String,
+    pub note: Option<String>,
+    pub saved_at: String,
+}
+
+/// Bookmarks a post, or files an existing bookmark under the form's folder
+/// and note. Blank values are left out.
+#[instrument(skip_all, fields(user_id = %user_id, post_id = %post_id), err)]
+pub async fn save_bookmark(db: &Database, user_id: Uuid, post_id: Uuid, form: SaveBookmark) -> Result<Bookmark> {
+    let now = Utc::now().to_rfc3339();
+    let note = form.note.trim();
+    db.upsert_bookmark(&Bookmark {
+        user_id,
+        post_id,
+        folder: form.folder.trim().to_string(),
+        note: (!note.is_empty()).then(|| note.to_string()),
+        created_at: now.clone(),
+        updated_at: now,
+    }).await?;
+    db.find_bookmark(user_id, post_id)
+        .await?
+        .ok_or_else(|| anyhow!("Bookmark was not saved"))
+}
+
+#[instrument(skip_all, fields(user_id = %user_id, post_id = %post_id), err)]
+pub async fn get_bookmark(db: &Database, user_id: Uuid, post_id: Uuid) -> Result<Option<Bookmark>> {
+    db.find_bookmark(user_id, post_id).await
+}
+
+/// The user's bookmarked posts, most recently saved first.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn get_bookmarks(db: &Database, user_id: Uuid) -> Result<Vec<SavedPost>> {
+    let mut posts: BTreeMap<Uuid, Post> = db
+        .list_bookmarked_posts(user_id)
+        .await?
+        .into_iter()
+        .map(|post| (post.id, post))
+        .collect();
+    // A post deleted between the two reads is left out.
+    Ok(db
+        .list_bookmarks(user_id)
+        .await?
+        .into_iter()
+        .filter_map(|bookmark| Some(SavedPost { post: posts.remove(&bookmark.post_id)?, bookmark }))
+        .collect())
+}
+
+/// Ids of the posts the user bookmarked, to mark them in listings.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn get_bookmarked_ids(db: &Database, user_id: Uuid) -> Result<HashSet<Uuid>> {
+    Ok(db.list_bookmarks(user_id).await?.into_iter().map(|bookmark| bookmark.post_id).collect())
+}
+
+/// Names of the user's folders, sorted, for picking one when saving.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn get_folder_names(db: &Database, user_id: Uuid) -> Result<Vec<String>> {
+    let names: BTreeSet<String> = db
+        .list_bookmarks(user_id)
+        .await?
+        .into_iter()
+        .map(|bookmark| bookmark.folder)
+        .filter(|folder| !folder.is_empty())
+        .collect();
+    Ok(names.into_iter().collect())
+}
+
+#[instrument(skip_all, fields(user_id = %user_id, post_id = %post_id), err)]
+pub async fn remove_bookmark(db: &Database, user_id: Uuid, post_id: Uuid) -> Result<bool> {
+    db.delete_bookmark(user_id, post_id).await
+}
+
+/// The folders in use with their bookmark counts, by name. The unnamed
+/// folder, if used, comes first.
+pub fn folders(saved: &[SavedPost]) -> Vec<Folder> {
+    let mut counts = BTreeMap::new();
+    for entry in saved {
+        *counts.entry(entry.bookmark.folder.as_str()).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(name, count)| Folder { name: name.to_string(), count })
+        .collect()
+}
+
+/// `saved` for export, with post links made absolute against `base_url`.
+pub fn export(saved: &[SavedPost], base_url: &str, post_url: impl Fn(&Post) -> String) -> Vec<ExportedBookmark> {
+    let base_url = base_url.trim_end_matches('/');
+    saved
+        .iter()
+        .map(|entry| ExportedBookmark {
+            title: entry.post.title.clone(),
+            url: format!("{}{}", base_url, post_url(&entry.post)),
+            author: entry.post.author.clone(),
+            folder: entry.bookmark.folder.clone(),
+            note: entry.bookmark.note.clone(),
+            saved_at: entry.bookmark.created_at.clone(),
+        })
+        .collect()
+}
@@ -1,104 +1,83 @@
 use crate::models::comment::{Comment, CreateComment};
-use crate::services::db::DbPool;
+use crate::models::reaction::Reactions;
+use crate::services::db::Database;
 use anyhow::Result;
 use chrono::Utc;
+use std::collections::HashSet;
+use tracing::instrument;
 use uuid::Uuid;
-use rusqlite::{params, OptionalExtension};
 
-pub async fn create_comment(pool: &DbPool, comment: CreateComment, post_id: Uuid, author_id: Uuid) -> Result<Comment> {
-    let conn = pool.get()?;
+#[instrument(skip_all, fields(post_id = %post_id, author_id = %author_id), err)]
+pub async fn create_comment(db: &Database, comment: CreateComment, post_id: Uuid, author_id: Uuid) -> Result<Comment> {
     let now = Utc::now().to_rfc3339();
-    let id = Uuid::new_v4();
-
-    conn.execute(
-        "INSERT INTO comments (id, content, post_id, author_id, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![
-            id.to_string(),
-            comment.content,
-            post_id.to_string(),
-            author_id.to_string(),
-            now,
-            now
-        ],
-    )?;
-
-    Ok(Comment {
-        id,
+    let comment = Comment {
+        id: Uuid::new_v4(),
         content: comment.content,
         post_id,
+        parent_id: None,
         author_id,
         author: "".to_string(),
+        guest_name: None,
         created_at: now.clone(),
         updated_at: now,
-    })
-}
-
-pub async fn get_post_comments(pool: &DbPool, post_id: Uuid) -> Result<Vec<Comment>> {
-    let conn = pool.get()?;
-    let mut stmt = conn.prepare(
-        "SELECT c.id, c.content, c.post_id, c.author_id, u.username as author, c.created_at, c.updated_at 
-         FROM comments c
-         JOIN users u ON c.author_id = u.id
-         WHERE c.post_id = ? 
-         ORDER BY c.created_at DESC"
-    )?;
+        reactions: Reactions::default(),
+    };
 
-    let comments = stmt.query_map([post_id.to_string()], |row| {
-        Ok(Comment {
-            id: Uuid::parse_str(&row.get::<_, String>(0)?).unwrap(),
-            content: row.get(1)?,
-            post_id: Uuid::parse_str(&row.get::<_, String>(2)?).unwrap(),
-            author_id: Uuid::parse_str(&row.get::<_, String>(3)?).unwrap(),
-            author: row.get(4)?,
-            created_at: row.get(5)?,
-            updated_at: row.get(6)?,
-        })
-    })?;
-
-    let mut result = Vec::new();
-    for comment in comments {
-        result.push(comment?);
-    }
-    Ok(result)
+    db.insert_comment(&comment).await?;
+    Ok(comment)
 }
 
-pub async fn get_comment(pool: &DbPool, id: Uuid) -> Result<Option<Comment>> {
-    let conn = pool.get()?;
-    let mut stmt = conn.prepare(
-        "SELECT c.id, c.content, c.post_id, c.author_id, u.username as author, c.created_at, c.updated_at
-         FROM comments c
-         JOIN users u ON c.author_id = u.id
-         WHERE c.id = ?1"
-    )?;
-
-    let comment = stmt.query_row([id.to_string()], |row| {
-        Ok(Comment {
-            id: Uuid::parse_str(&row.get::<_, String>(0)?).unwrap(),
-            content: row.get(1)?,
-            post_id: Uuid::parse_str(&row.get::<_, String>(2)?).unwrap(),
-            author_id: Uuid::parse_str(&row.get::<_, String>(3)?).unwrap(),
-            author: row.get(4)?,
-            created_at: row.get(5)?,
-            updated_at: row.get(6)?,
-        })
-    }).optional()?;
+#[instrument(skip_all, fields(post_id = %post_id), err)]
+pub async fn get_post_comments(db: &Database, post_id: Uuid) -> Result<Vec<Comment>> {
+    db.list_post_comments(post_id).await
+}
 
-    Ok(comment)
+#[instrument(skip_all, fields(comment_id = %id), err)]
+pub async fn get_comment(db: &Database, id: Uuid) -> Result<Option<Comment>> {
+    db.find_comment(id).await
 }
 
-pub async fn update_comment(pool: &DbPool, id: Uuid, content: String) -> Result<bool> {
-    let conn = pool.get()?;
+#[instrument(skip_all, fields(comment_id = %id), err)]
+pub async fn update_comment(db: &Database, id: Uuid, content: String) -> Result<bool> {
     let now = Utc::now().to_rfc3339();
-    let rows = conn.execute(
-        "UPDATE comments SET content = ?1, updated_at = ?2 WHERE id = ?3",
-        params![content, now, id.to_string()],
-    )?;
-    Ok(rows > 0)
+    db.update_comment(id, &content, &now).await
+}
+
+#[instrument(skip_all, fields(comment_id = %id), err)]
+pub async fn delete_comment(db: &Database, id: Uuid) -> Result<bool> {
+    db.delete_comment(id).await
 }
 
-pub async fn delete_comment(pool: &DbPool, id: Uuid) -> Result<bool> {
-    let conn = pool.get()?;
-    let rows = conn.execute("DELETE FROM comments WHERE id = ?1", [id.to_string()])?;
-    Ok(rows > 0)
+/// Orders comments so every reply comes after the comment it answers,
+/// keeping the original order otherwise. Replies caught in a parent cycle
+/// are left at the end.
+pub fn parents_first(comments: Vec<Comment>) -> Vec<Comment> {
+    let ids: HashSet<Uuid> = comments.iter().map(|comment| comment.id).collect();
+    let mut placed = HashSet::with_capacity(comments.len());
+    let mut ordered = Vec::with_capacity(comments.len());
+    let mut pending = comments;
+
+    loop {
+        let before = pending.len();
+        let mut waiting = Vec::new();
+        for comment in pending {
+            let ready = match comment.parent_id {
+                Some(parent_id) => placed.contains(&parent_id) || !ids.contains(&parent_id),
+                None => true,
+            };
+            if ready {
+                placed.insert(comment.id);
+                ordered.push(comment);
+            } else {
+                waiting.push(comment);
+            }
+        }
+        pending = waiting;
+        if pending.is_empty() || pending.len() == before {
+            break;
+        }
+    }
+
+    ordered.extend(pending);
+    ordered
 }
@@ -1,57 +1,51 @@
-use rusqlite::{Connection, Result};
-use r2d2::Pool;
-use r2d2_sqlite::SqliteConnectionManager;
+use anyhow::Result;
+use serde::Deserialize;
+use std::sync::Arc;
 
-pub type DbPool = Pool<SqliteConnectionManager>;
+use crate::repositories::postgres::PostgresRepository;
+use crate::repositories::sqlite::{create_pool, SqliteRepository};
+use crate::repositories::Repository;
 
-pub fn init_db(conn: &Connection) -> Result<()> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS users (
-            id TEXT PRIMARY KEY,
-            username TEXT UNIQUE NOT NULL,
-            password_hash TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            updated_at TEXT NOT NULL
-        )",
-        [],
-    )?;
+/// Handle to whichever storage backend `DATABASE_URL` selected.
+pub type Database = Arc<dyn Repository>;
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS posts (
-            id TEXT PRIMARY KEY,
-            title TEXT NOT NULL,
-            content TEXT NOT NULL,
-            author_id TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            updated_at TEXT NOT NULL,
-            FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS comments (
-            id TEXT PRIMARY KEY,
-            content TEXT NOT NULL,
-            post_id TEXT NOT NULL,
-            author_id TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            updated_at TEXT NOT NULL,
-            FOREIGN KEY (post_id) REFERENCES posts(id) ON DELETE CASCADE,
-            FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
+/// Settings from the `database` table in `Rocket.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DbConfig {
+    /// Maximum number of pooled connections.
+    pub pool_size: u32,
+    /// How long a caller waits for a free connection before failing.
+    pub connection_timeout_secs: u64,
+    /// How long SQLite retries a locked database before returning `SQLITE_BUSY`.
+    pub busy_timeout_ms: u64,
+    /// Upper bound on Tokio's blocking thread pool, which runs all SQLite
+    /// and password hashing work.
+    pub blocking_threads: usize,
+}
 
-    Ok(())
+impl Default for DbConfig {
+    fn default() -> Self {
+        DbConfig {
+            pool_size: 10,
+            connection_timeout_secs: 5,
+            busy_timeout_ms: 5000,
+            blocking_threads: 64,
+        }
+    }
 }
 
-pub fn create_pool(db_path: &str) -> Pool<SqliteConnectionManager> {
-    let manager = SqliteConnectionManager::file(db_path)
-        .with_init(|conn| {
-            conn.execute_batch("PRAGMA foreign_keys=ON;")?;
-            init_db(conn)
-        });
+/// Opens the backend named by `url`: `postgres://` and `postgresql://` URLs
+/// use PostgreSQL, anything else is treated as a SQLite file path with an
+/// optional `sqlite://` or `sqlite:` prefix. Pending migrations are applied.
+pub async fn connect(url: &str, config: &DbConfig) -> Result<Database> {
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        return Ok(Arc::new(PostgresRepository::connect(url, config).await?));
+    }
 
-    Pool::new(manager).expect("Failed to create pool")
+    let path = url
+        .strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+        .unwrap_or(url);
+    Ok(Arc::new(SqliteRepository::new(create_pool(path, config))))
 }
@@ -0,0 +1,151 @@
+use crate::email::{Mailer, Verification};
+use crate::models::token::{generate_token, hash_token, timestamp, TokenPurpose, UserToken};
+use crate::models::user::normalize_email;
+use crate::models::User;
+use crate::services::db::Database;
+use crate::services::user_service;
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use serde_json::json;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// How long a verification link works.
+pub const VERIFY_TOKEN_HOURS: i64 = 24;
+
+/// What [`change_email`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmailChange {
+    /// The address was set or removed straight away.
+    Applied(Option<String>),
+    /// A link went to this address; the account keeps its current address
+    /// until the link is opened.
+    Pending(String),
+}
+
+/// Unverified accounts may not comment while verification is required.
+pub fn can_comment(mode: Verification, user: &User) -> bool {
+    mode != Verification::Required || user.email_verified
+}
+
+/// Stores a new verification token for `email`, replacing any earlier
+/// ones, and mails its link there.
+async fn send_link(db: &Database, mailer: &Mailer, base_url: &str, user: &User, email: &str) -> Result<()> {
+    let (token, token_hash) = generate_token();
+    let now = Utc::now();
+    db.delete_user_tokens(user.id, TokenPurpose::EmailVerification).await?;
+    db.insert_user_token(&UserToken {
+        token_hash,
+        user_id: user.id,
+        purpose: TokenPurpose::EmailVerification,
+        email: Some(email.to_string()),
+        expires_at: timestamp(now + Duration::hours(VERIFY_TOKEN_HOURS)),
+        created_at: timestamp(now),
+    }).await?;
+
+    let verify_url = format!("{}/verify-email?token={}", base_url.trim_end_matches('/'), token);
+    mailer.send_template(email, "Confirm your email address", "verify_email", &json!({
+        "username": user.username,
+        "email": email,
+        "verify_url": verify_url,
+        "expires_in_hours": VERIFY_TOKEN_HOURS,
+        "change": user.email.as_deref().is_some_and(|current| current != email),
+    })).await
+}
+
+/// Tells the current address that it is being replaced. Failing to reach
+/// it must not block the change, since a dead mailbox is a common reason
+/// to change address.
+async fn notify_old_address(mailer: &Mailer, user: &User, new_email: Option<&str>) {
+    let Some(old_email) = user.email.as_deref() else {
+        return;
+    };
+    let context = json!({ "username": user.username, "new_email": new_email });
+    if let Err(e) = mailer.send_template(old_email, "Your email address is changing", "email_changed", &context).await {
+        tracing::warn!(user_id = %user.id, error = %e, "failed to notify old email address");
+    }
+}
+
+async fn ensure_available(db: &Database, user_id: Uuid, email: &str) -> Result<()> {
+    match db.find_user_by_email(email).await? {
+        Some(existing_user) if existing_user.id != user_id => Err(anyhow!("Email is already in use")),
+        _ => Ok(()),
+    }
+}
+
+/// Mails a new verification link to the user's current address.
+#[instrument(skip_all, fields(user_id = %user.id), err)]
+pub async fn send_verification(db: &Database, mailer: &Mailer, base_url: &str, user: &User) -> Result<()> {
+    let email = user.email.as_deref().ok_or_else(|| anyhow!("Add an email address first"))?;
+    if user.email_verified {
+        return Err(anyhow!("Your email address is already verified"));
+    }
+    send_link(db, mailer, base_url, user, email).await
+}
+
+/// Changes the user's address the way `mode` asks. Unless verification is
+/// off, a new address only replaces the current one once its link is
+/// opened, and the current address is told about the request.
+#[instrument(skip_all, fields(user_id = %user.id), err)]
+pub async fn change_email(
+    db: &Database,
+    mailer: &Mailer,
+    base_url: &str,
+    mode: Verification,
+    user: &User,
+    email: &str,
+) -> Result<EmailChange> {
+    if mode == Verification::Off {
+        let email = user_service::set_email(db, user.id, email, false).await?;
+        return Ok(EmailChange::Applied(email));
+    }
+
+    let Some(email) = normalize_email(email) else {
+        if mode == Verification::Required {
+            return Err(anyhow!("An email address is required"));
+        }
+        user_service::set_email(db, user.id, "", false).await?;
+        db.delete_user_tokens(user.id, TokenPurpose::EmailVerification).await?;
+        notify_old_address(mailer, user, None).await;
+        return Ok(EmailChange::Applied(None));
+    };
+
+    if user.email.as_deref() == Some(email.as_str()) {
+        if user.email_verified {
+            return Ok(EmailChange::Applied(Some(email)));
+        }
+        send_link(db, mailer, base_url, user, &email).await?;
+        return Ok(EmailChange::Pending(email));
+    }
+
+    ensure_available(db, user.id, &email).await?;
+    send_link(db, mailer, base_url, user, &email).await?;
+    notify_old_address(mailer, user, Some(&email)).await;
+    Ok(EmailChange::Pending(email))
+}
+
+/// Marks the address named by a verification token as confirmed, making it
+/// the account's address if it is not already. The token is used up
+/// whether or not it is still valid. Returns the address.
+#[instrument(skip_all, err)]
+pub async fn confirm_email(db: &Database, token: &str) -> Result<String> {
+    let invalid = || anyhow!("This verification link is invalid or has expired");
+    let token = db
+        .take_user_token(&hash_token(token.trim()), TokenPurpose::EmailVerification)
+        .await?
+        .ok_or_else(invalid)?;
+    if token.expires_at <= timestamp(Utc::now()) {
+        return Err(invalid());
+    }
+    let email = token.email.ok_or_else(invalid)?;
+    let user = db.find_user_by_id(token.user_id).await?.ok_or_else(invalid)?;
+    if user.disabled {
+        return Err(anyhow!("This account has been disabled"));
+    }
+
+    // Another account may have taken the address since the link was sent
+    ensure_available(db, user.id, &email).await?;
+    user_service::set_email(db, user.id, &email, true).await?;
+    db.delete_user_tokens(user.id, TokenPurpose::EmailVerification).await?;
+    Ok(email)
+}
@@ -0,0 +1,39 @@
+use crate::repositories::SCHEMA_VERSION;
+use crate::services::db::Database;
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::Path;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Checks out a pooled connection and runs a trivial query.
+#[instrument(skip_all, err)]
+pub async fn check_database(db: &Database) -> Result<()> {
+    db.ping().await
+}
+
+/// Fails unless the database schema matches the version this build expects.
+#[instrument(skip_all, err)]
+pub async fn check_schema(db: &Database) -> Result<i32> {
+    let version = db.schema_version().await?;
+    if version != SCHEMA_VERSION {
+        return Err(anyhow!(
+            "schema version is {}, expected {}",
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+    Ok(version)
+}
+
+/// Verifies a directory exists and accepts new files.
+#[instrument(err)]
+pub fn check_writable(dir: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir.display()));
+    }
+    let probe = dir.join(format!(".readyz-{}", Uuid::new_v4()));
+    fs::write(&probe, b"ok")?;
+    fs::remove_file(&probe)?;
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use crate::importers::{ImportedSite, SkippedEntry};
+use crate::models::comment::Comment;
+use crate::models::post::Post;
+use crate::models::reaction::Reactions;
+use crate::models::user::Role;
+use crate::models::User;
+use crate::services::comment_service::parents_first;
+use crate::services::db::Database;
+use crate::services::{redirect_service, tag_service};
+use anyhow::Result;
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Accounts created for authors cannot log in until an operator resets
+/// their password.
+const UNUSABLE_PASSWORD_HASH: &str = "!";
+
+/// Owner of imported comments by visitors without an account. Each comment
+/// keeps the visitor's name in `guest_name`.
+pub const GUEST_ACCOUNT: &str = "imported-guests";
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub dry_run: bool,
+    pub users_created: Vec<String>,
+    pub users_matched: Vec<String>,
+    pub posts_created: Vec<String>,
+    /// Posts an earlier run of the same import created.
+    pub posts_existing: Vec<String>,
+    /// Posts whose slug was taken by another post, and the slug they got
+    /// instead.
+    pub slugs_changed: Vec<SlugChange>,
+    /// Posts whose HTML was not fully converted and should be reviewed.
+    pub posts_lossy: Vec<String>,
+    pub comments_created: usize,
+    pub skipped: Vec<SkippedEntry>,
+    pub redirects: Vec<RedirectMapping>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SlugChange {
+    pub title: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedirectMapping {
+    pub from: String,
+    pub to: String,
+}
+
+/// Resolves usernames to accounts, creating accounts as needed.
+struct Accounts<'a> {
+    db: &'a Database,
+    dry_run: bool,
+    ids: HashMap<String, Uuid>,
+}
+
+impl Accounts<'_> {
+    async fn resolve(&mut self, username: &str, summary: &mut ImportSummary) -> Result<Uuid> {
+        let username = account_name(username);
+        if let Some(id) = self.ids.get(&username) {
+            return Ok(*id);
+        }
+
+        let id = match self.db.find_user_by_username(&username).await? {
+            Some(user) => {
+                summary.users_matched.push(username.clone());
+                user.id
+            }
+            None => {
+                let id = Uuid::new_v4();
+                if !self.dry_run {
+                    let now = Utc::now().to_rfc3339();
+                    self.db.insert_user(&User {
+                        id,
+                        username: username.clone(),
+                        password_hash: UNUSABLE_PASSWORD_HASH.to_string(),
+                        role: Role::User,
+                        disabled: false,
+                        email: None,
+                        email_verified: false,
+                        created_at: now.clone(),
+                        updated_at: now,
+                    }).await?;
+                }
+                summary.users_created.push(username.clone());
+                id
+            }
+        };
+        self.ids.insert(username, id);
+        Ok(id)
+    }
+}
+
+/// Usernames must be at least three characters.
+fn account_name(name: &str) -> String {
+    let name = name.trim();
+    match name.chars().count() {
+        0 => "guest".to_string(),
+        1 | 2 => format!("{}-guest", name),
+        _ => name.to_string(),
+    }
+}
+
+/// A slug no post has and no earlier post of this import will take:
+/// `slug`, or else `slug-2`, `slug-3` and so on.
+async fn free_slug(db: &Database, slug: &str, planned: &HashSet<String>) -> Result<String> {
+    let mut candidate = slug.to_string();
+    let mut n = 1;
+    while planned.contains(&candidate) || db.find_post_by_slug(&candidate).await?.is_some() {
+        n += 1;
+        candidate = format!("{}-{}", slug, n);
+    }
+    Ok(candidate)
+}
+
+/// Writes an imported site through the service layer. Each post created is
+/// recorded under its platform and export id, and posts recorded by an
+/// earlier run are skipped, so running the same import twice creates
+/// nothing new. With `dry_run` nothing is written and the summary shows
+/// what would happen.
+#[instrument(skip_all, fields(dry_run), err)]
+pub async fn import_site(db: &Database, site: ImportedSite, dry_run: bool) -> Result<ImportSummary> {
+    let mut summary = ImportSummary { dry_run, skipped: site.skipped, ..Default::default() };
+    let mut accounts = Accounts { db, dry_run, ids: HashMap::new() };
+    let usernames: HashMap<String, String> = site
+        .authors
+        .into_iter()
+        .map(|author| (author.key, author.username))
+        .collect();
+    let mut planned_slugs = HashSet::new();
+    let mut planned_origins = HashSet::new();
+
+    for imported in site.posts {
+        let title = if imported.title.trim().is_empty() { "(untitled)".to_string() } else { imported.title };
+
+        let origin = format!("{}:{}", site.platform, imported.key);
+        if db.find_post_origin(&origin).await?.is_some() || !planned_origins.insert(origin.clone()) {
+            summary.posts_existing.push(title);
+            continue;
+        }
+
+        let slug = match imported.slug {
+            Some(slug) => {
+                let free = free_slug(db, &slug, &planned_slugs).await?;
+                if free != slug {
+                    summary.slugs_changed.push(SlugChange { title: title.clone(), from: slug, to: free.clone() });
+                }
+                planned_slugs.insert(free.clone());
+                Some(free)
+            }
+            None => None,
+        };
+
+        let username = usernames.get(&imported.author_key).unwrap_or(&imported.author_key);
+        let author_id = accounts.resolve(username, &mut summary).await?;
+        let post = Post {
+            id: Uuid::new_v4(),
+            title: title.clone(),
+            slug,
+            content: imported.markdown,
+            author_id,
+            author: String::new(),
+            created_at: imported.created_at,
+            updated_at: imported.updated_at,
+            seo_title: None,
+            seo_description: None,
+            excerpt: None,
+            reactions: Reactions::default(),
+        };
+
+        let comment_ids: HashMap<&str, Uuid> = imported
+            .comments
+            .iter()
+            .map(|comment| (comment.key.as_str(), Uuid::new_v4()))
+            .collect();
+        let mut comments = Vec::with_capacity(imported.comments.len());
+        for comment in &imported.comments {
+            // Only the site's own authors have accounts; everyone else
+            // comments as the guest account under their own name.
+            let (author_id, guest_name) = match comment.author_key.as_ref().and_then(|key| usernames.get(key)) {
+                Some(username) => (accounts.resolve(username, &mut summary).await?, None),
+                None => (accounts.resolve(GUEST_ACCOUNT, &mut summary).await?, Some(comment.author_name.clone())),
+            };
+            comments.push(Comment {
+                id: comment_ids[comment.key.as_str()],
+                content: comment.markdown.clone(),
+                post_id: post.id,
+                parent_id: comment
+                    .parent_key
+                    .as_deref()
+                    .and_then(|key| comment_ids.get(key).copied()),
+                author_id,
+                author: String::new(),
+                guest_name,
+                created_at: comment.created_at.clone(),
+                updated_at: comment.created_at.clone(),
+                reactions: Reactions::default(),
+            });
+        }
+
+        summary.comments_created += comments.len();
+        if !dry_run {
+            db.insert_post(&post).await?;
+            db.insert_post_origin(&origin, post.id).await?;
+            tag_service::tag_post(db, post.id, &imported.tags).await?;
+            for comment in parents_first(comments) {
+                db.insert_comment(&comment).await?;
+            }
+        }
+
+        if !imported.lossless {
+            summary.posts_lossy.push(title.clone());
+        }
+        if let Some(old_url) = &imported.old_url {
+            record_redirect(db, old_url, post.id, dry_run, &mut summary).await?;
+        }
+        summary.posts_created.push(title);
+    }
+
+    Ok(summary)
+}
+
+async fn record_redirect(
+    db: &Database,
+    old_url: &str,
+    post_id: Uuid,
+    dry_run: bool,
+    summary: &mut ImportSummary,
+) -> Result<()> {
+    let Some(from) = redirect_service::normalize_path(old_url) else {
+        return Ok(());
+    };
+    let to = format!("/posts/{}", post_id);
+    if from == "/" || from == to {
+        return Ok(());
+    }
+    if dry_run || redirect_service::add_redirect(db, &from, &to).await? {
+        summary.redirects.push(RedirectMapping { from, to });
+    }
+    Ok(())
+}
@@ -0,0 +1,302 @@
+use crate::email::Mailer;
+use crate::metrics;
+use crate::models::auth::verify_password_or_dummy;
+use crate::models::login_attempt::{LoginAttempt, LoginClient, LoginOutcome};
+use crate::models::token::timestamp;
+use crate::models::User;
+use crate::services::db::Database;
+use crate::services::{sso_service, user_service};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::IpAddr;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Most attempts shown to an account's owner.
+pub const HISTORY_LIMIT: i64 = 50;
+
+/// Limits on failed logins. Past its free attempts, each failure makes the
+/// next attempt wait, starting at a second and doubling up to
+/// `max_delay_secs`; at the lockout count, logins stop for
+/// `lockout_minutes`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoginConfig {
+    /// Failures in a row allowed on one account before waits start.
+    pub free_attempts: i32,
+    pub max_delay_secs: i64,
+    /// Failures in a row that lock an account. Its owner is emailed.
+    pub lockout_attempts: i32,
+    pub lockout_minutes: i64,
+    /// The same limits for all logins from one address, or one /64 network
+    /// for IPv6. Set higher, since many people may share an address.
+    pub address_free_attempts: i32,
+    pub address_lockout_attempts: i32,
+    /// Failures are forgotten after this long without another. Longer than
+    /// `lockout_minutes`, so a failure right after a lockout locks again.
+    pub forget_after_minutes: i64,
+    /// How long login attempts are kept for their account's owner to see.
+    pub history_days: i64,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        LoginConfig {
+            free_attempts: 3,
+            max_delay_secs: 300,
+            lockout_attempts: 10,
+            lockout_minutes: 30,
+            address_free_attempts: 20,
+            address_lockout_attempts: 100,
+            forget_after_minutes: 60,
+            history_days: 90,
+        }
+    }
+}
+
+/// How a username and password check ended.
+#[derive(Debug)]
+pub enum LoginResult {
+    /// The password was right. Any second step is still to come.
+    Success(User),
+    /// The password was right but the account is disabled.
+    Disabled,
+    /// The username or password was wrong.
+    Failed,
+    /// There were too many failures; the password was not checked.
+    Locked { retry_after: Duration },
+}
+
+/// Counters of failures, one for the account and one for the address.
+struct Limit {
+    key: String,
+    free_attempts: i32,
+    lockout_attempts: i32,
+    /// For metrics: `account` or `address`.
+    scope: &'static str,
+}
+
+impl Limit {
+    /// Unknown usernames are counted too, so the limits do not reveal
+    /// which accounts exist.
+    fn account(config: &LoginConfig, user: Option<&User>, username: &str) -> Limit {
+        let key = match user {
+            Some(user) => account_key(user.id),
+            None => format!("name:{}", username),
+        };
+        Limit {
+            key,
+            free_attempts: config.free_attempts,
+            lockout_attempts: config.lockout_attempts,
+            scope: "account",
+        }
+    }
+
+    fn address(config: &LoginConfig, ip: IpAddr) -> Limit {
+        Limit {
+            key: address_key(ip),
+            free_attempts: config.address_free_attempts,
+            lockout_attempts: config.address_lockout_attempts,
+            scope: "address",
+        }
+    }
+
+    /// How long to lock after the `failures`th failure in a row, if at all.
+    fn delay(&self, config: &LoginConfig, failures: i32) -> Option<Duration> {
+        if failures >= self.lockout_attempts {
+            Some(Duration::minutes(config.lockout_minutes))
+        } else if failures > self.free_attempts {
+            let doublings = (failures - self.free_attempts - 1).min(30) as u32;
+            Some(Duration::seconds((1i64 << doublings).min(config.max_delay_secs)))
+        } else {
+            None
+        }
+    }
+}
+
+fn account_key(user_id: Uuid) -> String {
+    format!("account:{}", user_id)
+}
+
+/// IPv6 clients are often given a whole /64 network, so they are counted by
+/// it.
+pub(crate) fn address_key(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => format!("ip:{}", ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => format!("ip:{}", ip),
+            None => {
+                let segments = ip.segments();
+                format!("ip:{:x}:{:x}:{:x}:{:x}::/64", segments[0], segments[1], segments[2], segments[3])
+            }
+        },
+    }
+}
+
+enum Count {
+    /// Failures in a row, this attempt included.
+    Failures(i32),
+    Locked(Duration),
+}
+
+/// Counts an attempt against `limit` and, past its free attempts, locks it
+/// right away; a right password lifts the lock again.
+async fn count_attempt(db: &Database, config: &LoginConfig, limit: &Limit, now: DateTime<Utc>) -> Result<Count> {
+    let forget_before = timestamp(now - Duration::minutes(config.forget_after_minutes));
+    match db.begin_login_attempt(&limit.key, &timestamp(now), &forget_before).await? {
+        Some(failures) => {
+            if let Some(delay) = limit.delay(config, failures) {
+                // Rounded up to a whole second, so no lock is shorter than asked
+                let until = now + delay;
+                let until = match until.timestamp_subsec_nanos() {
+                    0 => until,
+                    _ => until + Duration::seconds(1),
+                };
+                db.set_login_lock(&limit.key, &timestamp(until)).await?;
+            }
+            Ok(Count::Failures(failures))
+        }
+        None => {
+            let locked_until = db
+                .find_login_lock(&limit.key)
+                .await?
+                .and_then(|until| DateTime::parse_from_rfc3339(&until).ok());
+            let retry_after = locked_until.map(|until| until.with_timezone(&Utc) - now).unwrap_or_default();
+            Ok(Count::Locked(retry_after.max(Duration::seconds(1))))
+        }
+    }
+}
+
+/// Checks a username and password within the limits on the account and the
+/// client's address. The attempt is counted before the password is checked;
+/// a right password then clears the account's count and takes the attempt
+/// back from the address's. Unknown usernames and accounts without a
+/// password take as long as wrong passwords.
+///
+/// Failures on existing accounts are recorded for their owners. Successes
+/// are left to the caller, to record once any second step is done.
+#[instrument(skip_all, fields(username = %username), err)]
+pub async fn authenticate(
+    db: &Database,
+    mailer: &Mailer,
+    config: &LoginConfig,
+    client: &LoginClient,
+    username: &str,
+    password: &str,
+) -> Result<LoginResult> {
+    let now = Utc::now();
+    let user = user_service::get_user_by_username(db, username).await?;
+    let account = Limit::account(config, user.as_ref(), username);
+    let address = client.ip.map(|ip| Limit::address(config, ip));
+
+    let mut failures = Vec::new();
+    for limit in address.iter().chain([&account]) {
+        match count_attempt(db, config, limit, now).await? {
+            Count::Failures(count) => failures.push((limit, count)),
+            Count::Locked(retry_after) => {
+                if let Some(user) = &user {
+                    record_attempt(db, config, user.id, client, LoginOutcome::Locked).await?;
+                }
+                return Ok(LoginResult::Locked { retry_after });
+            }
+        }
+    }
+
+    let hash = user.as_ref().filter(|user| sso_service::has_password(user)).map(|user| user.password_hash.as_str());
+    let valid = verify_password_or_dummy(password, hash).await.unwrap_or(false);
+    match user {
+        Some(user) if valid && user.disabled => {
+            record_attempt(db, config, user.id, client, LoginOutcome::Disabled).await?;
+            Ok(LoginResult::Disabled)
+        }
+        Some(user) if valid => {
+            db.clear_login_failures(&account.key).await?;
+            if let Some(address) = &address {
+                db.forgive_login_attempt(&address.key).await?;
+            }
+            Ok(LoginResult::Success(user))
+        }
+        user => {
+            for (limit, count) in &failures {
+                if *count == limit.lockout_attempts {
+                    metrics::record_lockout(limit.scope);
+                }
+            }
+            if let Some(user) = &user {
+                record_attempt(db, config, user.id, client, LoginOutcome::WrongPassword).await?;
+                if failures.iter().any(|(limit, count)| limit.scope == "account" && *count == limit.lockout_attempts) {
+                    tracing::warn!(user_id = %user.id, "account locked after failed logins");
+                    send_lockout_notice(mailer, config, user, client);
+                }
+            }
+            Ok(LoginResult::Failed)
+        }
+    }
+}
+
+/// Emails the owner of a newly locked account, if it has an address. Sent in
+/// the background so the response time does not reveal whether the
+/// username exists. Failures are logged by the mailer.
+fn send_lockout_notice(mailer: &Mailer, config: &LoginConfig, user: &User, client: &LoginClient) {
+    let Some(email) = user.email.clone() else {
+        return;
+    };
+    let mailer = mailer.clone();
+    let context = json!({
+        "username": user.username,
+        "attempts": config.lockout_attempts,
+        "lockout_minutes": config.lockout_minutes,
+        "ip": client.ip.map(|ip| ip.to_string()),
+    });
+    tokio::spawn(async move {
+        mailer.send_template(&email, "Your account was locked", "account_locked", &context).await.ok();
+    });
+}
+
+/// Adds an attempt to its account's login history. Successful logins also
+/// clear out history older than `history_days`.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn record_attempt(
+    db: &Database,
+    config: &LoginConfig,
+    user_id: Uuid,
+    client: &LoginClient,
+    outcome: LoginOutcome,
+) -> Result<()> {
+    let now = Utc::now();
+    db.insert_login_attempt(&LoginAttempt {
+        id: Uuid::new_v4(),
+        user_id,
+        ip: client.ip.map(|ip| ip.to_string()),
+        user_agent: client.user_agent.clone(),
+        outcome,
+        created_at: timestamp(now),
+    }).await?;
+    if outcome == LoginOutcome::Success {
+        db.prune_login_records(&timestamp(now - Duration::days(config.history_days))).await?;
+    }
+    Ok(())
+}
+
+/// The account's most recent login attempts, newest first.
+pub async fn history(db: &Database, user_id: Uuid) -> Result<Vec<LoginAttempt>> {
+    db.list_login_attempts(user_id, HISTORY_LIMIT).await
+}
+
+/// Lifts any lock on logging in to the account, and on entering its
+/// two-factor codes, and forgets its failures. Returns whether anything was
+/// locked.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn unlock(db: &Database, user_id: Uuid) -> Result<bool> {
+    let now = timestamp(Utc::now());
+    let key = account_key(user_id);
+    let mut locked = db.find_login_lock(&key).await?.is_some_and(|until| until > now);
+    db.clear_login_failures(&key).await?;
+    if let Some(two_factor) = db.find_two_factor(user_id).await? {
+        locked |= two_factor.locked_until.is_some_and(|until| until > now);
+        db.set_two_factor_lock(user_id, None, &now).await?;
+    }
+    Ok(locked)
+}
@@ -0,0 +1,33 @@
+use crate::repositories::SCHEMA_VERSION;
+use crate::services::db::Database;
+use anyhow::Result;
+use serde::Serialize;
+use tracing::instrument;
+
+#[derive(Debug, Serialize)]
+pub struct SchemaStatus {
+    pub backend: &'static str,
+    pub version: i32,
+    pub expected: i32,
+}
+
+/// Reports the schema version. Connecting applies pending migrations, so
+/// on a handle from `db::connect` this confirms they ran.
+#[instrument(skip_all, err)]
+pub async fn schema_status(db: &Database) -> Result<SchemaStatus> {
+    Ok(SchemaStatus {
+        backend: db.backend(),
+        version: db.schema_version().await?,
+        expected: SCHEMA_VERSION,
+    })
+}
+
+#[instrument(skip_all, err)]
+pub async fn reindex(db: &Database) -> Result<()> {
+    db.reindex().await
+}
+
+#[instrument(skip_all, err)]
+pub async fn vacuum(db: &Database) -> Result<()> {
+    db.vacuum().await
+}
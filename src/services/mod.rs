@@ -1,4 +1,22 @@
 pub mod db;
 pub mod user_service;
+pub mod password_reset_service;
+pub mod email_verification_service;
+pub mod two_factor_service;
+pub mod login_service;
+pub mod sso_service;
 pub mod post_service;
 pub mod comment_service;
+pub mod tag_service;
+pub mod series_service;
+pub mod reaction_service;
+pub mod bookmark_service;
+pub mod profile_service;
+pub mod redirect_service;
+pub mod stats_service;
+pub mod health_service;
+pub mod maintenance_service;
+pub mod backup_service;
+pub mod transfer_service;
+pub mod import_service;
+pub mod sync_service;
@@ -0,0 +1,71 @@
+use crate::email::Mailer;
+use crate::models::token::{generate_token, hash_token, timestamp, TokenPurpose, UserToken};
+use crate::models::user::normalize_email;
+use crate::services::db::Database;
+use crate::services::{sso_service, user_service};
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use serde_json::json;
+use tracing::instrument;
+
+/// How long a reset link works.
+pub const RESET_TOKEN_MINUTES: i64 = 60;
+
+/// Mails a reset link to the account with this address. Unknown addresses,
+/// disabled accounts and accounts that only sign in through single sign-on
+/// get nothing; callers must not reveal which happened. Returns whether a
+/// message was sent. Requesting a new link invalidates earlier ones.
+#[instrument(skip_all, err)]
+pub async fn request_reset(db: &Database, mailer: &Mailer, base_url: &str, email: &str) -> Result<bool> {
+    let Some(email) = normalize_email(email) else {
+        return Ok(false);
+    };
+    let Some(user) = db.find_user_by_email(&email).await? else {
+        return Ok(false);
+    };
+    if user.disabled || !sso_service::has_password(&user) {
+        return Ok(false);
+    }
+
+    let (token, token_hash) = generate_token();
+    let now = Utc::now();
+    db.delete_user_tokens(user.id, TokenPurpose::PasswordReset).await?;
+    db.insert_user_token(&UserToken {
+        token_hash,
+        user_id: user.id,
+        purpose: TokenPurpose::PasswordReset,
+        email: None,
+        expires_at: timestamp(now + Duration::minutes(RESET_TOKEN_MINUTES)),
+        created_at: timestamp(now),
+    }).await?;
+
+    let reset_url = format!("{}/reset-password?token={}", base_url.trim_end_matches('/'), token);
+    mailer.send_template(&email, "Reset your password", "password_reset", &json!({
+        "username": user.username,
+        "reset_url": reset_url,
+        "expires_in_minutes": RESET_TOKEN_MINUTES,
+    })).await?;
+    Ok(true)
+}
+
+/// Sets a new password for the holder of a reset token. The token is used
+/// up whether or not it is still valid.
+#[instrument(skip_all, err)]
+pub async fn reset_password(db: &Database, token: &str, new_password: &str) -> Result<()> {
+    let invalid = || anyhow!("This reset link is invalid or has expired");
+    let token = db
+        .take_user_token(&hash_token(token.trim()), TokenPurpose::PasswordReset)
+        .await?
+        .ok_or_else(invalid)?;
+    if token.expires_at <= timestamp(Utc::now()) {
+        return Err(invalid());
+    }
+    let user = db.find_user_by_id(token.user_id).await?.ok_or_else(invalid)?;
+    if user.disabled {
+        return Err(anyhow!("This account has been disabled"));
+    }
+
+    user_service::reset_password(db, user.id, new_password).await?;
+    db.delete_user_tokens(user.id, TokenPurpose::PasswordReset).await?;
+    Ok(())
+}
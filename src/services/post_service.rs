@@ -1,124 +1,81 @@
-use crate::models::post::{Post, CreatePost};
-use crate::services::db::DbPool;
+use crate::models::post::{Post, CreatePost, PostSort, UpdatePostExcerpt, UpdatePostSeo};
+use crate::models::reaction::Reactions;
+use crate::services::db::Database;
 use anyhow::Result;
 use chrono::Utc;
+use tracing::instrument;
 use uuid::Uuid;
-use rusqlite::{params, OptionalExtension};
 
-pub async fn create_post(pool: &DbPool, post: CreatePost, author_id: Uuid) -> Result<Post> {
-    let conn = pool.get()?;
+#[instrument(skip_all, fields(author_id = %author_id), err)]
+pub async fn create_post(db: &Database, post: CreatePost, author_id: Uuid) -> Result<Post> {
     let now = Utc::now().to_rfc3339();
     let id = Uuid::new_v4();
 
-    conn.execute(
-        "INSERT INTO posts (id, title, content, author_id, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![
-            id.to_string(),
-            post.title,
-            post.content,
-            author_id.to_string(),
-            now,
-            now
-        ],
-    )?;
+    db.insert_post(&Post {
+        id,
+        title: post.title,
+        slug: None,
+        content: post.content,
+        author_id,
+        author: String::new(),
+        created_at: now.clone(),
+        updated_at: now,
+        seo_title: None,
+        seo_description: None,
+        excerpt: None,
+        reactions: Reactions::default(),
+    }).await?;
 
     // Get the post with author info
-    Ok(get_post(pool, id).await?.unwrap())
+    Ok(get_post(db, id).await?.unwrap())
 }
 
-pub async fn get_posts(pool: &DbPool) -> Result<Vec<Post>> {
-    let conn = pool.get()?;
-    let mut stmt = conn.prepare(
-        "SELECT p.id, p.title, p.content, p.author_id, u.username, p.created_at, p.updated_at
-         FROM posts p
-         LEFT JOIN users u ON u.id = p.author_id
-         ORDER BY p.created_at DESC"
-    )?;
-
-    let posts = stmt.query_map([], |row| {
-        let id_str: String = row.get(0)?;
-        let id = Uuid::parse_str(&id_str).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
-            0,
-            rusqlite::types::Type::Text,
-            Box::new(e),
-        ))?;
-
-        let author_id_str: String = row.get(3)?;
-        let author_id = Uuid::parse_str(&author_id_str).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
-            3,
-            rusqlite::types::Type::Text,
-            Box::new(e),
-        ))?;
-
-        Ok(Post {
-            id,
-            title: row.get(1)?,
-            content: row.get(2)?,
-            author_id,
-            author: row.get(4)?,
-            created_at: row.get(5)?,
-            updated_at: row.get(6)?,
-        })
-    })?;
-
-    let mut result = Vec::new();
-    for post in posts {
-        result.push(post?);
-    }
-    Ok(result)
+#[instrument(skip_all, fields(sort = ?sort), err)]
+pub async fn get_posts(db: &Database, sort: PostSort) -> Result<Vec<Post>> {
+    db.list_posts(sort).await
 }
 
-pub async fn get_post(pool: &DbPool, id: Uuid) -> Result<Option<Post>> {
-    let conn = pool.get()?;
-    let post = conn.query_row(
-        "SELECT p.id, p.title, p.content, p.author_id, u.username, p.created_at, p.updated_at
-         FROM posts p
-         LEFT JOIN users u ON u.id = p.author_id
-         WHERE p.id = ?1",
-        [id.to_string()],
-        |row| {
-            let id_str: String = row.get(0)?;
-            let id = Uuid::parse_str(&id_str).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
-                0,
-                rusqlite::types::Type::Text,
-                Box::new(e),
-            ))?;
+#[instrument(skip_all, fields(post_id = %id), err)]
+pub async fn get_post(db: &Database, id: Uuid) -> Result<Option<Post>> {
+    db.find_post(id).await
+}
 
-            let author_id_str: String = row.get(3)?;
-            let author_id = Uuid::parse_str(&author_id_str).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
-                3,
-                rusqlite::types::Type::Text,
-                Box::new(e),
-            ))?;
+#[instrument(skip_all, fields(post_id = %id), err)]
+pub async fn update_post(db: &Database, id: Uuid, title: String, content: String) -> Result<bool> {
+    let now = Utc::now().to_rfc3339();
+    db.update_post(id, &title, &content, &now).await
+}
 
-            Ok(Post {
-                id,
-                title: row.get(1)?,
-                content: row.get(2)?,
-                author_id,
-                author: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
-            })
-        },
-    ).optional()?;
+/// Sets a post's slug and dates, e.g. from a synchronised Markdown file.
+#[instrument(skip_all, fields(post_id = %id), err)]
+pub async fn set_post_metadata(
+    db: &Database,
+    id: Uuid,
+    slug: Option<&str>,
+    created_at: &str,
+    updated_at: &str,
+) -> Result<bool> {
+    db.update_post_metadata(id, slug, created_at, updated_at).await
+}
 
-    Ok(post)
+/// Sets the title and description search engines and link previews show.
+/// Empty fields clear the override.
+#[instrument(skip_all, fields(post_id = %id), err)]
+pub async fn set_post_seo(db: &Database, id: Uuid, form: &UpdatePostSeo) -> Result<bool> {
+    let seo_title = Some(form.seo_title.trim()).filter(|value| !value.is_empty());
+    let seo_description = Some(form.seo_description.trim()).filter(|value| !value.is_empty());
+    db.update_post_seo(id, seo_title, seo_description).await
 }
 
-pub async fn update_post(pool: &DbPool, id: Uuid, title: String, content: String) -> Result<bool> {
-    let conn = pool.get()?;
-    let now = Utc::now().to_rfc3339();
-    let rows = conn.execute(
-        "UPDATE posts SET title = ?1, content = ?2, updated_at = ?3 WHERE id = ?4",
-        params![title, content, now, id.to_string()],
-    )?;
-    Ok(rows > 0)
+/// Sets the excerpt shown in post listings. An empty one clears it, so the
+/// excerpt is taken from the content again.
+#[instrument(skip_all, fields(post_id = %id), err)]
+pub async fn set_post_excerpt(db: &Database, id: Uuid, form: &UpdatePostExcerpt) -> Result<bool> {
+    let excerpt = Some(form.excerpt.trim()).filter(|value| !value.is_empty());
+    db.update_post_excerpt(id, excerpt).await
 }
 
-pub async fn delete_post(pool: &DbPool, id: Uuid) -> Result<bool> {
-    let conn = pool.get()?;
-    let rows = conn.execute("DELETE FROM posts WHERE id = ?1", params![id.to_string()])?;
-    Ok(rows > 0)
+#[instrument(skip_all, fields(post_id = %id), err)]
+pub async fn delete_post(db: &Database, id: Uuid) -> Result<bool> {
+    db.delete_post(id).await
 }
@@ -0,0 +1,90 @@
+use crate::models::comment::Comment;
+use crate::models::post::Post;
+use crate::models::profile::{Profile, UpdateProfile};
+use crate::services::db::Database;
+use anyhow::Result;
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// How many of an author's comments their page shows.
+const RECENT_COMMENTS: i64 = 10;
+
+/// Everything shown on an author's public page.
+#[derive(Debug, Serialize)]
+pub struct AuthorPage {
+    pub username: String,
+    /// Display name, falling back to the username.
+    pub name: String,
+    /// Sign-up date as `YYYY-MM-DD`.
+    pub joined: String,
+    pub profile: Profile,
+    pub posts: Vec<Post>,
+    pub comments: Vec<AuthorComment>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorComment {
+    pub comment: Comment,
+    pub post_title: String,
+}
+
+/// Returns the user's profile, or an empty one if they never set it up.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn get_profile(db: &Database, user_id: Uuid) -> Result<Profile> {
+    Ok(db.find_profile(user_id).await?.unwrap_or_else(|| Profile { user_id, ..Profile::default() }))
+}
+
+/// Saves a validated profile form; blank fields are cleared.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn update_profile(db: &Database, user_id: Uuid, form: UpdateProfile) -> Result<Profile> {
+    let optional = |value: &str| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+    let profile = Profile {
+        user_id,
+        display_name: optional(&form.display_name),
+        bio: optional(&form.bio),
+        avatar_url: optional(&form.avatar_url),
+        website: optional(&form.website),
+        social_links: form.social_links(),
+        updated_at: Utc::now().to_rfc3339(),
+    };
+    db.upsert_profile(&profile).await?;
+    Ok(profile)
+}
+
+#[instrument(skip_all, fields(username = %username), err)]
+pub async fn get_author_page(db: &Database, username: &str) -> Result<Option<AuthorPage>> {
+    let Some(user) = db.find_user_by_username(username).await? else {
+        return Ok(None);
+    };
+    let profile = get_profile(db, user.id).await?;
+
+    let recent = db.list_comments_by_author(user.id, RECENT_COMMENTS).await?;
+    let post_ids: Vec<Uuid> = recent.iter().map(|comment| comment.post_id).collect();
+    let titles: HashMap<Uuid, String> = db
+        .list_posts_by_ids(&post_ids)
+        .await?
+        .into_iter()
+        .map(|post| (post.id, post.title))
+        .collect();
+    let comments = recent
+        .into_iter()
+        .filter_map(|comment| {
+            let post_title = titles.get(&comment.post_id)?.clone();
+            Some(AuthorComment { comment, post_title })
+        })
+        .collect();
+
+    Ok(Some(AuthorPage {
+        name: profile.display_name.clone().unwrap_or_else(|| user.username.clone()),
+        username: user.username,
+        // Accounts store a naive timestamp, which Tera's `date` filter
+        // does not parse; the calendar date is all the page shows.
+        joined: user.created_at.get(..10).unwrap_or(&user.created_at).to_string(),
+        posts: db.list_posts_by_author(user.id).await?,
+        comments,
+        profile,
+    }))
+}
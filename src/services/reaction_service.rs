@@ -0,0 +1,90 @@
+use crate::models::reaction::{ReactionTarget, Reactions, UserReactions};
+use crate::services::db::Database;
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+/// A reaction readers can leave. `name` is stored and appears in URLs;
+/// `emoji` is what readers see.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionKind {
+    pub name: String,
+    pub emoji: String,
+}
+
+/// The reactions offered on posts and comments, in the order they are
+/// shown. Counts of a kind taken off the list are kept but not shown.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReactionsConfig {
+    pub kinds: Vec<ReactionKind>,
+}
+
+impl Default for ReactionsConfig {
+    fn default() -> Self {
+        let kind = |name: &str, emoji: &str| ReactionKind { name: name.to_string(), emoji: emoji.to_string() };
+        ReactionsConfig {
+            kinds: vec![
+                kind("like", "\u{1F44D}"),
+                kind("love", "\u{2764}\u{FE0F}"),
+                kind("laugh", "\u{1F602}"),
+                kind("celebrate", "\u{1F389}"),
+                kind("insightful", "\u{1F4A1}"),
+            ],
+        }
+    }
+}
+
+impl ReactionsConfig {
+    pub fn offers(&self, kind: &str) -> bool {
+        self.kinds.iter().any(|offered| offered.name == kind)
+    }
+}
+
+/// One offered reaction under a post or comment.
+#[derive(Debug, Serialize)]
+pub struct ReactionButton<'a> {
+    pub name: &'a str,
+    pub emoji: &'a str,
+    pub count: i64,
+    /// Whether the reader has reacted this way.
+    pub reacted: bool,
+}
+
+/// The offered reactions with their counts, marking the kinds in `mine`.
+pub fn buttons<'a>(config: &'a ReactionsConfig, reactions: &Reactions, mine: &[String]) -> Vec<ReactionButton<'a>> {
+    config
+        .kinds
+        .iter()
+        .map(|kind| ReactionButton {
+            name: &kind.name,
+            emoji: &kind.emoji,
+            count: reactions.count(&kind.name),
+            reacted: mine.contains(&kind.name),
+        })
+        .collect()
+}
+
+/// Adds the user's reaction of this kind to a post or comment, or takes it
+/// back. Doing either twice changes nothing. Returns the new counts, or
+/// `None` if there is no such post or comment.
+#[instrument(skip_all, fields(target = ?target, target_id = %target_id, user_id = %user_id, kind = %kind), err)]
+pub async fn react(
+    db: &Database,
+    target: ReactionTarget,
+    target_id: Uuid,
+    user_id: Uuid,
+    kind: &str,
+    reacted: bool,
+) -> Result<Option<Reactions>> {
+    let now = Utc::now().to_rfc3339();
+    db.set_reaction(target, target_id, user_id, kind, reacted, &now).await
+}
+
+/// The kinds the user has reacted with to the post and its comments.
+#[instrument(skip_all, fields(post_id = %post_id, user_id = %user_id), err)]
+pub async fn get_user_reactions(db: &Database, post_id: Uuid, user_id: Uuid) -> Result<UserReactions> {
+    db.list_user_reactions(post_id, user_id).await
+}
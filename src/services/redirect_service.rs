@@ -0,0 +1,46 @@
+use crate::services::db::Database;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use tracing::instrument;
+
+/// Reduces an absolute URL or path to the form redirects are stored under:
+/// the path and query only, without a trailing slash.
+pub fn normalize_path(url: &str) -> Option<String> {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |start| &rest[start..]),
+        None => url,
+    };
+    if !path.starts_with('/') {
+        return None;
+    }
+
+    let path = path.split('#').next().unwrap_or(path);
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+    let path = match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+    Some(match query {
+        Some(query) if !query.is_empty() => format!("{}?{}", path, query),
+        _ => path.to_string(),
+    })
+}
+
+/// Records a permanent redirect. Returns false if `from` already redirects
+/// somewhere, in which case the existing target is kept.
+#[instrument(skip_all, fields(from = %from), err)]
+pub async fn add_redirect(db: &Database, from: &str, to: &str) -> Result<bool> {
+    let from = normalize_path(from).ok_or_else(|| anyhow!("Not a URL or path: {}", from))?;
+    db.insert_redirect(&from, to, &Utc::now().to_rfc3339()).await
+}
+
+#[instrument(skip_all, fields(path = %path), err)]
+pub async fn resolve(db: &Database, path: &str) -> Result<Option<String>> {
+    match normalize_path(path) {
+        Some(path) => db.find_redirect(&path).await,
+        None => Ok(None),
+    }
+}
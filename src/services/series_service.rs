@@ -0,0 +1,148 @@
+use crate::models::post::{slugify, Post};
+use crate::models::series::Series;
+use crate::services::db::Database;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use serde::Serialize;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// A post's place in its series, for the links on the post's page.
+#[derive(Debug, Serialize)]
+pub struct SeriesNav {
+    pub id: Uuid,
+    pub title: String,
+    pub url: String,
+    /// Part number of the current post, counting from 1.
+    pub position: usize,
+    pub total: usize,
+    pub parts: Vec<SeriesPart>,
+    pub prev: Option<SeriesPart>,
+    pub next: Option<SeriesPart>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeriesPart {
+    pub id: Uuid,
+    pub title: String,
+    pub url: String,
+    pub current: bool,
+}
+
+/// A part listed on a series page.
+#[derive(Debug, Serialize)]
+pub struct SeriesEntry<'a> {
+    pub url: String,
+    pub post: &'a Post,
+}
+
+/// Navigation for post `current` among the series' `posts`, or `None` when
+/// it is not one of them. The URLs come from the caller, so the server and
+/// the static export can each use their own.
+pub fn series_nav(
+    series: &Series,
+    posts: &[Post],
+    current: Uuid,
+    series_url: String,
+    post_url: impl Fn(&Post) -> String,
+) -> Option<SeriesNav> {
+    let index = posts.iter().position(|post| post.id == current)?;
+    let parts: Vec<SeriesPart> = posts
+        .iter()
+        .map(|post| SeriesPart { id: post.id, title: post.title.clone(), url: post_url(post), current: post.id == current })
+        .collect();
+    Some(SeriesNav {
+        id: series.id,
+        title: series.title.clone(),
+        url: series_url,
+        position: index + 1,
+        total: parts.len(),
+        prev: index.checked_sub(1).map(|prev| parts[prev].clone()),
+        next: parts.get(index + 1).cloned(),
+        parts,
+    })
+}
+
+/// Starts a series. Its slug comes from the title, with `-2`, `-3`, ...
+/// added when another series has it already.
+#[instrument(skip_all, fields(author_id = %author_id), err)]
+pub async fn create_series(db: &Database, author_id: Uuid, title: &str) -> Result<Series> {
+    let title = title.trim();
+    let base = slugify(title);
+    if base.is_empty() {
+        return Err(anyhow!("Series title {:?} has no letters or digits", title));
+    }
+    let mut slug = base.clone();
+    let mut suffix = 2;
+    while db.find_series_by_slug(&slug).await?.is_some() {
+        slug = format!("{}-{}", base, suffix);
+        suffix += 1;
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let id = Uuid::new_v4();
+    db.insert_series(&Series {
+        id,
+        title: title.to_string(),
+        slug,
+        author_id,
+        author: String::new(),
+        created_at: now.clone(),
+        updated_at: now,
+    }).await?;
+    db.find_series(id).await?.ok_or_else(|| anyhow!("Series {} vanished after insert", id))
+}
+
+#[instrument(skip_all, fields(series_id = %id), err)]
+pub async fn get_series(db: &Database, id: Uuid) -> Result<Option<Series>> {
+    db.find_series(id).await
+}
+
+#[instrument(skip_all, fields(slug = %slug), err)]
+pub async fn get_series_by_slug(db: &Database, slug: &str) -> Result<Option<Series>> {
+    db.find_series_by_slug(slug).await
+}
+
+#[instrument(skip_all, err)]
+pub async fn list_series(db: &Database) -> Result<Vec<Series>> {
+    db.list_series().await
+}
+
+#[instrument(skip_all, fields(author_id = %author_id), err)]
+pub async fn list_author_series(db: &Database, author_id: Uuid) -> Result<Vec<Series>> {
+    db.list_series_by_author(author_id).await
+}
+
+#[instrument(skip_all, fields(post_id = %post_id), err)]
+pub async fn get_post_series(db: &Database, post_id: Uuid) -> Result<Option<Series>> {
+    db.find_post_series(post_id).await
+}
+
+/// The series' posts in order.
+#[instrument(skip_all, fields(series_id = %series_id), err)]
+pub async fn get_series_posts(db: &Database, series_id: Uuid) -> Result<Vec<Post>> {
+    db.list_series_posts(series_id).await
+}
+
+/// Puts a post at `position` in the series, counting from 1, or last when
+/// `position` is missing or past the end. A post already in the series
+/// moves; one in another series leaves it.
+#[instrument(skip_all, fields(series_id = %series_id, post_id = %post_id), err)]
+pub async fn add_to_series(db: &Database, series_id: Uuid, post_id: Uuid, position: Option<usize>) -> Result<()> {
+    let mut post_ids: Vec<Uuid> = db
+        .list_series_posts(series_id)
+        .await?
+        .into_iter()
+        .map(|post| post.id)
+        .filter(|id| *id != post_id)
+        .collect();
+    let index = position.map_or(post_ids.len(), |position| position.saturating_sub(1).min(post_ids.len()));
+    post_ids.insert(index, post_id);
+    db.set_series_posts(series_id, &post_ids, &Utc::now().to_rfc3339()).await
+}
+
+/// Takes a post out of its series. Returns false if it was in none.
+#[instrument(skip_all, fields(post_id = %post_id), err)]
+pub async fn remove_from_series(db: &Database, post_id: Uuid) -> Result<bool> {
+    db.remove_series_post(post_id).await
+}
@@ -0,0 +1,166 @@
+use crate::models::identity::UserIdentity;
+use crate::models::token::timestamp;
+use crate::models::user::{normalize_email, CreateUser, Role};
+use crate::models::User;
+use crate::oidc::{Claims, OidcConfig};
+use crate::services::db::Database;
+use crate::services::user_service;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use tracing::instrument;
+
+/// Password hash of accounts created by single sign-on. bcrypt cannot parse
+/// it, so they cannot log in with a password or ask for a reset; signing in
+/// stays under the identity provider's control.
+pub const NO_PASSWORD: &str = "!sso";
+
+pub fn has_password(user: &User) -> bool {
+    user.password_hash != NO_PASSWORD
+}
+
+/// The highest role the claims map to, or the default one. `None` when no
+/// mapping is configured, in which case roles are managed here.
+pub fn mapped_role(config: &OidcConfig, claims: &Claims) -> Option<Role> {
+    if config.roles.is_empty() {
+        return None;
+    }
+    let matched = claims
+        .strings(&config.role_claim)
+        .into_iter()
+        .filter_map(|value| config.roles.get(value))
+        .max();
+    Some(matched.copied().unwrap_or(config.default_role))
+}
+
+/// A free username from the username claim or the email's local part,
+/// reduced to characters that are safe in URLs.
+async fn new_username(db: &Database, config: &OidcConfig, claims: &Claims) -> Result<String> {
+    let wanted = claims
+        .string(&config.username_claim)
+        .or_else(|| claims.email().and_then(|email| email.split('@').next()))
+        .unwrap_or_default();
+    let mut base: String = wanted
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        .take(32)
+        .collect();
+    if base.len() < 3 {
+        base = format!("user{}", base);
+    }
+    for n in 1..=100 {
+        let candidate = if n == 1 { base.clone() } else { format!("{}{}", base, n) };
+        if db.find_user_by_username(&candidate).await?.is_none() {
+            return Ok(candidate);
+        }
+    }
+    Err(anyhow!("Could not find a free username for {}", base))
+}
+
+async fn provision(db: &Database, config: &OidcConfig, claims: &Claims) -> Result<User> {
+    let username = new_username(db, config, claims).await?;
+    let form = CreateUser {
+        username,
+        password: String::new(),
+        email: String::new(),
+    };
+    let mut user = user_service::create_user(db, form, NO_PASSWORD.to_string()).await?;
+
+    // The address is only taken when no other account uses it
+    if let Some(email) = claims.email().and_then(normalize_email) {
+        if db.find_user_by_email(&email).await?.is_none() {
+            user.email = user_service::set_email(db, user.id, &email, claims.email_verified()).await?;
+            user.email_verified = user.email.is_some() && claims.email_verified();
+        }
+    }
+    if mapped_role(config, claims).is_none() && config.default_role != user.role {
+        user_service::set_role(db, user.id, config.default_role).await?;
+        user.role = config.default_role;
+    }
+    tracing::info!(user_id = %user.id, username = %user.username, "account created by single sign-on");
+    Ok(user)
+}
+
+/// An account to link a first sign-in to when `link_by_email` is on. Both
+/// sides must have verified the address, so nobody can claim someone
+/// else's account by registering with their address.
+async fn find_by_email(db: &Database, config: &OidcConfig, claims: &Claims) -> Result<Option<User>> {
+    if !config.link_by_email || !claims.email_verified() {
+        return Ok(None);
+    }
+    let Some(email) = claims.email().and_then(normalize_email) else {
+        return Ok(None);
+    };
+    Ok(db.find_user_by_email(&email).await?.filter(|user| user.email_verified))
+}
+
+/// Finds the account for a verified ID token: the one already linked to
+/// the identity, `link_to` (a signed-in user linking their account), one
+/// with the same verified address, or a new one. Applies the role mapping
+/// and returns the account to sign in as.
+#[instrument(skip_all, fields(issuer = %claims.iss), err)]
+pub async fn sign_in(db: &Database, config: &OidcConfig, claims: &Claims, link_to: Option<&User>) -> Result<User> {
+    let now = timestamp(Utc::now());
+    let email = claims.email().and_then(normalize_email);
+
+    let mut user = match db.find_identity(&claims.iss, &claims.sub).await? {
+        Some(identity) => {
+            if link_to.is_some_and(|user| user.id != identity.user_id) {
+                return Err(anyhow!(
+                    "That {} account is already linked to another user",
+                    config.display_name
+                ));
+            }
+            db.touch_identity(&claims.iss, &claims.sub, email.as_deref(), &now)
+                .await?;
+            db.find_user_by_id(identity.user_id)
+                .await?
+                .ok_or_else(|| anyhow!("User not found"))?
+        }
+        None => {
+            let user = match link_to {
+                Some(user) => db
+                    .find_user_by_id(user.id)
+                    .await?
+                    .ok_or_else(|| anyhow!("User not found"))?,
+                None => match find_by_email(db, config, claims).await? {
+                    Some(user) => user,
+                    None if config.auto_provision => provision(db, config, claims).await?,
+                    None => {
+                        return Err(anyhow!(
+                            "No account here is linked to your {} account. Ask an administrator for access.",
+                            config.display_name
+                        ))
+                    }
+                },
+            };
+            let identity = UserIdentity {
+                issuer: claims.iss.clone(),
+                subject: claims.sub.clone(),
+                user_id: user.id,
+                email: email.clone(),
+                created_at: now.clone(),
+                last_login_at: now,
+            };
+            if !db.insert_identity(&identity).await? {
+                return Err(anyhow!(
+                    "That {} account is already linked to another user",
+                    config.display_name
+                ));
+            }
+            tracing::info!(user_id = %user.id, "identity linked");
+            user
+        }
+    };
+
+    if user.disabled {
+        return Err(anyhow!("This account has been disabled"));
+    }
+    if let Some(role) = mapped_role(config, claims) {
+        if role != user.role {
+            user_service::set_role(db, user.id, role).await?;
+            tracing::info!(user_id = %user.id, from = %user.role, to = %role, "role set from identity provider");
+            user.role = role;
+        }
+    }
+    Ok(user)
+}
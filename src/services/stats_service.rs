@@ -0,0 +1,9 @@
+use crate::repositories::SiteCounts;
+use crate::services::db::Database;
+use anyhow::Result;
+use tracing::instrument;
+
+#[instrument(skip_all, err)]
+pub async fn get_counts(db: &Database) -> Result<SiteCounts> {
+    db.counts().await
+}
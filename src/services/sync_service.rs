@@ -0,0 +1,403 @@
+//! Keeps posts in step with a directory, or a bare git repository, of
+//! Markdown files with YAML front matter.
+
+use crate::markdown::{parse_front_matter, with_front_matter};
+use crate::models::post::{slugify, CreatePost, Post, PostSource};
+use crate::services::db::Database;
+use crate::services::{post_service, tag_service, user_service};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::instrument;
+use uuid::Uuid;
+use validator::Validate;
+
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown"];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncFrontMatter {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// `published` (the default) or `draft`. Drafts are not on the site.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Publication date, as RFC 3339 or `YYYY-MM-DD`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    /// Username of the author; defaults to the one given to the sync.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Author of new posts whose front matter names none.
+    pub author: Option<String>,
+    /// Write posts edited on the web back to their files.
+    pub write_back: bool,
+    /// Delete posts whose file was removed or became a draft. Without it
+    /// they are only reported.
+    pub prune: bool,
+    pub dry_run: bool,
+    /// Commit to read from a bare repository.
+    pub git_ref: String,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions { author: None, write_back: false, prune: false, dry_run: false, git_ref: "HEAD".to_string() }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SyncReport {
+    pub dry_run: bool,
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+    /// Posts whose file was removed or became a draft, kept because
+    /// pruning is off.
+    pub orphaned: Vec<String>,
+    pub unchanged: usize,
+    pub written_back: Vec<String>,
+    /// Posts edited on the web that were not written back.
+    pub web_edits: Vec<String>,
+    /// Posts changed on both sides since the last sync; neither side is touched.
+    pub conflicts: Vec<SyncIssue>,
+    pub skipped: Vec<SyncIssue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncIssue {
+    pub path: String,
+    pub reason: String,
+}
+
+/// Where the Markdown files live.
+enum Source {
+    Directory(PathBuf),
+    BareRepository { dir: PathBuf, git_ref: String },
+}
+
+impl Source {
+    fn open(path: &Path, git_ref: &str) -> Result<Source> {
+        if !path.is_dir() {
+            bail!("{} is not a directory", path.display());
+        }
+        let bare = path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir();
+        Ok(if bare {
+            Source::BareRepository { dir: path.to_path_buf(), git_ref: git_ref.to_string() }
+        } else {
+            Source::Directory(path.to_path_buf())
+        })
+    }
+
+    /// Reads every Markdown file, keyed by its path relative to the source.
+    fn read_all(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        match self {
+            Source::Directory(root) => {
+                let mut files = Vec::new();
+                collect_markdown(root, root, &mut files)?;
+                files.sort();
+                Ok(files)
+            }
+            Source::BareRepository { dir, git_ref } => {
+                let listing = git(dir, &["ls-tree", "-r", "-z", "--name-only", git_ref])?;
+                let mut files = Vec::new();
+                for path in String::from_utf8(listing)?.split('\0').filter(|path| is_markdown(Path::new(path))) {
+                    let contents = git(dir, &["cat-file", "blob", &format!("{}:{}", git_ref, path)])?;
+                    files.push((path.to_string(), contents));
+                }
+                Ok(files)
+            }
+        }
+    }
+
+    fn write(&self, path: &str, contents: &[u8]) -> Result<()> {
+        match self {
+            Source::Directory(root) => Ok(fs::write(root.join(path), contents)?),
+            Source::BareRepository { .. } => bail!("A bare repository cannot be written to"),
+        }
+    }
+}
+
+fn collect_markdown(root: &Path, dir: &Path, files: &mut Vec<(String, Vec<u8>)>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        // Skips .git and other hidden directories and files.
+        if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+            continue;
+        }
+        if path.is_dir() {
+            collect_markdown(root, &path, files)?;
+        } else if is_markdown(&path) {
+            let relative = path.strip_prefix(root)?.to_string_lossy().replace('\\', "/");
+            files.push((relative, fs::read(&path)?));
+        }
+    }
+    Ok(())
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| MARKDOWN_EXTENSIONS.iter().any(|md| ext == *md))
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
+
+fn content_hash(contents: &[u8]) -> String {
+    Sha256::digest(contents).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_date(value: &str) -> Option<String> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc).to_rfc3339());
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc().to_rfc3339())
+}
+
+/// A Markdown file read and checked against the rules for posts.
+struct SourceFile {
+    meta: SyncFrontMatter,
+    content: String,
+    slug: String,
+    date: Option<String>,
+    hash: String,
+}
+
+fn read_file(path: &str, contents: &[u8]) -> Result<Option<SourceFile>, String> {
+    let text = std::str::from_utf8(contents).map_err(|_| "Not UTF-8".to_string())?;
+    let (meta, body): (SyncFrontMatter, _) =
+        parse_front_matter(text).map_err(|e| format!("Invalid front matter: {}", e))?;
+    match meta.status.as_deref() {
+        None | Some("published") => {}
+        Some("draft") => return Ok(None),
+        Some(other) => return Err(format!("Unknown status {:?}", other)),
+    }
+
+    let content = body.trim_start_matches(['\r', '\n']).to_string();
+    CreatePost { title: meta.title.clone(), content: content.clone() }
+        .validate()
+        .map_err(|e| e.to_string())?;
+
+    let stem = Path::new(path).file_stem().unwrap_or_default().to_string_lossy();
+    let slug = slugify(meta.slug.as_deref().unwrap_or(&stem));
+    if slug.is_empty() {
+        return Err("No usable slug".to_string());
+    }
+    let date = match &meta.date {
+        Some(value) => Some(parse_date(value).ok_or_else(|| format!("Unreadable date {:?}", value))?),
+        None => None,
+    };
+    Ok(Some(SourceFile { meta, content, slug, date, hash: content_hash(contents) }))
+}
+
+/// Creates and updates posts to match the Markdown files under `path`. Each
+/// synchronised post records the directory or repository, its file's path
+/// in it and the file's hash, so only changed files are applied. Posts whose
+/// file under `path` was removed are deleted when `prune` is set; posts
+/// from other directories are never touched. Posts edited on the web since
+/// the last sync are written back to their files when `write_back` is set,
+/// and reported otherwise.
+#[instrument(skip_all, fields(path = %path.display(), dry_run = options.dry_run), err)]
+pub async fn sync(db: &Database, path: &Path, options: &SyncOptions) -> Result<SyncReport> {
+    let source = Source::open(path, &options.git_ref)?;
+    // Fail before changing anything rather than at the first web edit.
+    if options.write_back && matches!(source, Source::BareRepository { .. }) {
+        bail!("{} is a bare repository and cannot be written back to", path.display());
+    }
+    let root = fs::canonicalize(path)?.to_string_lossy().into_owned();
+    let (source, files) = tokio::task::spawn_blocking(move || {
+        let files = source.read_all()?;
+        anyhow::Ok((source, files))
+    }).await??;
+
+    let mut report = SyncReport { dry_run: options.dry_run, ..Default::default() };
+    let mut sources = sources_by_path(db, &root).await?;
+    // Rows from before roots were kept are claimed by the first sync that
+    // has their file, and are never pruned.
+    let mut unclaimed = sources_by_path(db, "").await?;
+    let mut seen = BTreeSet::new();
+
+    for (path, contents) in files {
+        seen.insert(path.clone());
+        let recorded = sources.remove(&path).or_else(|| unclaimed.remove(&path));
+        let file = match read_file(&path, &contents) {
+            Ok(Some(file)) => file,
+            Ok(None) => {
+                if let Some(recorded) = recorded {
+                    remove(db, &root, &recorded, options, &mut report).await?;
+                } else {
+                    report.skipped.push(SyncIssue { path, reason: "Draft".to_string() });
+                }
+                continue;
+            }
+            Err(reason) => {
+                report.skipped.push(SyncIssue { path, reason });
+                continue;
+            }
+        };
+
+        let existing = match &recorded {
+            Some(recorded) => post_service::get_post(db, recorded.post_id).await?,
+            None => None,
+        };
+        let slug_owner = db.find_post_by_slug(&file.slug).await?;
+        if slug_owner.as_ref().is_some_and(|owner| Some(owner.id) != existing.as_ref().map(|post| post.id)) {
+            report.skipped.push(SyncIssue { path, reason: format!("Slug {:?} belongs to another post", file.slug) });
+            continue;
+        }
+
+        let (Some(recorded), Some(post)) = (recorded, existing) else {
+            create(db, &root, &path, file, options, &mut report).await?;
+            continue;
+        };
+        if recorded.root != root && !options.dry_run {
+            db.upsert_post_source(&PostSource { root: root.clone(), ..recorded.clone() }).await?;
+        }
+
+        let file_changed = file.hash != recorded.content_hash;
+        let web_changed = post.updated_at != recorded.post_updated_at;
+        match (file_changed, web_changed) {
+            (false, false) => report.unchanged += 1,
+            (true, false) => {
+                if !options.dry_run {
+                    post_service::update_post(db, post.id, file.meta.title.clone(), file.content.clone()).await?;
+                    let created_at = file.date.as_deref().unwrap_or(&post.created_at);
+                    let updated = post_service::get_post(db, post.id).await?.ok_or_else(|| anyhow!("Post vanished"))?;
+                    post_service::set_post_metadata(db, post.id, Some(&file.slug), created_at, &updated.updated_at).await?;
+                    tag_service::set_post_tags(db, post.id, &file.meta.tags).await?;
+                    record(db, post.id, &root, &path, file.hash, updated.updated_at).await?;
+                }
+                report.updated.push(path);
+            }
+            (false, true) if options.write_back => {
+                if !options.dry_run {
+                    let contents = render_post(db, &post, file.meta).await?;
+                    source.write(&path, contents.as_bytes())?;
+                    record(db, post.id, &root, &path, content_hash(contents.as_bytes()), post.updated_at).await?;
+                }
+                report.written_back.push(path);
+            }
+            (false, true) => report.web_edits.push(path),
+            (true, true) => report.conflicts.push(SyncIssue {
+                path,
+                reason: "Changed in the file and on the web since the last sync".to_string(),
+            }),
+        }
+    }
+
+    for recorded in sources.into_values().filter(|recorded| !seen.contains(&recorded.path)) {
+        remove(db, &root, &recorded, options, &mut report).await?;
+    }
+    Ok(report)
+}
+
+async fn sources_by_path(db: &Database, root: &str) -> Result<HashMap<String, PostSource>> {
+    Ok(db
+        .list_post_sources(root)
+        .await?
+        .into_iter()
+        .map(|source| (source.path.clone(), source))
+        .collect())
+}
+
+async fn create(
+    db: &Database,
+    root: &str,
+    path: &str,
+    file: SourceFile,
+    options: &SyncOptions,
+    report: &mut SyncReport,
+) -> Result<()> {
+    let Some(username) = file.meta.author.as_deref().or(options.author.as_deref()) else {
+        report.skipped.push(SyncIssue { path: path.to_string(), reason: "No author".to_string() });
+        return Ok(());
+    };
+    let Some(author) = user_service::get_user_by_username(db, username).await? else {
+        report.skipped.push(SyncIssue { path: path.to_string(), reason: format!("Unknown author {}", username) });
+        return Ok(());
+    };
+
+    if !options.dry_run {
+        let post = post_service::create_post(
+            db,
+            CreatePost { title: file.meta.title.clone(), content: file.content },
+            author.id,
+        ).await?;
+        // A dated post counts as unedited since that date.
+        let created_at = file.date.unwrap_or(post.created_at);
+        post_service::set_post_metadata(db, post.id, Some(&file.slug), &created_at, &created_at).await?;
+        tag_service::set_post_tags(db, post.id, &file.meta.tags).await?;
+        record(db, post.id, root, path, file.hash, created_at).await?;
+    }
+    report.created.push(path.to_string());
+    Ok(())
+}
+
+/// Deletes the post of a file that is gone or now a draft, if pruning and
+/// the file was recorded under `root`; otherwise reports it.
+async fn remove(
+    db: &Database,
+    root: &str,
+    recorded: &PostSource,
+    options: &SyncOptions,
+    report: &mut SyncReport,
+) -> Result<()> {
+    if !options.prune || recorded.root != root {
+        report.orphaned.push(recorded.path.clone());
+        return Ok(());
+    }
+    if !options.dry_run {
+        post_service::delete_post(db, recorded.post_id).await?;
+    }
+    report.deleted.push(recorded.path.clone());
+    Ok(())
+}
+
+async fn record(
+    db: &Database,
+    post_id: Uuid,
+    root: &str,
+    path: &str,
+    content_hash: String,
+    post_updated_at: String,
+) -> Result<()> {
+    db.upsert_post_source(&PostSource {
+        post_id,
+        root: root.to_string(),
+        path: path.to_string(),
+        content_hash,
+        post_updated_at,
+        synced_at: Utc::now().to_rfc3339(),
+    }).await
+}
+
+/// Renders a post as its Markdown file, keeping front matter fields the
+/// web editor does not manage, such as the date and status.
+async fn render_post(db: &Database, post: &Post, mut meta: SyncFrontMatter) -> Result<String> {
+    meta.title = post.title.clone();
+    meta.slug = post.slug.clone();
+    meta.tags = tag_service::get_post_tags(db, post.id).await?.into_iter().map(|tag| tag.name).collect();
+    with_front_matter(&meta, &format!("\n{}", post.content))
+}
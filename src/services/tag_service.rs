@@ -0,0 +1,47 @@
+use crate::models::post::slugify;
+use crate::models::tag::Tag;
+use crate::services::db::Database;
+use anyhow::{anyhow, Result};
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Returns the tag whose slug matches `name`, creating it if needed.
+#[instrument(skip_all, fields(tag = %name), err)]
+pub async fn find_or_create_tag(db: &Database, name: &str) -> Result<Tag> {
+    let name = name.trim();
+    let slug = slugify(name);
+    if slug.is_empty() {
+        return Err(anyhow!("Tag name {:?} has no letters or digits", name));
+    }
+
+    if let Some(tag) = db.find_tag_by_slug(&slug).await? {
+        return Ok(tag);
+    }
+    let tag = Tag { id: Uuid::new_v4(), name: name.to_string(), slug };
+    db.insert_tag(&tag).await?;
+    Ok(tag)
+}
+
+/// Adds the named tags to a post, creating any that do not exist yet.
+#[instrument(skip_all, fields(post_id = %post_id), err)]
+pub async fn tag_post(db: &Database, post_id: Uuid, names: &[String]) -> Result<Vec<Tag>> {
+    let mut tags = Vec::with_capacity(names.len());
+    for name in names {
+        let tag = find_or_create_tag(db, name).await?;
+        db.add_post_tag(post_id, tag.id).await?;
+        tags.push(tag);
+    }
+    Ok(tags)
+}
+
+/// Replaces a post's tags with the named ones.
+#[instrument(skip_all, fields(post_id = %post_id), err)]
+pub async fn set_post_tags(db: &Database, post_id: Uuid, names: &[String]) -> Result<Vec<Tag>> {
+    db.remove_post_tags(post_id).await?;
+    tag_post(db, post_id, names).await
+}
+
+#[instrument(skip_all, fields(post_id = %post_id), err)]
+pub async fn get_post_tags(db: &Database, post_id: Uuid) -> Result<Vec<Tag>> {
+    db.list_post_tags(post_id).await
+}
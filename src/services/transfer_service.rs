@@ -0,0 +1,556 @@
+use crate::models::bookmark::Bookmark;
+use crate::models::comment::Comment;
+use crate::models::post::{Post, PostSort};
+use crate::models::profile::Profile;
+use crate::models::reaction::{Reaction, ReactionTarget, Reactions};
+use crate::models::series::Series;
+use crate::models::user::{normalize_email, Role};
+use crate::models::User;
+use crate::markdown;
+use crate::services::comment_service::parents_first;
+use crate::services::db::Database;
+use crate::services::tag_service;
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use tracing::instrument;
+use uuid::Uuid;
+
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Stored in place of a password hash for imported accounts without one.
+/// It is not a valid bcrypt hash, so nobody can log in until an operator
+/// resets the password.
+const UNUSABLE_PASSWORD_HASH: &str = "!";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedUser {
+    pub id: Uuid,
+    pub username: String,
+    pub role: Role,
+    pub disabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedPost {
+    #[serde(flatten)]
+    pub post: Post,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedSeries {
+    #[serde(flatten)]
+    pub series: Series,
+    /// IDs of the series' posts, in order.
+    #[serde(default)]
+    pub posts: Vec<Uuid>,
+}
+
+/// Everything a site holds. The lists after `comments` came later and may
+/// be missing from older exports.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SiteExport {
+    pub version: u32,
+    pub exported_at: String,
+    pub users: Vec<ExportedUser>,
+    pub posts: Vec<ExportedPost>,
+    pub comments: Vec<Comment>,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub series: Vec<ExportedSeries>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub users_created: usize,
+    pub users_matched: usize,
+    pub posts_created: usize,
+    pub posts_skipped: usize,
+    pub comments_created: usize,
+    pub comments_skipped: usize,
+    pub profiles_created: usize,
+    pub profiles_skipped: usize,
+    pub series_created: usize,
+    pub series_skipped: usize,
+    pub reactions_created: usize,
+    pub reactions_skipped: usize,
+    pub bookmarks_created: usize,
+    pub bookmarks_skipped: usize,
+    /// Records that could not be imported as-is, with the reason.
+    pub conflicts: Vec<ImportConflict>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportConflict {
+    pub kind: &'static str,
+    pub id: Uuid,
+    pub reason: String,
+}
+
+impl ImportReport {
+    fn conflict(&mut self, kind: &'static str, id: Uuid, reason: String) {
+        self.conflicts.push(ImportConflict { kind, id, reason });
+    }
+}
+
+#[instrument(skip_all, err)]
+pub async fn export_site(db: &Database, include_password_hashes: bool) -> Result<SiteExport> {
+    let users = db.list_users().await?;
+    let (mut profiles, mut bookmarks) = (Vec::new(), Vec::new());
+    for user in &users {
+        profiles.extend(db.find_profile(user.id).await?);
+        bookmarks.extend(db.list_bookmarks(user.id).await?);
+    }
+    let users = users
+        .into_iter()
+        .map(|user| ExportedUser {
+            id: user.id,
+            username: user.username,
+            role: user.role,
+            disabled: user.disabled,
+            password_hash: include_password_hashes.then_some(user.password_hash),
+            email: user.email,
+            email_verified: user.email_verified,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        })
+        .collect();
+
+    let mut posts = Vec::new();
+    for post in db.list_posts(PostSort::Newest).await? {
+        let tags = db.list_post_tags(post.id).await?.into_iter().map(|tag| tag.name).collect();
+        posts.push(ExportedPost { post, tags });
+    }
+
+    let mut series = Vec::new();
+    for entry in db.list_series().await? {
+        let posts = db.list_series_posts(entry.id).await?.into_iter().map(|post| post.id).collect();
+        series.push(ExportedSeries { series: entry, posts });
+    }
+
+    let mut reactions = db.list_reactions(ReactionTarget::Post).await?;
+    reactions.extend(db.list_reactions(ReactionTarget::Comment).await?);
+
+    Ok(SiteExport {
+        version: EXPORT_VERSION,
+        exported_at: Utc::now().to_rfc3339(),
+        users,
+        posts,
+        comments: db.list_comments().await?,
+        profiles,
+        series,
+        reactions,
+        bookmarks,
+    })
+}
+
+/// Maps authors in an export to local user IDs, preferring the username so
+/// hand-edited archives and partial user lists still resolve.
+struct Authors {
+    by_username: HashMap<String, Uuid>,
+    by_exported_id: HashMap<Uuid, Uuid>,
+}
+
+impl Authors {
+    async fn resolve(&mut self, db: &Database, username: &str, exported_id: Uuid) -> Result<Option<Uuid>> {
+        if let Some(id) = self.by_username.get(username) {
+            return Ok(Some(*id));
+        }
+        if let Some(user) = db.find_user_by_username(username).await? {
+            self.by_username.insert(user.username, user.id);
+            return Ok(Some(user.id));
+        }
+        Ok(self.by_exported_id.get(&exported_id).copied())
+    }
+}
+
+/// Imports an export into `db`. Users are matched by username and authors
+/// are resolved by username, so IDs are remapped as needed. Records already
+/// present with the same content are skipped, so re-running an import is
+/// harmless; records whose ID is taken by different content are left alone
+/// and reported as conflicts. Profiles, reactions and bookmarks follow
+/// their user, and are only added where the user has none yet.
+#[instrument(skip_all, err)]
+pub async fn import_site(db: &Database, export: SiteExport) -> Result<ImportReport> {
+    check_version(export.version)?;
+
+    let mut report = ImportReport::default();
+    let mut authors = Authors { by_username: HashMap::new(), by_exported_id: HashMap::new() };
+
+    for user in export.users {
+        if let Some(existing) = db.find_user_by_username(&user.username).await? {
+            authors.by_exported_id.insert(user.id, existing.id);
+            authors.by_username.insert(existing.username, existing.id);
+            report.users_matched += 1;
+            continue;
+        }
+
+        let id = if db.find_user_by_id(user.id).await?.is_some() {
+            let id = Uuid::new_v4();
+            report.conflict("user", user.id, format!("ID belongs to another account; imported {} as {}", user.username, id));
+            id
+        } else {
+            user.id
+        };
+        let email = match user.email.as_deref().and_then(normalize_email) {
+            Some(email) if db.find_user_by_email(&email).await?.is_some() => {
+                report.conflict("user", user.id, format!("Email of {} belongs to another account; imported without it", user.username));
+                None
+            }
+            email => email,
+        };
+        authors.by_exported_id.insert(user.id, id);
+        authors.by_username.insert(user.username.clone(), id);
+        db.insert_user(&User {
+            id,
+            username: user.username,
+            password_hash: user.password_hash.unwrap_or_else(|| UNUSABLE_PASSWORD_HASH.to_string()),
+            role: user.role,
+            disabled: user.disabled,
+            email_verified: email.is_some() && user.email_verified,
+            email,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }).await?;
+        report.users_created += 1;
+    }
+
+    for mut profile in export.profiles {
+        let Some(&user_id) = authors.by_exported_id.get(&profile.user_id) else {
+            report.conflict("profile", profile.user_id, "Unknown user".to_string());
+            continue;
+        };
+        if db.find_profile(user_id).await?.is_some() {
+            report.profiles_skipped += 1;
+            continue;
+        }
+        profile.user_id = user_id;
+        db.upsert_profile(&profile).await?;
+        report.profiles_created += 1;
+    }
+
+    // Posts and comments that are in the site after the import, whether
+    // created now or already there
+    let mut posts = HashSet::new();
+    let mut comments = HashSet::new();
+
+    for ExportedPost { mut post, tags } in export.posts {
+        let Some(author_id) = authors.resolve(db, &post.author, post.author_id).await? else {
+            report.conflict("post", post.id, format!("Unknown author {}", post.author));
+            continue;
+        };
+        match db.find_post(post.id).await? {
+            Some(existing) if existing.title == post.title && existing.content == post.content => {
+                posts.insert(post.id);
+                report.posts_skipped += 1;
+            }
+            Some(_) => report.conflict("post", post.id, "A different post with this ID already exists".to_string()),
+            None => {
+                if let Some(slug) = &post.slug {
+                    if db.find_post_by_slug(slug).await?.is_some() {
+                        report.conflict("post", post.id, format!("Slug {} is taken; imported without a slug", slug));
+                        post.slug = None;
+                    }
+                }
+                post.author_id = author_id;
+                db.insert_post(&post).await?;
+                tag_service::tag_post(db, post.id, &tags).await?;
+                posts.insert(post.id);
+                report.posts_created += 1;
+            }
+        }
+    }
+
+    for ExportedSeries { mut series, posts: parts } in export.series {
+        let Some(author_id) = authors.resolve(db, &series.author, series.author_id).await? else {
+            report.conflict("series", series.id, format!("Unknown author {}", series.author));
+            continue;
+        };
+        if db.find_series(series.id).await?.is_some() {
+            report.series_skipped += 1;
+            continue;
+        }
+        let base = series.slug.clone();
+        let mut suffix = 2;
+        while db.find_series_by_slug(&series.slug).await?.is_some() {
+            series.slug = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        if series.slug != base {
+            report.conflict("series", series.id, format!("Slug {} is taken; imported as {}", base, series.slug));
+        }
+        // Posts already in a local series stay there
+        let mut post_ids = Vec::new();
+        for post_id in parts {
+            if posts.contains(&post_id) && db.find_post_series(post_id).await?.is_none() {
+                post_ids.push(post_id);
+            }
+        }
+        series.author_id = author_id;
+        db.insert_series(&series).await?;
+        db.set_series_posts(series.id, &post_ids, &series.updated_at).await?;
+        report.series_created += 1;
+    }
+
+    for mut comment in parents_first(export.comments) {
+        let Some(author_id) = authors.resolve(db, &comment.author, comment.author_id).await? else {
+            report.conflict("comment", comment.id, format!("Unknown author {}", comment.author));
+            continue;
+        };
+        if db.find_post(comment.post_id).await?.is_none() {
+            report.conflict("comment", comment.id, format!("Post {} does not exist", comment.post_id));
+            continue;
+        }
+        match db.find_comment(comment.id).await? {
+            Some(existing) if existing.content == comment.content => {
+                comments.insert(comment.id);
+                report.comments_skipped += 1;
+            }
+            Some(_) => report.conflict("comment", comment.id, "A different comment with this ID already exists".to_string()),
+            None => {
+                if let Some(parent_id) = comment.parent_id {
+                    if db.find_comment(parent_id).await?.is_none() {
+                        report.conflict("comment", comment.id, format!("Parent comment {} is missing; imported as a top-level comment", parent_id));
+                        comment.parent_id = None;
+                    }
+                }
+                comment.author_id = author_id;
+                db.insert_comment(&comment).await?;
+                comments.insert(comment.id);
+                report.comments_created += 1;
+            }
+        }
+    }
+
+    let mut reacted = HashSet::new();
+    for target in [ReactionTarget::Post, ReactionTarget::Comment] {
+        for reaction in db.list_reactions(target).await? {
+            reacted.insert((target, reaction.target_id, reaction.user_id, reaction.kind));
+        }
+    }
+    for reaction in export.reactions {
+        let Some(&user_id) = authors.by_exported_id.get(&reaction.user_id) else {
+            report.conflict("reaction", reaction.target_id, format!("Unknown user {}", reaction.user_id));
+            continue;
+        };
+        let imported = match reaction.target {
+            ReactionTarget::Post => posts.contains(&reaction.target_id),
+            ReactionTarget::Comment => comments.contains(&reaction.target_id),
+        };
+        if !imported {
+            report.conflict("reaction", reaction.target_id, "The post or comment was not imported".to_string());
+            continue;
+        }
+        if !reacted.insert((reaction.target, reaction.target_id, user_id, reaction.kind.clone())) {
+            report.reactions_skipped += 1;
+            continue;
+        }
+        db.set_reaction(reaction.target, reaction.target_id, user_id, &reaction.kind, true, &reaction.created_at).await?;
+        report.reactions_created += 1;
+    }
+
+    for mut bookmark in export.bookmarks {
+        let Some(&user_id) = authors.by_exported_id.get(&bookmark.user_id) else {
+            report.conflict("bookmark", bookmark.post_id, format!("Unknown user {}", bookmark.user_id));
+            continue;
+        };
+        if !posts.contains(&bookmark.post_id) {
+            report.conflict("bookmark", bookmark.post_id, "The post was not imported".to_string());
+            continue;
+        }
+        if db.find_bookmark(user_id, bookmark.post_id).await?.is_some() {
+            report.bookmarks_skipped += 1;
+            continue;
+        }
+        bookmark.user_id = user_id;
+        db.upsert_bookmark(&bookmark).await?;
+        report.bookmarks_created += 1;
+    }
+
+    Ok(report)
+}
+
+fn check_version(version: u32) -> Result<()> {
+    if version != EXPORT_VERSION {
+        return Err(anyhow!(
+            "Unsupported export version {} (expected {})",
+            version,
+            EXPORT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+const MANIFEST_PATH: &str = "manifest.json";
+
+/// `manifest.json` at the root of an archive. Post bodies live in separate
+/// Markdown files so the archive stays readable without this tool.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveManifest {
+    version: u32,
+    exported_at: String,
+    users: Vec<ExportedUser>,
+    /// Archive paths of the Markdown files, one per post.
+    posts: Vec<String>,
+    comments: Vec<Comment>,
+    #[serde(default)]
+    profiles: Vec<Profile>,
+    #[serde(default)]
+    series: Vec<ExportedSeries>,
+    #[serde(default)]
+    reactions: Vec<Reaction>,
+    #[serde(default)]
+    bookmarks: Vec<Bookmark>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PostFrontMatter {
+    id: Uuid,
+    title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    slug: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    author: String,
+    author_id: Uuid,
+    created_at: String,
+    updated_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seo_title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seo_description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    excerpt: Option<String>,
+}
+
+/// Writes `export` as a gzipped tar archive holding `manifest.json` and one
+/// Markdown file with YAML front matter per post.
+pub fn write_archive<W: Write>(export: SiteExport, output: W) -> Result<()> {
+    let mut archive = tar::Builder::new(GzEncoder::new(output, Compression::default()));
+    let mtime = Utc::now().timestamp().max(0) as u64;
+
+    let mut post_paths = Vec::with_capacity(export.posts.len());
+    for ExportedPost { post, tags } in export.posts {
+        let path = format!("posts/{}.md", post.id);
+        let front_matter = PostFrontMatter {
+            id: post.id,
+            title: post.title,
+            slug: post.slug,
+            tags,
+            author: post.author,
+            author_id: post.author_id,
+            created_at: post.created_at,
+            updated_at: post.updated_at,
+            seo_title: post.seo_title,
+            seo_description: post.seo_description,
+            excerpt: post.excerpt,
+        };
+        let document = markdown::with_front_matter(&front_matter, &post.content)?;
+        append_file(&mut archive, &path, document.as_bytes(), mtime)?;
+        post_paths.push(path);
+    }
+
+    let manifest = ArchiveManifest {
+        version: export.version,
+        exported_at: export.exported_at,
+        users: export.users,
+        posts: post_paths,
+        comments: export.comments,
+        profiles: export.profiles,
+        series: export.series,
+        reactions: export.reactions,
+        bookmarks: export.bookmarks,
+    };
+    append_file(&mut archive, MANIFEST_PATH, &serde_json::to_vec_pretty(&manifest)?, mtime)?;
+
+    archive.into_inner()?.finish()?.flush()?;
+    Ok(())
+}
+
+fn append_file<W: Write>(archive: &mut tar::Builder<W>, path: &str, data: &[u8], mtime: u64) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    archive.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+/// Reads an archive produced by `write_archive` back into an export.
+pub fn read_archive<R: Read>(input: R) -> Result<SiteExport> {
+    let mut files = HashMap::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(input));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let mut contents = String::new();
+        entry
+            .read_to_string(&mut contents)
+            .with_context(|| format!("{} is not UTF-8 text", path))?;
+        files.insert(path, contents);
+    }
+
+    let manifest = files
+        .get(MANIFEST_PATH)
+        .ok_or_else(|| anyhow!("Archive has no {}", MANIFEST_PATH))?;
+    let manifest: ArchiveManifest = serde_json::from_str(manifest).context("Invalid manifest")?;
+    check_version(manifest.version)?;
+
+    let mut posts = Vec::with_capacity(manifest.posts.len());
+    for path in &manifest.posts {
+        let document = files
+            .get(path)
+            .ok_or_else(|| anyhow!("Archive is missing {}", path))?;
+        let (meta, body): (PostFrontMatter, &str) =
+            markdown::parse_front_matter(document).with_context(|| format!("Invalid post {}", path))?;
+        posts.push(ExportedPost {
+            post: Post {
+                id: meta.id,
+                title: meta.title,
+                slug: meta.slug,
+                content: body.to_string(),
+                author_id: meta.author_id,
+                author: meta.author,
+                created_at: meta.created_at,
+                updated_at: meta.updated_at,
+                seo_title: meta.seo_title,
+                seo_description: meta.seo_description,
+                excerpt: meta.excerpt,
+                reactions: Reactions::default(),
+            },
+            tags: meta.tags,
+        });
+    }
+
+    Ok(SiteExport {
+        version: manifest.version,
+        exported_at: manifest.exported_at,
+        users: manifest.users,
+        posts,
+        comments: manifest.comments,
+        profiles: manifest.profiles,
+        series: manifest.series,
+        reactions: manifest.reactions,
+        bookmarks: manifest.bookmarks,
+    })
+}
@@ -0,0 +1,236 @@
+use crate::models::auth::verify_password;
+use crate::models::token::{hash_token, timestamp};
+use crate::models::two_factor::TwoFactor;
+use crate::models::user::Role;
+use crate::models::User;
+use crate::qr;
+use crate::services::db::Database;
+use crate::totp;
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Wrong codes allowed before code entry is locked.
+pub const MAX_FAILED_ATTEMPTS: i32 = 5;
+/// How long code entry stays locked after too many wrong codes.
+pub const LOCKOUT_MINUTES: i64 = 15;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Unambiguous characters for recovery codes; ten of them give about 50
+/// bits, plenty for single-use codes behind the attempt limit.
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TwoFactorConfig {
+    /// Shown next to the account in authenticator apps.
+    pub issuer: String,
+    /// Roles that cannot sign in without two-factor authentication.
+    pub required_roles: Vec<Role>,
+}
+
+impl Default for TwoFactorConfig {
+    fn default() -> Self {
+        TwoFactorConfig { issuer: "Blog".to_string(), required_roles: Vec::new() }
+    }
+}
+
+impl TwoFactorConfig {
+    pub fn required_for(&self, role: Role) -> bool {
+        self.required_roles.contains(&role)
+    }
+}
+
+/// What a user who gave the right password still has to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginStep {
+    Done,
+    /// Enter a code from their app or a recovery code.
+    Code,
+    /// Set up two-factor authentication, which their role requires.
+    Enrol,
+}
+
+/// What the setup page shows.
+#[derive(Debug, Clone, Serialize)]
+pub struct Enrolment {
+    /// Base32, for apps that cannot scan the QR code.
+    pub secret: String,
+    pub uri: String,
+    pub qr_svg: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+    /// Whether the user's role stops them from turning it off.
+    pub required: bool,
+}
+
+pub async fn is_enabled(db: &Database, user_id: Uuid) -> Result<bool> {
+    Ok(db.find_two_factor(user_id).await?.is_some_and(|two_factor| two_factor.enabled))
+}
+
+pub async fn login_step(db: &Database, config: &TwoFactorConfig, user: &User) -> Result<LoginStep> {
+    if is_enabled(db, user.id).await? {
+        Ok(LoginStep::Code)
+    } else if config.required_for(user.role) {
+        Ok(LoginStep::Enrol)
+    } else {
+        Ok(LoginStep::Done)
+    }
+}
+
+pub async fn status(db: &Database, config: &TwoFactorConfig, user: &User) -> Result<TwoFactorStatus> {
+    Ok(TwoFactorStatus {
+        enabled: is_enabled(db, user.id).await?,
+        recovery_codes_left: db.count_recovery_codes(user.id).await?,
+        required: config.required_for(user.role),
+    })
+}
+
+/// Starts or resumes setup with a pending secret. Reloading the setup page
+/// shows the same secret until it is confirmed.
+#[instrument(skip_all, fields(user_id = %user.id), err)]
+pub async fn begin_enrolment(db: &Database, config: &TwoFactorConfig, user: &User) -> Result<Enrolment> {
+    let secret = match db.find_two_factor(user.id).await? {
+        Some(two_factor) if two_factor.enabled => {
+            return Err(anyhow!("Two-factor authentication is already on"));
+        }
+        Some(two_factor) => two_factor.secret,
+        None => {
+            let now = timestamp(Utc::now());
+            let two_factor = TwoFactor {
+                user_id: user.id,
+                secret: totp::generate_secret(),
+                enabled: false,
+                last_used_step: None,
+                failed_attempts: 0,
+                locked_until: None,
+                created_at: now.clone(),
+                updated_at: now,
+            };
+            db.upsert_two_factor(&two_factor).await?;
+            two_factor.secret
+        }
+    };
+
+    let uri = totp::provisioning_uri(&config.issuer, &user.username, &secret);
+    let qr_svg = qr::svg(uri.as_bytes())?;
+    Ok(Enrolment { secret, uri, qr_svg })
+}
+
+/// Turns two-factor authentication on once the user proves their app has
+/// the secret. Returns the new recovery codes, which are shown only once.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn confirm_enrolment(db: &Database, user_id: Uuid, code: &str) -> Result<Vec<String>> {
+    let two_factor = match db.find_two_factor(user_id).await? {
+        Some(two_factor) if two_factor.enabled => return Err(anyhow!("Two-factor authentication is already on")),
+        Some(two_factor) => two_factor,
+        None => return Err(anyhow!("Start setting up two-factor authentication first")),
+    };
+    let now = Utc::now();
+    let step = totp::verify(&two_factor.secret, code, now.timestamp() as u64).ok_or_else(|| {
+        anyhow!("That code is not right. Check that the time on your device is correct and try again")
+    })?;
+
+    db.upsert_two_factor(&TwoFactor {
+        enabled: true,
+        last_used_step: Some(step as i64),
+        failed_attempts: 0,
+        locked_until: None,
+        updated_at: timestamp(now),
+        ..two_factor
+    }).await?;
+    new_recovery_codes(db, user_id).await
+}
+
+/// Recovery codes are compared without case, spaces or dashes.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+async fn new_recovery_codes(db: &Database, user_id: Uuid) -> Result<Vec<String>> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars: String = (0..10)
+                .map(|_| RECOVERY_CODE_ALPHABET[OsRng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect();
+    let hashes: Vec<String> = codes.iter().map(|code| hash_token(&normalize_recovery_code(code))).collect();
+    db.replace_recovery_codes(user_id, &hashes).await?;
+    Ok(codes)
+}
+
+/// Accepts a current code from the app, or an unused recovery code, which
+/// is then used up. Every attempt counts towards the limit until one
+/// succeeds; past it, code entry locks for [`LOCKOUT_MINUTES`].
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn verify_code(db: &Database, user_id: Uuid, code: &str) -> Result<()> {
+    let locked = || anyhow!("Too many wrong codes. Try again in {} minutes", LOCKOUT_MINUTES);
+    let two_factor = db
+        .find_two_factor(user_id)
+        .await?
+        .filter(|two_factor| two_factor.enabled)
+        .ok_or_else(|| anyhow!("Two-factor authentication is not on"))?;
+
+    let now = Utc::now();
+    let lock_until = timestamp(now + Duration::minutes(LOCKOUT_MINUTES));
+    let attempts = db.begin_two_factor_attempt(user_id, &timestamp(now)).await?.ok_or_else(locked)?;
+    if attempts > MAX_FAILED_ATTEMPTS {
+        db.set_two_factor_lock(user_id, Some(&lock_until), &timestamp(now)).await?;
+        return Err(locked());
+    }
+
+    let accepted = match totp::verify(&two_factor.secret, code, now.timestamp() as u64) {
+        Some(step) => db.use_two_factor_step(user_id, step as i64, &timestamp(now)).await?,
+        None => {
+            let code = normalize_recovery_code(code);
+            code.len() == 10 && db.take_recovery_code(user_id, &hash_token(&code)).await?
+        }
+    };
+    if accepted {
+        db.set_two_factor_lock(user_id, None, &timestamp(now)).await?;
+        Ok(())
+    } else if attempts == MAX_FAILED_ATTEMPTS {
+        db.set_two_factor_lock(user_id, Some(&lock_until), &timestamp(now)).await?;
+        Err(locked())
+    } else {
+        Err(anyhow!("That code is not right"))
+    }
+}
+
+/// Replaces the recovery codes after checking a code.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn regenerate_recovery_codes(db: &Database, user_id: Uuid, code: &str) -> Result<Vec<String>> {
+    verify_code(db, user_id, code).await?;
+    new_recovery_codes(db, user_id).await
+}
+
+/// Turns two-factor authentication off; needs the password and a code.
+#[instrument(skip_all, fields(user_id = %user.id), err)]
+pub async fn disable(db: &Database, config: &TwoFactorConfig, user: &User, password: &str, code: &str) -> Result<()> {
+    if config.required_for(user.role) {
+        return Err(anyhow!("Your role requires two-factor authentication"));
+    }
+    if !verify_password(password, &user.password_hash).await? {
+        return Err(anyhow!("Password is incorrect"));
+    }
+    verify_code(db, user.id, code).await?;
+    reset(db, user.id).await?;
+    Ok(())
+}
+
+/// Removes two-factor authentication without any checks, e.g. for a user
+/// who lost their device and recovery codes. Returns false if it was off.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn reset(db: &Database, user_id: Uuid) -> Result<bool> {
+    db.replace_recovery_codes(user_id, &[]).await?;
+    db.delete_two_factor(user_id).await
+}
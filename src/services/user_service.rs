@@ -1,146 +1,153 @@
 use crate::models::User;
-use crate::models::user::CreateUser;
-use crate::services::db::DbPool;
+use crate::models::auth::{hash_password, verify_password};
+use crate::models::user::{normalize_email, CreateUser, Role};
+use crate::services::db::Database;
 use anyhow::{Result, anyhow};
-use bcrypt::{hash, verify, DEFAULT_COST};
 use chrono::Utc;
+use tracing::instrument;
 use uuid::Uuid;
-use rusqlite::{params, OptionalExtension};
 
-pub async fn create_user(pool: &DbPool, user: CreateUser, password_hash: String) -> Result<User> {
-    let conn = pool.get()?;
+#[instrument(skip_all, fields(username = %user.username), err)]
+pub async fn create_user(db: &Database, user: CreateUser, password_hash: String) -> Result<User> {
     let now = Utc::now().naive_utc().to_string();
-    let id = Uuid::new_v4();
-
-    conn.execute(
-        "INSERT INTO users (id, username, password_hash, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![
-            id.to_string(),
-            user.username,
-            password_hash,
-            now,
-            now
-        ],
-    )?;
-
-    Ok(User {
-        id,
+    if db.find_user_by_username(&user.username).await?.is_some() {
+        return Err(anyhow!("Username is already taken"));
+    }
+    if let Some(email) = normalize_email(&user.email) {
+        if db.find_user_by_email(&email).await?.is_some() {
+            return Err(anyhow!("Email is already in use"));
+        }
+    }
+    let user = User {
+        id: Uuid::new_v4(),
         username: user.username,
         password_hash,
+        role: Role::User,
+        disabled: false,
+        email: normalize_email(&user.email),
+        email_verified: false,
         created_at: now.clone(),
         updated_at: now,
-    })
-}
+    };
 
-pub async fn get_user_by_username(pool: &DbPool, username: &str) -> Result<Option<User>> {
-    let conn = pool.get()?;
-    let user = conn.query_row(
-        "SELECT id, username, password_hash, created_at, updated_at FROM users WHERE username = ?",
-        params![username],
-        |row| {
-            let id_str: String = row.get(0)?;
-            let id = Uuid::parse_str(&id_str).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
-                0,
-                rusqlite::types::Type::Text,
-                Box::new(e),
-            ))?;
-            
-            Ok(User {
-                id,
-                username: row.get(1)?,
-                password_hash: row.get(2)?,
-                created_at: row.get(3)?,
-                updated_at: row.get(4)?,
-            })
-        },
-    ).optional()?;
+    db.insert_user(&user).await?;
     Ok(user)
 }
 
-pub async fn get_user_by_id(pool: &DbPool, id: Uuid) -> Result<Option<User>> {
-    let conn = pool.get()?;
-    let user = conn.query_row(
-        "SELECT id, username, password_hash, created_at, updated_at FROM users WHERE id = ?",
-        params![id.to_string()],
-        |row| {
-            let id_str: String = row.get(0)?;
-            let id = Uuid::parse_str(&id_str).map_err(|e| rusqlite::Error::FromSqlConversionFailure(
-                0,
-                rusqlite::types::Type::Text,
-                Box::new(e),
-            ))?;
-            
-            Ok(User {
-                id,
-                username: row.get(1)?,
-                password_hash: row.get(2)?,
-                created_at: row.get(3)?,
-                updated_at: row.get(4)?,
-            })
-        },
-    ).optional()?;
-    Ok(user)
+#[instrument(skip_all, fields(username = %username), err)]
+pub async fn get_user_by_username(db: &Database, username: &str) -> Result<Option<User>> {
+    db.find_user_by_username(username).await
+}
+
+#[instrument(skip_all, fields(user_id = %id), err)]
+pub async fn get_user_by_id(db: &Database, id: Uuid) -> Result<Option<User>> {
+    db.find_user_by_id(id).await
 }
 
+#[instrument(skip_all, fields(user_id = %user_id), err)]
 pub async fn update_username(
-    pool: &DbPool,
+    db: &Database,
     user_id: Uuid,
     username: String,
 ) -> Result<User> {
-    let conn = pool.get()?;
     let now = Utc::now().naive_utc().to_string();
-    let mut updated_user = get_user_by_id(pool, user_id).await?.ok_or_else(|| anyhow!("User not found"))?;
+    let mut updated_user = get_user_by_id(db, user_id).await?.ok_or_else(|| anyhow!("User not found"))?;
 
     // Check if username is already taken by another user
-    if let Some(existing_user) = get_user_by_username(pool, &username).await? {
+    if let Some(existing_user) = get_user_by_username(db, &username).await? {
         if existing_user.id != user_id {
             return Err(anyhow!("Username is already taken"));
         }
     }
 
-    conn.execute(
-        "UPDATE users SET username = ?, updated_at = ? WHERE id = ?",
-        params![
-            username,
-            now,
-            user_id.to_string()
-        ],
-    )?;
+    db.update_username(user_id, &username, &now).await?;
 
     updated_user.username = username;
     updated_user.updated_at = now;
     Ok(updated_user)
 }
 
+#[instrument(skip_all, fields(user_id = %user_id), err)]
 pub async fn update_password(
-    pool: &DbPool,
+    db: &Database,
     user_id: Uuid,
     current_password: String,
     new_password: String,
     current_password_hash: &str,
 ) -> Result<User> {
-    let conn = pool.get()?;
     let now = Utc::now().naive_utc().to_string();
-    let mut updated_user = get_user_by_id(pool, user_id).await?.ok_or_else(|| anyhow!("User not found"))?;
+    let mut updated_user = get_user_by_id(db, user_id).await?.ok_or_else(|| anyhow!("User not found"))?;
 
     // Verify current password
-    if !verify(&current_password, current_password_hash)? {
+    if !verify_password(&current_password, current_password_hash).await? {
         return Err(anyhow!("Current password is incorrect"));
     }
 
     // Update password
-    let new_password_hash = hash(&new_password, DEFAULT_COST)?;
-    conn.execute(
-        "UPDATE users SET password_hash = ?, updated_at = ? WHERE id = ?",
-        params![
-            new_password_hash,
-            now,
-            user_id.to_string()
-        ],
-    )?;
+    let new_password_hash = hash_password(&new_password).await?;
+    db.update_password_hash(user_id, &new_password_hash, &now).await?;
 
     updated_user.password_hash = new_password_hash;
     updated_user.updated_at = now;
     Ok(updated_user)
 }
+
+/// Sets or, when blank, removes the user's email address without any
+/// confirmation. Returns the address as stored.
+#[instrument(skip_all, fields(user_id = %user_id, verified), err)]
+pub async fn set_email(db: &Database, user_id: Uuid, email: &str, verified: bool) -> Result<Option<String>> {
+    let now = Utc::now().naive_utc().to_string();
+    let email = normalize_email(email);
+    if let Some(email) = &email {
+        if let Some(existing_user) = db.find_user_by_email(email).await? {
+            if existing_user.id != user_id {
+                return Err(anyhow!("Email is already in use"));
+            }
+        }
+    }
+    if !db.set_user_email(user_id, email.as_deref(), verified && email.is_some(), &now).await? {
+        return Err(anyhow!("User not found"));
+    }
+    Ok(email)
+}
+
+/// Sets a new password without checking the current one. For operators and
+/// recovery flows only.
+#[instrument(skip_all, fields(user_id = %user_id), err)]
+pub async fn reset_password(db: &Database, user_id: Uuid, new_password: &str) -> Result<()> {
+    let now = Utc::now().naive_utc().to_string();
+    let password_hash = hash_password(new_password).await?;
+    if !db.update_password_hash(user_id, &password_hash, &now).await? {
+        return Err(anyhow!("User not found"));
+    }
+    Ok(())
+}
+
+#[instrument(skip_all, err)]
+pub async fn list_users(db: &Database) -> Result<Vec<User>> {
+    db.list_users().await
+}
+
+#[instrument(skip_all, fields(user_id = %user_id, role = %role), err)]
+pub async fn set_role(db: &Database, user_id: Uuid, role: Role) -> Result<()> {
+    let now = Utc::now().naive_utc().to_string();
+    if !db.set_user_role(user_id, role, &now).await? {
+        return Err(anyhow!("User not found"));
+    }
+    Ok(())
+}
+
+/// Disabled accounts cannot log in and their existing sessions stop working.
+#[instrument(skip_all, fields(user_id = %user_id, disabled), err)]
+pub async fn set_disabled(db: &Database, user_id: Uuid, disabled: bool) -> Result<()> {
+    let now = Utc::now().naive_utc().to_string();
+    if !db.set_user_disabled(user_id, disabled, &now).await? {
+        return Err(anyhow!("User not found"));
+    }
+    Ok(())
+}
+
+#[instrument(skip_all, fields(user_id = %id), err)]
+pub async fn delete_user(db: &Database, id: Uuid) -> Result<bool> {
+    db.delete_user(id).await
+}
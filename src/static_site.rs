@@ -0,0 +1,497 @@
+//! Renders every public page into a directory of plain files that any
+//! static host can serve, using the same Tera templates as the server.
+
+use anyhow::{anyhow, Context as _, Result};
+use rocket_dyn_templates::tera::{Context, Tera};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::feeds::{self, FeedEntry};
+use crate::markdown;
+use crate::models::post::{slugify, Post, PostSort};
+use crate::models::series::Series;
+use crate::seo::{self, SeoConfig, Sitemap, SitemapUrl};
+use crate::services::db::Database;
+use crate::services::series_service::{self, SeriesEntry};
+use crate::services::{comment_service, post_service, profile_service, tag_service};
+
+/// Records what the previous build wrote, so the next one can skip
+/// unchanged posts and remove pages that no longer exist.
+const MANIFEST_FILE: &str = ".build-manifest.json";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StaticSiteConfig {
+    pub output_dir: PathBuf,
+    /// Public address of the site, e.g. `https://blog.example.com/docs`.
+    /// Feeds and the sitemap need absolute URLs.
+    pub base_url: Option<String>,
+    pub posts_per_page: usize,
+    /// Number of most recent posts in `feed.xml`.
+    pub feed_size: usize,
+    pub templates_dir: PathBuf,
+    pub static_dir: PathBuf,
+}
+
+impl Default for StaticSiteConfig {
+    fn default() -> Self {
+        StaticSiteConfig {
+            output_dir: PathBuf::from("public"),
+            base_url: None,
+            posts_per_page: 10,
+            feed_size: 20,
+            templates_dir: PathBuf::from("templates"),
+            static_dir: PathBuf::from("static"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BuildReport {
+    pub posts_rendered: usize,
+    /// Post pages left as they were because nothing they show had changed.
+    pub posts_unchanged: usize,
+    pub pages_written: usize,
+    pub pages_unchanged: usize,
+    pub assets_copied: usize,
+    /// Files from the previous build that are no longer part of the site.
+    pub removed: Vec<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Manifest {
+    templates: String,
+    posts: BTreeMap<Uuid, String>,
+    files: BTreeSet<String>,
+}
+
+#[derive(Serialize)]
+struct Link {
+    name: String,
+    url: String,
+}
+
+/// A post with everything its page and listings show.
+struct PostPage {
+    post: Post,
+    comments: Vec<crate::models::comment::Comment>,
+    tags: Vec<Link>,
+    author_url: String,
+    series: Option<series_service::SeriesNav>,
+}
+
+struct Build<'a> {
+    tera: Tera,
+    output: &'a Path,
+    report: BuildReport,
+    files: BTreeSet<String>,
+}
+
+impl Build<'_> {
+    /// Renders `template` to `path` (relative to the output directory),
+    /// rewriting root-relative links so the site works from any directory.
+    fn render(&mut self, template: &str, path: &str, context: serde_json::Value) -> Result<()> {
+        let html = self
+            .tera
+            .render(template, &Context::from_value(context)?)
+            .with_context(|| format!("Failed to render {}", path))?;
+        let html = if path.ends_with(".html") { relativize(&html, path.matches('/').count()) } else { html };
+        if write_if_changed(&self.output.join(path), html.as_bytes())? {
+            self.report.pages_written += 1;
+        } else {
+            self.report.pages_unchanged += 1;
+        }
+        self.files.insert(path.to_string());
+        Ok(())
+    }
+}
+
+/// Builds the site into `config.output_dir`. Post pages whose content,
+/// comments and tags are unchanged since the last build are not rendered
+/// again unless the templates changed or `full` is set; listings, feeds and
+/// the sitemap are always regenerated, but only rewritten when different.
+#[instrument(skip_all, fields(output = %config.output_dir.display()), err)]
+pub async fn build(db: &Database, config: &StaticSiteConfig, seo: &SeoConfig, full: bool) -> Result<BuildReport> {
+    let base_url = config
+        .base_url
+        .as_deref()
+        .map(|url| url.trim_end_matches('/').to_string())
+        .ok_or_else(|| anyhow!("static_site.base_url is required for feeds and the sitemap"))?;
+    if config.posts_per_page == 0 {
+        return Err(anyhow!("static_site.posts_per_page must be at least 1"));
+    }
+
+    let output = config.output_dir.as_path();
+    fs::create_dir_all(output)?;
+    let previous: Manifest = match fs::read(output.join(MANIFEST_FILE)) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+        Err(_) => Manifest::default(),
+    };
+
+    let (tera, templates_hash) = load_templates(&config.templates_dir)?;
+    let templates_hash = hash(&[templates_hash.as_bytes(), base_url.as_bytes()]);
+    let full = full || previous.templates != templates_hash;
+    let mut build = Build { tera, output, report: BuildReport::default(), files: BTreeSet::new() };
+    let mut manifest = Manifest { templates: templates_hash, ..Default::default() };
+
+    let mut pages = Vec::new();
+    let mut authors: BTreeMap<String, (String, Vec<usize>)> = BTreeMap::new();
+    let mut tags: BTreeMap<String, (String, Vec<usize>)> = BTreeMap::new();
+    let mut author_slugs: BTreeMap<Uuid, String> = BTreeMap::new();
+    let mut all_series = Vec::new();
+    for series in series_service::list_series(db).await? {
+        let parts = series_service::get_series_posts(db, series.id).await?;
+        if !parts.is_empty() {
+            all_series.push((series, parts));
+        }
+    }
+    for post in post_service::get_posts(db, PostSort::Newest).await? {
+        let author_slug = match author_slugs.get(&post.author_id) {
+            Some(slug) => slug.clone(),
+            None => {
+                // Usernames that slugify to nothing or to another author's
+                // slug fall back to the account ID.
+                let slug = slugify(&post.author);
+                let slug = if slug.is_empty() || authors.contains_key(&slug) { post.author_id.to_string() } else { slug };
+                author_slugs.insert(post.author_id, slug.clone());
+                slug
+            }
+        };
+        authors
+            .entry(author_slug.clone())
+            .or_insert_with(|| (post.author.clone(), Vec::new()))
+            .1
+            .push(pages.len());
+
+        let post_tags = tag_service::get_post_tags(db, post.id).await?;
+        for tag in &post_tags {
+            tags.entry(tag.slug.clone()).or_insert_with(|| (tag.name.clone(), Vec::new())).1.push(pages.len());
+        }
+
+        pages.push(PostPage {
+            comments: comment_service::get_post_comments(db, post.id).await?,
+            tags: post_tags
+                .into_iter()
+                .map(|tag| Link { url: format!("/tags/{}/", tag.slug), name: tag.name })
+                .collect(),
+            author_url: format!("/authors/{}/", author_slug),
+            series: all_series.iter().find_map(|(series, parts)| {
+                series_service::series_nav(series, parts, post.id, series_path(series), post_path)
+            }),
+            post,
+        });
+    }
+
+    for page in &pages {
+        let path = format!("posts/{}/index.html", page.post.id);
+        let tag_names: Vec<String> = page.tags.iter().map(|tag| tag.name.clone()).collect();
+        let meta = seo::post_meta(
+            &page.post,
+            &format!("{}{}", base_url, post_path(&page.post)),
+            &format!("{}{}", base_url, page.author_url),
+            &tag_names,
+        );
+        let context = json!({
+            "post": page.post,
+            "sections": markdown::sections(&page.post.content),
+            "comments": page.comments,
+            "tags": page.tags,
+            "author_url": page.author_url,
+            "series": page.series,
+            "seo": meta,
+            "title": meta.title,
+            "static_site": true,
+            "feed_url": "/feed.xml",
+        });
+        let fingerprint = hash(&[context.to_string().as_bytes()]);
+        let unchanged = !full
+            && previous.posts.get(&page.post.id) == Some(&fingerprint)
+            && output.join(&path).exists();
+        if unchanged {
+            build.files.insert(path);
+            build.report.posts_unchanged += 1;
+        } else {
+            build.render("post", &path, context)?;
+            build.report.posts_rendered += 1;
+        }
+        manifest.posts.insert(page.post.id, fingerprint);
+    }
+
+    let all: Vec<usize> = (0..pages.len()).collect();
+    render_listing(&mut build, &pages, &all, "", None, config.posts_per_page)?;
+    for (slug, (name, indexes)) in &tags {
+        let heading = format!("Posts tagged \u{201c}{}\u{201d}", name);
+        render_listing(&mut build, &pages, indexes, &format!("tags/{}/", slug), Some(&heading), config.posts_per_page)?;
+    }
+    for (slug, (name, indexes)) in &authors {
+        let author = profile_service::get_author_page(db, name)
+            .await?
+            .ok_or_else(|| anyhow!("Author {} not found", name))?;
+        let feed_path = format!("/authors/{}/feed.xml", slug);
+        build.render("author", &format!("authors/{}/index.html", slug), json!({
+            "author": author,
+            "static_site": true,
+            "feed_url": feed_path,
+        }))?;
+        let entries: Vec<_> = indexes.iter().take(config.feed_size).map(|&index| feed_entry(&pages[index])).collect();
+        let title = format!("Posts by {}", author.name);
+        build.render("feed", &feed_path[1..], feeds::atom_context(&base_url, &feed_path, &title, &entries, post_path))?;
+    }
+
+    for (series, parts) in &all_series {
+        let dir = series_path(series);
+        let feed_path = format!("{}feed.xml", dir);
+        build.render("series", &format!("{}index.html", &dir[1..]), json!({
+            "series": series,
+            "parts": parts.iter().map(|post| SeriesEntry { url: post_path(post), post }).collect::<Vec<_>>(),
+            "author_url": author_slugs.get(&series.author_id).map(|slug| format!("/authors/{}/", slug)),
+            "static_site": true,
+            "feed_url": feed_path,
+        }))?;
+        let entries: Vec<_> = parts
+            .iter()
+            .filter_map(|post| pages.iter().find(|page| page.post.id == post.id))
+            .map(feed_entry)
+            .collect();
+        build.render("feed", &feed_path[1..], feeds::atom_context(&base_url, &feed_path, &series.title, &entries, post_path))?;
+    }
+
+    let entries: Vec<_> = pages.iter().take(config.feed_size).map(feed_entry).collect();
+    let feed = feeds::atom_context(&base_url, "/feed.xml", "Blog", &entries, post_path);
+    let updated = feed["updated"].clone();
+    build.render("feed", "feed.xml", feed)?;
+
+    let mut urls = vec![SitemapUrl { loc: format!("{}/", base_url), lastmod: updated.as_str().map(str::to_string) }];
+    for page in &pages {
+        urls.push(SitemapUrl {
+            loc: format!("{}{}", base_url, post_path(&page.post)),
+            lastmod: Some(page.post.updated_at.clone()),
+        });
+    }
+    for (prefix, groups) in [("tags", &tags), ("authors", &authors)] {
+        for (slug, (_, indexes)) in groups {
+            urls.push(SitemapUrl {
+                loc: format!("{}/{}/{}/", base_url, prefix, slug),
+                lastmod: indexes.iter().map(|&index| pages[index].post.updated_at.clone()).max(),
+            });
+        }
+    }
+    for (series, parts) in &all_series {
+        urls.push(SitemapUrl {
+            loc: format!("{}{}", base_url, series_path(series)),
+            lastmod: parts.iter().map(|post| &post.updated_at).chain([&series.updated_at]).max().cloned(),
+        });
+    }
+    let sitemap = Sitemap::new(&base_url, urls, seo.sitemap_max_urls);
+    for path in sitemap.paths() {
+        if let Some((template, context)) = sitemap.file(&path) {
+            build.render(template, &path, context)?;
+        }
+    }
+    if write_if_changed(&output.join("robots.txt"), seo::robots_txt(seo, &base_url).as_bytes())? {
+        build.report.pages_written += 1;
+    } else {
+        build.report.pages_unchanged += 1;
+    }
+    build.files.insert("robots.txt".to_string());
+
+    if config.static_dir.is_dir() {
+        copy_assets(&mut build, &config.static_dir, Path::new("static"))?;
+    }
+
+    for stale in previous.files.difference(&build.files) {
+        let path = output.join(stale);
+        if path.is_file() {
+            fs::remove_file(&path)?;
+            remove_empty_parents(output, &path);
+            build.report.removed.push(stale.clone());
+        }
+    }
+
+    manifest.files = build.files;
+    fs::write(output.join(MANIFEST_FILE), serde_json::to_vec_pretty(&manifest)?)?;
+    Ok(build.report)
+}
+
+fn feed_entry(page: &PostPage) -> FeedEntry<'_> {
+    FeedEntry { post: &page.post, tags: page.tags.iter().map(|tag| tag.name.clone()).collect() }
+}
+
+fn post_path(post: &Post) -> String {
+    format!("/posts/{}/", post.id)
+}
+
+fn series_path(series: &Series) -> String {
+    format!("/series/{}/", series.slug)
+}
+
+/// Renders a paginated list of posts under `dir`: the first page at
+/// `dir/index.html`, later ones at `dir/page/<n>/index.html`.
+fn render_listing(
+    build: &mut Build,
+    pages: &[PostPage],
+    indexes: &[usize],
+    dir: &str,
+    heading: Option<&str>,
+    per_page: usize,
+) -> Result<()> {
+    let page_url = |number: usize| match number {
+        1 => format!("/{}", dir),
+        n => format!("/{}page/{}/", dir, n),
+    };
+    let chunks: Vec<&[usize]> = if indexes.is_empty() { vec![&[]] } else { indexes.chunks(per_page).collect() };
+    let total = chunks.len();
+
+    for (number, chunk) in (1..).zip(chunks) {
+        let posts: Vec<_> = chunk
+            .iter()
+            .map(|&index| &pages[index].post)
+            .collect();
+        let path = format!("{}index.html", page_url(number).trim_start_matches('/'));
+        build.render("index", &path, json!({
+            "posts": posts,
+            "title": heading.unwrap_or("Home"),
+            "heading": heading,
+            "static_site": true,
+            "feed_url": "/feed.xml",
+            "pagination": {
+                "current": number,
+                "total": total,
+                "prev_url": (number > 1).then(|| page_url(number - 1)),
+                "next_url": (number < total).then(|| page_url(number + 1)),
+            },
+        }))?;
+    }
+    Ok(())
+}
+
+/// Loads templates the way `rocket_dyn_templates` does, so names and
+/// autoescaping match the server. Returns a hash of their sources.
+fn load_templates(dir: &Path) -> Result<(Tera, String)> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.retain(|path| path.extension().is_some_and(|ext| ext == "tera"));
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    let mut templates = Vec::new();
+    for path in files {
+        let relative = path.strip_prefix(dir)?;
+        // `index.html.tera` is registered as `index`.
+        let name = relative.with_extension("").with_extension("").to_string_lossy().replace('\\', "/");
+        hasher.update(name.as_bytes());
+        hasher.update(fs::read(&path)?);
+        templates.push((path, Some(name)));
+    }
+
+    let mut tera = Tera::default();
+    tera.autoescape_on(vec![".html.tera", ".htm.tera", ".xml.tera", ".html", ".htm", ".xml"]);
+    tera.add_template_files(templates)
+        .with_context(|| format!("Failed to load templates from {}", dir.display()))?;
+    Ok((tera, hex(&hasher.finalize())))
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn copy_assets(build: &mut Build, source: &Path, target: &Path) -> Result<()> {
+    let mut files = Vec::new();
+    collect_files(source, &mut files)?;
+    for path in files {
+        let relative = target.join(path.strip_prefix(source)?);
+        if write_if_changed(&build.output.join(&relative), &fs::read(&path)?)? {
+            build.report.assets_copied += 1;
+        }
+        build.files.insert(relative.to_string_lossy().replace('\\', "/"));
+    }
+    Ok(())
+}
+
+/// Leaves files with identical content untouched, so their modification
+/// times stay put for tools that sync by timestamp.
+fn write_if_changed(path: &Path, contents: &[u8]) -> Result<bool> {
+    if fs::read(path).is_ok_and(|existing| existing == contents) {
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    Ok(true)
+}
+
+fn remove_empty_parents(root: &Path, path: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == root || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+/// Rewrites root-relative `href`, `src` and `action` attributes into links
+/// relative to a page `depth` directories below the site root. Paths whose
+/// last segment has no extension point at a directory's `index.html`.
+pub fn relativize(html: &str, depth: usize) -> String {
+    let prefix = if depth == 0 { "./".to_string() } else { "../".repeat(depth) };
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = ["href=\"/", "src=\"/", "action=\"/"]
+        .iter()
+        .filter_map(|attr| rest.find(attr).map(|index| index + attr.len() - 1))
+        .min()
+    {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find('"').unwrap_or(rest.len());
+        let url = &rest[..end];
+        rest = &rest[end..];
+
+        // Protocol-relative URLs point at another host.
+        if url.starts_with("//") {
+            result.push_str(url);
+            continue;
+        }
+        let split = url.find(['?', '#']).unwrap_or(url.len());
+        let (path, suffix) = url.split_at(split);
+        let path = path.trim_start_matches('/');
+        result.push_str(&prefix);
+        result.push_str(path);
+        let is_file = path.rsplit('/').next().is_some_and(|segment| segment.contains('.'));
+        if !path.is_empty() && !is_file && !path.ends_with('/') {
+            result.push('/');
+        }
+        result.push_str(suffix);
+    }
+    result.push_str(rest);
+    result
+}
+
+fn hash(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hex(&hasher.finalize())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
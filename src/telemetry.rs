@@ -0,0 +1,64 @@
+use anyhow::{anyhow, Result};
+use rocket::figment::Figment;
+use serde::Deserialize;
+use std::time::Duration;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TracingConfig {
+    pub format: LogFormat,
+    pub filter: String,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        TracingConfig {
+            format: LogFormat::Pretty,
+            filter: "info".to_string(),
+        }
+    }
+}
+
+/// Installs the global tracing subscriber using the `tracing` table of the
+/// Rocket configuration. `RUST_LOG`, when set, takes precedence over the
+/// configured filter. Fails if a subscriber is already installed.
+pub fn init(figment: &Figment) -> Result<()> {
+    let config: TracingConfig = figment.extract_inner("tracing").unwrap_or_default();
+
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&config.filter))
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE);
+
+    match config.format {
+        LogFormat::Pretty => builder.pretty().try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).try_init(),
+    }
+    .map_err(|e| anyhow!(e))
+}
+
+/// SQLite profile callback, installed on every pooled connection. Emits one
+/// event per statement inside whichever service span is currently entered
+/// and feeds the SQL latency histogram.
+pub fn log_sql(sql: &str, elapsed: Duration) {
+    crate::metrics::record_sql(sql, elapsed);
+    tracing::debug!(
+        target: "blog::sql",
+        elapsed_us = elapsed.as_micros() as u64,
+        sql = sql.trim(),
+        "sql statement"
+    );
+}
@@ -0,0 +1,98 @@
+//! Time-based one-time passwords (RFC 6238) as authenticator apps expect
+//! them: HMAC-SHA1, six digits, a new code every 30 seconds.
+
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rocket::http::RawStr;
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+
+pub const DIGITS: usize = 6;
+pub const PERIOD_SECS: u64 = 30;
+/// Steps either side of the current one that still count, to allow for
+/// clock drift and typing time.
+const SKEW_STEPS: u64 = 1;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A new 160-bit secret, base32 encoded as apps expect.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    base32_encode(&bytes)
+}
+
+/// RFC 4648 base32 without padding.
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let value = buffer.iter().fold(0u64, |acc, &byte| acc << 8 | byte as u64);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            result.push(BASE32_ALPHABET[(value >> (35 - i * 5)) as usize & 31] as char);
+        }
+    }
+    result
+}
+
+/// Decodes base32, ignoring case, spaces and padding.
+pub fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET.iter().position(|&a| a as char == c.to_ascii_uppercase())?;
+        buffer = buffer << 5 | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(result)
+}
+
+/// The time step a Unix time falls in.
+pub fn step_at(unix_time: u64) -> u64 {
+    unix_time / PERIOD_SECS
+}
+
+/// The code for a time step (RFC 4226 HOTP with the step as counter).
+pub fn code_at(secret: &[u8], step: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[19] & 0x0f) as usize;
+    let value = u32::from_be_bytes([digest[offset] & 0x7f, digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    format!("{:0width$}", value % 10u32.pow(DIGITS as u32), width = DIGITS)
+}
+
+/// Checks a code against the steps around `unix_time` and returns the step
+/// it belongs to, so callers can refuse to accept it twice.
+pub fn verify(secret: &str, code: &str, unix_time: u64) -> Option<u64> {
+    let secret = base32_decode(secret)?;
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS {
+        return None;
+    }
+    let now = step_at(unix_time);
+    (now.saturating_sub(SKEW_STEPS)..=now + SKEW_STEPS)
+        .find(|&step| bool::from(code_at(&secret, step).as_bytes().ct_eq(code.as_bytes())))
+}
+
+/// The `otpauth://` link authenticator apps read from the QR code.
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = RawStr::new(issuer).percent_encode();
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        RawStr::new(account).percent_encode(),
+        secret,
+        issuer,
+        DIGITS,
+        PERIOD_SECS,
+    )
+}
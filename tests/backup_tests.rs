@@ -0,0 +1,93 @@
+mod common;
+
+use anyhow::Result;
+use blog::services::backup_service::{self, BackupConfig};
+use blog::services::user_service;
+use std::fs;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+fn test_config(compress: bool) -> BackupConfig {
+    BackupConfig {
+        dir: PathBuf::from(format!("test_backups_{}", Uuid::new_v4())),
+        compress,
+        keep_last: 2,
+        ..BackupConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn test_backup_and_restore() -> Result<()> {
+    for compress in [true, false] {
+        let test_db = common::setup_test_db().await?;
+        let db = &test_db.db;
+        let config = test_config(compress);
+
+        let alice = common::create_test_user(db, "alice").await?;
+        let report = backup_service::create_backup(db, &config).await?;
+        assert_eq!(report.backup.compressed, compress);
+        assert!(report.backup.path.exists());
+        assert_eq!(backup_service::verify_backup(&report.backup.path).await?, report.schema_version);
+
+        // Changes made after the backup are rolled back by the restore
+        let bob = common::create_test_user(db, "bob").await?;
+        backup_service::restore_backup(db, &report.backup.path).await?;
+        assert!(user_service::get_user_by_id(db, alice).await?.is_some());
+        assert!(user_service::get_user_by_id(db, bob).await?.is_none());
+
+        fs::remove_dir_all(&config.dir)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_backup_requires_sqlite() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        let config = test_config(true);
+        let result = backup_service::create_backup(&test_db.db, &config).await;
+        assert_eq!(result.is_ok(), test_db.backend == "sqlite", "{}", test_db.backend);
+
+        fs::remove_dir_all(&config.dir).ok();
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_backup_retention() -> Result<()> {
+    let test_db = common::setup_test_db().await?;
+    let config = test_config(true);
+
+    let mut created = Vec::new();
+    for _ in 0..3 {
+        created.push(backup_service::create_backup(&test_db.db, &config).await?.backup.path);
+    }
+
+    let remaining: Vec<PathBuf> = backup_service::list_backups(&config.dir)
+        .await?
+        .into_iter()
+        .map(|backup| backup.path)
+        .collect();
+    assert_eq!(remaining, vec![created[2].clone(), created[1].clone()]);
+
+    fs::remove_dir_all(&config.dir)?;
+    test_db.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_restore_rejects_corrupt_backup() -> Result<()> {
+    let test_db = common::setup_test_db().await?;
+    let config = test_config(false);
+    fs::create_dir_all(&config.dir)?;
+
+    let path = config.dir.join("blog-20240101T000000.000Z.db");
+    fs::write(&path, b"not a database")?;
+    assert!(backup_service::verify_backup(&path).await.is_err());
+    assert!(backup_service::restore_backup(&test_db.db, &path).await.is_err());
+
+    fs::remove_dir_all(&config.dir)?;
+    test_db.cleanup().await;
+    Ok(())
+}
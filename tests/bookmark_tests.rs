@@ -0,0 +1,117 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::post::CreatePost;
+use blog::services::bookmark_service;
+use blog::services::db::Database;
+use blog::services::post_service;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::Value;
+use uuid::Uuid;
+
+async fn log_in(client: &Client, username: &str) {
+    client
+        .post("/login")
+        .header(ContentType::Form)
+        .body(format!("username={}&password=testpass123", username))
+        .dispatch()
+        .await;
+}
+
+/// Saves a bookmark and answers with where the browser was sent.
+async fn save(client: &Client, path: &str, form: &str) -> (Status, String) {
+    let response = client.put(path.to_string()).header(ContentType::Form).body(form).dispatch().await;
+    (response.status(), response.headers().get_one("Location").unwrap_or_default().to_string())
+}
+
+async fn page(client: &Client, path: &str) -> Result<String> {
+    let response = client.get(path).dispatch().await;
+    assert_eq!(response.status(), Status::Ok, "{}", path);
+    response.into_string().await.context("response has a body")
+}
+
+async fn bookmarks(db: &Database) -> Result<()> {
+    let author = common::create_test_user(db, "writer").await?;
+    let reader = common::create_test_user(db, "reader").await?;
+    common::create_test_user(db, "other").await?;
+    let looms = post_service::create_post(db, CreatePost { title: "Looms".to_string(), content: "Cards".to_string() }, author).await?;
+    let gears = post_service::create_post(db, CreatePost { title: "Gears".to_string(), content: "Teeth".to_string() }, author).await?;
+    let client = common::site(db.clone()).await?;
+    let looms_path = format!("/posts/{}/bookmark", looms.id);
+
+    // Only signed-in readers have bookmarks
+    assert_ne!(client.get("/bookmarks").dispatch().await.status(), Status::Ok);
+    assert_eq!(save(&client, &looms_path, "").await.0, Status::Unauthorized);
+    log_in(&client, "reader").await;
+
+    // A bookmark can be filed in a folder with a note, and refiled later
+    let (status, location) = save(&client, &looms_path, "folder=+History+&note=Read+the+part+on+cards").await;
+    assert_eq!((status, location.as_str()), (Status::SeeOther, format!("/posts/{}", looms.id).as_str()));
+    let bookmark = bookmark_service::get_bookmark(db, reader, looms.id).await?.context("bookmark saved")?;
+    assert_eq!((bookmark.folder.as_str(), bookmark.note.as_deref()), ("History", Some("Read the part on cards")));
+    save(&client, &looms_path, "folder=Weaving&note=++").await;
+    let refiled = bookmark_service::get_bookmark(db, reader, looms.id).await?.context("bookmark kept")?;
+    assert_eq!((refiled.folder.as_str(), refiled.note), ("Weaving", None));
+    assert_eq!(refiled.created_at, bookmark.created_at);
+
+    let post_page = page(&client, &format!("/posts/{}", looms.id)).await?;
+    assert!(post_page.contains("Bookmarked in Weaving"));
+    assert!(post_page.contains(r#"<option value="Weaving">"#));
+
+    // The home page toggles bookmarks in place, and other pages can be asked to come back to
+    let home = page(&client, "/").await?;
+    assert!(home.contains(r#"aria-pressed="true">&#9733; Bookmarked"#));
+    assert!(home.contains(r#"aria-pressed="false">&#9734; Bookmark"#));
+    let gears_path = format!("/posts/{}/bookmark", gears.id);
+    assert_eq!(save(&client, &format!("{}?back=/", gears_path), "").await.1, "/");
+    assert_eq!(save(&client, &format!("{}?back=//evil.example", gears_path), "").await.1, format!("/posts/{}", gears.id));
+    assert_eq!(save(&client, &format!("/posts/{}/bookmark", Uuid::new_v4()), "").await.1, "/");
+    let (status, _) = save(&client, &gears_path, &format!("folder={}", "x".repeat(51))).await;
+    assert_eq!(status, Status::SeeOther);
+    assert_eq!(bookmark_service::get_bookmark(db, reader, gears.id).await?.context("still saved")?.folder, "");
+
+    // The reading list shows the most recently saved first, by folder
+    let list = page(&client, "/bookmarks").await?;
+    assert!(list.find("Gears") < list.find("Looms"));
+    assert!(list.contains("Unfiled (1)") && list.contains("Weaving (1)"));
+    let weaving = page(&client, "/bookmarks?folder=Weaving").await?;
+    assert!(weaving.contains("Looms") && !weaving.contains(">Gears<"));
+    let unfiled = page(&client, "/bookmarks?folder=").await?;
+    assert!(unfiled.contains(">Gears<") && !unfiled.contains(">Looms<"));
+
+    // Exports carry absolute links for use elsewhere
+    let response = client.get("/bookmarks/export.json").dispatch().await;
+    assert_eq!(response.headers().get_one("Content-Disposition"), Some(r#"attachment; filename="bookmarks.json""#));
+    let exported: Value = response.into_json().await.context("export is JSON")?;
+    assert_eq!(exported[1]["url"], format!("https://blog.example/posts/{}", looms.id));
+    assert_eq!((&exported[1]["folder"], &exported[1]["title"]), (&Value::from("Weaving"), &Value::from("Looms")));
+    let html = page(&client, "/bookmarks/export.html").await?;
+    assert!(html.starts_with("<!DOCTYPE NETSCAPE-Bookmark-file-1>"));
+    assert!(html.contains("<H3>Weaving</H3>"));
+    assert!(html.contains(&format!(r#"<A HREF="https://blog.example/posts/{}" ADD_DATE=""#, gears.id)));
+
+    // Bookmarks are private
+    client.post("/logout").dispatch().await;
+    log_in(&client, "other").await;
+    assert!(page(&client, "/bookmarks").await?.contains("No bookmarks yet"));
+    assert!(!page(&client, "/").await?.contains("&#9733; Bookmarked"));
+
+    // Removing a bookmark, or its post, takes it off the list
+    log_in(&client, "reader").await;
+    let response = client.delete(format!("{}?back=/bookmarks", gears_path)).dispatch().await;
+    assert_eq!(response.headers().get_one("Location"), Some("/bookmarks"));
+    assert!(bookmark_service::get_bookmark(db, reader, gears.id).await?.is_none());
+    assert!(post_service::delete_post(db, looms.id).await?);
+    assert!(db.list_bookmarks(reader).await?.is_empty());
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_bookmarks() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        bookmarks(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
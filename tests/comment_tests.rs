@@ -1,169 +1,98 @@
 mod common;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use blog::models::comment::CreateComment;
+use blog::models::post::CreatePost;
+use blog::services::db::Database;
+use blog::services::{comment_service, post_service, user_service};
 use uuid::Uuid;
-use chrono::Utc;
 
-#[tokio::test]
-async fn test_comment_crud() -> Result<()> {
-    let (db_name, pool) = common::setup_test_db().await?;
-    let user_id = common::create_test_user(&pool, "testuser").await?;
-    let commenter_id = common::create_test_user(&pool, "commenter").await?;
+async fn create_test_post(db: &Database, author_id: Uuid) -> Result<Uuid> {
+    let post = post_service::create_post(
+        db,
+        CreatePost {
+            title: "Test Post".to_string(),
+            content: "This is a test post content.".to_string(),
+        },
+        author_id,
+    ).await?;
+    Ok(post.id)
+}
+
+fn test_comment() -> CreateComment {
+    CreateComment { content: "This is a test comment.".to_string() }
+}
+
+async fn comment_crud(db: &Database) -> Result<()> {
+    let user_id = common::create_test_user(db, "testuser").await?;
+    let commenter_id = common::create_test_user(db, "commenter").await?;
 
     // Create a post first
-    let post_id = Uuid::new_v4();
-    let now = Utc::now().to_rfc3339();
-    let conn = pool.get()?;
-    conn.execute(
-        "INSERT INTO posts (id, title, content, author_id, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        rusqlite::params![
-            post_id.to_string(),
-            "Test Post",
-            "This is a test post content.",
-            user_id.to_string(),
-            now,
-            now
-        ],
-    )?;
+    let post_id = create_test_post(db, user_id).await?;
 
     // Create comment
-    let comment_id = Uuid::new_v4();
-    let now = Utc::now().to_rfc3339();
-    conn.execute(
-        "INSERT INTO comments (id, content, post_id, author_id, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        rusqlite::params![
-            comment_id.to_string(),
-            "This is a test comment.",
-            post_id.to_string(),
-            commenter_id.to_string(),
-            now,
-            now
-        ],
-    )?;
+    let comment = comment_service::create_comment(db, test_comment(), post_id, commenter_id).await?;
 
     // Read comment
-    let fetched_comment = conn.query_row(
-        "SELECT c.id, c.content, c.post_id, c.author_id, u.username as author, c.created_at, c.updated_at
-         FROM comments c
-         JOIN users u ON c.author_id = u.id
-         WHERE c.id = ?1",
-        [comment_id.to_string()],
-        |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, String>(2)?,
-                row.get::<_, String>(3)?,
-                row.get::<_, String>(4)?,
-                row.get::<_, String>(5)?,
-                row.get::<_, String>(6)?,
-            ))
-        }
-    )?;
-
-    assert_eq!(fetched_comment.0, comment_id.to_string());
-    assert_eq!(fetched_comment.1, "This is a test comment.");
-    assert_eq!(fetched_comment.2, post_id.to_string());
-    assert_eq!(fetched_comment.3, commenter_id.to_string());
-    assert_eq!(fetched_comment.4, "commenter");
+    let fetched_comment = comment_service::get_comment(db, comment.id).await?.expect("comment exists");
+    assert_eq!(fetched_comment.id, comment.id);
+    assert_eq!(fetched_comment.content, "This is a test comment.");
+    assert_eq!(fetched_comment.post_id, post_id);
+    assert_eq!(fetched_comment.author_id, commenter_id);
+    assert_eq!(fetched_comment.author, "commenter");
 
     // List comments for post
-    let mut stmt = conn.prepare("SELECT id FROM comments WHERE post_id = ?1")?;
-    let comments: Vec<String> = stmt.query_map([post_id.to_string()], |row| row.get(0))?
-        .collect::<Result<Vec<_>, _>>()?;
+    let comments = comment_service::get_post_comments(db, post_id).await?;
     assert_eq!(comments.len(), 1);
-    assert_eq!(comments[0], comment_id.to_string());
+    assert_eq!(comments[0].id, comment.id);
 
     // Update comment
-    let now = Utc::now().to_rfc3339();
-    conn.execute(
-        "UPDATE comments SET content = ?1, updated_at = ?2 WHERE id = ?3",
-        rusqlite::params![
-            "Updated comment content.",
-            now,
-            comment_id.to_string()
-        ],
-    )?;
-
-    let updated_comment = conn.query_row(
-        "SELECT content FROM comments WHERE id = ?1",
-        [comment_id.to_string()],
-        |row| row.get::<_, String>(0)
-    )?;
-    assert_eq!(updated_comment, "Updated comment content.");
+    assert!(comment_service::update_comment(db, comment.id, "Updated comment content.".to_string()).await?);
+
+    let updated_comment = comment_service::get_comment(db, comment.id).await?.expect("comment exists");
+    assert_eq!(updated_comment.content, "Updated comment content.");
 
     // Delete comment
-    conn.execute(
-        "DELETE FROM comments WHERE id = ?1",
-        [comment_id.to_string()],
-    )?;
-
-    let count: i64 = conn.query_row(
-        "SELECT COUNT(*) FROM comments WHERE id = ?1",
-        [comment_id.to_string()],
-        |row| row.get(0)
-    )?;
-    assert_eq!(count, 0);
-
-    common::cleanup_test_db(&db_name);
+    assert!(comment_service::delete_comment(db, comment.id).await?);
+    assert!(comment_service::get_comment(db, comment.id).await?.is_none());
+
     Ok(())
 }
 
-#[tokio::test]
-async fn test_delete_user_cascades_to_comments() -> Result<()> {
-    let (db_name, pool) = common::setup_test_db().await?;
-    let user_id = common::create_test_user(&pool, "testuser").await?;
-    let commenter_id = common::create_test_user(&pool, "commenter").await?;
+async fn delete_user_cascades_to_comments(db: &Database) -> Result<()> {
+    let user_id = common::create_test_user(db, "testuser").await?;
+    let commenter_id = common::create_test_user(db, "commenter").await?;
 
     // Create post
-    let post_id = Uuid::new_v4();
-    let now = Utc::now().to_rfc3339();
-    let conn = pool.get()?;
-    conn.execute(
-        "INSERT INTO posts (id, title, content, author_id, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        rusqlite::params![
-            post_id.to_string(),
-            "Test Post",
-            "This is a test post content.",
-            user_id.to_string(),
-            now,
-            now
-        ],
-    )?;
+    let post_id = create_test_post(db, user_id).await?;
 
     // Create comment
-    let comment_id = Uuid::new_v4();
-    let now = Utc::now().to_rfc3339();
-    conn.execute(
-        "INSERT INTO comments (id, content, post_id, author_id, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        rusqlite::params![
-            comment_id.to_string(),
-            "This is a test comment.",
-            post_id.to_string(),
-            commenter_id.to_string(),
-            now,
-            now
-        ],
-    )?;
+    let comment = comment_service::create_comment(db, test_comment(), post_id, commenter_id).await?;
 
     // Delete the user who made the comment
-    conn.execute(
-        "DELETE FROM users WHERE id = ?1",
-        [commenter_id.to_string()],
-    )?;
+    assert!(user_service::delete_user(db, commenter_id).await?);
 
     // Verify comment was also deleted
-    let count: i64 = conn.query_row(
-        "SELECT COUNT(*) FROM comments WHERE id = ?1",
-        [comment_id.to_string()],
-        |row| row.get(0)
-    )?;
-    assert_eq!(count, 0);
-
-    common::cleanup_test_db(&db_name);
+    assert!(comment_service::get_comment(db, comment.id).await?.is_none());
+    assert!(comment_service::get_post_comments(db, post_id).await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_comment_crud() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        comment_crud(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_user_cascades_to_comments() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        delete_user_cascades_to_comments(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
     Ok(())
 }
@@ -1,84 +1,189 @@
-use r2d2::Pool;
-use r2d2_sqlite::SqliteConnectionManager;
-use uuid::Uuid;
-use chrono;
-use bcrypt;
-use anyhow::Result;
+// Each test crate compiles this module and uses only some of it.
+#![allow(dead_code)]
+
+use anyhow::{bail, Result};
+use blog::config;
+use blog::email::{EmailConfig, Mailer, OutboxTransport};
+use blog::feeds::SiteConfig;
+use blog::metrics::MetricsConfig;
+use blog::models::auth::hash_password;
+use blog::models::user::CreateUser;
+use blog::oidc::{self, OidcConfig};
+use blog::repositories::postgres::PostgresRepository;
+use blog::routes;
+use blog::routes::health::HealthConfig;
+use blog::seo::SeoConfig;
+use blog::services::db::{connect, Database, DbConfig};
+use blog::services::login_service::LoginConfig;
+use blog::services::reaction_service::ReactionsConfig;
+use blog::services::two_factor_service::TwoFactorConfig;
+use blog::services::user_service;
+use rocket::figment::Figment;
+use rocket::local::asynchronous::Client;
+use rocket::{Build, Rocket};
+use rocket_dyn_templates::Template;
+use sqlx::postgres::PgConnectOptions;
+use sqlx::{ConnectOptions, Connection, Executor};
+use std::env;
 use std::fs;
+use std::io::{self, Write};
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Once};
+use uuid::Uuid;
+
+enum Cleanup {
+    Sqlite(String),
+    Postgres { admin: Box<PgConnectOptions>, database: String },
+}
+
+/// A freshly migrated, empty database on one backend.
+pub struct TestDb {
+    pub backend: &'static str,
+    pub db: Database,
+    cleanup: Cleanup,
+}
 
-type DbPool = Pool<SqliteConnectionManager>;
+impl TestDb {
+    pub async fn cleanup(self) {
+        drop(self.db);
+        match self.cleanup {
+            Cleanup::Sqlite(db_name) => cleanup_test_db(&db_name),
+            Cleanup::Postgres { admin, database } => {
+                if let Ok(mut conn) = admin.connect().await {
+                    conn.execute(format!("DROP DATABASE IF EXISTS \"{}\" WITH (FORCE)", database).as_str())
+                        .await
+                        .ok();
+                    conn.close().await.ok();
+                }
+            }
+        }
+    }
+}
 
-/// Creates a test database pool with a unique name for parallel testing
-pub async fn setup_test_db() -> Result<(String, DbPool)> {
+/// Creates a SQLite test database with a unique name for parallel testing
+pub async fn setup_test_db() -> Result<TestDb> {
     let db_name = format!("test_{}.db", Uuid::new_v4());
-    let manager = SqliteConnectionManager::file(&db_name);
-    let pool = Pool::new(manager)?;
-    
-    // Create tables
-    let conn = pool.get()?;
-    conn.execute_batch("
-        PRAGMA foreign_keys = ON;
-        
-        CREATE TABLE IF NOT EXISTS users (
-            id TEXT PRIMARY KEY,
-            username TEXT UNIQUE NOT NULL,
-            password_hash TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            updated_at TEXT NOT NULL
-        );
+    let db = connect(&db_name, &DbConfig::default()).await?;
+    Ok(TestDb { backend: "sqlite", db, cleanup: Cleanup::Sqlite(db_name) })
+}
 
-        CREATE TABLE IF NOT EXISTS posts (
-            id TEXT PRIMARY KEY,
-            title TEXT NOT NULL,
-            content TEXT NOT NULL,
-            author_id TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            updated_at TEXT NOT NULL,
-            FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE CASCADE
-        );
+/// Creates a PostgreSQL test database on the server named by
+/// `TEST_POSTGRES_URL`, which must allow `CREATE DATABASE`.
+pub async fn setup_postgres_test_db(admin_url: &str) -> Result<TestDb> {
+    let admin = PgConnectOptions::from_str(admin_url)?;
+    let database = format!("test_{}", Uuid::new_v4().simple());
 
-        CREATE TABLE IF NOT EXISTS comments (
-            id TEXT PRIMARY KEY,
-            content TEXT NOT NULL,
-            post_id TEXT NOT NULL,
-            author_id TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            updated_at TEXT NOT NULL,
-            FOREIGN KEY (post_id) REFERENCES posts(id) ON DELETE CASCADE,
-            FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE CASCADE
-        );
-    ")?;
+    let mut conn = admin.connect().await?;
+    conn.execute(format!("CREATE DATABASE \"{}\"", database).as_str()).await?;
+    conn.close().await?;
 
-    Ok((db_name.to_string(), pool))
+    let repository =
+        PostgresRepository::connect_with(admin.clone().database(&database), &DbConfig::default()).await?;
+    Ok(TestDb {
+        backend: "postgres",
+        db: Arc::new(repository),
+        cleanup: Cleanup::Postgres { admin: Box::new(admin), database },
+    })
 }
 
-/// Creates a test user and returns their ID
-pub async fn create_test_user(pool: &DbPool, username: &str) -> Result<Uuid> {
-    use bcrypt::{hash, DEFAULT_COST};
-    let password_hash = hash("testpass123", DEFAULT_COST)?;
-    let id = Uuid::new_v4();
-    let now = chrono::Utc::now().to_rfc3339();
+/// One test database per available backend: always SQLite, plus PostgreSQL
+/// when `TEST_POSTGRES_URL` is set. Without it, the PostgreSQL runs are
+/// skipped with a notice, or fail when `CI` is set so they are not skipped
+/// unnoticed there.
+pub async fn test_databases() -> Result<Vec<TestDb>> {
+    let mut databases = vec![setup_test_db().await?];
+    match env::var("TEST_POSTGRES_URL") {
+        Ok(url) => databases.push(setup_postgres_test_db(&url).await?),
+        Err(_) if env::var_os("CI").is_some() => bail!("TEST_POSTGRES_URL must be set when CI is set"),
+        Err(_) => {
+            // Written directly, since the test harness hides `eprintln!`
+            // output of passing tests.
+            static NOTICE: Once = Once::new();
+            NOTICE.call_once(|| {
+                writeln!(io::stderr(), "note: TEST_POSTGRES_URL is not set; skipping the PostgreSQL runs").ok();
+            });
+        }
+    }
+    Ok(databases)
+}
 
-    let conn = pool.get()?;
-    conn.execute(
-        "INSERT INTO users (id, username, password_hash, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5)",
-        rusqlite::params![
-            id.to_string(),
-            username,
-            password_hash,
-            now,
-            now
-        ],
-    )?;
-    
-    Ok(id)
+/// Creates a test user and returns their ID
+pub async fn create_test_user(db: &Database, username: &str) -> Result<Uuid> {
+    let password_hash = hash_password("testpass123").await?;
+    let user = user_service::create_user(
+        db,
+        CreateUser { username: username.to_string(), password: "testpass123".to_string(), email: String::new() },
+        password_hash,
+    )
+    .await?;
+    Ok(user.id)
 }
 
 /// Cleanup test database
 pub fn cleanup_test_db(db_name: &str) {
-    if Path::new(db_name).exists() {
-        fs::remove_file(db_name).ok();
+    for suffix in ["", "-wal", "-shm"] {
+        let path = format!("{}{}", db_name, suffix);
+        if Path::new(&path).exists() {
+            fs::remove_file(path).ok();
+        }
+    }
+}
+
+/// The settings of a test site. Each defaults to what `main` uses without a
+/// `Rocket.toml`, except for a fixed secret key and `https://blog.example/`
+/// as the base URL.
+pub struct Site {
+    pub figment: Figment,
+    pub site: SiteConfig,
+    pub seo: SeoConfig,
+    pub metrics: MetricsConfig,
+    pub health: HealthConfig,
+    pub login: LoginConfig,
+    pub two_factor: TwoFactorConfig,
+    pub oidc: OidcConfig,
+}
+
+impl Default for Site {
+    fn default() -> Self {
+        Site {
+            figment: Figment::from(config::defaults())
+                .merge(("secret_key", "hPRYyVRiMyxpw5sBB1XeCMN1kFsDCqKvBi2QJxBVHQk=")),
+            site: SiteConfig { base_url: "https://blog.example/".to_string() },
+            seo: SeoConfig::default(),
+            metrics: MetricsConfig::default(),
+            health: HealthConfig::default(),
+            login: LoginConfig::default(),
+            two_factor: TwoFactorConfig::default(),
+            oidc: OidcConfig::default(),
+        }
+    }
+}
+
+impl Site {
+    /// The site as `main` builds it, without the background fairings. Mails
+    /// go to an outbox of their own.
+    pub fn rocket(self, db: Database) -> Result<Rocket<Build>> {
+        let outbox = Arc::new(OutboxTransport::new(format!("test_outbox_{}", Uuid::new_v4())));
+        let mailer = Mailer::new(outbox, "Blog <no-reply@example.com>", Path::new("emails"))?;
+        Ok(rocket::custom(self.figment)
+            .mount("/", routes::routes())
+            .manage(db)
+            .manage(self.metrics)
+            .manage(self.health)
+            .manage(self.site)
+            .manage(self.seo)
+            .manage(mailer)
+            .manage(EmailConfig::default())
+            .manage(self.login)
+            .manage(self.two_factor)
+            .manage(oidc::Client::new(self.oidc))
+            .manage(ReactionsConfig::default())
+            .attach(Template::fairing()))
     }
 }
+
+/// A client for the site with default settings.
+pub async fn site(db: Database) -> Result<Client> {
+    Ok(Client::tracked(Site::default().rocket(db)?).await?)
+}
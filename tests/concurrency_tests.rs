@@ -0,0 +1,86 @@
+mod common;
+
+use anyhow::Result;
+use blog::metrics::PASSWORD_HASHES_RUNNING;
+use blog::models::auth::{hash_password, max_concurrent_hashes};
+use blog::models::post::{CreatePost, PostSort};
+use blog::repositories::sqlite::create_pool;
+use blog::services::db::{connect, DbConfig};
+use blog::services::post_service;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[test]
+fn test_sqlite_connection_settings() -> Result<()> {
+    let db_name = format!("test_{}.db", Uuid::new_v4());
+    let pool = create_pool(&db_name, &DbConfig { busy_timeout_ms: 1234, ..DbConfig::default() });
+
+    // Every pooled connection gets the settings, not just the first
+    let connections = [pool.get()?, pool.get()?];
+    for conn in &connections {
+        let journal_mode: String = conn.pragma_query_value(None, "journal_mode", |row| row.get(0))?;
+        assert_eq!(journal_mode, "wal");
+        let busy_timeout: i64 = conn.pragma_query_value(None, "busy_timeout", |row| row.get(0))?;
+        assert_eq!(busy_timeout, 1234);
+        let synchronous: i64 = conn.pragma_query_value(None, "synchronous", |row| row.get(0))?;
+        assert_eq!(synchronous, 1, "NORMAL");
+    }
+
+    drop(connections);
+    drop(pool);
+    common::cleanup_test_db(&db_name);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_sqlite_writers() -> Result<()> {
+    const WRITERS: usize = 8;
+    const POSTS_EACH: usize = 25;
+
+    let db_name = format!("test_{}.db", Uuid::new_v4());
+    let db = connect(&db_name, &DbConfig::default()).await?;
+    let author = common::create_test_user(&db, "writer").await?;
+
+    // Writers on separate pooled connections wait for the lock in turn
+    // instead of failing with SQLITE_BUSY
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                for n in 0..POSTS_EACH {
+                    let post = CreatePost { title: format!("Post {} by {}", n, writer), content: "Body".to_string() };
+                    post_service::create_post(&db, post, author).await?;
+                }
+                anyhow::Ok(())
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.await??;
+    }
+    assert_eq!(post_service::get_posts(&db, PostSort::default()).await?.len(), WRITERS * POSTS_EACH);
+
+    drop(db);
+    common::cleanup_test_db(&db_name);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_password_hashing_is_bounded() -> Result<()> {
+    let limit = max_concurrent_hashes();
+    let hashes: Vec<_> = (0..limit * 3).map(|n| tokio::spawn(async move { hash_password(&n.to_string()).await })).collect();
+
+    // Sample how many hashes run at once until all of them are done
+    let mut most = 0;
+    while !hashes.iter().all(|hash| hash.is_finished()) {
+        most = most.max(PASSWORD_HASHES_RUNNING.get());
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    for hash in hashes {
+        hash.await??;
+    }
+    assert!(most as usize <= limit, "{} hashes ran at once, limit is {}", most, limit);
+    assert!(most > 0);
+    assert_eq!(PASSWORD_HASHES_RUNNING.get(), 0);
+    Ok(())
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::post::PostSort;
+use blog::services::db::Database;
+use blog::services::post_service;
+use blog::fairings;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+
+/// The token in the first form on a page.
+async fn page_token(client: &Client, path: &str) -> Result<String> {
+    let body = client.get(path).dispatch().await.into_string().await.context("page has a body")?;
+    let field = r#"name="csrf_token" value=""#;
+    let start = body.find(field).context("page has a token")? + field.len();
+    let end = start + body[start..].find('"').context("token ends")?;
+    Ok(body[start..end].to_string())
+}
+
+async fn post_form(client: &Client, path: &str, form: &str) -> (Status, String) {
+    let response = client.post(path).header(ContentType::Form).body(form).dispatch().await;
+    (response.status(), response.into_string().await.unwrap_or_default())
+}
+
+async fn post_count(db: &Database) -> Result<usize> {
+    Ok(post_service::get_posts(db, PostSort::Newest).await?.len())
+}
+
+async fn csrf_tokens(db: &Database) -> Result<()> {
+    common::create_test_user(db, "writer").await?;
+    let client = Client::tracked(common::Site::default().rocket(db.clone())?.attach(fairings::Csrf)).await?;
+
+    // Logging in needs no token, and gives the browser a new one
+    let anonymous = page_token(&client, "/login").await?;
+    let (status, _) = post_form(&client, "/login", "username=writer&password=testpass123").await;
+    assert_eq!(status, Status::SeeOther);
+    let token = page_token(&client, "/posts/new").await?;
+    assert_ne!(token, anonymous);
+    assert_eq!(page_token(&client, "/profile").await?, token);
+
+    // Signed in, forms without the right token are refused with an explanation
+    for form in ["title=One&content=Text".to_string(), format!("csrf_token={}&title=One&content=Text", anonymous)] {
+        let (status, body) = post_form(&client, "/posts", &form).await;
+        assert_eq!(status, Status::Forbidden);
+        assert!(body.contains("This form has expired"));
+    }
+    assert_eq!(post_count(db).await?, 0);
+
+    // The token works as the first field, after `_method`, or as a header
+    let (status, _) = post_form(&client, "/posts", &format!("csrf_token={}&title=One&content=Text", token)).await;
+    assert_eq!(status, Status::SeeOther);
+    let response = client
+        .post("/posts")
+        .header(ContentType::Form)
+        .header(Header::new("X-CSRF-Token", token.clone()))
+        .body("title=Two&content=Text")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    let post = post_service::get_posts(db, PostSort::Newest).await?.pop().context("posts were created")?;
+    let (status, _) = post_form(&client, &format!("/posts/{}", post.id), "_method=delete").await;
+    assert_eq!(status, Status::Forbidden);
+    let (status, _) = post_form(&client, &format!("/posts/{}", post.id), &format!("_method=delete&csrf_token={}", token)).await;
+    assert_eq!(status, Status::SeeOther);
+    assert_eq!(post_count(db).await?, 1);
+
+    // Scripts send the token as a header, e.g. when reacting to a post
+    let post = post_service::get_posts(db, PostSort::Newest).await?.pop().context("a post is left")?;
+    let reaction = format!("/posts/{}/reactions/like", post.id);
+    assert_eq!(client.put(reaction.clone()).dispatch().await.status(), Status::Forbidden);
+    let response = client.put(reaction).header(Header::new("X-CSRF-Token", token.clone())).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Bearer-token calls are not forms a browser could be tricked into sending
+    let response = client
+        .post("/posts")
+        .header(ContentType::Form)
+        .header(Header::new("Authorization", "Bearer api-token"))
+        .body("title=Three&content=Text")
+        .dispatch()
+        .await;
+    assert_ne!(response.status(), Status::Forbidden);
+
+    // Logging out needs the token too, and replaces it
+    let (status, _) = post_form(&client, "/logout", "").await;
+    assert_eq!(status, Status::Forbidden);
+    let (status, _) = post_form(&client, "/logout", &format!("csrf_token={}", token)).await;
+    assert_eq!(status, Status::SeeOther);
+    assert_ne!(page_token(&client, "/login").await?, token);
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_csrf_tokens() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        csrf_tokens(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
@@ -0,0 +1,338 @@
+mod common;
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use blog::email::smtp::Tls;
+use blog::email::{Mailer, OutboxTransport, SmtpConfig, SmtpTransport, Verification};
+use blog::models::auth::verify_password;
+use blog::models::token::{hash_token, TokenPurpose, UserToken};
+use blog::services::db::Database;
+use blog::services::email_verification_service::{self, EmailChange};
+use blog::services::{password_reset_service, user_service};
+use serde_json::json;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use uuid::Uuid;
+
+fn outbox_mailer() -> (Mailer, PathBuf) {
+    let dir = PathBuf::from(format!("test_outbox_{}", Uuid::new_v4()));
+    let mailer = Mailer::new(Arc::new(OutboxTransport::new(&dir)), "Blög <no-reply@example.com>", Path::new("emails"))
+        .expect("email templates load");
+    (mailer, dir)
+}
+
+/// Messages in the outbox, oldest first.
+fn outbox(dir: &Path) -> Result<Vec<String>> {
+    let mut paths: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries.map(|entry| entry.map(|entry| entry.path())).collect::<Result<_, _>>()?,
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+    paths.iter().map(|path| Ok(fs::read_to_string(path)?)).collect()
+}
+
+/// Decodes the body parts of a message, in order.
+fn body_parts(message: &str) -> Result<Vec<String>> {
+    let mut parts = Vec::new();
+    for section in message.split("Content-Transfer-Encoding: ").skip(1) {
+        let (encoding, rest) = section.split_once("\r\n\r\n").context("part has a body")?;
+        let body = rest.split("\r\n--").next().unwrap_or_default();
+        parts.push(match encoding.trim() {
+            "base64" => String::from_utf8(BASE64.decode(body.replace("\r\n", ""))?)?,
+            "quoted-printable" => decode_quoted_printable(body)?,
+            _ => body.to_string(),
+        });
+    }
+    Ok(parts)
+}
+
+fn decode_quoted_printable(body: &str) -> Result<String> {
+    let body = body.replace("=\r\n", "");
+    let mut bytes = Vec::with_capacity(body.len());
+    let mut input = body.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'=' {
+            let hex: Vec<u8> = input.by_ref().take(2).collect();
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex)?, 16)?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[tokio::test]
+async fn test_outbox_message() -> Result<()> {
+    let (mailer, dir) = outbox_mailer();
+    let context = json!({ "username": "<ada>", "reset_url": "https://blog.example.com/reset-password?token=abc", "expires_in_minutes": 60 });
+    mailer.send_template("Adä <ada@example.com>", "Reset your password", "password_reset", &context).await?;
+
+    let messages = outbox(&dir)?;
+    assert_eq!(messages.len(), 1);
+    let message = &messages[0];
+    assert!(message.is_ascii(), "non-ASCII display names are encoded");
+    assert!(message.contains("From: =?utf-8?b?QmzDtmc=?= <no-reply@example.com>\r\n"));
+    assert!(message.contains("To: =?utf-8?b?QWTDpA==?= <ada@example.com>\r\n"));
+    assert!(message.contains("Subject: Reset your password\r\n"));
+    assert!(message.contains("Content-Type: multipart/alternative"));
+
+    let parts = body_parts(message)?;
+    assert_eq!(parts.len(), 2);
+    assert!(parts[0].starts_with("Hi <ada>,"), "plain text is not escaped");
+    assert!(parts[0].contains("https://blog.example.com/reset-password?token=abc"));
+    assert!(parts[1].contains("Hi &lt;ada&gt;,"), "HTML is escaped");
+    assert!(parts[1].contains(r#"href="https://blog.example.com/reset-password?token=abc""#));
+
+    // Header injection is refused
+    assert!(mailer.send_template("a@example.com\r\nBcc: b@example.com", "Hi", "password_reset", &context).await.is_err());
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// Commands and message data received by the stand-in.
+type Session = Result<(Vec<String>, String)>;
+
+/// Accepts one SMTP session on a local port and returns what it received.
+fn smtp_stand_in() -> Result<(u16, thread::JoinHandle<Session>)> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = thread::spawn(move || -> Session {
+        let (stream, _) = listener.accept()?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        let (mut commands, mut data) = (Vec::new(), String::new());
+        writer.write_all(b"220 stand-in ready\r\n")?;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            let command = line.trim_end().to_string();
+            line.clear();
+            let reply: &[u8] = match command.split_whitespace().next().unwrap_or_default() {
+                "EHLO" => b"250-stand-in\r\n250-8BITMIME\r\n250 AUTH PLAIN LOGIN\r\n",
+                "AUTH" => b"235 Authenticated\r\n",
+                "MAIL" | "RCPT" => b"250 OK\r\n",
+                "DATA" => {
+                    writer.write_all(b"354 Go ahead\r\n")?;
+                    loop {
+                        let mut data_line = String::new();
+                        reader.read_line(&mut data_line)?;
+                        if data_line == ".\r\n" {
+                            break;
+                        }
+                        data.push_str(data_line.strip_prefix('.').unwrap_or(&data_line));
+                    }
+                    b"250 Queued\r\n"
+                }
+                "QUIT" => {
+                    commands.push(command);
+                    writer.write_all(b"221 Bye\r\n")?;
+                    break;
+                }
+                _ => b"502 Not implemented\r\n",
+            };
+            commands.push(command);
+            writer.write_all(reply)?;
+        }
+        Ok((commands, data))
+    });
+    Ok((port, handle))
+}
+
+#[tokio::test]
+async fn test_smtp_transport() -> Result<()> {
+    let (port, server) = smtp_stand_in()?;
+    let transport = SmtpTransport::new(SmtpConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        tls: Tls::None,
+        username: Some("blog".to_string()),
+        password: Some("secret".to_string()),
+        ..SmtpConfig::default()
+    });
+    let mailer = Mailer::new(Arc::new(transport), "Blog <no-reply@example.com>", Path::new("emails"))?;
+    let context = json!({ "username": "ada", "reset_url": "https://example.com/r", "expires_in_minutes": 60 });
+    mailer.send_template("Ada <ada@example.com>", "Reset your password", "password_reset", &context).await?;
+
+    let (commands, data) = server.join().expect("stand-in thread")?;
+    assert_eq!(commands[0], "EHLO localhost");
+    assert_eq!(commands[1], format!("AUTH PLAIN {}", BASE64.encode("\0blog\0secret")));
+    assert_eq!(commands[2..], ["MAIL FROM:<no-reply@example.com>", "RCPT TO:<ada@example.com>", "DATA", "QUIT"]);
+    assert!(data.contains("Subject: Reset your password\r\n"));
+    assert!(body_parts(&data)?[0].contains("https://example.com/r"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_smtp_requires_starttls() -> Result<()> {
+    let (port, _server) = smtp_stand_in()?;
+    let transport = SmtpTransport::new(SmtpConfig { host: "127.0.0.1".to_string(), port, ..SmtpConfig::default() });
+    let mailer = Mailer::new(Arc::new(transport), "no-reply@example.com", Path::new("emails"))?;
+    let context = json!({ "username": "ada", "reset_url": "https://example.com/r", "expires_in_minutes": 60 });
+    let error = mailer.send_template("ada@example.com", "Hi", "password_reset", &context).await.unwrap_err();
+    assert!(format!("{:#}", error).contains("STARTTLS"), "never falls back to plain text: {:#}", error);
+    Ok(())
+}
+
+/// The token from the reset link in the newest outbox message.
+fn reset_token(dir: &Path) -> Result<String> {
+    let message = outbox(dir)?.pop().context("no email sent")?;
+    let text = body_parts(&message)?.remove(0);
+    let token = text.split("token=").nth(1).context("no reset link")?;
+    Ok(token.split_whitespace().next().unwrap_or_default().to_string())
+}
+
+async fn password_reset(db: &Database) -> Result<()> {
+    let (mailer, dir) = outbox_mailer();
+    let base_url = "https://blog.example.com/";
+    let user_id = common::create_test_user(db, "forgetful").await?;
+    let other_id = common::create_test_user(db, "other").await?;
+
+    // Addresses are unique regardless of case
+    assert_eq!(user_service::set_email(db, user_id, " Forgetful@Example.com ", true).await?.as_deref(), Some("forgetful@example.com"));
+    assert!(user_service::set_email(db, other_id, "FORGETFUL@example.com", false).await.is_err());
+
+    // Unknown addresses get no email
+    assert!(!password_reset_service::request_reset(db, &mailer, base_url, "nobody@example.com").await?);
+    assert!(outbox(&dir)?.is_empty());
+
+    assert!(password_reset_service::request_reset(db, &mailer, base_url, "FORGETFUL@example.com").await?);
+    let first = reset_token(&dir)?;
+    assert!(outbox(&dir)?[0].contains("To: forgetful@example.com"));
+    fs::remove_dir_all(&dir)?;
+
+    // A newer link replaces the older one
+    password_reset_service::request_reset(db, &mailer, base_url, "forgetful@example.com").await?;
+    let token = reset_token(&dir)?;
+    assert_ne!(first, token);
+    assert!(password_reset_service::reset_password(db, &first, "newpass123").await.is_err());
+
+    assert!(password_reset_service::reset_password(db, "not-a-token", "newpass123").await.is_err());
+    password_reset_service::reset_password(db, &token, "newpass123").await?;
+    let user = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert!(verify_password("newpass123", &user.password_hash).await?);
+
+    // Links work once
+    assert!(password_reset_service::reset_password(db, &token, "again12345").await.is_err());
+
+    // Expired links are refused, and used up all the same
+    db.insert_user_token(&UserToken {
+        token_hash: hash_token("expired"),
+        user_id,
+        purpose: TokenPurpose::PasswordReset,
+        email: None,
+        expires_at: "2000-01-01T00:00:00Z".to_string(),
+        created_at: "2000-01-01T00:00:00Z".to_string(),
+    }).await?;
+    assert!(password_reset_service::reset_password(db, "expired", "newpass123").await.is_err());
+    assert!(db.take_user_token(&hash_token("expired"), TokenPurpose::PasswordReset).await?.is_none());
+
+    // Disabled accounts cannot reset their password
+    user_service::set_disabled(db, user_id, true).await?;
+    assert!(!password_reset_service::request_reset(db, &mailer, base_url, "forgetful@example.com").await?);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_password_reset() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        password_reset(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+/// The token from the newest link mailed to `to`.
+fn mailed_token(dir: &Path, to: &str) -> Result<String> {
+    let message = outbox(dir)?
+        .into_iter()
+        .rfind(|message| message.contains(&format!("To: {}\r\n", to)) && message.contains("Subject: Confirm"))
+        .with_context(|| format!("no link sent to {}", to))?;
+    let text = body_parts(&message)?.remove(0);
+    let token = text.split("token=").nth(1).context("no link")?;
+    Ok(token.split_whitespace().next().unwrap_or_default().to_string())
+}
+
+async fn email_verification(db: &Database) -> Result<()> {
+    let (mailer, dir) = outbox_mailer();
+    let base_url = "https://blog.example.com";
+    let user_id = common::create_test_user(db, "newcomer").await?;
+    user_service::set_email(db, user_id, "newcomer@example.com", false).await?;
+    let user = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert!(!user.email_verified);
+    assert!(!email_verification_service::can_comment(Verification::Required, &user));
+    assert!(email_verification_service::can_comment(Verification::Optional, &user));
+
+    email_verification_service::send_verification(db, &mailer, base_url, &user).await?;
+    let token = mailed_token(&dir, "newcomer@example.com")?;
+    assert!(body_parts(&outbox(&dir)?[0])?[0].contains("https://blog.example.com/verify-email?token="));
+    assert!(email_verification_service::confirm_email(db, "not-a-token").await.is_err());
+    assert_eq!(email_verification_service::confirm_email(db, &token).await?, "newcomer@example.com");
+    let user = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert!(user.email_verified);
+    assert!(email_verification_service::can_comment(Verification::Required, &user));
+
+    // Links work once, and verified addresses need no new one
+    assert!(email_verification_service::confirm_email(db, &token).await.is_err());
+    assert!(email_verification_service::send_verification(db, &mailer, base_url, &user).await.is_err());
+    fs::remove_dir_all(&dir)?;
+
+    // A change waits for the new address to confirm and tells the old one
+    let change = email_verification_service::change_email(db, &mailer, base_url, Verification::Optional, &user, "Moved@Example.com").await?;
+    assert_eq!(change, EmailChange::Pending("moved@example.com".to_string()));
+    let notice = outbox(&dir)?.into_iter().find(|message| message.contains("To: newcomer@example.com")).context("no notice")?;
+    assert!(body_parts(&notice)?[0].contains("moved@example.com"));
+    let token = mailed_token(&dir, "moved@example.com")?;
+    let user = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert_eq!(user.email.as_deref(), Some("newcomer@example.com"));
+    assert!(user.email_verified);
+
+    email_verification_service::confirm_email(db, &token).await?;
+    let user = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert_eq!(user.email.as_deref(), Some("moved@example.com"));
+    assert!(user.email_verified);
+    fs::remove_dir_all(&dir)?;
+
+    // An address claimed by someone else before the link is opened
+    email_verification_service::change_email(db, &mailer, base_url, Verification::Required, &user, "contested@example.com").await?;
+    let token = mailed_token(&dir, "contested@example.com")?;
+    let other_id = common::create_test_user(db, "quicker").await?;
+    user_service::set_email(db, other_id, "contested@example.com", true).await?;
+    assert!(email_verification_service::confirm_email(db, &token).await.is_err());
+    assert!(email_verification_service::change_email(db, &mailer, base_url, Verification::Required, &user, "contested@example.com").await.is_err());
+
+    // Verification can be required, or turned off
+    assert!(email_verification_service::change_email(db, &mailer, base_url, Verification::Required, &user, " ").await.is_err());
+    let change = email_verification_service::change_email(db, &mailer, base_url, Verification::Off, &user, "direct@example.com").await?;
+    assert_eq!(change, EmailChange::Applied(Some("direct@example.com".to_string())));
+    let user = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert!(!user.email_verified);
+
+    // Expired links are refused
+    db.insert_user_token(&UserToken {
+        token_hash: hash_token("expired"),
+        user_id,
+        purpose: TokenPurpose::EmailVerification,
+        email: Some("direct@example.com".to_string()),
+        expires_at: "2000-01-01T00:00:00Z".to_string(),
+        created_at: "2000-01-01T00:00:00Z".to_string(),
+    }).await?;
+    assert!(email_verification_service::confirm_email(db, "expired").await.is_err());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_email_verification() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        email_verification(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::markdown::{self, Heading};
+use blog::models::post::{CreatePost, Post};
+use blog::models::reaction::Reactions;
+use blog::services::db::Database;
+use blog::services::post_service;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+
+async fn page(client: &Client, path: &str) -> Result<String> {
+    let response = client.get(path).dispatch().await;
+    assert_eq!(response.status(), Status::Ok, "{}", path);
+    response.into_string().await.context("response has a body")
+}
+
+async fn excerpts(db: &Database) -> Result<()> {
+    let author = common::create_test_user(db, "writer").await?;
+    let content = format!(
+        "Cards drive the loom.\n\n<!--more-->\n\n## Punch cards\n\n{}\n\n### Punch cards\n\nAgain.",
+        "hole ".repeat(450)
+    );
+    let post = post_service::create_post(db, CreatePost { title: "Looms".to_string(), content }, author).await?;
+    assert_eq!(post.summary(), "Cards drive the loom.");
+    assert_eq!(post.word_count(), 459);
+    assert_eq!(post.reading_minutes(), 3);
+    let client = common::site(db.clone()).await?;
+
+    // Listings show the summary and reading time
+    let index = page(&client, "/").await?;
+    assert!(index.contains("Cards drive the loom."));
+    assert!(!index.contains("hole hole"));
+    assert!(index.contains("3 min read"));
+
+    // The post page links its headings from a table of contents
+    let post_page = page(&client, &format!("/posts/{}", post.id)).await?;
+    assert!(post_page.contains(r##"<a href="#punch-cards" "##));
+    assert!(post_page.contains(r##"<a href="#punch-cards-1" "##));
+    assert!(post_page.contains(r#"<h2 id="punch-cards">Punch cards</h2>"#));
+    assert!(post_page.contains(r#"<h3 id="punch-cards-1">Punch cards</h3>"#));
+    assert!(!post_page.contains("&lt;!--more--&gt;"));
+
+    // Authors can write their own excerpt, and clear it again
+    client.post("/login").header(ContentType::Form).body("username=writer&password=testpass123").dispatch().await;
+    let response = client
+        .post(format!("/posts/{}/excerpt", post.id))
+        .header(ContentType::Form)
+        .body("_method=put&excerpt=+All+about+looms.+")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    let updated = post_service::get_post(db, post.id).await?.context("post exists")?;
+    assert_eq!(updated.excerpt.as_deref(), Some("All about looms."));
+    assert_eq!(updated.updated_at, post.updated_at);
+    assert!(page(&client, "/").await?.contains("All about looms."));
+    assert!(page(&client, &format!("/posts/{}", post.id)).await?.contains(r#"<meta name="description" content="All about looms.">"#));
+
+    client
+        .post(format!("/posts/{}/excerpt", post.id))
+        .header(ContentType::Form)
+        .body("_method=put&excerpt=")
+        .dispatch()
+        .await;
+    assert_eq!(post_service::get_post(db, post.id).await?.context("post exists")?.excerpt, None);
+
+    // Other authors cannot set it
+    common::create_test_user(db, "rival").await?;
+    client.post("/login").header(ContentType::Form).body("username=rival&password=testpass123").dispatch().await;
+    client
+        .post(format!("/posts/{}/excerpt", post.id))
+        .header(ContentType::Form)
+        .body("_method=put&excerpt=Mine")
+        .dispatch()
+        .await;
+    assert_eq!(post_service::get_post(db, post.id).await?.context("post exists")?.excerpt, None);
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_excerpts() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        excerpts(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_markdown_text() {
+    // Without a marker the excerpt is the opening words, headings left out
+    assert_eq!(markdown::excerpt("# Title\n\nA **bold** [link](https://example.com)."), "A bold link.");
+    let long = markdown::excerpt(&"word, ".repeat(100));
+    assert!(long.ends_with("word\u{2026}"));
+    assert_eq!(long.split_whitespace().count(), 55);
+
+    assert_eq!(markdown::word_count("<p>One</p> *two* three"), 3);
+    assert_eq!(markdown::reading_minutes(0), 1);
+    assert_eq!(markdown::reading_minutes(201), 2);
+
+    let document = "Intro\n\n# Overview #\n\n```\n## Not a heading\n```\n\n    # Indented code\n\n##No space\n\n## `Code` and *more*\n\n## Overview\n\n#\n";
+    let heading = |level, text: &str, anchor: &str| Heading { level, text: text.to_string(), anchor: anchor.to_string() };
+    assert_eq!(
+        markdown::headings(document),
+        [
+            heading(1, "Overview", "overview"),
+            heading(2, "Code and more", "code-and-more"),
+            heading(2, "Overview", "overview-1"),
+            heading(1, "", "section"),
+        ]
+    );
+    let sections = markdown::sections(document);
+    assert_eq!(sections[0].body, "Intro");
+    assert!(sections[1].body.contains("## Not a heading"));
+    assert!(markdown::sections("# Only\n\nText")[0].heading.is_some());
+}
+
+#[test]
+fn test_post_serialization() -> Result<()> {
+    let post = Post {
+        id: uuid::Uuid::new_v4(),
+        title: "Looms".to_string(),
+        slug: None,
+        content: "Intro <!--more--> rest\n\n## Cards".to_string(),
+        author_id: uuid::Uuid::new_v4(),
+        author: "writer".to_string(),
+        created_at: "2024-01-01T00:00:00+00:00".to_string(),
+        updated_at: "2024-01-01T00:00:00+00:00".to_string(),
+        seo_title: None,
+        seo_description: None,
+        excerpt: None,
+        reactions: Reactions::default(),
+    };
+    let json = serde_json::to_value(&post)?;
+    assert_eq!(json["summary"], "Intro");
+    assert_eq!(json["word_count"], 3);
+    assert_eq!(json["reading_minutes"], 1);
+    assert_eq!(json["toc"][0]["anchor"], "cards");
+    assert!(json["excerpt"].is_null());
+
+    // The derived fields are not read back
+    let read: Post = serde_json::from_value(json)?;
+    assert_eq!(read.excerpt, None);
+    assert_eq!(read.content, post.content);
+    Ok(())
+}
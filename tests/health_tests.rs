@@ -0,0 +1,87 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::repositories::SCHEMA_VERSION;
+use blog::routes::health::HealthConfig;
+use blog::services::db::{connect, Database, DbConfig};
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use serde_json::Value;
+use std::fs;
+use uuid::Uuid;
+
+async fn site(db: Database, health: HealthConfig) -> Result<Client> {
+    Ok(Client::tracked(common::Site { health, ..Default::default() }.rocket(db)?).await?)
+}
+
+async fn probe(client: &Client, path: &str) -> Result<(Status, Value)> {
+    let response = client.get(path).dispatch().await;
+    let status = response.status();
+    let body = response.into_string().await.context("probes have a body")?;
+    Ok((status, serde_json::from_str(&body)?))
+}
+
+/// The named check from a `/readyz` body.
+fn check<'a>(body: &'a Value, name: &str) -> &'a Value {
+    body["checks"]
+        .as_array()
+        .and_then(|checks| checks.iter().find(|check| check["name"] == name))
+        .unwrap_or_else(|| panic!("no {} check in {}", name, body))
+}
+
+async fn health_probes(db: &Database) -> Result<()> {
+    let uploads = std::env::temp_dir().join(format!("test_uploads_{}", Uuid::new_v4()));
+    fs::create_dir(&uploads)?;
+    let client = site(db.clone(), HealthConfig { uploads_dir: Some(uploads.clone()) }).await?;
+
+    let (status, body) = probe(&client, "/healthz").await?;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["status"], "ok");
+
+    let (status, body) = probe(&client, "/readyz").await?;
+    assert_eq!(status, Status::Ok, "{}", body);
+    assert_eq!(body["status"], "ready");
+    assert_eq!(check(&body, "database")["ok"], true);
+    assert_eq!(check(&body, "schema")["detail"], format!("version {}", SCHEMA_VERSION));
+    assert_eq!(check(&body, "uploads_dir")["ok"], true);
+
+    // An uploads directory that is gone makes the instance unavailable,
+    // while it stays alive
+    fs::remove_dir(&uploads)?;
+    let (status, body) = probe(&client, "/readyz").await?;
+    assert_eq!(status, Status::ServiceUnavailable);
+    assert_eq!(body["status"], "unavailable");
+    assert_eq!(check(&body, "uploads_dir")["ok"], false);
+    assert_eq!(check(&body, "database")["ok"], true);
+    assert_eq!(probe(&client, "/healthz").await?.0, Status::Ok);
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_health_probes() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        health_probes(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_readyz_schema_mismatch() -> Result<()> {
+    let db_name = format!("test_{}.db", Uuid::new_v4());
+    let db = connect(&db_name, &DbConfig::default()).await?;
+    let client = site(db.clone(), HealthConfig::default()).await?;
+    assert_eq!(probe(&client, "/readyz").await?.0, Status::Ok);
+
+    // A database left at an older schema is not ready to serve
+    rusqlite::Connection::open(&db_name)?.pragma_update(None, "user_version", SCHEMA_VERSION - 1)?;
+    let (status, body) = probe(&client, "/readyz").await?;
+    assert_eq!(status, Status::ServiceUnavailable);
+    let detail = check(&body, "schema")["detail"].as_str().unwrap_or_default();
+    assert!(detail.contains(&format!("expected {}", SCHEMA_VERSION)), "{}", detail);
+
+    drop(client);
+    drop(db);
+    common::cleanup_test_db(&db_name);
+    Ok(())
+}
@@ -0,0 +1,267 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::importers::{ghost, html, wordpress};
+use blog::services::db::Database;
+use blog::models::post::CreatePost;
+use blog::services::{comment_service, import_service, post_service, redirect_service, tag_service, user_service};
+
+const WXR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0"
+    xmlns:content="http://purl.org/rss/1.0/modules/content/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:wp="http://wordpress.org/export/1.2/">
+<channel>
+    <wp:author><wp:author_id>7</wp:author_id><wp:author_login><![CDATA[alice]]></wp:author_login></wp:author>
+    <item>
+        <title>Hello world</title>
+        <wp:post_id>10</wp:post_id>
+        <link>https://old.example.com/2020/01/hello-world/</link>
+        <dc:creator><![CDATA[alice]]></dc:creator>
+        <content:encoded><![CDATA[First paragraph with <strong>bold</strong>.
+
+Second line
+continues here.
+
+<table><tr><td>cell</td></tr></table>]]></content:encoded>
+        <wp:post_date_gmt>2020-01-02 03:04:05</wp:post_date_gmt>
+        <wp:post_modified_gmt>2020-01-03 00:00:00</wp:post_modified_gmt>
+        <wp:post_name><![CDATA[hello-world]]></wp:post_name>
+        <wp:status><![CDATA[publish]]></wp:status>
+        <wp:post_type><![CDATA[post]]></wp:post_type>
+        <category domain="category" nicename="uncategorized"><![CDATA[Uncategorized]]></category>
+        <category domain="post_tag" nicename="rust"><![CDATA[Rust]]></category>
+        <wp:comment>
+            <wp:comment_id>1</wp:comment_id>
+            <wp:comment_author><![CDATA[Bob Visitor]]></wp:comment_author>
+            <wp:comment_date_gmt>2020-01-02 05:00:00</wp:comment_date_gmt>
+            <wp:comment_content><![CDATA[Nice post]]></wp:comment_content>
+            <wp:comment_approved><![CDATA[1]]></wp:comment_approved>
+            <wp:comment_type><![CDATA[comment]]></wp:comment_type>
+            <wp:comment_parent>0</wp:comment_parent>
+        </wp:comment>
+        <wp:comment>
+            <wp:comment_id>2</wp:comment_id>
+            <wp:comment_author><![CDATA[Alice]]></wp:comment_author>
+            <wp:comment_user_id>7</wp:comment_user_id>
+            <wp:comment_date_gmt>2020-01-02 06:00:00</wp:comment_date_gmt>
+            <wp:comment_content><![CDATA[Thanks!]]></wp:comment_content>
+            <wp:comment_approved><![CDATA[1]]></wp:comment_approved>
+            <wp:comment_type><![CDATA[comment]]></wp:comment_type>
+            <wp:comment_parent>1</wp:comment_parent>
+        </wp:comment>
+        <wp:comment>
+            <wp:comment_id>3</wp:comment_id>
+            <wp:comment_author><![CDATA[Spammer]]></wp:comment_author>
+            <wp:comment_user_id>0</wp:comment_user_id>
+            <wp:comment_date_gmt>2020-01-02 07:00:00</wp:comment_date_gmt>
+            <wp:comment_content><![CDATA[Buy now]]></wp:comment_content>
+            <wp:comment_approved><![CDATA[spam]]></wp:comment_approved>
+            <wp:comment_parent>0</wp:comment_parent>
+        </wp:comment>
+    </item>
+    <item>
+        <title>Hello again</title>
+        <wp:post_id>11</wp:post_id>
+        <dc:creator><![CDATA[alice]]></dc:creator>
+        <content:encoded><![CDATA[Same slug as an existing post.]]></content:encoded>
+        <wp:post_date_gmt>2020-02-01 00:00:00</wp:post_date_gmt>
+        <wp:post_name><![CDATA[greeting]]></wp:post_name>
+        <wp:status><![CDATA[publish]]></wp:status>
+        <wp:post_type><![CDATA[post]]></wp:post_type>
+        <wp:comment>
+            <wp:comment_id>4</wp:comment_id>
+            <wp:comment_author><![CDATA[Bob Visitor]]></wp:comment_author>
+            <wp:comment_date_gmt>2020-02-01 01:00:00</wp:comment_date_gmt>
+            <wp:comment_content><![CDATA[Again!]]></wp:comment_content>
+            <wp:comment_approved><![CDATA[1]]></wp:comment_approved>
+            <wp:comment_parent>0</wp:comment_parent>
+        </wp:comment>
+    </item>
+    <item>
+        <title>Draft</title>
+        <dc:creator><![CDATA[alice]]></dc:creator>
+        <wp:status><![CDATA[draft]]></wp:status>
+        <wp:post_type><![CDATA[post]]></wp:post_type>
+    </item>
+    <item>
+        <title>About</title>
+        <dc:creator><![CDATA[alice]]></dc:creator>
+        <wp:status><![CDATA[publish]]></wp:status>
+        <wp:post_type><![CDATA[page]]></wp:post_type>
+    </item>
+</channel>
+</rss>"#;
+
+const GHOST: &str = r##"{
+    "db": [{
+        "meta": { "version": "5.0.0" },
+        "data": {
+            "posts": [
+                {
+                    "id": "p1", "title": "Ghost post", "slug": "ghost-post", "type": "post",
+                    "status": "published", "html": "<h2>Intro</h2><p>Some <em>text</em> and <a href=\"https://example.com\">a link</a>.</p>",
+                    "created_at": "2021-05-01T10:00:00.000Z", "published_at": "2021-05-02T10:00:00.000Z",
+                    "updated_at": "2021-05-03T10:00:00.000Z"
+                },
+                {
+                    "id": "p2", "title": "Unfinished", "slug": "unfinished", "type": "post",
+                    "status": "draft", "html": "<p>Draft</p>", "created_at": "2021-05-01T10:00:00.000Z"
+                }
+            ],
+            "users": [{ "id": "u1", "slug": "carol" }, { "id": "u2", "slug": "dave" }],
+            "posts_authors": [
+                { "post_id": "p1", "author_id": "u2", "sort_order": 1 },
+                { "post_id": "p1", "author_id": "u1", "sort_order": 0 }
+            ],
+            "tags": [
+                { "id": "t1", "name": "Databases" },
+                { "id": "t2", "name": "#hidden", "visibility": "internal" }
+            ],
+            "posts_tags": [
+                { "post_id": "p1", "tag_id": "t1" },
+                { "post_id": "p1", "tag_id": "t2" }
+            ]
+        }
+    }]
+}"##;
+
+#[test]
+fn test_html_to_markdown() {
+    let converted = html::to_markdown(
+        "<h1>Title</h1><p>A <strong>bold</strong> <em>claim</em> with <code>code</code>.</p>\
+         <ul><li>one</li><li>two</li></ul><blockquote><p>Quoted</p></blockquote>\
+         <pre><code>fn main() {}\n</code></pre><p><img src=\"/a.png\" alt=\"pic\"></p>",
+    );
+    assert!(converted.lossless);
+    assert_eq!(
+        converted.markdown,
+        "# Title\n\nA **bold** *claim* with `code`.\n\n- one\n- two\n\n> Quoted\n\n```\nfn main() {}\n```\n\n![pic](/a.png)"
+    );
+
+    let converted = html::to_markdown("<p>Before</p><iframe src=\"https://video\"></iframe><script>x()</script>");
+    assert!(!converted.lossless);
+    assert_eq!(converted.markdown, "Before\n\n<iframe src=\"https://video\"></iframe>");
+}
+
+#[test]
+fn test_parse_wordpress() -> Result<()> {
+    let site = wordpress::parse(WXR)?;
+    assert_eq!(site.posts.len(), 2);
+    assert_eq!(site.skipped.len(), 2);
+
+    let post = &site.posts[0];
+    assert_eq!(post.key, "10");
+    assert_eq!(post.slug.as_deref(), Some("hello-world"));
+    assert_eq!(post.tags, vec!["Rust"]);
+    assert_eq!(post.created_at, "2020-01-02T03:04:05+00:00");
+    assert!(post.markdown.starts_with("First paragraph with **bold**.\n\nSecond line\\\ncontinues here."));
+    assert!(!post.lossless, "tables are kept as HTML");
+
+    // The spam comment is dropped; the reply keeps its parent
+    assert_eq!(post.comments.len(), 2);
+    assert_eq!(post.comments[1].parent_key.as_deref(), Some("1"));
+    assert_eq!(post.comments[0].author_key, None, "visitors have no account");
+    assert_eq!(post.comments[1].author_key.as_deref(), Some("alice"));
+    Ok(())
+}
+
+async fn import_wordpress(db: &Database) -> Result<()> {
+    // An existing account with the same username is reused
+    let alice = common::create_test_user(db, "alice").await?;
+    // A local post already has the slug of the second imported post
+    let local = post_service::create_post(
+        db,
+        CreatePost { title: "Greeting".to_string(), content: "Local".to_string() },
+        alice,
+    ).await?;
+    post_service::set_post_metadata(db, local.id, Some("greeting"), &local.created_at, &local.updated_at).await?;
+
+    let dry_run = import_service::import_site(db, wordpress::parse(WXR)?, true).await?;
+    assert_eq!(dry_run.posts_created, vec!["Hello world", "Hello again"]);
+    assert_eq!(dry_run.comments_created, 3);
+    assert!(db.find_post_by_slug("hello-world").await?.is_none(), "dry run writes nothing");
+    assert!(user_service::get_user_by_username(db, import_service::GUEST_ACCOUNT).await?.is_none());
+
+    let summary = import_service::import_site(db, wordpress::parse(WXR)?, false).await?;
+    assert_eq!(summary.users_matched, vec!["alice"]);
+    assert_eq!(summary.users_created, vec![import_service::GUEST_ACCOUNT], "one account for all visitors");
+    assert_eq!(summary.posts_lossy, vec!["Hello world"]);
+    assert_eq!(summary.skipped.len(), 2);
+    assert!(user_service::get_user_by_username(db, "Bob Visitor").await?.is_none());
+
+    // A slug taken by a post the import did not create gets a new one
+    assert_eq!(summary.slugs_changed.len(), 1);
+    assert_eq!((summary.slugs_changed[0].from.as_str(), summary.slugs_changed[0].to.as_str()), ("greeting", "greeting-2"));
+    let again_post = db.find_post_by_slug("greeting-2").await?.expect("post imported under a new slug");
+    assert_eq!(again_post.title, "Hello again");
+    assert_eq!(db.find_post_by_slug("greeting").await?.map(|post| post.id), Some(local.id));
+
+    let post = db.find_post_by_slug("hello-world").await?.expect("post imported");
+    assert_eq!(post.author_id, alice);
+    assert_eq!(post.created_at, "2020-01-02T03:04:05+00:00");
+    let tags = tag_service::get_post_tags(db, post.id).await?;
+    assert_eq!(tags.iter().map(|tag| tag.name.as_str()).collect::<Vec<_>>(), vec!["Rust"]);
+
+    let comments = comment_service::get_post_comments(db, post.id).await?;
+    assert_eq!(comments.len(), 2);
+    let question = comments.iter().find(|comment| comment.content == "Nice post").expect("comment");
+    let reply = comments.iter().find(|comment| comment.content == "Thanks!").expect("reply");
+    assert_eq!(reply.parent_id, Some(question.id));
+    assert_eq!(reply.author_id, alice);
+    assert_eq!(reply.guest_name, None);
+    assert_eq!(question.guest_name.as_deref(), Some("Bob Visitor"));
+    let guests = user_service::get_user_by_username(db, import_service::GUEST_ACCOUNT).await?.expect("guest account");
+    assert_eq!(question.author_id, guests.id);
+    let again_comments = comment_service::get_post_comments(db, again_post.id).await?;
+    assert_eq!(again_comments[0].author_id, guests.id);
+
+    let target = format!("/posts/{}", post.id);
+    assert_eq!(summary.redirects.len(), 1, "only posts with a link get a redirect");
+    assert_eq!(summary.redirects[0].from, "/2020/01/hello-world");
+    assert_eq!(redirect_service::resolve(db, "/2020/01/hello-world/").await?, Some(target));
+
+    // Importing the same file again creates nothing
+    let again = import_service::import_site(db, wordpress::parse(WXR)?, false).await?;
+    assert!(again.posts_created.is_empty());
+    assert_eq!(again.posts_existing, vec!["Hello world", "Hello again"]);
+    assert!(again.users_created.is_empty());
+    assert!(again.slugs_changed.is_empty());
+    assert!(again.redirects.is_empty());
+    assert!(db.find_post_by_slug("greeting-3").await?.is_none());
+    assert_eq!(comment_service::get_post_comments(db, post.id).await?.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_import_wordpress() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        import_wordpress(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_import_ghost() -> Result<()> {
+    let test_db = common::setup_test_db().await?;
+    let db = &test_db.db;
+
+    let site = ghost::parse(GHOST)?;
+    assert_eq!(site.skipped.len(), 1);
+    let summary = import_service::import_site(db, site, false).await?;
+    assert_eq!(summary.users_created, vec!["carol"], "the first listed author owns the post");
+
+    let post = db.find_post_by_slug("ghost-post").await?.expect("post imported");
+    assert_eq!(post.content, "## Intro\n\nSome *text* and [a link](https://example.com).");
+    assert_eq!(post.created_at, "2021-05-02T10:00:00+00:00");
+    let tags = tag_service::get_post_tags(db, post.id).await?;
+    assert_eq!(tags.iter().map(|tag| tag.name.as_str()).collect::<Vec<_>>(), vec!["Databases"]);
+    assert_eq!(
+        redirect_service::resolve(db, "/ghost-post/").await?,
+        Some(format!("/posts/{}", post.id))
+    );
+
+    test_db.cleanup().await;
+    Ok(())
+}
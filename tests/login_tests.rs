@@ -0,0 +1,303 @@
+mod common;
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use blog::email::{Mailer, OutboxTransport};
+use blog::models::login_attempt::{LoginAttempt, LoginClient, LoginOutcome};
+use blog::models::token::timestamp;
+use blog::services::db::Database;
+use blog::services::login_service::{self, LoginConfig, LoginResult};
+use blog::services::{sso_service, two_factor_service, user_service};
+use chrono::{Duration, Utc};
+use rocket::http::{ContentType, Header};
+use rocket::local::asynchronous::Client;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+use uuid::Uuid;
+
+const PASSWORD: &str = "testpass123";
+
+fn outbox_mailer() -> (Mailer, PathBuf) {
+    let dir = PathBuf::from(format!("test_outbox_{}", Uuid::new_v4()));
+    let mailer = Mailer::new(Arc::new(OutboxTransport::new(&dir)), "Blog <no-reply@example.com>", Path::new("emails"))
+        .expect("email templates load");
+    (mailer, dir)
+}
+
+/// Waits for the messages sent in the background to reach the outbox.
+async fn outbox(dir: &Path, expected: usize) -> Vec<String> {
+    for _ in 0..50 {
+        let messages: Vec<String> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|entry| fs::read_to_string(entry.ok()?.path()).ok()).collect(),
+            Err(_) => Vec::new(),
+        };
+        if messages.len() >= expected {
+            return messages;
+        }
+        tokio::time::sleep(StdDuration::from_millis(50)).await;
+    }
+    Vec::new()
+}
+
+/// The decoded text part of a message.
+fn text_part(message: &str) -> String {
+    let section = message.split("Content-Transfer-Encoding: ").nth(1).unwrap_or_default();
+    let (encoding, rest) = section.split_once("\r\n\r\n").unwrap_or_default();
+    let body = rest.split("\r\n--").next().unwrap_or_default();
+    match encoding.trim() {
+        "base64" => String::from_utf8(BASE64.decode(body.replace("\r\n", "")).unwrap_or_default()).unwrap_or_default(),
+        // Soft line breaks only; the notices are ASCII
+        "quoted-printable" => body.replace("=\r\n", ""),
+        _ => body.to_string(),
+    }
+}
+
+fn client(ip: &str) -> LoginClient {
+    LoginClient { ip: Some(ip.parse().unwrap()), user_agent: Some("TestBrowser/1.0".to_string()) }
+}
+
+/// Limits with no waits between attempts, so only lockouts get in the way.
+fn no_delays() -> LoginConfig {
+    LoginConfig { max_delay_secs: 0, ..LoginConfig::default() }
+}
+
+async fn attempt(
+    db: &Database,
+    mailer: &Mailer,
+    config: &LoginConfig,
+    client: &LoginClient,
+    username: &str,
+    password: &str,
+) -> Result<LoginResult> {
+    login_service::authenticate(db, mailer, config, client, username, password).await
+}
+
+async fn lockout(db: &Database) -> Result<()> {
+    let (mailer, dir) = outbox_mailer();
+    let config = LoginConfig { lockout_attempts: 3, lockout_minutes: 15, ..no_delays() };
+    let from = client("198.51.100.7");
+    let user_id = common::create_test_user(db, "target").await?;
+    user_service::set_email(db, user_id, "target@example.com", true).await?;
+
+    assert!(matches!(attempt(db, &mailer, &config, &from, "target", PASSWORD).await?, LoginResult::Success(_)));
+
+    // A success clears earlier failures
+    for _ in 0..2 {
+        assert!(matches!(attempt(db, &mailer, &config, &from, "target", "wrong").await?, LoginResult::Failed));
+    }
+    assert!(matches!(attempt(db, &mailer, &config, &from, "target", PASSWORD).await?, LoginResult::Success(_)));
+    for _ in 0..3 {
+        assert!(matches!(attempt(db, &mailer, &config, &from, "target", "wrong").await?, LoginResult::Failed));
+    }
+
+    // Now even the right password is refused, from anywhere
+    match attempt(db, &mailer, &config, &client("203.0.113.9"), "target", PASSWORD).await? {
+        LoginResult::Locked { retry_after } => {
+            assert!(retry_after > Duration::minutes(14) && retry_after <= Duration::minutes(15) + Duration::seconds(1))
+        }
+        other => panic!("expected a lockout, got {:?}", other),
+    }
+
+    // The owner hears about it once
+    let messages = outbox(&dir, 1).await;
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].contains("To: target@example.com"));
+    assert!(messages[0].contains("Subject: Your account was locked"));
+    let text = text_part(&messages[0]);
+    assert!(text.contains("3 failed attempts"), "{}", text);
+    assert!(text.contains("198.51.100.7"));
+
+    // Unknown usernames lock the same way, without anyone to tell
+    for _ in 0..3 {
+        assert!(matches!(attempt(db, &mailer, &config, &from, "nobody", "wrong").await?, LoginResult::Failed));
+    }
+    assert!(matches!(attempt(db, &mailer, &config, &from, "nobody", "wrong").await?, LoginResult::Locked { .. }));
+
+    // An operator can lift the lock
+    assert!(login_service::unlock(db, user_id).await?);
+    assert!(!login_service::unlock(db, user_id).await?);
+    assert!(matches!(attempt(db, &mailer, &config, &from, "target", PASSWORD).await?, LoginResult::Success(_)));
+    assert_eq!(outbox(&dir, 1).await.len(), 1);
+    fs::remove_dir_all(&dir)?;
+
+    // The owner sees each attempt, newest first
+    let history = login_service::history(db, user_id).await?;
+    let outcomes: Vec<LoginOutcome> = history.iter().map(|attempt| attempt.outcome).collect();
+    assert_eq!(outcomes.iter().filter(|outcome| **outcome == LoginOutcome::WrongPassword).count(), 5);
+    assert_eq!(outcomes.iter().filter(|outcome| **outcome == LoginOutcome::Locked).count(), 1);
+    assert_eq!(outcomes.iter().filter(|outcome| **outcome == LoginOutcome::Success).count(), 0);
+    assert!(history.windows(2).all(|pair| pair[0].created_at >= pair[1].created_at));
+    let locked = history.iter().find(|attempt| attempt.outcome == LoginOutcome::Locked).unwrap();
+    assert_eq!(locked.ip.as_deref(), Some("203.0.113.9"));
+    assert_eq!(locked.user_agent.as_deref(), Some("TestBrowser/1.0"));
+    Ok(())
+}
+
+async fn backoff(db: &Database) -> Result<()> {
+    let (mailer, _) = outbox_mailer();
+    let config = LoginConfig { free_attempts: 1, ..LoginConfig::default() };
+    let from = client("198.51.100.8");
+    common::create_test_user(db, "hasty").await?;
+
+    // Each failure past the free one doubles the wait before the next attempt;
+    // keep failing until an attempt lands within a wait, however slow bcrypt is
+    let mut wait = None;
+    for failures in 0..6 {
+        match attempt(db, &mailer, &config, &from, "hasty", "wrong").await? {
+            LoginResult::Failed => {}
+            LoginResult::Locked { retry_after } => {
+                assert!(failures >= 2);
+                assert!(retry_after <= Duration::seconds(1 << (failures - 2)) + Duration::seconds(1));
+                wait = Some(retry_after);
+                break;
+            }
+            other => panic!("expected a failure or a wait, got {:?}", other),
+        }
+    }
+    let wait = wait.expect("an attempt had to wait");
+    tokio::time::sleep((wait + Duration::milliseconds(500)).to_std()?).await;
+    assert!(matches!(attempt(db, &mailer, &config, &from, "hasty", PASSWORD).await?, LoginResult::Success(_)));
+    Ok(())
+}
+
+async fn address_limits(db: &Database) -> Result<()> {
+    let (mailer, _) = outbox_mailer();
+    let config = LoginConfig { address_lockout_attempts: 3, ..no_delays() };
+    common::create_test_user(db, "shared").await?;
+
+    // Addresses in one IPv6 /64 count together, across usernames
+    for (ip, username) in [("2001:db8::1", "a"), ("2001:db8::2", "b"), ("2001:db8::3", "c")] {
+        assert!(matches!(attempt(db, &mailer, &config, &client(ip), username, "wrong").await?, LoginResult::Failed));
+    }
+    let result = attempt(db, &mailer, &config, &client("2001:db8::4"), "shared", PASSWORD).await?;
+    assert!(matches!(result, LoginResult::Locked { .. }));
+    let result = attempt(db, &mailer, &config, &client("2001:db8:1::1"), "shared", PASSWORD).await?;
+    assert!(matches!(result, LoginResult::Success(_)));
+    Ok(())
+}
+
+/// Sends the login form from `from`, claiming to be `spoofed` in `X-Real-IP`.
+async fn login_as(client: &Client, from: &str, spoofed: &str, username: &str, password: &str) -> Result<Option<String>> {
+    let response = client
+        .post("/login")
+        .remote(from.parse()?)
+        .header(Header::new("X-Real-IP", spoofed.to_string()))
+        .header(ContentType::Form)
+        .body(format!("username={}&password={}", username, password))
+        .dispatch()
+        .await;
+    Ok(response.headers().get_one("Location").map(str::to_string))
+}
+
+async fn spoofed_addresses(db: &Database) -> Result<()> {
+    common::create_test_user(db, "shared").await?;
+    let config = LoginConfig { address_lockout_attempts: 3, ..no_delays() };
+    let client = Client::tracked(common::Site { login: config, ..Default::default() }.rocket(db.clone())?).await?;
+
+    // Failures count against the connection's address, whatever the header says
+    for (spoofed, username) in [("203.0.113.1", "a"), ("203.0.113.2", "b"), ("203.0.113.3", "c")] {
+        login_as(&client, "198.51.100.7:4000", spoofed, username, "wrong").await?;
+    }
+    let location = login_as(&client, "198.51.100.7:4000", "203.0.113.4", "shared", PASSWORD).await?;
+    assert_eq!(location.as_deref(), Some("/login"));
+
+    // Nor can another client take on the locked address
+    let location = login_as(&client, "198.51.100.8:4000", "198.51.100.7", "shared", PASSWORD).await?;
+    assert_eq!(location.as_deref(), Some("/"));
+    Ok(())
+}
+
+async fn other_refusals(db: &Database) -> Result<()> {
+    let (mailer, _) = outbox_mailer();
+    let config = no_delays();
+    let from = LoginClient::default();
+
+    // Accounts that only use single sign-on have no password to guess
+    let sso_id = common::create_test_user(db, "sso-only").await?;
+    db.update_password_hash(sso_id, sso_service::NO_PASSWORD, &timestamp(Utc::now())).await?;
+    let result = attempt(db, &mailer, &config, &from, "sso-only", sso_service::NO_PASSWORD).await?;
+    assert!(matches!(result, LoginResult::Failed));
+
+    // Disabled accounts are only told so with the right password
+    let disabled_id = common::create_test_user(db, "gone").await?;
+    user_service::set_disabled(db, disabled_id, true).await?;
+    assert!(matches!(attempt(db, &mailer, &config, &from, "gone", "wrong").await?, LoginResult::Failed));
+    assert!(matches!(attempt(db, &mailer, &config, &from, "gone", PASSWORD).await?, LoginResult::Disabled));
+    let history = login_service::history(db, disabled_id).await?;
+    assert_eq!(history.len(), 2);
+    assert!(history.iter().all(|attempt| attempt.ip.is_none()));
+
+    // Old history goes when the owner next logs in
+    let old = timestamp(Utc::now() - Duration::days(config.history_days + 1));
+    db.insert_login_attempt(&LoginAttempt {
+        id: Uuid::new_v4(),
+        user_id: sso_id,
+        ip: None,
+        user_agent: None,
+        outcome: LoginOutcome::WrongCode,
+        created_at: old,
+    }).await?;
+    assert_eq!(login_service::history(db, sso_id).await?.len(), 2);
+    login_service::record_attempt(db, &config, sso_id, &from, LoginOutcome::Success).await?;
+    let history = login_service::history(db, sso_id).await?;
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].outcome, LoginOutcome::Success);
+    assert!(history.iter().all(|attempt| attempt.outcome != LoginOutcome::WrongCode));
+
+    // Unlocking also lifts a two-factor code lock
+    let now = timestamp(Utc::now());
+    two_factor_service::begin_enrolment(db, &Default::default(), &user_service::get_user_by_id(db, sso_id).await?.unwrap())
+        .await?;
+    db.set_two_factor_lock(sso_id, Some(&timestamp(Utc::now() + Duration::minutes(5))), &now).await?;
+    assert!(login_service::unlock(db, sso_id).await?);
+    assert_eq!(db.find_two_factor(sso_id).await?.expect("two-factor exists").locked_until, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_login_lockout() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        lockout(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_login_backoff() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        backoff(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_login_address_limits() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        address_limits(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_login_other_refusals() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        other_refusals(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_login_spoofed_addresses() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        spoofed_addresses(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
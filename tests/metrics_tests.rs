@@ -0,0 +1,68 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::metrics::MetricsConfig;
+use blog::services::db::Database;
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use std::net::SocketAddr;
+
+async fn site(db: Database, metrics: MetricsConfig) -> Result<Client> {
+    Ok(Client::tracked(common::Site { metrics, ..Default::default() }.rocket(db)?).await?)
+}
+
+async fn scrape(client: &Client, from: &str, authorization: Option<&str>) -> Status {
+    let mut request = client.get("/metrics").remote(from.parse::<SocketAddr>().unwrap());
+    if let Some(value) = authorization {
+        request = request.header(Header::new("Authorization", value.to_string()));
+    }
+    request.dispatch().await.status()
+}
+
+async fn metrics_access(db: &Database) -> Result<()> {
+    common::create_test_user(db, "writer").await?;
+
+    // Open by default, with the site's counts
+    let client = site(db.clone(), MetricsConfig::default()).await?;
+    let body = client.get("/metrics").dispatch().await.into_string().await.context("metrics have a body")?;
+    assert!(body.contains("blog_users 1"));
+
+    // A token must be sent as a bearer token, exactly
+    let client = site(db.clone(), MetricsConfig { token: Some("s3cret".to_string()), allowed_ips: vec![] }).await?;
+    assert_eq!(scrape(&client, "192.0.2.1:4000", None).await, Status::Unauthorized);
+    assert_eq!(scrape(&client, "192.0.2.1:4000", Some("Bearer s3cre")).await, Status::Unauthorized);
+    assert_eq!(scrape(&client, "192.0.2.1:4000", Some("Bearer s3cret2")).await, Status::Unauthorized);
+    assert_eq!(scrape(&client, "192.0.2.1:4000", Some("s3cret")).await, Status::Unauthorized);
+    assert_eq!(scrape(&client, "192.0.2.1:4000", Some("Bearer s3cret")).await, Status::Ok);
+
+    // Other addresses are refused before the token is checked
+    let client = site(db.clone(), MetricsConfig {
+        token: Some("s3cret".to_string()),
+        allowed_ips: vec!["127.0.0.1".parse()?],
+    })
+    .await?;
+    assert_eq!(scrape(&client, "192.0.2.1:4000", Some("Bearer s3cret")).await, Status::Forbidden);
+    assert_eq!(scrape(&client, "127.0.0.1:4000", None).await, Status::Unauthorized);
+    assert_eq!(scrape(&client, "127.0.0.1:4000", Some("Bearer s3cret")).await, Status::Ok);
+    assert_eq!(client.get("/metrics").dispatch().await.status(), Status::Forbidden);
+
+    // The address comes from the connection, not from a header the client sets
+    let response = client
+        .get("/metrics")
+        .remote("192.0.2.1:4000".parse()?)
+        .header(Header::new("X-Real-IP", "127.0.0.1"))
+        .header(Header::new("Authorization", "Bearer s3cret"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_metrics_access() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        metrics_access(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
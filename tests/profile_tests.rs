@@ -0,0 +1,81 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::comment::CreateComment;
+use blog::models::post::CreatePost;
+use blog::models::profile::UpdateProfile;
+use blog::services::db::Database;
+use blog::services::{comment_service, post_service, profile_service};
+use validator::Validate;
+
+fn form() -> UpdateProfile {
+    UpdateProfile {
+        display_name: "Ada Lovelace".to_string(),
+        bio: "Writes about engines.\nAnd poetry.".to_string(),
+        avatar_url: "https://example.com/ada.png".to_string(),
+        website: String::new(),
+        social_links: "https://social.example/@ada\n\n  https://code.example/ada  \n".to_string(),
+    }
+}
+
+#[test]
+fn test_profile_validation() {
+    assert!(form().validate().is_ok());
+    assert!(UpdateProfile { website: "javascript:alert(1)".to_string(), ..form() }.validate().is_err());
+    assert!(UpdateProfile { social_links: "https://ok.example\nftp://no.example".to_string(), ..form() }.validate().is_err());
+    assert!(UpdateProfile { social_links: "https://example.com\n".repeat(11), ..form() }.validate().is_err());
+    assert!(UpdateProfile { display_name: "x".repeat(101), ..form() }.validate().is_err());
+}
+
+async fn author_profile(db: &Database) -> Result<()> {
+    let ada = common::create_test_user(db, "ada").await?;
+    let reader = common::create_test_user(db, "reader").await?;
+
+    // Users without a profile get an empty one and their username as name
+    let profile = profile_service::get_profile(db, ada).await?;
+    assert_eq!(profile.user_id, ada);
+    assert!(profile.display_name.is_none() && profile.social_links.is_empty());
+    let page = profile_service::get_author_page(db, "ada").await?.expect("author exists");
+    assert_eq!(page.name, "ada");
+
+    let saved = profile_service::update_profile(db, ada, form()).await?;
+    assert_eq!(saved.website, None, "blank fields are cleared");
+    let profile = profile_service::get_profile(db, ada).await?;
+    assert_eq!(profile.display_name.as_deref(), Some("Ada Lovelace"));
+    assert_eq!(profile.social_links, vec!["https://social.example/@ada", "https://code.example/ada"]);
+
+    // Saving again replaces the previous profile
+    profile_service::update_profile(db, ada, UpdateProfile { display_name: String::new(), ..form() }).await?;
+    assert!(profile_service::get_profile(db, ada).await?.display_name.is_none());
+
+    let first = post_service::create_post(db, CreatePost { title: "Engines".to_string(), content: "Body".to_string() }, ada).await?;
+    post_service::create_post(db, CreatePost { title: "Not mine".to_string(), content: "Body".to_string() }, reader).await?;
+    comment_service::create_comment(db, CreateComment { content: "Thanks for reading".to_string() }, first.id, ada).await?;
+    comment_service::create_comment(db, CreateComment { content: "Great".to_string() }, first.id, reader).await?;
+    let second = post_service::create_post(db, CreatePost { title: "Looms".to_string(), content: "Body".to_string() }, reader).await?;
+    comment_service::create_comment(db, CreateComment { content: "Nice loom".to_string() }, second.id, ada).await?;
+
+    let page = profile_service::get_author_page(db, "ada").await?.expect("author exists");
+    assert_eq!(page.username, "ada");
+    assert_eq!(page.profile.avatar_url.as_deref(), Some("https://example.com/ada.png"));
+    assert_eq!(page.posts.iter().map(|post| post.title.as_str()).collect::<Vec<_>>(), vec!["Engines"]);
+    let mut comments: Vec<(&str, &str)> = page
+        .comments
+        .iter()
+        .map(|entry| (entry.comment.content.as_str(), entry.post_title.as_str()))
+        .collect();
+    comments.sort();
+    assert_eq!(comments, vec![("Nice loom", "Looms"), ("Thanks for reading", "Engines")]);
+
+    assert!(profile_service::get_author_page(db, "nobody").await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_author_profile() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        author_profile(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
@@ -0,0 +1,212 @@
+use anyhow::Result;
+use blog::config;
+use blog::fairings::RateLimit;
+use blog::rate_limit::{
+    Quota, RateLimitConfig, RateLimiter, RateLimitStore, RouteGroup, SqliteStore, StoreKind,
+};
+use rocket::figment::providers::{Format, Toml};
+use rocket::figment::Figment;
+use rocket::http::{Header, Method, Status};
+use rocket::local::asynchronous::Client;
+use rocket::{get, post, routes};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use uuid::Uuid;
+
+const NOW: f64 = 1_700_000_000.0;
+
+fn ip(address: &str) -> Option<IpAddr> {
+    Some(address.parse().unwrap())
+}
+
+/// A group's name, routes, and buckets per address and per user.
+type Group<'a> = (&'a str, &'a [&'a str], Option<Quota>, Option<Quota>);
+
+fn config(groups: &[Group]) -> RateLimitConfig {
+    let groups = groups
+        .iter()
+        .map(|(name, routes, per_ip, per_user)| {
+            let routes = routes.iter().map(|route| route.to_string()).collect();
+            (name.to_string(), RouteGroup { routes, per_ip: *per_ip, per_user: *per_user })
+        })
+        .collect::<BTreeMap<_, _>>();
+    RateLimitConfig { groups, ..RateLimitConfig::default() }
+}
+
+#[tokio::test]
+async fn test_token_buckets() -> Result<()> {
+    let quota = Quota { burst: 3, per_minute: 6 };
+    let limiter = RateLimiter::new(&config(&[("comments", &["POST /posts/*/comments"], Some(quota), None)]))?;
+    let post = |at| limiter.check_at(Method::Post, "/posts/1/comments", ip("198.51.100.1"), None, at);
+
+    // The burst passes, then the bucket refills at one token every ten seconds
+    for remaining in [2, 1, 0] {
+        let decision = post(NOW).await?.expect("group applies");
+        assert!(decision.allowed);
+        assert_eq!((decision.limit, decision.remaining), (3, remaining));
+    }
+    let refused = post(NOW + 1.0).await?.expect("group applies");
+    assert!(!refused.allowed);
+    assert_eq!(refused.group, "comments");
+    assert_eq!(refused.retry_after_secs, 9);
+    assert_eq!(refused.reset_secs, 29);
+    assert!(post(NOW + 10.0).await?.unwrap().allowed);
+    assert!(!post(NOW + 10.0).await?.unwrap().allowed);
+
+    // Other addresses, other methods and other paths are not affected
+    let other = limiter.check_at(Method::Post, "/posts/1/comments", ip("198.51.100.2"), None, NOW + 10.0).await?;
+    assert!(other.unwrap().allowed);
+    assert!(limiter.check_at(Method::Get, "/posts/1/comments", ip("198.51.100.1"), None, NOW).await?.is_none());
+    assert!(limiter.check_at(Method::Post, "/posts/1/comments/2", ip("198.51.100.1"), None, NOW).await?.is_none());
+
+    // A long wait only fills the bucket to its burst
+    for _ in 0..3 {
+        assert!(post(NOW + 3600.0).await?.unwrap().allowed);
+    }
+    assert!(!post(NOW + 3600.0).await?.unwrap().allowed);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_users_groups_and_exemptions() -> Result<()> {
+    let per_ip = Quota { burst: 4, per_minute: 1 };
+    let per_user = Quota { burst: 2, per_minute: 1 };
+    let mut limits = config(&[
+        ("site", &["/**"], Some(Quota { burst: 100, per_minute: 60 }), None),
+        ("comments", &["POST /posts/*/comments"], Some(per_ip), Some(per_user)),
+    ]);
+    limits.exempt_ips = vec!["192.0.2.1".parse()?];
+    let limiter = RateLimiter::new(&limits)?;
+    let user = Some(Uuid::new_v4());
+
+    // A signed-in user is held to their own bucket wherever they post from,
+    // and the tightest bucket is the one reported
+    let decision = limiter.check_at(Method::Post, "/posts/1/comments", ip("198.51.100.1"), user, NOW).await?.unwrap();
+    assert_eq!((decision.group.as_str(), decision.remaining), ("comments", 1));
+    limiter.check_at(Method::Post, "/posts/2/comments", ip("203.0.113.1"), user, NOW).await?;
+    let refused = limiter.check_at(Method::Post, "/posts/3/comments", ip("203.0.113.2"), user, NOW).await?.unwrap();
+    assert!(!refused.allowed);
+    assert_eq!(refused.retry_after_secs, 60);
+
+    // Everyone else shares the address's bucket; IPv6 counts by /64
+    for host in ["2001:db8::1", "2001:db8::2", "2001:db8::3", "2001:db8::4"] {
+        assert!(limiter.check_at(Method::Post, "/posts/1/comments", ip(host), None, NOW).await?.unwrap().allowed);
+    }
+    let refused = limiter.check_at(Method::Post, "/posts/1/comments", ip("2001:db8::5"), None, NOW).await?.unwrap();
+    assert!(!refused.allowed);
+
+    // Pages only count against the site-wide group
+    let page = limiter.check_at(Method::Get, "/", ip("2001:db8::5"), None, NOW).await?.unwrap();
+    assert_eq!((page.group.as_str(), page.allowed), ("site", true));
+
+    // Exempt addresses and requests without an address are not limited
+    for _ in 0..10 {
+        let exempt = limiter.check_at(Method::Post, "/posts/1/comments", ip("192.0.2.1"), None, NOW).await?;
+        assert!(exempt.is_none());
+    }
+    let anonymous = limiter.check_at(Method::Post, "/posts/1/comments", None, None, NOW).await?;
+    assert!(anonymous.is_none());
+
+    // Mistakes in the configuration are caught up front
+    for routes in ["/posts/**/comments", "FETCH /posts", "posts"] {
+        let bad = config(&[("bad", &[routes], Some(per_ip), None)]);
+        assert!(RateLimiter::new(&bad).is_err(), "{}", routes);
+    }
+    assert!(RateLimiter::new(&config(&[("bad", &["/"], Some(Quota { burst: 0, per_minute: 1 }), None)])).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sqlite_store_is_shared() -> Result<()> {
+    let path = PathBuf::from(format!("test_rate_limits_{}.db", Uuid::new_v4()));
+    let quota = Quota { burst: 2, per_minute: 60 };
+    let first = SqliteStore::open(&path)?;
+    let second = SqliteStore::open(&path)?;
+
+    assert_eq!(first.take("register:ip:198.51.100.1", quota, NOW).await?, (1.0, true));
+    assert_eq!(second.take("register:ip:198.51.100.1", quota, NOW).await?, (0.0, true));
+    assert!(!first.take("register:ip:198.51.100.1", quota, NOW).await?.1);
+    assert!(second.take("register:ip:198.51.100.1", quota, NOW + 1.0).await?.1);
+    assert!(first.take("register:ip:198.51.100.2", quota, NOW).await?.1);
+
+    // The limiter can use it in place of memory
+    let mut limits = config(&[("register", &["POST /register"], Some(quota), None)]);
+    limits.store = StoreKind::Sqlite;
+    limits.path = path.clone();
+    let limiter = RateLimiter::new(&limits)?;
+    let decision = limiter.check_at(Method::Post, "/register", ip("198.51.100.1"), None, NOW + 1.0).await?;
+    assert!(!decision.unwrap().allowed);
+
+    drop((first, second, limiter));
+    for suffix in ["", "-wal", "-shm"] {
+        fs::remove_file(format!("{}{}", path.display(), suffix)).ok();
+    }
+    Ok(())
+}
+
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+#[get("/")]
+fn index() -> &'static str {
+    "index"
+}
+
+#[post("/register")]
+fn register() -> &'static str {
+    CREATED.fetch_add(1, Ordering::SeqCst);
+    "registered"
+}
+
+#[rocket::async_test]
+async fn test_rate_limit_fairing() -> Result<()> {
+    let figment = Figment::from(config::defaults()).merge(Toml::string(
+        r#"
+        [rate_limit.groups.register]
+        routes = ["POST /register"]
+        per_ip = { burst = 2, per_minute = 1 }
+        "#,
+    ));
+    let rocket = rocket::custom(figment).mount("/", routes![index, register]).attach(RateLimit);
+    let client = Client::untracked(rocket).await?;
+    let from = "198.51.100.9:4000".parse()?;
+
+    for remaining in ["1", "0"] {
+        let response = client.post("/register").remote(from).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("RateLimit-Limit"), Some("2"));
+        assert_eq!(response.headers().get_one("RateLimit-Remaining"), Some(remaining));
+        assert!(response.headers().get_one("Retry-After").is_none());
+    }
+
+    // The refused request never reaches its route, whatever address the
+    // client claims to have
+    let response = client.post("/register").remote(from).dispatch().await;
+    assert_eq!(response.status(), Status::TooManyRequests);
+    for spoofed in ["203.0.113.1", "203.0.113.2"] {
+        let response = client.post("/register").remote(from).header(Header::new("X-Real-IP", spoofed)).dispatch().await;
+        assert_eq!(response.status(), Status::TooManyRequests);
+    }
+    assert_eq!(response.headers().get_one("RateLimit-Remaining"), Some("0"));
+    assert_eq!(response.headers().get_one("Retry-After"), Some("60"));
+    assert_eq!(CREATED.load(Ordering::SeqCst), 2);
+
+    // Routes outside every group are left alone
+    let response = client.get("/").remote(from).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.headers().get_one("RateLimit-Limit").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_client_address_header_is_off_by_default() {
+    assert!(rocket::Config::from(Figment::from(config::defaults())).ip_header.is_none());
+    assert!(rocket::Config::from(config::figment()).ip_header.is_none());
+
+    // Proxy deployments opt in
+    let figment = config::figment().merge(("ip_header", "X-Forwarded-For"));
+    let header = rocket::Config::from(figment).ip_header;
+    assert_eq!(header.as_ref().map(|header| header.as_str()), Some("X-Forwarded-For"));
+}
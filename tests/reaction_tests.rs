@@ -0,0 +1,95 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::comment::CreateComment;
+use blog::models::post::{CreatePost, PostSort};
+use blog::services::db::Database;
+use blog::services::{comment_service, post_service, user_service};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::Value;
+use uuid::Uuid;
+
+async fn react(client: &Client, path: &str, reacted: bool) -> (Status, Value) {
+    let request = if reacted { client.put(path.to_string()) } else { client.delete(path.to_string()) };
+    let response = request.dispatch().await;
+    (response.status(), response.into_json().await.unwrap_or_default())
+}
+
+async fn page(client: &Client, path: &str) -> Result<String> {
+    let response = client.get(path).dispatch().await;
+    assert_eq!(response.status(), Status::Ok, "{}", path);
+    response.into_string().await.context("response has a body")
+}
+
+async fn reactions(db: &Database) -> Result<()> {
+    let author = common::create_test_user(db, "writer").await?;
+    let reader = common::create_test_user(db, "reader").await?;
+    let looms = post_service::create_post(db, CreatePost { title: "Looms".to_string(), content: "Cards".to_string() }, author).await?;
+    let comment = comment_service::create_comment(db, CreateComment { content: "Neat".to_string() }, looms.id, author).await?;
+    let newer = post_service::create_post(db, CreatePost { title: "Newer".to_string(), content: "Later".to_string() }, author).await?;
+    let client = common::site(db.clone()).await?;
+    let post_path = |kind: &str| format!("/posts/{}/reactions/{}", looms.id, kind);
+
+    // Only signed-in readers can react
+    assert_eq!(react(&client, &post_path("like"), true).await.0, Status::Unauthorized);
+    client.post("/login").header(ContentType::Form).body("username=reader&password=testpass123").dispatch().await;
+
+    // One reaction of each kind per reader, answered with the new counts
+    let (status, body) = react(&client, &post_path("like"), true).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!((body["counts"]["like"].as_i64(), body["total"].as_i64(), body["reacted"].as_bool()), (Some(1), Some(1), Some(true)));
+    assert_eq!(react(&client, &post_path("like"), true).await.1["total"], 1);
+    assert_eq!(react(&client, &post_path("love"), true).await.1["total"], 2);
+    let (_, body) = react(&client, &post_path("love"), false).await;
+    assert_eq!((body["total"].as_i64(), body["reacted"].as_bool()), (Some(1), Some(false)));
+    assert!(body["counts"].get("love").is_none());
+
+    assert_eq!(react(&client, &post_path("shrug"), true).await.0, Status::BadRequest);
+    assert_eq!(react(&client, "/posts/not-a-post/reactions/like", true).await.0, Status::BadRequest);
+    assert_eq!(react(&client, &format!("/posts/{}/reactions/like", Uuid::new_v4()), true).await.0, Status::NotFound);
+
+    // Comments take reactions too, and keep their own counts
+    let comment_path = format!("/posts/{}/comments/{}/reactions/laugh", looms.id, comment.id);
+    assert_eq!(react(&client, &comment_path, true).await.1["counts"]["laugh"], 1);
+    let comments = comment_service::get_post_comments(db, looms.id).await?;
+    assert_eq!(comments[0].reactions.count("laugh"), 1);
+    assert_eq!(post_service::get_post(db, looms.id).await?.context("post exists")?.reactions.total, 1);
+
+    // The post page shows the counts and marks the reader's own reactions
+    let post_page = page(&client, &format!("/posts/{}", looms.id)).await?;
+    assert!(post_page.contains(r#"data-kind="like" aria-pressed="true""#));
+    assert!(post_page.contains(r#"data-kind="love" aria-pressed="false""#));
+    assert!(post_page.contains(r#"data-kind="laugh" aria-pressed="true""#));
+    assert!(post_page.contains(&format!(r#"data-reactions="/posts/{}/comments/{}/reactions""#, looms.id, comment.id)));
+
+    // The most liked posts can be listed first
+    let titles = |posts: Vec<blog::models::post::Post>| posts.into_iter().map(|post| post.title).collect::<Vec<_>>();
+    assert_eq!(titles(post_service::get_posts(db, PostSort::Newest).await?), ["Newer", "Looms"]);
+    assert_eq!(titles(post_service::get_posts(db, PostSort::MostLiked).await?), ["Looms", "Newer"]);
+    let liked = page(&client, "/?sort=liked").await?;
+    assert!(liked.find("Looms") < liked.find("Newer"));
+    assert!(liked.contains("1 reaction<"));
+    let newest = page(&client, "/?sort=bogus").await?;
+    assert!(newest.find("Newer") < newest.find("Looms"));
+    assert!(newest.contains(r#"<a href="/?sort=liked""#));
+
+    // A reader's reactions leave the counts with them
+    assert_eq!(react(&client, &format!("/posts/{}/reactions/like", newer.id), true).await.0, Status::Ok);
+    user_service::delete_user(db, reader).await?;
+    for post in post_service::get_posts(db, PostSort::Newest).await? {
+        assert_eq!(post.reactions.total, 0, "{}", post.title);
+        assert!(post.reactions.counts.is_empty());
+    }
+    assert_eq!(comment_service::get_post_comments(db, looms.id).await?[0].reactions.total, 0);
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_reactions() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        reactions(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
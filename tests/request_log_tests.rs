@@ -0,0 +1,163 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::fairings;
+use blog::models::post::CreatePost;
+use blog::services::db::{connect, Database, DbConfig};
+use blog::services::{post_service, series_service};
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::Value;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, OnceLock};
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+/// Everything logged by this test binary, one JSON object per line.
+#[derive(Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Logs {
+    /// Installs the subscriber `main` uses for JSON logs, writing here.
+    fn get() -> &'static Logs {
+        static LOGS: OnceLock<Logs> = OnceLock::new();
+        LOGS.get_or_init(|| {
+            let logs = Logs::default();
+            let writer = logs.clone();
+            tracing_subscriber::fmt()
+                .with_env_filter(EnvFilter::new("info,blog=debug"))
+                .json()
+                .with_current_span(true)
+                .with_writer(move || writer.clone())
+                .init();
+            logs
+        })
+    }
+
+    /// The events logged while handling the request with this ID.
+    fn for_request(&self, id: &str) -> Vec<Value> {
+        let text = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+        text.lines()
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .filter(|event| {
+                event["spans"]
+                    .as_array()
+                    .is_some_and(|spans| spans.iter().any(|span| span["request_id"] == id))
+            })
+            .collect()
+    }
+}
+
+async fn site(db: Database) -> Result<Client> {
+    Ok(Client::tracked(common::Site::default().rocket(db)?.attach(fairings::RequestLog)).await?)
+}
+
+async fn request_id(client: &Client, sent: Option<&str>) -> Option<String> {
+    let mut request = client.get("/");
+    if let Some(id) = sent {
+        request = request.header(Header::new("X-Request-Id", id.to_string()));
+    }
+    request.dispatch().await.headers().get_one("X-Request-Id").map(str::to_string)
+}
+
+async fn request_ids(db: &Database) -> Result<()> {
+    Logs::get();
+    let client = site(db.clone()).await?;
+
+    // Generated when missing, a new one each time
+    let first = request_id(&client, None).await.context("a request ID is generated")?;
+    let second = request_id(&client, None).await.context("a request ID is generated")?;
+    Uuid::parse_str(&first)?;
+    assert_ne!(first, second);
+
+    // Echoed when the caller sends a sane one, replaced otherwise
+    assert_eq!(request_id(&client, Some("edge-1234_abc")).await.as_deref(), Some("edge-1234_abc"));
+    for unusable in ["has space", "quote\"d", &"x".repeat(65)] {
+        let id = request_id(&client, Some(unusable)).await.context("a request ID is generated")?;
+        Uuid::parse_str(&id).with_context(|| format!("{:?} was replaced", unusable))?;
+    }
+
+    // The route, service and SQL events are all tagged with it
+    let id = format!("trace-{}", Uuid::new_v4().simple());
+    assert_eq!(request_id(&client, Some(&id)).await.as_deref(), Some(id.as_str()));
+    let events = Logs::get().for_request(&id);
+    let messages: Vec<&str> = events.iter().filter_map(|event| event["fields"]["message"].as_str()).collect();
+    assert!(messages.contains(&"sql statement"), "{:?}", messages);
+    assert!(messages.contains(&"request completed"), "{:?}", messages);
+    assert!(events.iter().any(|event| event["span"]["name"] == "get_posts"));
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_request_ids() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        request_ids(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_failures_are_logged() -> Result<()> {
+    Logs::get();
+    let db_name = format!("test_{}.db", Uuid::new_v4());
+    let db = connect(&db_name, &DbConfig::default()).await?;
+    let author = common::create_test_user(&db, "writer").await?;
+    let post = post_service::create_post(&db, CreatePost { title: "Looms".to_string(), content: "Cards".to_string() }, author).await?;
+    let series = series_service::create_series(&db, author, "Weaving").await?;
+    series_service::add_to_series(&db, series.id, post.id, None).await?;
+    let client = site(db.clone()).await?;
+    client.post("/login").header(ContentType::Form).body("username=writer&password=testpass123").dispatch().await;
+    let sqlite = rusqlite::Connection::open(&db_name)?;
+
+    // A reader's reactions and bookmarks that cannot be read are an error
+    // too, not an empty list
+    sqlite.execute("ALTER TABLE post_reactions RENAME TO post_reactions_gone", [])?;
+    assert_eq!(client.get(format!("/posts/{}", post.id)).dispatch().await.status(), Status::InternalServerError);
+    sqlite.execute("ALTER TABLE bookmarks RENAME TO bookmarks_gone", [])?;
+    for path in ["/", "/bookmarks", "/bookmarks/export.json"] {
+        assert_eq!(client.get(path).dispatch().await.status(), Status::InternalServerError, "{}", path);
+    }
+
+    // Posts that cannot be read are an error, not an empty home page
+    client.post("/logout").dispatch().await;
+    sqlite.execute("ALTER TABLE posts RENAME TO posts_gone", [])?;
+    let id = format!("fail-{}", Uuid::new_v4().simple());
+    let status = client.get("/").header(Header::new("X-Request-Id", id.clone())).dispatch().await.status();
+    assert_eq!(status, Status::InternalServerError);
+    let series_page = format!("/series/{}", series.slug);
+    let series_feed = format!("/series/{}/feed.xml", series.slug);
+    for path in ["/authors/writer", "/authors/writer/feed.xml", &series_page, &series_feed] {
+        assert_eq!(client.get(path).dispatch().await.status(), Status::InternalServerError, "{}", path);
+    }
+
+    let events = Logs::get().for_request(&id);
+    let failure = events
+        .iter()
+        .find(|event| event["fields"]["message"] == "failed to load page data")
+        .context("the failure is logged")?;
+    assert_eq!(failure["level"], "ERROR");
+    assert!(failure["fields"]["error"].as_str().unwrap_or_default().contains("no such table"), "{}", failure);
+    let completed = events
+        .iter()
+        .find(|event| event["fields"]["message"] == "request failed")
+        .context("the request is logged as failed")?;
+    assert_eq!(completed["span"]["status"], 500);
+
+    drop(client);
+    drop(sqlite);
+    drop(db);
+    common::cleanup_test_db(&db_name);
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use anyhow::{Context, Result};
+use blog::fairings;
+use blog::security_headers::CspNonce;
+use rocket::figment::providers::{Format, Toml};
+use rocket::figment::{Figment, Profile};
+use rocket::fs::FileServer;
+use rocket::http::{ContentType, Cookie, Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket::{get, post, routes, Responder};
+use rocket_dyn_templates::Template;
+
+#[get("/page")]
+fn page(nonce: CspNonce) -> String {
+    nonce.as_str().to_string()
+}
+
+#[derive(Responder)]
+struct Framed(&'static str, Header<'static>);
+
+#[get("/embeddable")]
+fn embeddable() -> Framed {
+    Framed("embed me", Header::new("X-Frame-Options", "SAMEORIGIN"))
+}
+
+#[post("/change")]
+fn change() -> &'static str {
+    "changed"
+}
+
+async fn client(profile: &str, toml: &str) -> Result<Client> {
+    let figment = Figment::from(rocket::Config::default())
+        .merge(("secret_key", "hPRYyVRiMyxpw5sBB1XeCMN1kFsDCqKvBi2QJxBVHQk="))
+        .merge(Toml::string(toml).nested())
+        .select(Profile::new(profile));
+    let rocket = rocket::custom(figment)
+        .mount("/", routes![page, embeddable, change])
+        .mount("/static", FileServer::from(rocket::fs::relative!("static")))
+        .attach(Template::fairing())
+        .attach(fairings::Csrf)
+        .attach(fairings::SecurityHeaders);
+    Ok(Client::untracked(rocket).await?)
+}
+
+fn header<'a>(response: &'a LocalResponse<'_>, name: &str) -> Option<&'a str> {
+    response.headers().get_one(name)
+}
+
+const CONFIG: &str = r#"
+[default.security_headers]
+hsts_max_age_secs = 0
+
+[default.security_headers.csp]
+default-src = "'self'"
+script-src = "'self'"
+img-src = "'self' https:"
+
+[release.security_headers]
+hsts_max_age_secs = 31536000
+hsts_include_subdomains = true
+frame_ancestors = "https://partner.example"
+
+[release.security_headers.csp]
+img-src = "'self'"
+"#;
+
+#[rocket::async_test]
+async fn test_security_headers() -> Result<()> {
+    let client = client("default", CONFIG).await?;
+
+    // Each page gets its own nonce, the one its templates were given
+    let response = client.get("/page").dispatch().await;
+    let csp = header(&response, "Content-Security-Policy").context("policy is set")?.to_string();
+    let nonce = response.into_string().await.context("page has a body")?;
+    assert!(csp.contains(&format!("script-src 'self' 'nonce-{}'", nonce)), "{}", csp);
+    assert!(csp.contains("img-src 'self' https:"));
+    assert!(csp.contains("frame-ancestors 'none'"));
+    assert!(csp.contains("report-uri /csp-reports"));
+    let again = client.get("/page").dispatch().await.into_string().await.context("page has a body")?;
+    assert_ne!(again, nonce);
+
+    // Static files are covered too; HSTS stays off outside release
+    let response = client.get("/static/assets/css/output.css").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert!(header(&response, "Content-Security-Policy").is_some());
+    assert_eq!(header(&response, "X-Content-Type-Options"), Some("nosniff"));
+    assert_eq!(header(&response, "X-Frame-Options"), Some("DENY"));
+    assert_eq!(header(&response, "Referrer-Policy"), Some("strict-origin-when-cross-origin"));
+    assert!(header(&response, "Permissions-Policy").is_some_and(|value| value.contains("camera=()")));
+    assert!(header(&response, "Strict-Transport-Security").is_none());
+
+    // A route's own headers win
+    let response = client.get("/embeddable").dispatch().await;
+    assert_eq!(header(&response, "X-Frame-Options"), Some("SAMEORIGIN"));
+
+    // Release settings are laid over the defaults, directive by directive
+    let client = self::client("release", CONFIG).await?;
+    let response = client.get("/page").dispatch().await;
+    assert_eq!(header(&response, "Strict-Transport-Security"), Some("max-age=31536000; includeSubDomains"));
+    assert!(header(&response, "X-Frame-Options").is_none());
+    let csp = header(&response, "Content-Security-Policy").context("policy is set")?;
+    assert!(csp.contains("img-src 'self';"), "{}", csp);
+    assert!(csp.contains("default-src 'self'"));
+    assert!(csp.contains("frame-ancestors https://partner.example"));
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_report_only_and_disabled() -> Result<()> {
+    let client = client("default", "[default.security_headers]\ncsp_report_only = true\nreport_violations = false").await?;
+    let response = client.get("/page").dispatch().await;
+    assert!(header(&response, "Content-Security-Policy").is_none());
+    let csp = header(&response, "Content-Security-Policy-Report-Only").context("policy is reported")?;
+    assert!(!csp.contains("report-uri"));
+    let response = client.post("/csp-reports").header(ContentType::JSON).body("{}").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let client = self::client("default", "[default.security_headers]\nenabled = false").await?;
+    let response = client.get("/page").dispatch().await;
+    assert!(header(&response, "Content-Security-Policy").is_none());
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_csp_reports() -> Result<()> {
+    let client = client("default", "").await?;
+    let session = || Cookie::new("user_id", uuid::Uuid::new_v4().to_string());
+    let csp_report = ContentType::new("application", "csp-report");
+
+    // Browsers send reports with the session's cookies and no CSRF token
+    let report = r#"{"csp-report": {"document-uri": "https://blog.example/posts/1",
+        "violated-directive": "script-src", "blocked-uri": "inline"}}"#;
+    let response = client.post("/csp-reports").header(csp_report.clone()).private_cookie(session()).body(report).dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+    let reports = r#"[{"type": "csp-violation", "url": "https://blog.example/",
+        "body": {"documentURL": "https://blog.example/", "effectiveDirective": "img-src", "blockedURL": "https://x.example/a.png"}}]"#;
+    let response = client
+        .post("/csp-reports")
+        .header(ContentType::new("application", "reports+json"))
+        .body(reports)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+
+    // Forms another site could send still need the token
+    let response = client.post("/change").header(ContentType::Form).private_cookie(session()).body("").dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    for junk in ["not json", "{}", "[]"] {
+        let response = client.post("/csp-reports").header(csp_report.clone()).body(junk).dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest, "{}", junk);
+    }
+    Ok(())
+}
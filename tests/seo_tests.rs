@@ -0,0 +1,145 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::post::CreatePost;
+use blog::seo::{self, SeoConfig, Sitemap, SitemapUrl};
+use blog::services::db::Database;
+use blog::services::{post_service, tag_service};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+
+async fn body(client: &Client, path: &str) -> Result<(Status, String)> {
+    let response = client.get(path).dispatch().await;
+    Ok((response.status(), response.into_string().await.context("response has a body")?))
+}
+
+async fn seo_pages(db: &Database) -> Result<()> {
+    let author = common::create_test_user(db, "writer").await?;
+    let content = "# Engines\n\nThe <em>analytical</em> engine weaves patterns & numbers.";
+    let post = post_service::create_post(db, CreatePost { title: "Engines".to_string(), content: content.to_string() }, author).await?;
+    tag_service::tag_post(db, post.id, &["History".to_string()]).await?;
+    let other = post_service::create_post(db, CreatePost { title: "Looms".to_string(), content: "Cards".to_string() }, author).await?;
+    let client = common::site(db.clone()).await?;
+
+    // Posts describe themselves to search engines and link previews
+    let url = format!("https://blog.example/posts/{}", post.id);
+    let (status, page) = body(&client, &format!("/posts/{}", post.id)).await?;
+    assert_eq!(status, Status::Ok);
+    assert!(page.contains("<title>Engines - Blog</title>"));
+    assert!(page.contains(&format!(r#"<link rel="canonical" href="{}">"#, url)));
+    assert!(page.contains(r#"<meta property="og:title" content="Engines">"#));
+    assert!(page.contains(r#"content="Engines The analytical engine weaves patterns &amp; numbers.""#), "{}", page);
+    assert!(page.contains(r#"<meta property="article:tag" content="History">"#));
+    assert!(page.contains(r#"<meta name="twitter:card" content="summary">"#));
+    assert!(page.contains(r#""@type":"BlogPosting""#));
+    assert!(page.contains(r#""keywords":["History"]"#));
+    assert!(page.contains(r#"patterns \u0026 numbers"#));
+
+    // Authors can set their own title and description
+    client.post("/login").header(ContentType::Form).body("username=writer&password=testpass123").dispatch().await;
+    let (status, edit) = body(&client, &format!("/posts/{}/edit", post.id)).await?;
+    assert_eq!(status, Status::Ok);
+    assert!(edit.contains(r#"name="seo_title""#));
+    let response = client
+        .post(format!("/posts/{}/seo", post.id))
+        .header(ContentType::Form)
+        .body("_method=put&seo_title=Babbage%27s+Engines&seo_description=A+short+history.")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    let (_, page) = body(&client, &format!("/posts/{}", post.id)).await?;
+    assert!(page.contains("<title>Babbage&#x27;s Engines - Blog</title>"));
+    assert!(page.contains(r#"<meta name="description" content="A short history.">"#));
+    let updated = post_service::get_post(db, post.id).await?.context("post exists")?;
+    assert_eq!(updated.updated_at, post.updated_at);
+
+    // Clearing them goes back to the post's own
+    client
+        .post(format!("/posts/{}/seo", post.id))
+        .header(ContentType::Form)
+        .body("_method=put&seo_title=+&seo_description=")
+        .dispatch()
+        .await;
+    let cleared = post_service::get_post(db, post.id).await?.context("post exists")?;
+    assert_eq!((cleared.seo_title, cleared.seo_description), (None, None));
+
+    // Other authors cannot change them
+    common::create_test_user(db, "rival").await?;
+    client.post("/login").header(ContentType::Form).body("username=rival&password=testpass123").dispatch().await;
+    client
+        .post(format!("/posts/{}/seo", post.id))
+        .header(ContentType::Form)
+        .body("_method=put&seo_title=Mine&seo_description=")
+        .dispatch()
+        .await;
+    assert_eq!(post_service::get_post(db, post.id).await?.context("post exists")?.seo_title, None);
+
+    // The sitemap lists the home page, posts and authors
+    let (status, sitemap) = body(&client, "/sitemap.xml").await?;
+    assert_eq!(status, Status::Ok);
+    assert!(sitemap.contains("<urlset"));
+    assert!(sitemap.contains(&format!("<loc>{}</loc>", url)));
+    assert!(sitemap.contains(&format!("<lastmod>{}</lastmod>", post.updated_at)));
+    assert!(sitemap.contains(&format!("<loc>https://blog.example/posts/{}</loc>", other.id)));
+    assert!(sitemap.contains("<loc>https://blog.example/authors/writer</loc>"));
+    assert_eq!(body(&client, "/sitemaps/1.xml").await?.0, Status::NotFound);
+
+    // Past the limit it becomes an index of parts
+    let client = Client::tracked(common::Site { seo: SeoConfig { sitemap_max_urls: 2, ..SeoConfig::default() }, ..Default::default() }.rocket(db.clone())?).await?;
+    let (_, index) = body(&client, "/sitemap.xml").await?;
+    assert!(index.contains("<sitemapindex"));
+    assert!(index.contains("<loc>https://blog.example/sitemaps/2.xml</loc>"));
+    let (status, part) = body(&client, "/sitemaps/2.xml").await?;
+    assert_eq!(status, Status::Ok);
+    assert!(part.contains("<urlset") && part.matches("<url>").count() == 2);
+    assert_eq!(body(&client, "/sitemaps/3.xml").await?.0, Status::NotFound);
+
+    let (_, robots) = body(&client, "/robots.txt").await?;
+    assert!(robots.contains("Disallow: /profile\n"));
+    assert!(robots.contains("Sitemap: https://blog.example/sitemap.xml"));
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_seo_pages() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        seo_pages(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_descriptions() {
+    assert_eq!(seo::describe("## Title\n\n**Bold** and `code`, a < b > c.\n\n<p>Para</p>"), "Title Bold and code, a < b c. Para");
+    let long = "word ".repeat(100);
+    let description = seo::describe(&long);
+    assert!(description.ends_with('\u{2026}') && description.chars().count() <= 160);
+    assert!(!description.contains("wor\u{2026}"));
+    assert_eq!(seo::describe(&"x".repeat(300)).chars().count(), 160);
+}
+
+#[test]
+fn test_robots_and_sitemaps() {
+    let closed = SeoConfig { allow_crawling: false, robots_extra: "User-agent: Archiver\nAllow: /".to_string(), ..SeoConfig::default() };
+    assert_eq!(seo::robots_txt(&closed, "https://blog.example"), "User-agent: *\nDisallow: /\n\nUser-agent: Archiver\nAllow: /\n");
+
+    let urls = |count: usize| {
+        (0..count)
+            .map(|n| SitemapUrl { loc: format!("https://blog.example/{}", n), lastmod: Some(format!("2024-01-{:02}", n + 1)) })
+            .collect::<Vec<_>>()
+    };
+    let sitemap = Sitemap::new("https://blog.example/", urls(5), 2);
+    assert_eq!(sitemap.paths(), ["sitemap.xml", "sitemaps/1.xml", "sitemaps/2.xml", "sitemaps/3.xml"]);
+    let (template, index) = sitemap.file("sitemap.xml").unwrap();
+    assert_eq!(template, "sitemap_index");
+    assert_eq!(index["sitemaps"][1]["lastmod"], "2024-01-04");
+    assert_eq!(sitemap.file("sitemaps/3.xml").unwrap().1["urls"].as_array().unwrap().len(), 1);
+    assert!(sitemap.file("sitemaps/0.xml").is_none());
+
+    let sitemap = Sitemap::new("https://blog.example", urls(2), 0);
+    assert_eq!(sitemap.paths().len(), 3);
+    let sitemap = Sitemap::new("https://blog.example", urls(2), 100_000);
+    assert_eq!(sitemap.paths(), ["sitemap.xml"]);
+    assert!(sitemap.file("sitemaps/1.xml").is_none());
+}
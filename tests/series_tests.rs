@@ -0,0 +1,113 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::post::{CreatePost, Post};
+use blog::services::db::Database;
+use blog::services::{post_service, series_service};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use uuid::Uuid;
+
+async fn page(client: &Client, path: &str) -> Result<(Status, String)> {
+    let response = client.get(path).dispatch().await;
+    Ok((response.status(), response.into_string().await.unwrap_or_default()))
+}
+
+async fn set_series(client: &Client, post: &Post, form: &str) -> Status {
+    client
+        .post(format!("/posts/{}/series", post.id))
+        .header(ContentType::Form)
+        .body(format!("_method=put&{}", form))
+        .dispatch()
+        .await
+        .status()
+}
+
+async fn titles(db: &Database, series_id: Uuid) -> Result<Vec<String>> {
+    let posts = series_service::get_series_posts(db, series_id).await?;
+    Ok(posts.into_iter().map(|post| post.title).collect())
+}
+
+async fn series(db: &Database) -> Result<()> {
+    let author = common::create_test_user(db, "writer").await?;
+    let mut posts = Vec::new();
+    for title in ["Setup", "Basics", "Advanced"] {
+        let post = CreatePost { title: title.to_string(), content: format!("All about {}", title) };
+        posts.push(post_service::create_post(db, post, author).await?);
+    }
+    let client = common::site(db.clone()).await?;
+    client.post("/login").header(ContentType::Form).body("username=writer&password=testpass123").dispatch().await;
+
+    // Starting a series from the edit page, then adding to it
+    assert_eq!(set_series(&client, &posts[0], "series=&new_series=Learning+Rust&position=").await, Status::SeeOther);
+    let rust = series_service::get_series_by_slug(db, "learning-rust").await?.context("series exists")?;
+    assert_eq!(rust.author, "writer");
+    for post in [&posts[2], &posts[1]] {
+        set_series(&client, post, &format!("series={}&new_series=&position=", rust.id)).await;
+    }
+    assert_eq!(titles(db, rust.id).await?, ["Setup", "Advanced", "Basics"]);
+
+    // Reordering moves one part and keeps the rest in order
+    set_series(&client, &posts[1], &format!("series={}&new_series=&position=2", rust.id)).await;
+    assert_eq!(titles(db, rust.id).await?, ["Setup", "Basics", "Advanced"]);
+    set_series(&client, &posts[0], &format!("series={}&new_series=&position=9", rust.id)).await;
+    assert_eq!(titles(db, rust.id).await?, ["Basics", "Advanced", "Setup"]);
+    set_series(&client, &posts[0], &format!("series={}&new_series=&position=1", rust.id)).await;
+
+    // Each part links to its neighbours and the series page
+    let (status, middle) = page(&client, &format!("/posts/{}", posts[1].id)).await?;
+    assert_eq!(status, Status::Ok);
+    assert!(middle.contains("Part 2 of 3"));
+    assert!(middle.contains(r#"<a href="/series/learning-rust""#));
+    assert!(middle.contains(&format!(r#"href="/posts/{}" rel="prev""#, posts[0].id)));
+    assert!(middle.contains(&format!(r#"href="/posts/{}" rel="next""#, posts[2].id)));
+    let (_, edit) = page(&client, &format!("/posts/{}/edit", posts[1].id)).await?;
+    assert!(edit.contains(&format!(r#"<option value="{}" selected>Learning Rust</option>"#, rust.id)));
+    assert!(edit.contains("Move up") && edit.contains("Move down"));
+
+    let (status, landing) = page(&client, "/series/learning-rust").await?;
+    assert_eq!(status, Status::Ok);
+    assert!(landing.find("Setup") < landing.find("Basics") && landing.find("Basics") < landing.find("Advanced"));
+    assert!(landing.contains("3 parts"));
+    let (status, feed) = page(&client, "/series/learning-rust/feed.xml").await?;
+    assert_eq!(status, Status::Ok);
+    assert!(feed.contains("<title>Learning Rust</title>"));
+    assert!(feed.contains(&format!("<id>https://blog.example/posts/{}</id>", posts[2].id)));
+    assert!(page(&client, "/sitemap.xml").await?.1.contains("<loc>https://blog.example/series/learning-rust</loc>"));
+    assert_eq!(page(&client, "/series/missing").await?.0, Status::NotFound);
+
+    // A second series with the same title gets its own slug, and moving a
+    // post there takes it out of the first
+    set_series(&client, &posts[2], "series=&new_series=Learning+Rust&position=").await;
+    let second = series_service::get_series_by_slug(db, "learning-rust-2").await?.context("series exists")?;
+    assert_eq!(titles(db, second.id).await?, ["Advanced"]);
+    assert_eq!(titles(db, rust.id).await?, ["Setup", "Basics"]);
+
+    // Leaving both fields empty takes a post out of its series
+    set_series(&client, &posts[2], "series=&new_series=&position=").await;
+    assert!(series_service::get_post_series(db, posts[2].id).await?.is_none());
+    assert!(!page(&client, &format!("/posts/{}", posts[2].id)).await?.1.contains("Part 1 of"));
+
+    // Other authors can neither use the series nor move its posts
+    let rival = common::create_test_user(db, "rival").await?;
+    let theirs = post_service::create_post(db, CreatePost { title: "Theirs".to_string(), content: "Mine".to_string() }, rival).await?;
+    client.post("/login").header(ContentType::Form).body("username=rival&password=testpass123").dispatch().await;
+    set_series(&client, &theirs, &format!("series={}&new_series=&position=1", rust.id)).await;
+    set_series(&client, &posts[1], "series=&new_series=&position=").await;
+    assert_eq!(titles(db, rust.id).await?, ["Setup", "Basics"]);
+
+    // Deleting a post closes the gap it leaves
+    post_service::delete_post(db, posts[0].id).await?;
+    let (_, basics) = page(&client, &format!("/posts/{}", posts[1].id)).await?;
+    assert!(basics.contains("Part 1 of 1"));
+    Ok(())
+}
+
+#[rocket::async_test]
+async fn test_series() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        series(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::comment::CreateComment;
+use blog::models::post::CreatePost;
+use blog::seo::SeoConfig;
+use blog::services::db::Database;
+use blog::services::{comment_service, post_service, series_service, tag_service};
+use blog::static_site::{self, StaticSiteConfig};
+use std::fs;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+fn test_config() -> StaticSiteConfig {
+    StaticSiteConfig {
+        output_dir: PathBuf::from(format!("test_site_{}", Uuid::new_v4())),
+        base_url: Some("https://example.com/docs/".to_string()),
+        posts_per_page: 2,
+        ..StaticSiteConfig::default()
+    }
+}
+
+async fn build_site(db: &Database) -> Result<()> {
+    let config = test_config();
+    let out = &config.output_dir;
+    let author = common::create_test_user(db, "Site Author").await?;
+    let mut posts = Vec::new();
+    for n in 1..=3 {
+        let post = post_service::create_post(
+            db,
+            CreatePost { title: format!("Post {}", n), content: format!("Body {}", n) },
+            author,
+        ).await?;
+        posts.push(post);
+    }
+    tag_service::tag_post(db, posts[0].id, &["Release Notes".to_string()]).await?;
+
+    let report = static_site::build(db, &config, &SeoConfig::default(), false).await?;
+    assert_eq!(report.posts_rendered, 3);
+    for page in [
+        "index.html",
+        "page/2/index.html",
+        "tags/release-notes/index.html",
+        "authors/site-author/index.html",
+        "authors/site-author/feed.xml",
+        "feed.xml",
+        "sitemap.xml",
+        "robots.txt",
+        "static/assets/css/output.css",
+    ] {
+        assert!(out.join(page).is_file(), "{} missing", page);
+    }
+
+    // Links are relative to each page, and sign-in controls are left out
+    let post_page = fs::read_to_string(out.join(format!("posts/{}/index.html", posts[0].id)))?;
+    assert!(post_page.contains(r#"href="../../static/assets/css/output.css""#));
+    assert!(post_page.contains(r#"href="../../tags/release-notes/""#));
+    assert!(!post_page.contains("/login"));
+    let canonical = format!(r#"<link rel="canonical" href="https://example.com/docs/posts/{}/">"#, posts[0].id);
+    assert!(post_page.contains(&canonical));
+    assert!(post_page.contains(r#"<meta property="article:tag" content="Release Notes">"#));
+    let robots = fs::read_to_string(out.join("robots.txt"))?;
+    assert!(robots.contains("Sitemap: https://example.com/docs/sitemap.xml"));
+    let index = fs::read_to_string(out.join("index.html"))?;
+    assert!(index.contains(r#"href="./page/2/""#));
+    let feed = fs::read_to_string(out.join("feed.xml"))?;
+    assert!(feed.contains(&format!("<id>https://example.com/docs/posts/{}/</id>", posts[0].id)));
+
+    // Nothing changed: no post is rendered again and no file is rewritten
+    let report = static_site::build(db, &config, &SeoConfig::default(), false).await?;
+    assert_eq!((report.posts_rendered, report.posts_unchanged, report.pages_written), (0, 3, 0));
+
+    // Only the post with a new comment is rendered again
+    comment_service::create_comment(db, CreateComment { content: "Nice".to_string() }, posts[1].id, author).await?;
+    let report = static_site::build(db, &config, &SeoConfig::default(), false).await?;
+    assert_eq!((report.posts_rendered, report.posts_unchanged), (1, 2));
+
+    // Deleted posts disappear, along with pagination they no longer fill
+    post_service::delete_post(db, posts[2].id).await?;
+    let report = static_site::build(db, &config, &SeoConfig::default(), false).await?;
+    assert!(report.removed.contains(&format!("posts/{}/index.html", posts[2].id)));
+    assert!(report.removed.contains(&"page/2/index.html".to_string()));
+    assert!(!out.join("page").exists());
+
+    let report = static_site::build(db, &config, &SeoConfig::default(), true).await?;
+    assert_eq!(report.posts_rendered, 2);
+
+    // Series get a page and a feed, and their posts link to each other
+    let series = series_service::create_series(db, author, "Getting Started").await?;
+    series_service::add_to_series(db, series.id, posts[1].id, None).await?;
+    series_service::add_to_series(db, series.id, posts[0].id, Some(1)).await?;
+    let report = static_site::build(db, &config, &SeoConfig::default(), false).await?;
+    assert_eq!(report.posts_rendered, 2);
+    let series_page = fs::read_to_string(out.join("series/getting-started/index.html"))?;
+    assert!(series_page.find("Post 1") < series_page.find("Post 2"));
+    assert!(series_page.contains(&format!(r#"href="../../posts/{}/""#, posts[0].id)));
+    assert!(out.join("series/getting-started/feed.xml").is_file());
+    let post_page = fs::read_to_string(out.join(format!("posts/{}/index.html", posts[1].id)))?;
+    assert!(post_page.contains("Part 2 of 2"));
+    assert!(post_page.contains(&format!(r#"href="../../posts/{}/" rel="prev""#, posts[0].id)));
+    assert!(fs::read_to_string(out.join("sitemap.xml"))?.contains("https://example.com/docs/series/getting-started/"));
+
+    // A sitemap too big for one file becomes an index of smaller ones
+    let seo = SeoConfig { sitemap_max_urls: 2, ..SeoConfig::default() };
+    static_site::build(db, &config, &seo, false).await?;
+    let sitemap = fs::read_to_string(out.join("sitemap.xml"))?;
+    assert!(sitemap.contains("<loc>https://example.com/docs/sitemaps/3.xml</loc>"), "{}", sitemap);
+    assert!(out.join("sitemaps/3.xml").is_file() && !out.join("sitemaps/4.xml").exists());
+    let report = static_site::build(db, &config, &SeoConfig::default(), false).await?;
+    assert!(report.removed.contains(&"sitemaps/1.xml".to_string()));
+
+    fs::remove_dir_all(out)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_build_static_site() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        build_site(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_relativize() {
+    let html = r#"<a href="/">Home</a><a href="/posts/1?x=1#c">P</a><img src="/static/a.png"><a href="//cdn.example.com/x">C</a><a href="https://example.com/">E</a>"#;
+    assert_eq!(
+        static_site::relativize(html, 2),
+        r#"<a href="../../">Home</a><a href="../../posts/1/?x=1#c">P</a><img src="../../static/a.png"><a href="//cdn.example.com/x">C</a><a href="https://example.com/">E</a>"#
+    );
+}
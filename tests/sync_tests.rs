@@ -0,0 +1,182 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::post::PostSort;
+use blog::services::db::Database;
+use blog::services::sync_service::{self, SyncOptions};
+use blog::services::{post_service, tag_service};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use uuid::Uuid;
+
+const FIRST: &str = "---
+title: First post
+tags: [rust, release]
+date: 2024-01-05
+---
+
+Hello from a file.
+";
+
+fn test_dir() -> Result<PathBuf> {
+    let dir = PathBuf::from(format!("test_sync_{}", Uuid::new_v4()));
+    fs::create_dir_all(dir.join("notes"))?;
+    Ok(dir)
+}
+
+fn options() -> SyncOptions {
+    SyncOptions { author: Some("writer".to_string()), ..SyncOptions::default() }
+}
+
+async fn sync_directory(db: &Database) -> Result<()> {
+    common::create_test_user(db, "writer").await?;
+    let dir = test_dir()?;
+    fs::write(dir.join("first.md"), FIRST)?;
+    fs::write(dir.join("notes/draft.md"), "---\ntitle: Later\nstatus: draft\n---\nNot yet\n")?;
+    fs::write(dir.join("notes/broken.md"), "No front matter\n")?;
+
+    let report = sync_service::sync(db, &dir, &SyncOptions { dry_run: true, ..options() }).await?;
+    assert_eq!(report.created, vec!["first.md"]);
+    assert!(post_service::get_posts(db, PostSort::Newest).await?.is_empty(), "dry run writes nothing");
+
+    let report = sync_service::sync(db, &dir, &options()).await?;
+    assert_eq!(report.created, vec!["first.md"]);
+    assert_eq!(report.skipped.len(), 2);
+    let post = db.find_post_by_slug("first").await?.expect("post created");
+    assert_eq!(post.title, "First post");
+    assert_eq!(post.content, "Hello from a file.\n");
+    assert_eq!(post.created_at, "2024-01-05T00:00:00+00:00");
+    let tags: Vec<_> = tag_service::get_post_tags(db, post.id).await?.into_iter().map(|tag| tag.name).collect();
+    assert_eq!(tags, vec!["release", "rust"]);
+
+    let report = sync_service::sync(db, &dir, &options()).await?;
+    assert_eq!((report.created.len(), report.updated.len(), report.unchanged), (0, 0, 1));
+
+    // Editing the file updates the post in place
+    fs::write(dir.join("first.md"), FIRST.replace("Hello", "Hi").replace("[rust, release]", "[rust]"))?;
+    let report = sync_service::sync(db, &dir, &options()).await?;
+    assert_eq!(report.updated, vec!["first.md"]);
+    let post = post_service::get_post(db, post.id).await?.expect("post kept");
+    assert_eq!(post.content, "Hi from a file.\n");
+    assert_eq!(tag_service::get_post_tags(db, post.id).await?.len(), 1);
+
+    // A web edit is reported, then written back on request
+    post_service::update_post(db, post.id, "Edited title".to_string(), "Edited on the web.".to_string()).await?;
+    let report = sync_service::sync(db, &dir, &options()).await?;
+    assert_eq!(report.web_edits, vec!["first.md"]);
+    let report = sync_service::sync(db, &dir, &SyncOptions { write_back: true, ..options() }).await?;
+    assert_eq!(report.written_back, vec!["first.md"]);
+    let file = fs::read_to_string(dir.join("first.md"))?;
+    assert!(file.contains("title: Edited title"));
+    assert!(file.contains("date: 2024-01-05"), "front matter the editor does not manage is kept");
+    assert!(file.ends_with("---\n\nEdited on the web."));
+    let report = sync_service::sync(db, &dir, &options()).await?;
+    assert_eq!(report.unchanged, 1);
+
+    // Changes on both sides are left alone
+    post_service::update_post(db, post.id, "Web".to_string(), "Web".to_string()).await?;
+    fs::write(dir.join("first.md"), FIRST)?;
+    let report = sync_service::sync(db, &dir, &SyncOptions { write_back: true, ..options() }).await?;
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(post_service::get_post(db, post.id).await?.expect("post kept").title, "Web");
+
+    // Removing the file only deletes the post when pruning
+    fs::remove_file(dir.join("first.md"))?;
+    let report = sync_service::sync(db, &dir, &options()).await?;
+    assert_eq!(report.orphaned, vec!["first.md"]);
+    assert!(report.deleted.is_empty());
+    assert!(post_service::get_post(db, post.id).await?.is_some());
+    let report = sync_service::sync(db, &dir, &SyncOptions { prune: true, ..options() }).await?;
+    assert_eq!(report.deleted, vec!["first.md"]);
+    assert!(post_service::get_post(db, post.id).await?.is_none());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sync_directory() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        sync_directory(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+async fn sync_other_directories(db: &Database) -> Result<()> {
+    common::create_test_user(db, "writer").await?;
+    let blog = test_dir()?;
+    fs::write(blog.join("first.md"), FIRST)?;
+    sync_service::sync(db, &blog, &options()).await?;
+    let post = db.find_post_by_slug("first").await?.expect("post created");
+
+    // Neither an empty directory nor one with other files owns the post
+    let empty = test_dir()?;
+    let report = sync_service::sync(db, &empty, &SyncOptions { prune: true, ..options() }).await?;
+    assert!(report.deleted.is_empty() && report.orphaned.is_empty());
+    let other = test_dir()?;
+    fs::write(other.join("first.md"), FIRST.replace("First post", "Other first"))?;
+    fs::write(other.join("second.md"), "---\ntitle: Second\n---\nElsewhere\n")?;
+    let report = sync_service::sync(db, &other, &SyncOptions { prune: true, ..options() }).await?;
+    assert_eq!(report.created, vec!["second.md"]);
+    assert_eq!(report.skipped.len(), 1, "the same path elsewhere is a different file");
+    assert!(report.deleted.is_empty());
+    assert_eq!(post_service::get_post(db, post.id).await?.expect("post kept").title, "First post");
+
+    let report = sync_service::sync(db, &blog, &SyncOptions { prune: true, ..options() }).await?;
+    assert_eq!((report.unchanged, report.deleted.len()), (1, 0));
+
+    for dir in [blog, empty, other] {
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sync_keeps_posts_of_other_directories() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        sync_other_directories(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<()> {
+    let status = Command::new("git")
+        .current_dir(dir)
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .status()?;
+    anyhow::ensure!(status.success(), "git {:?} failed", args);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sync_bare_repository() -> Result<()> {
+    let test_db = common::setup_test_db().await?;
+    let db = &test_db.db;
+    common::create_test_user(db, "writer").await?;
+
+    let work = test_dir()?;
+    fs::write(work.join("notes/first.md"), FIRST)?;
+    git(&work, &["init", "-q"])?;
+    git(&work, &["add", "."])?;
+    git(&work, &["commit", "-q", "-m", "Add post"])?;
+    let bare = PathBuf::from(format!("{}.git", work.display()));
+    git(Path::new("."), &["clone", "-q", "--bare", &work.to_string_lossy(), &bare.to_string_lossy()])?;
+
+    // Write-back is refused before any post is created
+    let error = sync_service::sync(db, &bare, &SyncOptions { write_back: true, ..options() }).await.unwrap_err();
+    assert!(error.to_string().contains("bare repository"), "{}", error);
+    assert!(db.find_post_by_slug("first").await?.is_none());
+
+    let report = sync_service::sync(db, &bare, &options()).await?;
+    assert_eq!(report.created, vec!["notes/first.md"]);
+    assert!(db.find_post_by_slug("first").await?.is_some());
+
+    fs::remove_dir_all(&work)?;
+    fs::remove_dir_all(&bare)?;
+    test_db.cleanup().await;
+    Ok(())
+}
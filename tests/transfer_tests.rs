@@ -0,0 +1,138 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::bookmark::SaveBookmark;
+use blog::models::comment::CreateComment;
+use blog::models::post::{CreatePost, PostSort};
+use blog::models::profile::UpdateProfile;
+use blog::models::reaction::ReactionTarget;
+use blog::services::db::Database;
+use blog::services::{
+    bookmark_service, comment_service, post_service, profile_service, reaction_service, series_service, transfer_service,
+    user_service,
+};
+
+const CONTENT: &str = "Intro\n\n---\n\nA body containing a front matter fence.\n";
+
+async fn archive_round_trip(source: &Database, target: &Database) -> Result<()> {
+    let author_id = common::create_test_user(source, "author").await?;
+    let post = post_service::create_post(
+        source,
+        CreatePost { title: "Title: with colon".to_string(), content: CONTENT.to_string() },
+        author_id,
+    ).await?;
+    let comment =
+        comment_service::create_comment(source, CreateComment { content: "First".to_string() }, post.id, author_id).await?;
+    let reader_id = common::create_test_user(source, "reader").await?;
+    profile_service::update_profile(source, author_id, UpdateProfile {
+        display_name: "The Author".to_string(),
+        bio: "Writes.".to_string(),
+        avatar_url: String::new(),
+        website: "https://author.example".to_string(),
+        social_links: String::new(),
+    }).await?;
+    let series = series_service::create_series(source, author_id, "Fences").await?;
+    series_service::add_to_series(source, series.id, post.id, None).await?;
+    reaction_service::react(source, ReactionTarget::Post, post.id, reader_id, "love", true).await?;
+    reaction_service::react(source, ReactionTarget::Comment, comment.id, reader_id, "like", true).await?;
+    bookmark_service::save_bookmark(source, reader_id, post.id, SaveBookmark {
+        folder: "Later".to_string(),
+        note: "Read twice".to_string(),
+    }).await?;
+
+    let mut archive = Vec::new();
+    transfer_service::write_archive(transfer_service::export_site(source, false).await?, &mut archive)?;
+    let export = transfer_service::read_archive(archive.as_slice())?;
+    assert_eq!(export.posts.len(), 1);
+    assert_eq!(export.posts[0].post.content, CONTENT);
+
+    let report = transfer_service::import_site(target, export).await?;
+    assert_eq!((report.users_created, report.posts_created, report.comments_created), (2, 1, 1));
+    assert_eq!(
+        (report.profiles_created, report.series_created, report.reactions_created, report.bookmarks_created),
+        (1, 1, 2, 1)
+    );
+    assert!(report.conflicts.is_empty(), "{:?}", report.conflicts);
+
+    let imported = post_service::get_post(target, post.id).await?.expect("post imported");
+    assert_eq!(imported.title, "Title: with colon");
+    assert_eq!(imported.content, CONTENT);
+    assert_eq!(imported.author, "author");
+    let comments = comment_service::get_post_comments(target, post.id).await?;
+    assert_eq!(comments.len(), 1);
+
+    // Profiles, series, reactions and bookmarks come along, tied to the
+    // imported accounts
+    let author = profile_service::get_author_page(target, "author").await?.expect("author imported");
+    assert_eq!(author.profile.display_name.as_deref(), Some("The Author"));
+    assert_eq!(author.profile.website.as_deref(), Some("https://author.example"));
+    let imported_series = series_service::get_series_by_slug(target, &series.slug).await?.expect("series imported");
+    assert_eq!(imported_series.author, "author");
+    let parts = series_service::get_series_posts(target, imported_series.id).await?;
+    assert_eq!(parts.iter().map(|part| part.id).collect::<Vec<_>>(), vec![post.id]);
+    assert_eq!(imported.reactions.count("love"), 1);
+    assert_eq!(comments[0].reactions.count("like"), 1);
+    let reader = user_service::get_user_by_username(target, "reader").await?.expect("reader imported");
+    let mine = reaction_service::get_user_reactions(target, post.id, reader.id).await?;
+    assert_eq!(mine.post, vec!["love"]);
+    assert_eq!(mine.comments.get(&comment.id), Some(&vec!["like".to_string()]));
+    let bookmark = bookmark_service::get_bookmark(target, reader.id, post.id).await?.expect("bookmark imported");
+    assert_eq!((bookmark.folder.as_str(), bookmark.note.as_deref()), ("Later", Some("Read twice")));
+
+    // A second import is a no-op
+    let report = transfer_service::import_site(target, transfer_service::read_archive(archive.as_slice())?).await?;
+    assert_eq!((report.users_matched, report.posts_skipped, report.comments_skipped), (2, 1, 1));
+    assert_eq!(
+        (report.profiles_skipped, report.series_skipped, report.reactions_skipped, report.bookmarks_skipped),
+        (1, 1, 2, 1)
+    );
+    assert!(report.conflicts.is_empty(), "{:?}", report.conflicts);
+    assert_eq!(post_service::get_post(target, post.id).await?.expect("post exists").reactions.total, 1);
+
+    // Local edits are kept and reported rather than overwritten
+    assert!(post_service::update_post(target, post.id, "Edited".to_string(), "Edited".to_string()).await?);
+    let report = transfer_service::import_site(target, transfer_service::read_archive(archive.as_slice())?).await?;
+    // The post's reaction and bookmark are reported along with it
+    let conflicts: Vec<_> = report.conflicts.iter().map(|conflict| (conflict.kind, conflict.id)).collect();
+    assert_eq!(conflicts, vec![("post", post.id), ("reaction", post.id), ("bookmark", post.id)]);
+    assert_eq!(post_service::get_post(target, post.id).await?.expect("post exists").title, "Edited");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_archive_round_trip() -> Result<()> {
+    for target in common::test_databases().await? {
+        let source = common::setup_test_db().await?;
+        archive_round_trip(&source.db, &target.db).await.context(target.backend)?;
+        source.cleanup().await;
+        target.cleanup().await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_import_resolves_authors_by_username() -> Result<()> {
+    let source = common::setup_test_db().await?;
+    let target = common::setup_test_db().await?;
+
+    let author_id = common::create_test_user(&source.db, "shared").await?;
+    post_service::create_post(
+        &source.db,
+        CreatePost { title: "Post".to_string(), content: "Body".to_string() },
+        author_id,
+    ).await?;
+    let existing_id = common::create_test_user(&target.db, "shared").await?;
+
+    let mut export = transfer_service::export_site(&source.db, false).await?;
+    export.users.clear();
+    let report = transfer_service::import_site(&target.db, export).await?;
+    assert_eq!(report.posts_created, 1);
+
+    let posts = post_service::get_posts(&target.db, PostSort::Newest).await?;
+    assert_eq!(posts[0].author_id, existing_id);
+
+    source.cleanup().await;
+    target.cleanup().await;
+    Ok(())
+}
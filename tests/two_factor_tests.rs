@@ -0,0 +1,139 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::user::Role;
+use blog::qr;
+use blog::services::db::Database;
+use blog::services::two_factor_service::{self, LoginStep, TwoFactorConfig, MAX_FAILED_ATTEMPTS};
+use blog::services::user_service;
+use blog::totp;
+use chrono::Utc;
+
+#[test]
+fn test_totp_rfc6238_vectors() {
+    let secret = b"12345678901234567890";
+    assert_eq!(totp::code_at(secret, totp::step_at(59)), "287082");
+    assert_eq!(totp::code_at(secret, totp::step_at(1111111109)), "081804");
+    assert_eq!(totp::code_at(secret, totp::step_at(1234567890)), "005924");
+
+    let encoded = totp::base32_encode(secret);
+    assert_eq!(encoded, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    assert_eq!(totp::base32_decode(&encoded.to_lowercase()).as_deref(), Some(&secret[..]));
+    assert_eq!(totp::base32_encode(b"fooba"), "MZXW6YTB");
+    assert_eq!(totp::base32_encode(b"f"), "MY");
+    assert_eq!(totp::base32_decode("MY======").as_deref(), Some(&b"f"[..]));
+    assert_eq!(totp::base32_decode("M1"), None);
+
+    // Codes from the neighbouring steps count, older ones do not
+    assert_eq!(totp::verify(&encoded, "287082", 59), Some(1));
+    assert_eq!(totp::verify(&encoded, "287 082", 80), Some(1));
+    assert_eq!(totp::verify(&encoded, "287082", 120), None);
+    assert_eq!(totp::verify(&encoded, "28708", 59), None);
+}
+
+#[test]
+fn test_provisioning_uri() {
+    let uri = totp::provisioning_uri("My Blog", "ann", "GEZDGNBV");
+    assert_eq!(
+        uri,
+        "otpauth://totp/My%20Blog:ann?secret=GEZDGNBV&issuer=My%20Blog&algorithm=SHA1&digits=6&period=30"
+    );
+}
+
+#[test]
+fn test_qr_code_svg() -> Result<()> {
+    // Version 1 is 21 modules, plus the quiet zone on both sides
+    let svg = qr::svg(b"blog 2fa")?;
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.contains(r#"viewBox="0 0 29 29""#));
+    // The top left finder pattern starts at the quiet zone
+    assert!(svg.contains(r#"d="M4,4h1v1h-1z"#));
+
+    // Larger inputs pick larger versions; too much data is refused
+    let uri = totp::provisioning_uri("Blog", "someone-with-a-long-name", &totp::generate_secret());
+    assert!(!qr::svg(uri.as_bytes())?.contains(r#"viewBox="0 0 29 29""#));
+    assert!(qr::svg(&[b'x'; 3000]).is_err());
+    Ok(())
+}
+
+fn current_step() -> u64 {
+    totp::step_at(Utc::now().timestamp() as u64)
+}
+
+async fn two_factor_flow(db: &Database) -> Result<()> {
+    let config = TwoFactorConfig::default();
+    let user_id = common::create_test_user(db, "careful").await?;
+    let user = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert_eq!(two_factor_service::login_step(db, &config, &user).await?, LoginStep::Done);
+
+    // The pending secret survives a reload and is not yet required
+    let enrolment = two_factor_service::begin_enrolment(db, &config, &user).await?;
+    assert!(enrolment.uri.contains(&enrolment.secret));
+    assert!(enrolment.qr_svg.starts_with("<svg"));
+    assert_eq!(two_factor_service::begin_enrolment(db, &config, &user).await?.secret, enrolment.secret);
+    assert_eq!(two_factor_service::login_step(db, &config, &user).await?, LoginStep::Done);
+
+    let secret = totp::base32_decode(&enrolment.secret).context("secret is base32")?;
+    assert!(two_factor_service::confirm_enrolment(db, user_id, "abcdef").await.is_err());
+    let step = current_step();
+    let codes = two_factor_service::confirm_enrolment(db, user_id, &totp::code_at(&secret, step)).await?;
+    assert_eq!(codes.len(), two_factor_service::RECOVERY_CODE_COUNT);
+    assert_eq!(two_factor_service::login_step(db, &config, &user).await?, LoginStep::Code);
+    assert!(two_factor_service::begin_enrolment(db, &config, &user).await.is_err());
+    let status = two_factor_service::status(db, &config, &user).await?;
+    assert!(status.enabled && !status.required);
+    assert_eq!(status.recovery_codes_left, codes.len() as i64);
+
+    // A code works once; the next step's code is still fine
+    assert!(two_factor_service::verify_code(db, user_id, &totp::code_at(&secret, step)).await.is_err());
+    two_factor_service::verify_code(db, user_id, &totp::code_at(&secret, step + 1)).await?;
+
+    // Recovery codes ignore case and dashes, and are used up
+    two_factor_service::verify_code(db, user_id, &codes[0].to_uppercase().replace('-', " ")).await?;
+    assert!(two_factor_service::verify_code(db, user_id, &codes[0]).await.is_err());
+    assert_eq!(two_factor_service::status(db, &config, &user).await?.recovery_codes_left, codes.len() as i64 - 1);
+
+    // Too many wrong codes lock code entry, even for a right one
+    for _ in 1..MAX_FAILED_ATTEMPTS {
+        assert!(two_factor_service::verify_code(db, user_id, "nope-nope1").await.is_err());
+    }
+    let locked = two_factor_service::verify_code(db, user_id, &codes[1]).await.unwrap_err();
+    assert!(locked.to_string().contains("Too many"));
+    let two_factor = db.find_two_factor(user_id).await?.expect("two-factor exists");
+    assert!(two_factor.locked_until.is_some());
+    db.set_two_factor_lock(user_id, None, &two_factor.updated_at).await?;
+
+    // New recovery codes replace the old ones
+    let new_codes = two_factor_service::regenerate_recovery_codes(db, user_id, &codes[1]).await?;
+    assert!(two_factor_service::verify_code(db, user_id, &codes[2]).await.is_err());
+
+    // Turning it off needs the password too
+    assert!(two_factor_service::disable(db, &config, &user, "wrong", &new_codes[0]).await.is_err());
+    two_factor_service::disable(db, &config, &user, "testpass123", &new_codes[0]).await?;
+    assert_eq!(two_factor_service::login_step(db, &config, &user).await?, LoginStep::Done);
+    assert_eq!(two_factor_service::status(db, &config, &user).await?.recovery_codes_left, 0);
+    assert!(!two_factor_service::reset(db, user_id).await?);
+
+    // Required roles must enrol and cannot turn it off
+    let config = TwoFactorConfig { required_roles: vec![Role::Admin], ..TwoFactorConfig::default() };
+    user_service::set_role(db, user_id, Role::Admin).await?;
+    let admin = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert_eq!(two_factor_service::login_step(db, &config, &admin).await?, LoginStep::Enrol);
+    let enrolment = two_factor_service::begin_enrolment(db, &config, &admin).await?;
+    let secret = totp::base32_decode(&enrolment.secret).context("secret is base32")?;
+    let codes = two_factor_service::confirm_enrolment(db, user_id, &totp::code_at(&secret, current_step())).await?;
+    assert!(two_factor_service::status(db, &config, &admin).await?.required);
+    assert!(two_factor_service::disable(db, &config, &admin, "testpass123", &codes[0]).await.is_err());
+    assert!(two_factor_service::reset(db, user_id).await?);
+    assert_eq!(two_factor_service::login_step(db, &config, &admin).await?, LoginStep::Enrol);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_two_factor_flow() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        two_factor_flow(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use anyhow::{Context, Result};
+use blog::models::auth::verify_password;
+use blog::models::post::{CreatePost, PostSort};
+use blog::models::user::Role;
+use blog::services::db::Database;
+use blog::services::{post_service, transfer_service, user_service};
+
+async fn user_administration(db: &Database) -> Result<()> {
+    let user_id = common::create_test_user(db, "testuser").await?;
+
+    let user = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert_eq!(user.role, Role::User);
+    assert!(!user.disabled);
+
+    // Grant a role
+    user_service::set_role(db, user_id, Role::Admin).await?;
+    let user = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert_eq!(user.role, Role::Admin);
+
+    // Disable and re-enable
+    user_service::set_disabled(db, user_id, true).await?;
+    assert!(user_service::get_user_by_id(db, user_id).await?.expect("user exists").disabled);
+    user_service::set_disabled(db, user_id, false).await?;
+    assert!(!user_service::get_user_by_id(db, user_id).await?.expect("user exists").disabled);
+
+    // Reset password without the current one
+    user_service::reset_password(db, user_id, "resetpass1").await?;
+    let user = user_service::get_user_by_id(db, user_id).await?.expect("user exists");
+    assert!(verify_password("resetpass1", &user.password_hash).await?);
+
+    let users = user_service::list_users(db).await?;
+    assert_eq!(users.len(), 1);
+
+    Ok(())
+}
+
+async fn export_import_round_trip(source: &Database, target: &Database) -> Result<()> {
+    let user_id = common::create_test_user(source, "author").await?;
+    post_service::create_post(
+        source,
+        CreatePost { title: "Exported".to_string(), content: "Body".to_string() },
+        user_id,
+    ).await?;
+
+    let export = transfer_service::export_site(source, false).await?;
+    assert!(export.users[0].password_hash.is_none());
+
+    let report = transfer_service::import_site(target, export).await?;
+    assert_eq!(report.users_created, 1);
+    assert_eq!(report.posts_created, 1);
+
+    // Importing again changes nothing
+    let export = transfer_service::export_site(source, false).await?;
+    let report = transfer_service::import_site(target, export).await?;
+    assert_eq!(report.users_matched, 1);
+    assert_eq!(report.posts_skipped, 1);
+
+    let posts = post_service::get_posts(target, PostSort::Newest).await?;
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].author, "author");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_user_administration() -> Result<()> {
+    for test_db in common::test_databases().await? {
+        user_administration(&test_db.db).await.context(test_db.backend)?;
+        test_db.cleanup().await;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_import_round_trip() -> Result<()> {
+    for source in common::test_databases().await? {
+        let target = common::setup_test_db().await?;
+        export_import_round_trip(&source.db, &target.db).await.context(source.backend)?;
+        source.cleanup().await;
+        target.cleanup().await;
+    }
+    Ok(())
+}